@@ -1,16 +1,23 @@
 //! A client for communicating between RPUs.
 
 mod connection_pool;
+mod subscription;
+
+pub use subscription::Subscription;
 
 use crate::{Error, Request};
-use serde::Serialize;
+use serde::{de::DeserializeOwned, Serialize};
 use std::{
     convert::TryInto,
+    io,
     marker::{PhantomData, Unpin},
     net::SocketAddr,
     time::{Duration, Instant},
 };
-use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    sync::{mpsc, oneshot},
+};
 
 /// A client instance.
 ///
@@ -22,6 +29,9 @@ pub struct Client<T> {
 }
 
 impl<T> Client<T> {
+    /// The number of pushed responses that are buffered for a `Subscription`.
+    const SUBSCRIPTION_BUFFER_SIZE: usize = 16;
+
     /// Create a new client instance.
     ///
     /// # Example
@@ -56,12 +66,60 @@ impl<T> Client<T> {
         Ok(res?)
     }
 
+    /// Send a subscription request to the server specified.
+    ///
+    /// The connection stays open and the server pushes responses
+    /// until the returned `Subscription` is dropped or the server ends it.
+    pub async fn subscribe<Req>(&mut self, req: Req) -> Result<Subscription<Req::Response>, Error>
+    where
+        Req: Request<T>,
+        T: Serialize,
+    {
+        let (mut stream, addr) = self.stream().await?;
+
+        log::trace!("Sending subscription request to {}: {:?}", addr, req);
+        write_request(&mut *stream, req).await?;
+
+        // The server acknowledges the subscription with an empty response.
+        let ack: Result<(), String> = read_response(&mut *stream).await?;
+        ack?;
+
+        let (mut sender, receiver) = mpsc::channel(Self::SUBSCRIPTION_BUFFER_SIZE);
+        let (cancel, mut cancelled) = oneshot::channel();
+        tokio::spawn(async move {
+            loop {
+                let res = tokio::select! {
+                    res = read_response(&mut *stream) => res,
+                    // The subscription was dropped (while waiting for the server).
+                    _ = &mut cancelled => break,
+                };
+                let res = match res {
+                    Ok(Ok(res)) => Ok(res),
+                    Ok(Err(err)) => Err(Error::Server(err)),
+                    // The server closed the subscription.
+                    Err(Error::IO(err)) if err.kind() == io::ErrorKind::UnexpectedEof => break,
+                    Err(err) => Err(err),
+                };
+                log::trace!("Received pushed response from {}: {:?}", addr, res);
+                let failed = res.is_err();
+                if sender.send(res).await.is_err() || failed {
+                    // The subscription was dropped or the connection is broken.
+                    break;
+                }
+            }
+            // The stream is not put back into the connection pool.
+            drop(stream);
+        });
+
+        Ok(Subscription::new(receiver, cancel))
+    }
+
     /// Get a working TCP stream.
     ///
     /// A stream could be closed by the receiver while being
     /// in the pool. This is catched and a new stream will be
     /// returned in this case.
-    async fn stream(&self) -> Result<(connection_pool::StreamGuard<'_>, SocketAddr), Error> {
+    async fn stream(&self) -> Result<(connection_pool::StreamGuard<'static>, SocketAddr), Error> {
         let deadline = Instant::now() + Duration::from_secs(3);
         let delay = Duration::from_secs(1);
 
@@ -124,6 +182,16 @@ where
     S: AsyncRead + AsyncWrite + Unpin,
     Req: Request<T>,
     T: Serialize,
+{
+    write_request(stream, req).await?;
+    read_response(stream).await
+}
+
+async fn write_request<S, Req, T>(stream: &mut S, req: Req) -> Result<(), Error>
+where
+    S: AsyncWrite + Unpin,
+    Req: Request<T>,
+    T: Serialize,
 {
     let req: T = req.into();
     // serialize request
@@ -135,6 +203,14 @@ where
         .map_err(|_| Error::MessageTooLong)?;
     vec[..4].copy_from_slice(&size.to_le_bytes());
     stream.write_all(&vec).await?;
+    Ok(())
+}
+
+async fn read_response<S, R>(stream: &mut S) -> Result<Result<R, String>, Error>
+where
+    S: AsyncRead + Unpin,
+    R: DeserializeOwned,
+{
     // read response length
     let mut len_buf = [0; 4];
     stream.read_exact(&mut len_buf).await?;
@@ -0,0 +1,47 @@
+use crate::Error;
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+use tokio::{
+    stream::Stream,
+    sync::{mpsc, oneshot},
+};
+
+/// A stream of responses pushed by the server.
+///
+/// Created by [`Client::subscribe`](struct.Client.html#method.subscribe).
+/// The stream ends once the server closes the subscription.
+/// Dropping the `Subscription` closes the connection.
+pub struct Subscription<R> {
+    receiver: mpsc::Receiver<Result<R, Error>>,
+    /// Stops the task reading the pushed responses when dropped.
+    _cancel: oneshot::Sender<()>,
+}
+
+impl<R> Subscription<R> {
+    pub(super) const fn new(
+        receiver: mpsc::Receiver<Result<R, Error>>,
+        cancel: oneshot::Sender<()>,
+    ) -> Self {
+        Self {
+            receiver,
+            _cancel: cancel,
+        }
+    }
+
+    /// Receive the next response.
+    ///
+    /// Returns `None` once the subscription was closed.
+    pub async fn recv(&mut self) -> Option<Result<R, Error>> {
+        self.receiver.recv().await
+    }
+}
+
+impl<R> Stream for Subscription<R> {
+    type Item = Result<R, Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.receiver.poll_recv(cx)
+    }
+}
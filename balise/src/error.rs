@@ -14,6 +14,10 @@ pub enum Error {
     #[error(display = "The message is too long.")]
     MessageTooLong,
 
+    /// The subscription was closed by the other side.
+    #[error(display = "The subscription was closed.")]
+    SubscriptionClosed,
+
     /// An IO error.
     #[error(display = "{}", 0)]
     IO(#[error(from)] std::io::Error),
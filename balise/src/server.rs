@@ -1,7 +1,7 @@
 //! A server for communicating between RPUs.
 
 use crate::{Error, Request};
-use serde::de::DeserializeOwned;
+use serde::{de::DeserializeOwned, Serialize};
 use std::{
     convert::TryInto,
     fmt::Debug,
@@ -15,6 +15,7 @@ use tokio::{
     fs,
     io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
     net::TcpListener,
+    sync::{mpsc, oneshot},
 };
 
 type ServerResult = Result<Response, Error>;
@@ -22,7 +23,119 @@ type ServerResult = Result<Response, Error>;
 /// A transparent response to a `Request`.
 ///
 /// Use the `handle` method to create a matching response.
-pub struct Response(pub(crate) Vec<u8>);
+pub struct Response {
+    data: Vec<u8>,
+    subscription: Option<Pushed>,
+}
+
+/// The responses pushed for a `Subscription`.
+struct Pushed {
+    receiver: mpsc::Receiver<Vec<u8>>,
+    /// Notifies the `Publisher` once the subscription ended (when dropped).
+    _closed: oneshot::Sender<()>,
+}
+
+/// A value a request handler can respond with.
+///
+/// This is either the response of the `Request` itself
+/// or a `Subscription` of responses.
+pub trait IntoResponse<R> {
+    /// Encode the value into a `Response`.
+    fn into_response(self) -> ServerResult;
+}
+
+impl<R> IntoResponse<R> for R
+where
+    R: Serialize,
+{
+    fn into_response(self) -> ServerResult {
+        Ok(Response {
+            data: postcard::to_stdvec(&self)?,
+            subscription: None,
+        })
+    }
+}
+
+impl<R> IntoResponse<R> for Subscription<R> {
+    fn into_response(self) -> ServerResult {
+        // The subscription is acknowledged with an empty response.
+        Ok(Response {
+            data: postcard::to_stdvec(&())?,
+            subscription: Some(Pushed {
+                receiver: self.receiver,
+                _closed: self.closed,
+            }),
+        })
+    }
+}
+
+/// A stream of responses pushed to a client.
+///
+/// Return a `Subscription` from a request handler to keep the connection open
+/// and send every response published by the corresponding `Publisher`.
+/// The connection is closed once the `Publisher` is dropped.
+pub struct Subscription<R> {
+    receiver: mpsc::Receiver<Vec<u8>>,
+    closed: oneshot::Sender<()>,
+    response_data: PhantomData<fn() -> R>,
+}
+
+impl<R> Subscription<R> {
+    /// Create a new `Subscription` and the `Publisher` to send responses.
+    ///
+    /// Up to `buffer` responses are queued before `Publisher::publish` waits.
+    #[must_use]
+    pub fn channel(buffer: usize) -> (Publisher<R>, Self) {
+        let (sender, receiver) = mpsc::channel(buffer);
+        let (closed, closed_receiver) = oneshot::channel();
+        let publisher = Publisher {
+            sender,
+            closed: Some(closed_receiver),
+            response_data: PhantomData,
+        };
+        let subscription = Self {
+            receiver,
+            closed,
+            response_data: PhantomData,
+        };
+        (publisher, subscription)
+    }
+}
+
+/// The sending half of a `Subscription`.
+pub struct Publisher<R> {
+    sender: mpsc::Sender<Vec<u8>>,
+    /// Resolves once the subscription ended (`None` if it already did).
+    closed: Option<oneshot::Receiver<()>>,
+    response_data: PhantomData<fn(R)>,
+}
+
+impl<R> Publisher<R>
+where
+    R: Serialize,
+{
+    /// Send a `response` to the subscribed client.
+    ///
+    /// Fails if the connection to the client was closed.
+    pub async fn publish(&mut self, response: &R) -> Result<(), Error> {
+        let data = postcard::to_stdvec(response)?;
+        self.sender
+            .send(data)
+            .await
+            .map_err(|_| Error::SubscriptionClosed)
+    }
+
+    /// Wait until the subscription ended (e.g. because the client disconnected).
+    ///
+    /// Use this to stop publishing while there is nothing to publish.
+    pub async fn closed(&mut self) {
+        if let Some(closed) = &mut self.closed {
+            // The sender is never used, it is only dropped.
+            let _ = closed.await;
+            self.closed = None;
+        }
+    }
+}
 
 #[cfg(feature = "tls")]
 pub use native_tls::Identity as TlsIdentity;
@@ -154,21 +267,31 @@ where
             stream.read_exact(&mut buf).await?;
 
             // handle the request
-            let res = match self.handle_request(&addr, &buf).await {
-                Ok(res) => Ok(res),
-                Err(err) => Err(err.to_string()),
+            let (res, subscription) = match self.handle_request(&addr, &buf).await {
+                Ok(response) => (Ok(response.data), response.subscription),
+                Err(err) => (Err(err.to_string()), None),
             };
 
-            // serialize response
-            let vec = vec![0; 4];
-            let mut vec = postcard::serialize_with_flavor(&res, postcard::flavors::StdVec(vec))?;
+            write_response(&mut stream, &res).await?;
 
-            // send response
-            let size: u32 = (vec.len() - 4)
-                .try_into()
-                .map_err(|_| Error::MessageTooLong)?;
-            vec[..4].copy_from_slice(&size.to_le_bytes());
-            stream.write_all(&vec).await?;
+            if let Some(mut subscription) = subscription {
+                // Push all published responses until the subscription ends.
+                let (mut reader, mut writer) = tokio::io::split(stream);
+                let mut buf = [0; 1];
+                loop {
+                    tokio::select! {
+                        data = subscription.receiver.recv() => match data {
+                            Some(data) => write_response(&mut writer, &Ok::<_, String>(data)).await?,
+                            None => break,
+                        },
+                        // The client does not send anything while subscribed,
+                        // so this only returns once the client closed the connection.
+                        _ = reader.read(&mut buf) => break,
+                    }
+                }
+                log::trace!("Subscription of {} ended.", addr);
+                break;
+            }
 
             // Simulate connection drop
             // let _ = stream.shutdown(std::net::Shutdown::Both);
@@ -177,17 +300,39 @@ where
         Ok(())
     }
 
-    async fn handle_request(&self, addr: &SocketAddr, req: &[u8]) -> Result<Vec<u8>, Error> {
+    async fn handle_request(&self, addr: &SocketAddr, req: &[u8]) -> Result<Response, Error> {
         // Deserialize request.
         let req: T = postcard::from_bytes(req)?;
         log::trace!("Received request from {}: {:?}", addr, req);
         // handle the actual request
-        let res = (self.handler.clone())(req).await.map(|response| response.0);
-        log::trace!("Send response to {}: {:?}", addr, res);
-        Ok(res?)
+        let res = (self.handler.clone())(req).await;
+        log::trace!(
+            "Send response to {}: {:?}",
+            addr,
+            res.as_ref().map(|response| &response.data)
+        );
+        res
     }
 }
 
+/// Serialize a response and send it to the client.
+async fn write_response<S>(stream: &mut S, res: &Result<Vec<u8>, String>) -> Result<(), Error>
+where
+    S: AsyncWrite + Unpin,
+{
+    // serialize response
+    let vec = vec![0; 4];
+    let mut vec = postcard::serialize_with_flavor(res, postcard::flavors::StdVec(vec))?;
+
+    // send response
+    let size: u32 = (vec.len() - 4)
+        .try_into()
+        .map_err(|_| Error::MessageTooLong)?;
+    vec[..4].copy_from_slice(&size.to_le_bytes());
+    stream.write_all(&vec).await?;
+    Ok(())
+}
+
 /// Load the identity from a file path.
 ///
 /// `identity_path` is a file path to a `.pfx` file containing the server's identity.
@@ -207,13 +352,14 @@ pub async fn load_identity(
 }
 
 /// Call the request `handler` and encode the response.
-pub async fn handle_params<T, R, H, F>(params: R, handler: H) -> ServerResult
+///
+/// The `handler` can either return the response or a `Subscription` of responses.
+pub async fn handle_params<T, R, H, F, O>(params: R, handler: H) -> ServerResult
 where
     R: Request<T>,
     H: FnOnce(R) -> F,
-    F: Future<Output = Result<R::Response, crate::BoxError>>,
+    F: Future<Output = Result<O, crate::BoxError>>,
+    O: IntoResponse<R::Response>,
 {
-    let res = handler(params).await?;
-    let data = postcard::to_stdvec(&res)?;
-    Ok(Response(data))
+    handler(params).await?.into_response()
 }
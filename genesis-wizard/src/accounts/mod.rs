@@ -3,7 +3,7 @@ use crate::AccountMeta;
 use dialoguer::{theme::Theme, Input, MultiSelect, Select};
 use hexutil::{FromHex, ToHex};
 use pinxit::{Identity, PeerId};
-use prellblock_client_api::account::{Account, AccountType, Expiry};
+use prellblock_client_api::account::{Account, AccountType, Expiry, Permission};
 use std::{
     cmp::Reverse,
     time::{Duration, SystemTime},
@@ -288,8 +288,43 @@ fn handle_set_writing_rights<'a>(theme: &'a dyn Theme, account: &mut Account) {
         .items(&writing_rights_options)
         .default(1);
     match writing_rights_select.interact().unwrap() {
-        0 => account.writing_rights = true,
-        1 => account.writing_rights = false,
+        0 => {
+            account.writing_rights = true;
+            handle_set_writing_scopes(theme, account);
+        }
+        1 => {
+            account.writing_rights = false;
+            account.writing_scopes.clear();
+        }
         _ => panic!("Invalid selection"),
     }
 }
+
+fn handle_set_writing_scopes<'a>(theme: &'a dyn Theme, account: &mut Account) {
+    account.writing_scopes.clear();
+    let options = ["Allow all keys", "Add a key scope", "Done"];
+    let mut select = Select::with_theme(theme);
+    select
+        .with_prompt("Select the keys the account may write:")
+        .items(&options)
+        .default(0);
+    loop {
+        match select.interact().unwrap() {
+            0 => {
+                account.writing_scopes.clear();
+                return;
+            }
+            1 => {
+                let name = Input::<String>::new()
+                    .with_prompt("Enter name")
+                    .interact()
+                    .unwrap();
+                let matching = reading_rights::handle_select_matching(theme);
+                account.writing_scopes.push(Permission::new(name, matching));
+                select.default(2);
+            }
+            2 => return,
+            _ => panic!("Invalid selection"),
+        }
+    }
+}
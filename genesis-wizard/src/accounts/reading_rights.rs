@@ -2,7 +2,9 @@ use super::Identifier;
 use crate::AccountMeta;
 use dialoguer::{theme::Theme, Input, MultiSelect, Select};
 use hexutil::ToHex;
-use prellblock_client_api::account::{Account, Permission, ReadingPermission, ReadingRight};
+use prellblock_client_api::account::{
+    Account, Matching, Permission, ReadingPermission, ReadingRight,
+};
 
 pub(super) fn handle_set_reading_rights<'a>(
     theme: &'a dyn Theme,
@@ -162,7 +164,7 @@ fn handle_list_permission_item(reading_right: &mut ReadingRight) {
     reading_right
         .namespace
         .iter()
-        .for_each(|namespace| println!("  - {}", namespace.scope));
+        .for_each(|namespace| println!("  - {} ({:?})", namespace.scope, namespace.matching));
 }
 
 fn handle_select_permitted_accounts<'a>(
@@ -217,7 +219,10 @@ fn handle_select_permitted_namespaces<'a>(theme: &'a dyn Theme, reading_right: &
                     .with_prompt("Enter name")
                     .interact()
                     .unwrap();
-                reading_right.namespace.push(Permission { scope: name });
+                let matching = handle_select_matching(theme);
+                reading_right
+                    .namespace
+                    .push(Permission::new(name, matching));
             }
             1 => {
                 return;
@@ -230,3 +235,22 @@ fn handle_select_permitted_namespaces<'a>(theme: &'a dyn Theme, reading_right: &
         }
     }
 }
+
+pub(super) fn handle_select_matching<'a>(theme: &'a dyn Theme) -> Matching {
+    let matching_options = [
+        "Exact (only the key itself)",
+        "Prefix (all keys starting with the name)",
+        "Glob (`*` matches any characters, `?` a single one)",
+    ];
+    let mut matching_select = Select::with_theme(theme);
+    matching_select
+        .with_prompt("Select how keys are matched against the name:")
+        .items(&matching_options)
+        .default(0);
+    match matching_select.interact().unwrap() {
+        0 => Matching::Exact,
+        1 => Matching::Prefix,
+        2 => Matching::Glob,
+        _ => panic!("Invalid selection"),
+    }
+}
@@ -3,7 +3,7 @@
 
 use dialoguer::{
     theme::{ColorfulTheme, Theme},
-    Confirm, Password, Select,
+    Confirm, Input, Password, Select,
 };
 use hexutil::ToHex;
 use newtype_enum::Enum;
@@ -132,6 +132,10 @@ fn handle_finish(theme: &'_ dyn Theme, accounts: Vec<AccountMeta>, ca: Option<CA
         "account private and public keys for the accounts",
         "config",
     );
+    let admins = accounts
+        .iter()
+        .filter(|meta| meta.account.account_type == AccountType::Admin)
+        .count();
     let mut transactions = Vec::new();
     for AccountMeta {
         account,
@@ -186,13 +190,25 @@ fn handle_finish(theme: &'_ dyn Theme, accounts: Vec<AccountMeta>, ca: Option<CA
                 account_type: Some(account.account_type),
                 expire_at: Some(account.expire_at),
                 has_writing_rights: Some(account.writing_rights),
+                writing_scopes: Some(account.writing_scopes),
+                delegates: Some(account.delegates),
                 reading_rights: Some(account.reading_rights),
+                limits: Some(account.limits),
             },
             timestamp: SystemTime::now(),
         });
         transactions.push(br_account_transaction.sign(&signing_identity).unwrap());
     }
 
+    let admin_threshold = handle_set_admin_threshold(theme, admins);
+    if admin_threshold > 1 {
+        let threshold_transaction = Transaction::from_variant(transaction::SetAdminThreshold {
+            threshold: admin_threshold,
+            timestamp: SystemTime::now(),
+        });
+        transactions.push(threshold_transaction.sign(&signing_identity).unwrap());
+    }
+
     // Write certificates
     if let Some(ca) = ca {
         if ca.created {
@@ -230,3 +246,24 @@ fn handle_finish(theme: &'_ dyn Theme, accounts: Vec<AccountMeta>, ca: Option<CA
     )
     .unwrap();
 }
+
+/// Ask for the number of admins needed to manage accounts (k of `admins`).
+fn handle_set_admin_threshold(theme: &'_ dyn Theme, admins: usize) -> usize {
+    if admins <= 1 {
+        return 1;
+    }
+    loop {
+        let threshold = Input::<usize>::with_theme(theme)
+            .with_prompt(format!(
+                "How many of the {} admins are needed to manage accounts?",
+                admins
+            ))
+            .default(1)
+            .interact()
+            .unwrap();
+        if threshold >= 1 && threshold <= admins {
+            break threshold;
+        }
+        println!("The threshold must be between 1 and {}.", admins);
+    }
+}
@@ -13,6 +13,10 @@ pub enum Error {
     #[error(display = "Invalid signature: {}", 0)]
     SignatureError(#[error(from)] ed25519_dalek::SignatureError),
 
+    /// A `PeerId` is not a valid point on the curve.
+    #[error(display = "Invalid public key.")]
+    InvalidPublicKey,
+
     /// A `Signable` failed to create a message.
     #[error(display = "Unable to create signable message: {}", 0)]
     SignableError(BoxError),
@@ -1,4 +1,5 @@
 use crate::{Error, PeerId, Signable, Signature};
+use curve25519_dalek::{edwards::CompressedEdwardsY, scalar::Scalar};
 use ed25519_dalek::{ExpandedSecretKey, SecretKey};
 use std::{fmt, str};
 
@@ -65,4 +66,32 @@ impl Identity {
         let signature = expanded.sign(data.as_ref(), &self.id.0);
         Ok(Signature(signature))
     }
+
+    /// Agree on a shared secret with the owner of `peer_id` (Diffie-Hellman on the ed25519 keys).
+    ///
+    /// Both sides get the same secret. Hash it before using it as a key.
+    ///
+    /// # Example
+    /// ```
+    /// use pinxit::Identity;
+    ///
+    /// let alice = Identity::generate();
+    /// let bob = Identity::generate();
+    /// assert_eq!(
+    ///     alice.key_agreement(bob.id()).unwrap(),
+    ///     bob.key_agreement(alice.id()).unwrap(),
+    /// );
+    /// ```
+    pub fn key_agreement(&self, peer_id: &PeerId) -> Result<[u8; 32], Error> {
+        // The first half of the expanded secret key is the (clamped) secret scalar.
+        let expanded = ExpandedSecretKey::from(&self.secret).to_bytes();
+        let mut scalar = [0; 32];
+        scalar.copy_from_slice(&expanded[..32]);
+        let scalar = Scalar::from_bits(scalar);
+
+        let point = CompressedEdwardsY::from_slice(peer_id.as_bytes())
+            .decompress()
+            .ok_or(Error::InvalidPublicKey)?;
+        Ok((scalar * point).compress().to_bytes())
+    }
 }
@@ -1,9 +1,9 @@
 //! This module contains basic structures for `Account`s.
 
 use chrono::prelude::*;
-use pinxit::PeerId;
+use pinxit::{PeerId, Signable};
 use serde::{Deserialize, Serialize};
-use std::net::SocketAddr;
+use std::{net::SocketAddr, time::SystemTime};
 
 /// `Account` stores data needed for permission checking.
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -24,9 +24,23 @@ pub struct Account {
     #[serde(default)]
     pub writing_rights: bool,
 
+    /// The keys the `Account` is allowed to write. (Default `Vec::new()`).
+    /// When empty, the account is allowed to write all keys (if it has writing rights).
+    #[serde(default)]
+    pub writing_scopes: Vec<Permission>,
+
+    /// The accounts allowed to write into this `Account`'s namespace. (Default `Vec::new()`).
+    /// Delegates are restricted by the writing rights and scopes of this account.
+    #[serde(default)]
+    pub delegates: Vec<PeerId>,
+
     /// The `Account`'s reading rights. (Default `Vec::new()`).
     #[serde(default)]
     pub reading_rights: Vec<ReadingPermission>,
+
+    /// The `Account`'s rate limits and storage quotas. (Default `Limits::default()`).
+    #[serde(default)]
+    pub limits: Limits,
 }
 
 impl Account {
@@ -38,7 +52,10 @@ impl Account {
             account_type: AccountType::default(),
             expire_at: Expiry::default(),
             writing_rights: false,
+            writing_scopes: Vec::new(),
+            delegates: Vec::new(),
             reading_rights: Vec::new(),
+            limits: Limits::default(),
         }
     }
 
@@ -53,9 +70,18 @@ impl Account {
         if let Some(writing_rights) = permissions.has_writing_rights {
             self.writing_rights = writing_rights;
         }
+        if let Some(writing_scopes) = permissions.writing_scopes {
+            self.writing_scopes = writing_scopes;
+        }
+        if let Some(delegates) = permissions.delegates {
+            self.delegates = delegates;
+        }
         if let Some(reading_rights) = permissions.reading_rights {
             self.reading_rights = reading_rights;
         }
+        if let Some(limits) = permissions.limits {
+            self.limits = limits;
+        }
     }
 }
 
@@ -70,8 +96,14 @@ pub struct Permissions {
     pub expire_at: Option<Expiry>,
     /// Whether the account shall have permissions to write into its namespace.
     pub has_writing_rights: Option<bool>,
+    /// The keys the account shall be allowed to write (all keys, if empty).
+    pub writing_scopes: Option<Vec<Permission>>,
+    /// The accounts that shall be allowed to write into the account's namespace.
+    pub delegates: Option<Vec<PeerId>>,
     /// Permissions for reading the namespaces of other accounts.
     pub reading_rights: Option<Vec<ReadingPermission>>,
+    /// Rate limits and storage quotas of the account.
+    pub limits: Option<Limits>,
 }
 
 /// The type of an account.
@@ -95,12 +127,55 @@ pub enum AccountType {
     Admin,
 }
 
+impl AccountType {
+    /// Get the kind of the account type (without the addresses of RPUs).
+    #[must_use]
+    pub const fn kind(&self) -> AccountKind {
+        match self {
+            Self::Normal => AccountKind::Normal,
+            Self::BlockReader => AccountKind::BlockReader,
+            Self::RPU { .. } => AccountKind::RPU,
+            Self::Admin => AccountKind::Admin,
+        }
+    }
+}
+
 impl Default for AccountType {
     fn default() -> Self {
         Self::Normal
     }
 }
 
+/// The kind of an `AccountType` (used to filter accounts).
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Eq, PartialEq)]
+#[allow(clippy::module_name_repetitions)]
+#[serde(rename_all = "snake_case")]
+pub enum AccountKind {
+    /// A normal account.
+    Normal,
+    /// A block reader account.
+    BlockReader,
+    /// An RPU account.
+    #[serde(rename = "rpu")]
+    RPU,
+    /// An admin account.
+    Admin,
+}
+
+/// Limits for the transactions of an account (no limit is set by default).
+///
+/// The limits are enforced deterministically using the timestamps of the transactions.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Limits {
+    /// The maximum number of transactions per second.
+    pub transactions_per_second: Option<u32>,
+    /// The maximum size of a single value in bytes.
+    pub bytes_per_value: Option<u64>,
+    /// The maximum number of bytes written per day (UTC).
+    pub bytes_per_day: Option<u64>,
+}
+
 /// An accounts permission can either be `never` expiring or expiring at a certain date (`AtDate`).
 ///
 /// # Example
@@ -116,6 +191,9 @@ impl Default for AccountType {
 ///
 /// let already_expired = Expiry::AtDate(Utc::now() - Duration::days(1));
 /// assert_eq!(already_expired.is_expired(), true);
+///
+/// let two_days_ago = (Utc::now() - Duration::days(2)).into();
+/// assert_eq!(already_expired.is_expired_at(two_days_ago), false);
 /// ```
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
@@ -130,9 +208,15 @@ impl Expiry {
     /// Check whether the expiry date has passed (if set).
     #[must_use]
     pub fn is_expired(&self) -> bool {
+        self.is_expired_at(SystemTime::now())
+    }
+
+    /// Check whether the expiry date has passed at the given `time` (if set).
+    #[must_use]
+    pub fn is_expired_at(&self, time: SystemTime) -> bool {
         match self {
             Self::Never => false,
-            Self::AtDate(expiry) => Utc::now() > *expiry,
+            Self::AtDate(expiry) => DateTime::<Utc>::from(time) > *expiry,
         }
     }
 }
@@ -165,14 +249,230 @@ pub struct ReadingRight {
 }
 
 /// A filter that can select a given scope.
+///
+/// # Example
+/// ```
+/// use prellblock_client_api::account::{Matching, Permission};
+///
+/// let exact = Permission::new("speed", Matching::Exact);
+/// assert!(exact.matches("speed"));
+/// assert!(!exact.matches("speed_limit"));
+///
+/// let prefix = Permission::new("brake_pressure_", Matching::Prefix);
+/// assert!(prefix.matches("brake_pressure_front"));
+/// assert!(!prefix.matches("brake"));
+///
+/// let glob = Permission::new("wagon_?_*_temperature", Matching::Glob);
+/// assert!(glob.matches("wagon_3_axle_2_temperature"));
+/// assert!(!glob.matches("wagon_12_axle_2_temperature"));
+/// ```
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Permission {
     /// The scope of this filter.
     pub scope: String,
+
+    /// How keys are matched against the `scope`. (Default `Matching::Exact`).
+    #[serde(default, rename = "match")]
+    pub matching: Matching,
 }
 
-// pub enum Permission {
-//     Exact(String),
-//     Prefix(String),
-//     RegEx(String), // ???
-// }
+impl Permission {
+    /// Create a new `Permission` for the given `scope`.
+    #[must_use]
+    pub fn new(scope: impl Into<String>, matching: Matching) -> Self {
+        Self {
+            scope: scope.into(),
+            matching,
+        }
+    }
+
+    /// Check whether the `key` is selected by this filter.
+    #[must_use]
+    pub fn matches(&self, key: &str) -> bool {
+        match self.matching {
+            Matching::Exact => self.scope == key,
+            Matching::Prefix => key.starts_with(&self.scope),
+            Matching::Glob => glob_matches(&self.scope, key),
+        }
+    }
+
+    /// Check whether all keys selected by this filter are also selected by `other`.
+    ///
+    /// Globs are approximated by their literal prefix, so this may be `false`
+    /// although `other` selects all keys.
+    ///
+    /// ```
+    /// use prellblock_client_api::account::{Matching, Permission};
+    ///
+    /// let sensors = Permission::new("sensor_", Matching::Prefix);
+    /// assert!(Permission::new("sensor_speed", Matching::Exact).is_covered_by(&sensors));
+    /// assert!(Permission::new("sensor_wagon_", Matching::Prefix).is_covered_by(&sensors));
+    /// assert!(Permission::new("sensor_?_speed", Matching::Glob).is_covered_by(&sensors));
+    /// assert!(!Permission::new("sens", Matching::Prefix).is_covered_by(&sensors));
+    /// assert!(!sensors.is_covered_by(&Permission::new("sensor_speed", Matching::Exact)));
+    /// ```
+    #[must_use]
+    pub fn is_covered_by(&self, other: &Self) -> bool {
+        if self == other {
+            return true;
+        }
+        match self.matching {
+            Matching::Exact => other.matches(&self.scope),
+            Matching::Prefix => other.covers_prefix(&self.scope),
+            Matching::Glob => match self.scope.find(|c| c == '*' || c == '?') {
+                Some(index) => other.covers_prefix(&self.scope[..index]),
+                None => other.matches(&self.scope),
+            },
+        }
+    }
+
+    /// Check whether all keys starting with `prefix` are selected by this filter.
+    fn covers_prefix(&self, prefix: &str) -> bool {
+        match self.matching {
+            Matching::Exact => false,
+            Matching::Prefix => prefix.starts_with(&self.scope),
+            // The trailing `*` matches everything after the `prefix`.
+            Matching::Glob => self.scope.ends_with('*') && glob_matches(&self.scope, prefix),
+        }
+    }
+}
+
+/// The way a `Permission`'s scope selects keys.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Matching {
+    /// Select the key equal to the scope.
+    Exact,
+    /// Select all keys starting with the scope.
+    Prefix,
+    /// Select all keys matching the scope as a glob pattern.
+    ///
+    /// `*` matches any (possibly empty) sequence of characters, `?` matches a single character.
+    Glob,
+}
+
+impl Default for Matching {
+    fn default() -> Self {
+        Self::Exact
+    }
+}
+
+/// Match a `key` against a glob `pattern` (supporting `*` and `?`).
+fn glob_matches(pattern: &str, key: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let key: Vec<char> = key.chars().collect();
+
+    let mut pattern_index = 0;
+    let mut key_index = 0;
+    // The position after the last `*` and the key position it currently matches up to.
+    let mut backtrack = None;
+    while key_index < key.len() {
+        match pattern.get(pattern_index) {
+            Some('*') => {
+                pattern_index += 1;
+                backtrack = Some((pattern_index, key_index));
+            }
+            Some(&c) if c == '?' || c == key[key_index] => {
+                pattern_index += 1;
+                key_index += 1;
+            }
+            _ => match backtrack {
+                // Let the last `*` match one more character.
+                Some((star_pattern_index, star_key_index)) => {
+                    pattern_index = star_pattern_index;
+                    key_index = star_key_index + 1;
+                    backtrack = Some((star_pattern_index, key_index));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[pattern_index..].iter().all(|&c| c == '*')
+}
+
+/// Prefix of the signed data (to distinguish it from other signed messages).
+const KEY_ROTATION_SIGNING_CONTEXT: &str = "prellblock key rotation";
+
+/// The data the new key signs to take over an account in a `RotateKey` transaction.
+///
+/// This proves that the new key is owned by the account (and not by someone else).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyRotation {
+    /// The current ID of the account.
+    pub id: PeerId,
+    /// The new ID of the account.
+    pub new_id: PeerId,
+}
+
+impl Signable for KeyRotation {
+    type SignableData = Vec<u8>;
+    type Error = postcard::Error;
+    fn signable_data(&self) -> Result<Self::SignableData, Self::Error> {
+        postcard::to_stdvec(&(KEY_ROTATION_SIGNING_CONTEXT, self))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glob_without_wildcards() {
+        assert!(glob_matches("speed", "speed"));
+        assert!(!glob_matches("speed", "speed_limit"));
+        assert!(!glob_matches("speed", "spee"));
+        assert!(glob_matches("", ""));
+        assert!(!glob_matches("", "speed"));
+    }
+
+    #[test]
+    fn glob_question_mark() {
+        assert!(glob_matches("wagon_?", "wagon_3"));
+        assert!(!glob_matches("wagon_?", "wagon_"));
+        assert!(!glob_matches("wagon_?", "wagon_12"));
+        assert!(glob_matches("??", "äö"));
+    }
+
+    #[test]
+    fn glob_star() {
+        assert!(glob_matches("*", ""));
+        assert!(glob_matches("*", "speed"));
+        assert!(glob_matches("brake_*", "brake_"));
+        assert!(glob_matches("brake_*", "brake_pressure"));
+        assert!(!glob_matches("brake_*", "brake"));
+        assert!(glob_matches("*_temperature", "axle_temperature"));
+        assert!(!glob_matches("*_temperature", "axle_temperature_max"));
+        assert!(glob_matches("a**b", "ab"));
+    }
+
+    #[test]
+    fn glob_backtracking() {
+        // The `*` has to match more than its first candidate.
+        assert!(glob_matches("*_temperature", "axle_2_temperature"));
+        assert!(glob_matches("a*b*c", "aXbYbZc"));
+        assert!(!glob_matches("a*b*c", "aXbYbZ"));
+        assert!(glob_matches("*a?", "aaab"));
+        assert!(glob_matches(
+            "wagon_?_*_temperature",
+            "wagon_3_axle_2_temperature"
+        ));
+        assert!(!glob_matches(
+            "wagon_?_*_temperature",
+            "wagon_12_axle_2_temperature"
+        ));
+    }
+
+    #[test]
+    fn permission_matching() {
+        let glob = Permission::new("sensor_*", Matching::Glob);
+        let prefix = Permission::new("sensor_*", Matching::Prefix);
+        let exact = Permission::new("sensor_*", Matching::Exact);
+
+        assert!(glob.matches("sensor_speed"));
+        assert!(!prefix.matches("sensor_speed"));
+        assert!(prefix.matches("sensor_*_speed"));
+        assert!(exact.matches("sensor_*"));
+        assert!(!exact.matches("sensor_speed"));
+    }
+}
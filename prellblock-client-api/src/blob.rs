@@ -0,0 +1,112 @@
+//! Large values (blobs) that are stored off-chain.
+//!
+//! Only the `BlobHash` and the size of a blob are committed in a `Blob` transaction.
+//! The payload is uploaded to an RPU in chunks via `PutBlob`, replicated between the RPUs
+//! and read in chunks via `GetBlob` (all chunks are at most `BLOB_CHUNK_SIZE` bytes).
+//!
+//! # Example
+//! ```
+//! use prellblock_client_api::blob::BLOB_CHUNK_SIZE;
+//!
+//! let payload = vec![0xff; 3 * BLOB_CHUNK_SIZE as usize + 1];
+//! assert_eq!(payload.chunks(BLOB_CHUNK_SIZE as usize).count(), 4);
+//! ```
+
+use blake2::{
+    digest::{generic_array::typenum::Unsigned, FixedOutput},
+    Blake2b, Digest,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt,
+    hash::{Hash, Hasher},
+};
+
+/// The maximum size of a blob.
+pub const MAX_BLOB_SIZE: u64 = 64 * 1024 * 1024;
+
+/// The maximum size of a chunk of a `PutBlob` request or a `GetBlob` response.
+pub const BLOB_CHUNK_SIZE: u64 = 1024 * 1024;
+
+const HASH_SIZE: usize = <Blake2b as FixedOutput>::OutputSize::USIZE;
+
+/// The hash of the payload of a blob.
+///
+/// # Example
+/// ```
+/// use prellblock_client_api::blob::BlobHash;
+///
+/// let snapshot = vec![0xff; 5000];
+/// assert_eq!(BlobHash::of(&snapshot), BlobHash::of(&snapshot.clone()));
+/// assert_ne!(BlobHash::of(&snapshot), BlobHash::of(&snapshot[1..]));
+/// ```
+#[derive(Copy, Clone)]
+#[allow(clippy::module_name_repetitions)]
+pub struct BlobHash([u8; HASH_SIZE]);
+
+impl BlobHash {
+    /// Calculate the hash of the payload `data`.
+    #[must_use]
+    pub fn of(data: &[u8]) -> Self {
+        let result = Blake2b::digest(data);
+
+        let mut blob_hash = Self([0; HASH_SIZE]);
+        blob_hash.0.copy_from_slice(&result);
+        blob_hash
+    }
+
+    /// Get a reference to a binary representation.
+    #[must_use]
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl fmt::Debug for BlobHash {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl PartialEq for BlobHash {
+    fn eq(&self, other: &Self) -> bool {
+        self.0[..] == other.0[..]
+    }
+}
+
+impl Eq for BlobHash {}
+
+impl Hash for BlobHash {
+    fn hash<H>(&self, h: &mut H)
+    where
+        H: Hasher,
+    {
+        self.0[..].hash(h)
+    }
+}
+
+hexutil::impl_hex!(BlobHash, HASH_SIZE, |&self| &self.0, |data| {
+    Ok(Self(data))
+});
+
+/// The reference to a blob, written as value of its key by a `Blob` transaction.
+///
+/// The value is postcard-encoded (like values written by `KeyValue` transactions).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[allow(clippy::module_name_repetitions)]
+pub struct BlobRef {
+    /// The hash of the payload.
+    pub hash: BlobHash,
+    /// The size of the payload in bytes.
+    pub size: u64,
+}
+
+/// A chunk of a blob in response to a `GetBlob` request (or the `FetchBlob` of an RPU).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[allow(clippy::module_name_repetitions)]
+pub struct BlobChunk {
+    /// The data of the chunk (at most `BLOB_CHUNK_SIZE` bytes).
+    pub data: Vec<u8>,
+    /// The size of the whole blob.
+    pub size: u64,
+}
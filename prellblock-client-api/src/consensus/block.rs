@@ -1,4 +1,6 @@
-use super::{BlockNumber, LeaderTerm, SignatureList};
+use super::{
+    BlockNumber, LeaderTerm, MerkleHash, MerkleTree, SignatureList, StateRoot, TransactionHash,
+};
 use crate::Transaction;
 use blake2::{
     digest::{generic_array::typenum::Unsigned, FixedOutput},
@@ -6,7 +8,7 @@ use blake2::{
 };
 use pinxit::Signed;
 use serde::{Deserialize, Serialize};
-use std::{fmt, time::SystemTime};
+use std::{fmt, sync::Mutex, time::SystemTime};
 
 /// A `Block` stores transactions verified by the blockchain.
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -27,14 +29,159 @@ impl Block {
     /// Return the `Block`s block number.
     #[must_use]
     pub const fn block_number(&self) -> BlockNumber {
-        self.body.height
+        self.body.height()
+    }
+
+    /// Return the `BlockHeader` of the `Block`.
+    #[must_use]
+    pub fn header(&self) -> BlockHeader {
+        self.body.header()
     }
 }
 
 /// The `Body` of a `Block` stores the Block number (height in chain), the Hash of the previous `Block`
 /// and an Array of the actual `Transaction`s with their corresponding Signature in the `Block`.
+///
+/// The fields cannot be changed after creating the `Body` (its `BlockHeader` is cached).
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Body {
+    leader_term: LeaderTerm,
+    height: BlockNumber,
+    prev_block_hash: BlockHash,
+    timestamp: SystemTime,
+    state_root: StateRoot,
+    transactions: Vec<Signed<Transaction>>,
+
+    /// The `BlockHeader` (calculated once it is needed).
+    #[serde(skip)]
+    header: HeaderCache,
+}
+
+impl Body {
+    /// Create a new `Body`.
+    #[must_use]
+    pub fn new(
+        leader_term: LeaderTerm,
+        height: BlockNumber,
+        prev_block_hash: BlockHash,
+        timestamp: SystemTime,
+        state_root: StateRoot,
+        transactions: Vec<Signed<Transaction>>,
+    ) -> Self {
+        Self {
+            leader_term,
+            height,
+            prev_block_hash,
+            timestamp,
+            state_root,
+            transactions,
+            header: HeaderCache::default(),
+        }
+    }
+
+    /// The `LeaderTerm` of the `Block`.
+    #[must_use]
+    pub const fn leader_term(&self) -> LeaderTerm {
+        self.leader_term
+    }
+
+    /// The `BlockNumber` of the `Block`.
+    #[must_use]
+    pub const fn height(&self) -> BlockNumber {
+        self.height
+    }
+
+    /// The `BlockHash` of the previous `Block`.
+    #[must_use]
+    pub const fn prev_block_hash(&self) -> BlockHash {
+        self.prev_block_hash
+    }
+
+    /// The time, the leader proposed this block.
+    #[must_use]
+    pub const fn timestamp(&self) -> SystemTime {
+        self.timestamp
+    }
+
+    /// The `StateRoot` of the `WorldState` after applying the `Block`.
+    ///
+    /// The genesis block has the default state root (its state is given by the genesis transactions).
+    #[must_use]
+    pub const fn state_root(&self) -> StateRoot {
+        self.state_root
+    }
+
+    /// The actual data (`Signed<Transactions>`).
+    #[must_use]
+    pub fn transactions(&self) -> &[Signed<Transaction>] {
+        &self.transactions
+    }
+
+    /// Take the transactions out of the `Body`.
+    #[must_use]
+    pub fn into_transactions(self) -> Vec<Signed<Transaction>> {
+        self.transactions
+    }
+
+    /// Calculate the hash of the blocks body.
+    ///
+    /// This is the hash of the `BlockHeader`, the transactions
+    /// are included via their Merkle root.
+    #[must_use]
+    pub fn hash(&self) -> BlockHash {
+        self.header().hash()
+    }
+
+    /// Build the Merkle tree over the transactions.
+    #[must_use]
+    pub fn merkle_tree(&self) -> MerkleTree {
+        let transaction_hashes: Vec<_> =
+            self.transactions.iter().map(TransactionHash::of).collect();
+        MerkleTree::new(&transaction_hashes)
+    }
+
+    /// Return the `BlockHeader` of the body.
+    ///
+    /// The header is only calculated once (building the Merkle tree is expensive).
+    #[must_use]
+    pub fn header(&self) -> BlockHeader {
+        let mut header = self.header.0.lock().unwrap();
+        header
+            .get_or_insert_with(|| BlockHeader {
+                leader_term: self.leader_term,
+                height: self.height,
+                prev_block_hash: self.prev_block_hash,
+                timestamp: self.timestamp,
+                state_root: self.state_root,
+                transactions_root: self.merkle_tree().root(),
+            })
+            .clone()
+    }
+}
+
+/// The lazily calculated `BlockHeader` of a `Body`.
+#[derive(Default)]
+struct HeaderCache(Mutex<Option<BlockHeader>>);
+
+impl Clone for HeaderCache {
+    fn clone(&self) -> Self {
+        Self(Mutex::new(self.0.lock().unwrap().clone()))
+    }
+}
+
+impl fmt::Debug for HeaderCache {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&*self.0.lock().unwrap(), f)
+    }
+}
+
+/// The `BlockHeader` contains everything of a `Body` except the transactions,
+/// which are represented by their Merkle root.
+///
+/// The `BlockHash` is calculated over the header only.
+/// This allows proving a single transaction without the whole `Block`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BlockHeader {
     /// The `LeaderTerm` of the `Block`.
     pub leader_term: LeaderTerm,
 
@@ -47,25 +194,28 @@ pub struct Body {
     /// The time, the leader proposed this block.
     pub timestamp: SystemTime,
 
-    /// The actual data (`Signed<Transactions>`).
-    pub transactions: Vec<Signed<Transaction>>,
+    /// The `StateRoot` of the `WorldState` after applying the `Block`.
+    pub state_root: StateRoot,
+
+    /// The root of the `MerkleTree` over the transactions of the `Block`.
+    pub transactions_root: MerkleHash,
 }
 
-impl Body {
-    /// Calculate the hash of the blocks body.
+impl BlockHeader {
+    /// Calculate the hash of the header.
     #[must_use]
     pub fn hash(&self) -> BlockHash {
         let val = postcard::to_stdvec(self).unwrap();
 
         let result = Blake2b::digest(&val);
 
-        let mut body_hash = BlockHash([0; HASH_SIZE]);
-        body_hash.0.copy_from_slice(&result);
-        body_hash
+        let mut block_hash = BlockHash([0; HASH_SIZE]);
+        block_hash.0.copy_from_slice(&result);
+        block_hash
     }
 }
 
-const HASH_SIZE: usize = <Blake2b as FixedOutput>::OutputSize::USIZE;
+pub(super) const HASH_SIZE: usize = <Blake2b as FixedOutput>::OutputSize::USIZE;
 
 /// The datatype of hashes of blocks is `BlockHash`.
 #[derive(Copy, Clone)]
@@ -0,0 +1,28 @@
+use super::{BlockHash, BlockNumber, LeaderTerm};
+use pinxit::Signable;
+use serde::{Deserialize, Serialize};
+
+/// Prefix of the signed data (to distinguish it from other signed messages).
+const SIGNING_CONTEXT: &str = "prellblock block approval";
+
+/// The data an RPU signs to accept a `Block`.
+///
+/// The `SignatureList` of a `Block` contains the signatures of a supermajority of RPUs
+/// over this data. This allows clients to verify `Block`s on their own.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlockApproval {
+    /// The `LeaderTerm` of the `Block`.
+    pub leader_term: LeaderTerm,
+    /// The `BlockNumber` of the `Block`.
+    pub block_number: BlockNumber,
+    /// The `BlockHash` of the `Block`.
+    pub block_hash: BlockHash,
+}
+
+impl Signable for BlockApproval {
+    type SignableData = Vec<u8>;
+    type Error = postcard::Error;
+    fn signable_data(&self) -> Result<Self::SignableData, Self::Error> {
+        postcard::to_stdvec(&(SIGNING_CONTEXT, self))
+    }
+}
@@ -0,0 +1,297 @@
+use super::{block::HASH_SIZE, TransactionHash};
+use blake2::{Blake2b, Digest};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Prefix of leaf hashes (to distinguish them from inner nodes).
+const LEAF_PREFIX: u8 = 0;
+/// Prefix of inner node hashes.
+const NODE_PREFIX: u8 = 1;
+
+/// The hash of a node in a `MerkleTree`.
+#[derive(Copy, Clone)]
+#[allow(clippy::module_name_repetitions)]
+pub struct MerkleHash([u8; HASH_SIZE]);
+
+impl MerkleHash {
+    fn digest(prefix: u8, data: &[&[u8]]) -> Self {
+        let mut hasher = Blake2b::new();
+        hasher.input(&[prefix]);
+        for data in data {
+            hasher.input(data);
+        }
+
+        let mut merkle_hash = Self([0; HASH_SIZE]);
+        merkle_hash.0.copy_from_slice(&hasher.result());
+        merkle_hash
+    }
+
+    fn leaf(transaction_hash: &TransactionHash) -> Self {
+        Self::digest(LEAF_PREFIX, &[transaction_hash.as_bytes()])
+    }
+
+    fn node(left: &Self, right: &Self) -> Self {
+        Self::digest(NODE_PREFIX, &[&left.0, &right.0])
+    }
+}
+
+impl Default for MerkleHash {
+    fn default() -> Self {
+        Self([0; HASH_SIZE])
+    }
+}
+
+impl fmt::Debug for MerkleHash {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl PartialEq for MerkleHash {
+    fn eq(&self, other: &Self) -> bool {
+        self.0[..] == other.0[..]
+    }
+}
+
+impl Eq for MerkleHash {}
+
+hexutil::impl_hex!(MerkleHash, HASH_SIZE, |&self| &self.0, |data| {
+    Ok(Self(data))
+});
+
+/// A Merkle tree over the `TransactionHash`es of a `Block`.
+///
+/// A node without a sibling is moved up to the next level unchanged.
+/// The root of an empty tree is `MerkleHash::default()`.
+///
+/// # Example
+/// ```
+/// use pinxit::{Identity, Signable};
+/// use prellblock_client_api::{
+///     consensus::{MerkleTree, TransactionHash},
+///     transaction, Transaction,
+/// };
+/// use std::time::SystemTime;
+///
+/// let identity = Identity::generate();
+/// let transaction_hashes: Vec<_> = (0..5)
+///     .map(|i| {
+///         let transaction = Transaction::KeyValue(transaction::KeyValue {
+///             key: "speed".to_string(),
+///             value: vec![i],
+///             timestamp: SystemTime::now(),
+///         });
+///         TransactionHash::of(&transaction.sign(&identity).unwrap())
+///     })
+///     .collect();
+///
+/// let tree = MerkleTree::new(&transaction_hashes);
+/// let proof = tree.proof(3).unwrap();
+/// assert_eq!(proof.root(&transaction_hashes[3]), Some(tree.root()));
+/// assert_ne!(proof.root(&transaction_hashes[2]), Some(tree.root()));
+/// ```
+#[derive(Debug, Clone)]
+#[allow(clippy::module_name_repetitions)]
+pub struct MerkleTree {
+    /// All levels of the tree, starting with the leaves.
+    levels: Vec<Vec<MerkleHash>>,
+}
+
+impl MerkleTree {
+    /// Build a new tree over the given `transaction_hashes`.
+    #[must_use]
+    pub fn new(transaction_hashes: &[TransactionHash]) -> Self {
+        let mut level: Vec<_> = transaction_hashes.iter().map(MerkleHash::leaf).collect();
+        let mut levels = Vec::new();
+        while level.len() > 1 {
+            let next_level = level
+                .chunks(2)
+                .map(|pair| match pair {
+                    [left, right] => MerkleHash::node(left, right),
+                    [single] => *single,
+                    _ => unreachable!(),
+                })
+                .collect();
+            levels.push(level);
+            level = next_level;
+        }
+        levels.push(level);
+        Self { levels }
+    }
+
+    /// Get the root hash of the tree.
+    #[must_use]
+    pub fn root(&self) -> MerkleHash {
+        self.levels
+            .last()
+            .and_then(|level| level.first())
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Get the inclusion proof of the leaf at `index`.
+    ///
+    /// Returns `None` if there is no such leaf.
+    #[must_use]
+    pub fn proof(&self, index: usize) -> Option<MerkleProof> {
+        let leaf_count = self.levels[0].len();
+        if index >= leaf_count {
+            return None;
+        }
+
+        let mut siblings = Vec::new();
+        let mut node_index = index;
+        for level in &self.levels {
+            if let Some(sibling) = level.get(node_index ^ 1) {
+                siblings.push(*sibling);
+            }
+            node_index /= 2;
+        }
+
+        Some(MerkleProof {
+            index,
+            leaf_count,
+            siblings,
+        })
+    }
+}
+
+/// A proof that a transaction is included in a `MerkleTree`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[allow(clippy::module_name_repetitions)]
+pub struct MerkleProof {
+    /// The position of the transaction in the `Block`.
+    pub index: usize,
+    /// The number of transactions in the `Block`.
+    pub leaf_count: usize,
+    /// The hashes of the sibling nodes, starting at the leaf.
+    pub siblings: Vec<MerkleHash>,
+}
+
+impl MerkleProof {
+    /// Calculate the root hash of the tree containing the given `transaction_hash`.
+    ///
+    /// Compare the result with the `transactions_root` of a `BlockHeader`.
+    /// Returns `None` if the proof is malformed.
+    #[must_use]
+    pub fn root(&self, transaction_hash: &TransactionHash) -> Option<MerkleHash> {
+        if self.index >= self.leaf_count {
+            return None;
+        }
+
+        let mut hash = MerkleHash::leaf(transaction_hash);
+        let mut siblings = self.siblings.iter();
+        let mut node_index = self.index;
+        let mut level_len = self.leaf_count;
+        while level_len > 1 {
+            if node_index ^ 1 < level_len {
+                let sibling = siblings.next()?;
+                hash = if node_index % 2 == 0 {
+                    MerkleHash::node(&hash, sibling)
+                } else {
+                    MerkleHash::node(sibling, &hash)
+                };
+            }
+            node_index /= 2;
+            level_len = (level_len + 1) / 2;
+        }
+
+        if siblings.next().is_some() {
+            return None;
+        }
+        Some(hash)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{transaction, Transaction};
+    use pinxit::Identity;
+    use std::time::SystemTime;
+
+    fn transaction_hashes(count: u8) -> Vec<TransactionHash> {
+        let identity = Identity::generate();
+        (0..count)
+            .map(|i| {
+                let transaction = Transaction::KeyValue(transaction::KeyValue {
+                    key: "speed".to_string(),
+                    value: vec![i],
+                    timestamp: SystemTime::UNIX_EPOCH,
+                });
+                TransactionHash::of_unsigned(identity.id(), &transaction)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn empty_tree() {
+        let tree = MerkleTree::new(&[]);
+        assert_eq!(tree.root(), MerkleHash::default());
+        assert!(tree.proof(0).is_none());
+    }
+
+    #[test]
+    fn single_leaf() {
+        let hashes = transaction_hashes(1);
+        let tree = MerkleTree::new(&hashes);
+        assert_eq!(tree.root(), MerkleHash::leaf(&hashes[0]));
+
+        let proof = tree.proof(0).unwrap();
+        assert!(proof.siblings.is_empty());
+        assert_eq!(proof.root(&hashes[0]), Some(tree.root()));
+    }
+
+    #[test]
+    fn proofs_of_all_leaves() {
+        // Includes trees with nodes that are moved up without a sibling.
+        for count in 1..=17 {
+            let hashes = transaction_hashes(count);
+            let tree = MerkleTree::new(&hashes);
+            for (index, hash) in hashes.iter().enumerate() {
+                let proof = tree.proof(index).unwrap();
+                assert_eq!(proof.root(hash), Some(tree.root()), "{}/{}", index, count);
+            }
+            assert!(tree.proof(hashes.len()).is_none());
+        }
+    }
+
+    #[test]
+    fn root_depends_on_order() {
+        let hashes = transaction_hashes(4);
+        let mut swapped = hashes.clone();
+        swapped.swap(1, 2);
+        assert_ne!(
+            MerkleTree::new(&hashes).root(),
+            MerkleTree::new(&swapped).root()
+        );
+    }
+
+    #[test]
+    fn invalid_proofs() {
+        let hashes = transaction_hashes(6);
+        let tree = MerkleTree::new(&hashes);
+        let proof = tree.proof(4).unwrap();
+
+        // Another transaction.
+        assert_ne!(proof.root(&hashes[5]), Some(tree.root()));
+
+        // Another position.
+        let mut moved = proof.clone();
+        moved.index = 5;
+        assert_ne!(moved.root(&hashes[4]), Some(tree.root()));
+
+        // Malformed proofs.
+        let mut out_of_range = proof.clone();
+        out_of_range.index = out_of_range.leaf_count;
+        assert_eq!(out_of_range.root(&hashes[4]), None);
+
+        let mut missing_sibling = proof.clone();
+        missing_sibling.siblings.pop();
+        assert_eq!(missing_sibling.root(&hashes[4]), None);
+
+        let mut extra_sibling = proof;
+        extra_sibling.siblings.push(MerkleHash::default());
+        assert_eq!(extra_sibling.root(&hashes[4]), None);
+    }
+}
@@ -5,14 +5,22 @@ use serde::{Deserialize, Serialize};
 use std::time::SystemTime;
 
 mod block;
+mod block_approval;
 mod block_number;
 mod leader_term;
+mod merkle;
 mod signature_list;
+mod state_root;
+mod transaction_hash;
 
-pub use block::{Block, BlockHash, Body};
+pub use block::{Block, BlockHash, BlockHeader, Body};
+pub use block_approval::BlockApproval;
 pub use block_number::BlockNumber;
 pub use leader_term::LeaderTerm;
+pub use merkle::{MerkleHash, MerkleProof, MerkleTree};
 pub use signature_list::SignatureList;
+pub use state_root::StateRoot;
+pub use transaction_hash::TransactionHash;
 
 /// The first block in the chain, just a list of `Transaction`s.
 #[derive(Debug, Serialize, Deserialize)]
@@ -22,3 +30,18 @@ pub struct GenesisTransactions {
     /// The timestamp of genesis block creation.
     pub timestamp: SystemTime,
 }
+
+/// Check whether a number represents a supermajority (>2/3) compared
+/// to the total number of peers (`peer_count`) in the consenus.
+///
+/// # Panics
+///
+/// Panics if there are less than four peers.
+#[must_use]
+pub fn supermajority_reached(response_len: usize, peer_count: usize) -> bool {
+    if peer_count < 4 {
+        panic!("Cannot find consensus for less than four peers.");
+    }
+    let supermajority = peer_count * 2 / 3 + 1;
+    response_len >= supermajority
+}
@@ -0,0 +1,51 @@
+use super::block::HASH_SIZE;
+use blake2::{Blake2b, Digest};
+use serde::Serialize;
+use std::fmt;
+
+/// The hash of the `WorldState` after applying a `Block`.
+///
+/// Each `Body` commits to the state root, so diverging RPUs are detected.
+#[derive(Copy, Clone)]
+#[allow(clippy::module_name_repetitions)]
+pub struct StateRoot([u8; HASH_SIZE]);
+
+impl StateRoot {
+    /// Calculate the state root of a `state`.
+    ///
+    /// The `state` has to be serialized deterministically (e.g. without hash maps).
+    #[must_use]
+    pub fn of(state: &impl Serialize) -> Self {
+        let val = postcard::to_stdvec(state).unwrap();
+
+        let result = Blake2b::digest(&val);
+
+        let mut state_root = Self([0; HASH_SIZE]);
+        state_root.0.copy_from_slice(&result);
+        state_root
+    }
+}
+
+impl fmt::Debug for StateRoot {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl Default for StateRoot {
+    fn default() -> Self {
+        Self([0; HASH_SIZE])
+    }
+}
+
+impl PartialEq for StateRoot {
+    fn eq(&self, other: &Self) -> bool {
+        self.0[..] == other.0[..]
+    }
+}
+
+impl Eq for StateRoot {}
+
+hexutil::impl_hex!(StateRoot, HASH_SIZE, |&self| &self.0, |data| {
+    Ok(Self(data))
+});
@@ -0,0 +1,76 @@
+use super::block::HASH_SIZE;
+use crate::Transaction;
+use blake2::{Blake2b, Digest};
+use pinxit::{PeerId, Signed};
+use std::{
+    fmt,
+    hash::{Hash, Hasher},
+};
+
+/// The hash of a `Signed<Transaction>`.
+///
+/// This identifies a transaction submitted by a client.
+#[derive(Copy, Clone)]
+#[allow(clippy::module_name_repetitions)]
+pub struct TransactionHash([u8; HASH_SIZE]);
+
+impl TransactionHash {
+    /// Calculate the hash of a signed transaction.
+    #[must_use]
+    pub fn of(transaction: &Signed<Transaction>) -> Self {
+        let val = postcard::to_stdvec(transaction).unwrap();
+
+        let result = Blake2b::digest(&val);
+
+        let mut transaction_hash = Self([0; HASH_SIZE]);
+        transaction_hash.0.copy_from_slice(&result);
+        transaction_hash
+    }
+
+    /// Calculate the hash of a transaction signed by `signer`, ignoring the signature.
+    ///
+    /// Signatures are malleable, so this is used to recognize replayed transactions.
+    #[must_use]
+    pub fn of_unsigned(signer: &PeerId, transaction: &Transaction) -> Self {
+        let val = postcard::to_stdvec(&(signer, transaction)).unwrap();
+
+        let result = Blake2b::digest(&val);
+
+        let mut transaction_hash = Self([0; HASH_SIZE]);
+        transaction_hash.0.copy_from_slice(&result);
+        transaction_hash
+    }
+
+    /// Get a reference to a binary representation.
+    #[must_use]
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl fmt::Debug for TransactionHash {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl PartialEq for TransactionHash {
+    fn eq(&self, other: &Self) -> bool {
+        self.0[..] == other.0[..]
+    }
+}
+
+impl Eq for TransactionHash {}
+
+impl Hash for TransactionHash {
+    fn hash<H>(&self, h: &mut H)
+    where
+        H: Hasher,
+    {
+        self.0[..].hash(h)
+    }
+}
+
+hexutil::impl_hex!(TransactionHash, HASH_SIZE, |&self| &self.0, |data| {
+    Ok(Self(data))
+});
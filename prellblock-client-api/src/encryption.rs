@@ -0,0 +1,282 @@
+//! End-to-end encryption of values with content keys shared to the readers.
+//!
+//! Values of keys selected by a scope are encrypted by the client with a `ContentKey`.
+//! The content key is shared on-chain via a `ShareContentKey` transaction, wrapped for
+//! each reader with a key derived from the ed25519 identities of the sharer and the reader.
+//! The RPUs only store `EncryptedValue`s and cannot read them.
+
+use crate::account::Permission;
+use blake2::{Blake2b, Digest};
+use chacha20poly1305::{
+    aead::{generic_array::GenericArray, Aead, NewAead},
+    XChaCha20Poly1305,
+};
+use pinxit::{Identity, PeerId};
+use rand::{rngs::OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+const KEY_SIZE: usize = 32;
+const NONCE_SIZE: usize = 24;
+const KEY_ID_SIZE: usize = 16;
+
+/// Prefix of the hash identifying a content key.
+const KEY_ID_CONTEXT: &[u8] = b"prellblock content key id";
+/// Prefix of the hash deriving the key that wraps a content key for a reader.
+const WRAPPING_CONTEXT: &[u8] = b"prellblock content key wrapping";
+
+/// A symmetric key encrypting the values of the keys selected by a scope.
+///
+/// # Example
+/// ```
+/// use pinxit::Identity;
+/// use prellblock_client_api::encryption::{ContentKey, EncryptedValue};
+///
+/// let writer = Identity::generate();
+/// let reader = Identity::generate();
+///
+/// let content_key = ContentKey::generate();
+/// let encrypted = content_key.encrypt(b"secret");
+/// let value = encrypted.to_bytes();
+///
+/// let wrapped = content_key.wrap_for(&writer, reader.id()).unwrap();
+/// let unwrapped = ContentKey::unwrap_from(&wrapped, &reader, writer.id()).unwrap();
+/// let encrypted = EncryptedValue::from_bytes(&value).unwrap();
+/// assert_eq!(encrypted.key_id, content_key.id());
+/// assert_eq!(unwrapped.decrypt(&encrypted).unwrap(), b"secret");
+/// ```
+#[derive(Clone)]
+pub struct ContentKey([u8; KEY_SIZE]);
+
+impl ContentKey {
+    /// Generate a new random content key.
+    #[must_use]
+    pub fn generate() -> Self {
+        let mut key = [0; KEY_SIZE];
+        OsRng.fill_bytes(&mut key);
+        Self(key)
+    }
+
+    /// Get the public identifier of the key.
+    #[must_use]
+    pub fn id(&self) -> ContentKeyId {
+        let mut hasher = Blake2b::new();
+        hasher.input(KEY_ID_CONTEXT);
+        hasher.input(&self.0);
+
+        let mut key_id = ContentKeyId([0; KEY_ID_SIZE]);
+        key_id.0.copy_from_slice(&hasher.result()[..KEY_ID_SIZE]);
+        key_id
+    }
+
+    /// Encrypt a `value`.
+    #[must_use]
+    pub fn encrypt(&self, value: &[u8]) -> EncryptedValue {
+        let (nonce, ciphertext) = seal(&self.0, value);
+        EncryptedValue {
+            key_id: self.id(),
+            nonce,
+            ciphertext,
+        }
+    }
+
+    /// Decrypt an `EncryptedValue`.
+    ///
+    /// Returns `None` if the value was not encrypted with this key or was modified.
+    #[must_use]
+    pub fn decrypt(&self, value: &EncryptedValue) -> Option<Vec<u8>> {
+        if value.key_id != self.id() {
+            return None;
+        }
+        open(&self.0, &value.nonce, &value.ciphertext)
+    }
+
+    /// Wrap the key for the `reader` (to be shared by the signer of the `identity`).
+    pub fn wrap_for(
+        &self,
+        identity: &Identity,
+        reader: &PeerId,
+    ) -> Result<WrappedKey, pinxit::Error> {
+        let wrapping_key = wrapping_key(identity, identity.id(), reader)?;
+        let (nonce, ciphertext) = seal(&wrapping_key, &self.0);
+        Ok(WrappedKey {
+            reader: reader.clone(),
+            nonce,
+            ciphertext,
+        })
+    }
+
+    /// Unwrap a key that the `sharer` wrapped for the owner of the `identity`.
+    ///
+    /// Returns `None` if the key was not wrapped for the `identity` by the `sharer`.
+    #[must_use]
+    pub fn unwrap_from(wrapped: &WrappedKey, identity: &Identity, sharer: &PeerId) -> Option<Self> {
+        if wrapped.reader != *identity.id() {
+            return None;
+        }
+        let wrapping_key = wrapping_key(identity, sharer, identity.id()).ok()?;
+        let key = open(&wrapping_key, &wrapped.nonce, &wrapped.ciphertext)?;
+        if key.len() != KEY_SIZE {
+            return None;
+        }
+
+        let mut content_key = Self([0; KEY_SIZE]);
+        content_key.0.copy_from_slice(&key);
+        Some(content_key)
+    }
+}
+
+impl fmt::Debug for ContentKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Never print the key itself.
+        f.debug_tuple("ContentKey").field(&self.id()).finish()
+    }
+}
+
+/// The public identifier of a `ContentKey` (a truncated hash of the key).
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
+#[allow(clippy::module_name_repetitions)]
+pub struct ContentKeyId([u8; KEY_ID_SIZE]);
+
+impl fmt::Debug for ContentKeyId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+hexutil::impl_hex!(ContentKeyId, KEY_ID_SIZE, |&self| &self.0, |data| {
+    Ok(Self(data))
+});
+
+/// A value encrypted with a `ContentKey`.
+///
+/// The postcard-encoded `EncryptedValue` is written as value of the key.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EncryptedValue {
+    /// The identifier of the `ContentKey`.
+    pub key_id: ContentKeyId,
+    /// The random nonce.
+    pub nonce: [u8; NONCE_SIZE],
+    /// The encrypted value (including the authentication tag).
+    pub ciphertext: Vec<u8>,
+}
+
+impl EncryptedValue {
+    /// Encode the value (to be written as value of a key).
+    #[must_use]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        postcard::to_stdvec(self).unwrap()
+    }
+
+    /// Decode a value written by `to_bytes`.
+    ///
+    /// Returns `None` if the `value` is not an `EncryptedValue`.
+    #[must_use]
+    pub fn from_bytes(value: &[u8]) -> Option<Self> {
+        match postcard::take_from_bytes(value) {
+            Ok((encrypted, rest)) if rest.is_empty() => Some(encrypted),
+            _ => None,
+        }
+    }
+}
+
+/// A `ContentKey` wrapped for a single reader.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[allow(clippy::module_name_repetitions)]
+pub struct WrappedKey {
+    /// The reader that can unwrap the key.
+    pub reader: PeerId,
+    /// The random nonce.
+    pub nonce: [u8; NONCE_SIZE],
+    /// The encrypted content key (including the authentication tag).
+    pub ciphertext: Vec<u8>,
+}
+
+/// A `ContentKey` shared via a `ShareContentKey` transaction.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ContentKeyGrant {
+    /// The keys whose values are encrypted with the content key.
+    pub scope: Permission,
+    /// The identifier of the content key.
+    pub key_id: ContentKeyId,
+    /// The account that shared the key (needed to unwrap it).
+    pub sharer: PeerId,
+    /// The content key wrapped for each reader.
+    pub readers: Vec<WrappedKey>,
+}
+
+impl ContentKeyGrant {
+    /// Unwrap the content key for the owner of the `identity`.
+    ///
+    /// Returns `None` if the key was not shared with the `identity`.
+    #[must_use]
+    pub fn unwrap(&self, identity: &Identity) -> Option<ContentKey> {
+        let wrapped = self
+            .readers
+            .iter()
+            .find(|wrapped| wrapped.reader == *identity.id())?;
+        let content_key = ContentKey::unwrap_from(wrapped, identity, &self.sharer)?;
+        if content_key.id() == self.key_id {
+            Some(content_key)
+        } else {
+            None
+        }
+    }
+}
+
+/// Get the grant of the content key currently used for `key` (the latest matching grant).
+///
+/// Returns `None` if the values of `key` are not encrypted.
+#[must_use]
+pub fn current_grant<'a>(
+    grants: impl IntoIterator<Item = &'a ContentKeyGrant>,
+    key: &str,
+) -> Option<&'a ContentKeyGrant> {
+    grants
+        .into_iter()
+        .filter(|grant| grant.scope.matches(key))
+        .last()
+}
+
+/// Encrypt `data` with a random nonce.
+fn seal(key: &[u8; KEY_SIZE], data: &[u8]) -> ([u8; NONCE_SIZE], Vec<u8>) {
+    let mut nonce = [0; NONCE_SIZE];
+    OsRng.fill_bytes(&mut nonce);
+    let cipher = XChaCha20Poly1305::new(GenericArray::from_slice(key));
+    let ciphertext = cipher
+        .encrypt(GenericArray::from_slice(&nonce), data)
+        .expect("data is too large to be encrypted");
+    (nonce, ciphertext)
+}
+
+/// Decrypt `ciphertext` (returns `None` if it was modified).
+fn open(key: &[u8; KEY_SIZE], nonce: &[u8; NONCE_SIZE], ciphertext: &[u8]) -> Option<Vec<u8>> {
+    let cipher = XChaCha20Poly1305::new(GenericArray::from_slice(key));
+    cipher
+        .decrypt(GenericArray::from_slice(nonce), ciphertext)
+        .ok()
+}
+
+/// Derive the key wrapping a content key shared by `sharer` with `reader`.
+fn wrapping_key(
+    identity: &Identity,
+    sharer: &PeerId,
+    reader: &PeerId,
+) -> Result<[u8; KEY_SIZE], pinxit::Error> {
+    let other = if identity.id() == sharer {
+        reader
+    } else {
+        sharer
+    };
+    let shared_secret = identity.key_agreement(other)?;
+
+    let mut hasher = Blake2b::new();
+    hasher.input(WRAPPING_CONTEXT);
+    hasher.input(&shared_secret);
+    hasher.input(sharer.as_bytes());
+    hasher.input(reader.as_bytes());
+
+    let mut key = [0; KEY_SIZE];
+    key.copy_from_slice(&hasher.result()[..KEY_SIZE]);
+    Ok(key)
+}
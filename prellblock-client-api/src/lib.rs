@@ -4,15 +4,24 @@
 //! Library Crate used for Communication between external Clients and internal RPUs.
 
 pub mod account;
+pub mod blob;
 pub mod consensus;
+pub mod encryption;
+pub mod schema;
 
-use account::{Account, Permissions};
+use account::{Account, AccountKind, KeyRotation, Permission, Permissions};
 use balise::define_api;
-use consensus::{Block, BlockNumber};
+use blob::{BlobChunk, BlobHash, BlobRef};
+use consensus::{
+    Block, BlockHash, BlockHeader, BlockNumber, MerkleProof, SignatureList, TransactionHash,
+};
+use encryption::{ContentKeyGrant, ContentKeyId, WrappedKey};
 use newtype_enum::newtype_enum;
-use pinxit::{PeerId, Signable, Signature, Signed};
+use pinxit::{Identity, PeerId, Signable, Signature, Signed};
+use schema::{Schema, ValueType};
 use serde::{Deserialize, Serialize};
 use std::{
+    borrow::Cow,
     collections::HashMap,
     ops::{Bound, Deref, RangeBounds},
     time::{Duration, SystemTime},
@@ -237,6 +246,58 @@ pub enum Query {
     },
 }
 
+/// Aggregate the values selected by `Span`s in fixed time buckets.
+///
+/// The buckets are aligned to the UNIX epoch and use the (RPU write) time of the values.
+/// Only keys with a numeric schema (`Integer`, `Unsigned` or `Float`) can be aggregated,
+/// the values are decoded according to the schema.
+///
+/// # Example
+/// ```
+/// use prellblock_client_api::{AggregateQuery, Aggregation};
+/// use std::time::{Duration, SystemTime};
+///
+/// fn timestamp(s: &str) -> SystemTime {
+///     chrono::DateTime::parse_from_rfc3339(s).unwrap().into()
+/// }
+///
+/// // the mean of every minute between 8 AM and 10 AM.
+/// AggregateQuery {
+///     span: timestamp("2020-05-22T08:00:00Z").into(),
+///     end: timestamp("2020-05-22T10:00:00Z").into(),
+///     bucket: Duration::from_secs(60),
+///     aggregation: Aggregation::Mean,
+/// };
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AggregateQuery {
+    /// The span to aggregate (see `Query::Range`).
+    pub span: Span,
+    /// The last value to aggregate (see `Query::Range`).
+    pub end: Span,
+    /// The duration of a bucket.
+    pub bucket: Duration,
+    /// The aggregation function applied to each bucket.
+    pub aggregation: Aggregation,
+}
+
+/// An aggregation function of an `AggregateQuery`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Aggregation {
+    /// The smallest value.
+    Min,
+    /// The largest value.
+    Max,
+    /// The arithmetic mean of all values.
+    Mean,
+    /// The number of values.
+    Count,
+    /// The first (oldest) value.
+    First,
+    /// The last (newest) value.
+    Last,
+}
+
 /// The `Transaction`s in response to a `GetValue` request of a single data series of a peer.
 pub type ReadValuesOfSeries = HashMap<SystemTime, (Vec<u8>, SystemTime, Signature)>;
 
@@ -246,6 +307,49 @@ pub type ReadValuesOfPeer = HashMap<String, ReadValuesOfSeries>;
 /// The `Transaction`s in response to a `GetValue` request of all peers.
 pub type ReadValues = HashMap<PeerId, ReadValuesOfPeer>;
 
+/// The buckets in response to a `GetAggregate` request of a single data series of a peer.
+///
+/// The aggregated values are keyed by the start of their bucket.
+pub type AggregatesOfSeries = HashMap<SystemTime, f64>;
+
+/// The buckets in response to a `GetAggregate` request of a single peer.
+pub type AggregatesOfPeer = HashMap<String, AggregatesOfSeries>;
+
+/// The buckets in response to a `GetAggregate` request of all peers.
+pub type Aggregates = HashMap<PeerId, AggregatesOfPeer>;
+
+/// An opaque position to continue a paginated request at.
+///
+/// Cursors are created by the RPU and should be passed back unchanged
+/// to request the next `Page`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Cursor(Vec<u8>);
+
+impl Cursor {
+    /// Create a new cursor containing the given `data`.
+    #[must_use]
+    pub const fn new(data: Vec<u8>) -> Self {
+        Self(data)
+    }
+
+    /// Get the data of the cursor.
+    #[must_use]
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+/// A single page of the response to a paginated request.
+///
+/// The RPU limits the number of items per page.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Page<T> {
+    /// The items of this page.
+    pub items: T,
+    /// The cursor to request the next page with or `None` if this is the last page.
+    pub next: Option<Cursor>,
+}
+
 define_api! {
     /// The message API module for communication between RPUs.
     mod message;
@@ -255,21 +359,87 @@ define_api! {
         Ping => Pong,
 
         /// Simple transaction Message. Will write a key:value pair.
-        Execute(Signed<Transaction>) => (),
+        ///
+        /// Returns the `TransactionHash` that can be used to query the transaction's status.
+        Execute(Signed<Transaction>) => TransactionHash,
+
+        /// Transaction Message that waits until the transaction is committed.
+        ///
+        /// The `Duration` is the maximum time to wait for the transaction being committed.
+        /// An error is returned if the transaction was rejected or not committed in time.
+        ExecuteAndWait(Signed<Transaction>, Duration) => TransactionReceipt,
+
+        /// Upload a chunk of the payload of a blob (before sending the `Blob` transaction referencing it).
+        ///
+        /// The chunks are replicated to the other RPUs.
+        /// Returns whether all chunks of the payload were uploaded (and it matches its hash).
+        PutBlob(Signed<crate::PutBlob>) => bool,
+
+        /// Get a chunk of the payload of a blob written by a `Blob` transaction.
+        ///
+        /// The payload must be verified against its `BlobHash` by the client.
+        GetBlob(Signed<crate::GetBlob>) => BlobChunk,
 
         /// Get the values of the given peers, filtered by a filter and selected by a query.
-        GetValue(Signed<crate::GetValue>) => ReadValues,
+        ///
+        /// The response is paginated, use the `cursor` of the request to get the next page.
+        GetValue(Signed<crate::GetValue>) => Page<ReadValues>,
+
+        /// Aggregate the values of the given peers in fixed time buckets on the RPU.
+        ///
+        /// Aggregated values are not signed. The response is paginated like `GetValue`.
+        GetAggregate(Signed<crate::GetAggregate>) => Page<Aggregates>,
+
+        /// Get a proof that a value (returned by `GetValue`) is included in a `Block`.
+        ///
+        /// Returns `None` if the value was not found.
+        GetValueProof(Signed<crate::GetValueProof>) => Option<ValueProof>,
+
+        /// Get the proofs of several values (returned by `GetValue`) at once.
+        ///
+        /// The proofs are returned in the order of the request,
+        /// `None` if the value was not found.
+        GetValueProofs(Signed<crate::GetValueProofs>) => Vec<Option<ValueProof>>,
+
+        /// List the keys (time series) an account has written.
+        ///
+        /// Keys the requesting account is not allowed to read are omitted.
+        ListKeys(Signed<crate::ListKeys>) => Vec<KeyInfo>,
 
         /// Get a single account by it's `PeerId`.
         ///
         /// Accounts that are not found will be omitted in the return value.
         GetAccount(Signed<crate::GetAccount>) => Vec<Account>,
 
+        /// List all accounts matching a filter (e.g. to look up accounts by name).
+        ///
+        /// The accounts are returned with their `PeerId`, sorted by name.
+        ListAccounts(Signed<crate::ListAccounts>) => Vec<(PeerId, Account)>,
+
         /// Get a `Block` by it's `BlockNumber`.
-        GetBlock(Signed<crate::GetBlock>) => Vec<Block>,
+        ///
+        /// The response is paginated, use the `cursor` of the request to get the next page.
+        GetBlock(Signed<crate::GetBlock>) => Page<Vec<Block>>,
 
         /// Get the current number of blocks in the blockchain.
         GetCurrentBlockNumber(Signed<crate::GetCurrentBlockNumber>) => BlockNumber,
+
+        /// Get all schemas (in the order they are matched against keys).
+        GetSchemas(Signed<crate::GetSchemas>) => Vec<Schema>,
+
+        /// Get the content keys shared by an account (to encrypt and decrypt its values).
+        ///
+        /// Only the keys wrapped for the requesting account are included in the grants.
+        GetContentKeys(Signed<crate::GetContentKeys>) => Vec<ContentKeyGrant>,
+
+        /// Get the status of a transaction by it's `TransactionHash`.
+        GetTransactionStatus(Signed<crate::GetTransactionStatus>) => TransactionStatus,
+
+        /// Subscribe to newly committed values of the given peers, filtered by a filter.
+        ///
+        /// The connection stays open and the RPU pushes a `SubscriptionEvent`
+        /// for every matching value (and every block, if requested).
+        Subscribe(Signed<crate::Subscribe>) => SubscriptionEvent,
     }
 }
 
@@ -282,6 +452,126 @@ pub struct GetValue {
     pub filter: Filter<String>,
     /// The query to selct some values in the given time range.
     pub query: Query,
+    /// The position to continue at (`None` to get the first page).
+    pub cursor: Option<Cursor>,
+}
+
+/// Aggregate the values of the given peers in fixed time buckets.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GetAggregate {
+    /// A Vector of `PeerId`'s to select the `Accounts` from which to read.
+    pub peer_ids: Vec<PeerId>,
+    /// The filter to select some keys of the namespace.
+    pub filter: Filter<String>,
+    /// The query to select the values and the buckets.
+    pub query: AggregateQuery,
+    /// The position to continue at (`None` to get the first page).
+    pub cursor: Option<Cursor>,
+}
+
+/// Upload a chunk of the payload of a blob.
+///
+/// The payload is split into chunks of `BLOB_CHUNK_SIZE` bytes (the last one can be shorter,
+/// an empty payload is uploaded as one empty chunk). The chunks can be uploaded in any order.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PutBlob {
+    /// The hash of the whole payload.
+    pub hash: BlobHash,
+    /// The size of the whole payload (at most `MAX_BLOB_SIZE` bytes).
+    pub size: u64,
+    /// The position of the chunk in the payload (a multiple of `BLOB_CHUNK_SIZE`).
+    pub offset: u64,
+    /// The data of the chunk.
+    pub data: Vec<u8>,
+}
+
+/// Get a chunk of the payload of a blob.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GetBlob {
+    /// The `PeerId` of the account that has written the blob.
+    pub peer_id: PeerId,
+    /// The key the blob was written to.
+    pub key: String,
+    /// The hash of the payload.
+    pub hash: BlobHash,
+    /// The position of the chunk in the payload.
+    pub offset: u64,
+}
+
+/// Get a proof that a value (returned by `GetValue`) is included in a `Block`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GetValueProof {
+    /// The `PeerId` of the account that has written the value.
+    pub peer_id: PeerId,
+    /// The key of the value.
+    pub key: String,
+    /// The (client) timestamp of the value.
+    pub timestamp: SystemTime,
+}
+
+/// The maximum number of values in a single `GetValueProofs` request.
+pub const MAX_VALUE_PROOFS: usize = 1000;
+
+/// Get the proofs of several values (returned by `GetValue`) at once.
+///
+/// At most `MAX_VALUE_PROOFS` values can be requested at once.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GetValueProofs {
+    /// The values to prove.
+    pub values: Vec<GetValueProof>,
+}
+
+/// List the keys (time series) an account has written.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ListKeys {
+    /// The `PeerId` of the account that has written the keys.
+    pub peer_id: PeerId,
+}
+
+/// Information about a key (time series) in response to a `ListKeys` request.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyInfo {
+    /// The name of the key.
+    pub key: String,
+    /// The (RPU write) time of the first value.
+    pub first_write: SystemTime,
+    /// The (RPU write) time of the last value.
+    pub last_write: SystemTime,
+    /// The number of values written to the key.
+    pub count: u64,
+}
+
+/// A proof that a value is included in a `Block`, in response to a `GetValueProof` request.
+///
+/// The proof consists of the transaction that has written the value,
+/// the header of the `Block` and the path in the `MerkleTree` of the `Block`.
+/// The `signatures` of the RPUs sign the `BlockHash` of the `header`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ValueProof {
+    /// The transaction that has written the value.
+    pub transaction: Signed<Transaction>,
+    /// The header of the `Block` containing the transaction.
+    pub header: BlockHeader,
+    /// The inclusion proof of the transaction.
+    pub proof: MerkleProof,
+    /// The list of append signatures that accepted the `Block`.
+    pub signatures: SignatureList,
+}
+
+impl ValueProof {
+    /// Verify that the transaction is included in the `Block` described by the `header`.
+    ///
+    /// Returns the `BlockHash` that must be signed by a supermajority of RPUs in `signatures`.
+    /// Returns `None` if the transaction is not included.
+    #[must_use]
+    pub fn verify_inclusion(&self) -> Option<BlockHash> {
+        let transactions_root = self.proof.root(&TransactionHash::of(&self.transaction))?;
+        if transactions_root == self.header.transactions_root {
+            Some(self.header.hash())
+        } else {
+            None
+        }
+    }
 }
 
 /// Get a single account by it's `PeerId`.
@@ -293,17 +583,162 @@ pub struct GetAccount {
     pub peer_ids: Vec<PeerId>,
 }
 
+/// List all accounts matching a filter.
+///
+/// Unset fields do not filter, so the default lists all accounts.
+///
+/// # Example
+/// ```
+/// use prellblock_client_api::{account::Account, ListAccounts};
+///
+/// let filter = ListAccounts {
+///     name_prefix: Some("ICE ".to_string()),
+///     ..ListAccounts::default()
+/// };
+/// assert!(filter.matches(&Account::new("ICE 4711".to_string())));
+/// assert!(!filter.matches(&Account::new("RE 1".to_string())));
+/// ```
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ListAccounts {
+    /// Only list accounts of this kind.
+    pub kind: Option<AccountKind>,
+    /// Only list accounts with exactly this name.
+    pub name: Option<String>,
+    /// Only list accounts with a name starting with this prefix.
+    pub name_prefix: Option<String>,
+    /// Only list expired (`true`) or active (`false`) accounts.
+    pub expired: Option<bool>,
+}
+
+impl ListAccounts {
+    /// Check whether the `account` matches all filters.
+    #[must_use]
+    pub fn matches(&self, account: &Account) -> bool {
+        self.kind
+            .map_or(true, |kind| account.account_type.kind() == kind)
+            && self
+                .name
+                .as_ref()
+                .map_or(true, |name| account.name == *name)
+            && self
+                .name_prefix
+                .as_ref()
+                .map_or(true, |prefix| account.name.starts_with(prefix.as_str()))
+            && self
+                .expired
+                .map_or(true, |expired| account.expire_at.is_expired() == expired)
+    }
+}
+
 /// Get a `Block` by it's `BlockNumber`.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GetBlock {
     /// The filter to select some blocks.
     pub filter: Filter<BlockNumber>,
+    /// The position to continue at (`None` to get the first page).
+    pub cursor: Option<Cursor>,
 }
 
 /// Get the current number of blocks in the blockchain.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GetCurrentBlockNumber;
 
+/// Get all schemas (in the order they are matched against keys).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GetSchemas;
+
+/// Get the content keys shared by an account.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GetContentKeys {
+    /// The account whose values are encrypted.
+    pub owner: PeerId,
+}
+
+/// Get the status of a transaction by it's `TransactionHash`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GetTransactionStatus {
+    /// The hash of the transaction (returned by `Execute`).
+    pub transaction_hash: TransactionHash,
+}
+
+/// Subscribe to newly committed values of the given peers, filtered by a filter.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Subscribe {
+    /// A Vector of `PeerId`'s to select the `Accounts` from which to read.
+    pub peer_ids: Vec<PeerId>,
+    /// The filter to select some keys of the namespace.
+    pub filter: Filter<String>,
+    /// Whether to receive an event for each committed `Block` (requires block reading rights).
+    pub blocks: bool,
+}
+
+/// An event pushed to the client in response to a `Subscribe` request.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum SubscriptionEvent {
+    /// A value matching the subscription was committed.
+    Value {
+        /// The account that has written the value.
+        peer_id: PeerId,
+        /// The key of the value.
+        key: String,
+        /// The value.
+        value: Vec<u8>,
+        /// The timestamp of the transaction.
+        timestamp: SystemTime,
+        /// The signature of the transaction.
+        signature: Signature,
+        /// The number of the `Block` containing the value.
+        block_number: BlockNumber,
+    },
+    /// A `Block` was committed.
+    Block {
+        /// The number of the `Block`.
+        block_number: BlockNumber,
+        /// The hash of the `Block`.
+        block_hash: BlockHash,
+        /// The time, the leader proposed the `Block`.
+        timestamp: SystemTime,
+        /// The number of transactions in the `Block`.
+        transactions: usize,
+    },
+}
+
+/// The receipt of a committed transaction in response to an `ExecuteAndWait` request.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TransactionReceipt {
+    /// The hash of the committed transaction.
+    pub transaction_hash: TransactionHash,
+    /// The number of the `Block` containing the transaction.
+    pub block_number: BlockNumber,
+    /// The position of the transaction in the `Block`.
+    pub index: usize,
+}
+
+/// The status of a transaction in response to a `GetTransactionStatus` request.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TransactionStatus {
+    /// The transaction is not known to the RPU.
+    ///
+    /// A transaction that was submitted to another RPU could still be on it's way to the consensus.
+    /// Rejected transactions are unknown again after a while.
+    Unknown,
+    /// The transaction is waiting to be included in a block
+    /// (or to be sent to the consensus by the receiving RPU).
+    Pending,
+    /// The transaction was committed.
+    Committed {
+        /// The number of the `Block` containing the transaction.
+        block_number: BlockNumber,
+        /// The position of the transaction in the `Block`.
+        index: usize,
+    },
+    /// The transaction was rejected by the consensus.
+    Rejected {
+        /// The reason why the transaction is invalid.
+        reason: String,
+    },
+}
+
 #[derive(Serialize)]
 enum ClientMessageSigningData<'a> {
     Execute(&'a Transaction),
@@ -311,6 +746,17 @@ enum ClientMessageSigningData<'a> {
     GetAccount(&'a GetAccount),
     GetBlock(&'a GetBlock),
     GetCurrentBlockNumber(&'a GetCurrentBlockNumber),
+    GetTransactionStatus(&'a GetTransactionStatus),
+    Subscribe(&'a Subscribe),
+    GetValueProof(&'a GetValueProof),
+    ListAccounts(&'a ListAccounts),
+    ListKeys(&'a ListKeys),
+    GetSchemas(&'a GetSchemas),
+    PutBlob(&'a PutBlob),
+    GetBlob(&'a GetBlob),
+    GetContentKeys(&'a GetContentKeys),
+    GetValueProofs(&'a GetValueProofs),
+    GetAggregate(&'a GetAggregate),
 }
 
 macro_rules! impl_signable {
@@ -330,9 +776,25 @@ impl_signable!(
     GetValue => GetValue,
     GetAccount => GetAccount,
     GetBlock => GetBlock,
-    GetCurrentBlockNumber => GetCurrentBlockNumber
+    GetCurrentBlockNumber => GetCurrentBlockNumber,
+    GetTransactionStatus => GetTransactionStatus,
+    Subscribe => Subscribe,
+    GetValueProof => GetValueProof,
+    ListAccounts => ListAccounts,
+    ListKeys => ListKeys,
+    GetSchemas => GetSchemas,
+    PutBlob => PutBlob,
+    GetBlob => GetBlob,
+    GetContentKeys => GetContentKeys,
+    GetValueProofs => GetValueProofs,
+    GetAggregate => GetAggregate
 );
 
+/// How long a proposal waits for approvals (after the timestamp of its `ProposeAccountChange`).
+///
+/// Proposals that are not executed within this time are dropped.
+pub const PROPOSAL_LIFETIME: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
 /// A blockchain transaction for prellblock.
 #[allow(clippy::large_enum_variant)]
 #[newtype_enum(variants = "transaction")]
@@ -374,6 +836,231 @@ pub enum Transaction {
         /// The timestamp of transaction creation.
         timestamp: SystemTime,
     },
+    /// Set a `key` to a `value` in the namespace of another account (the `owner`).
+    ///
+    /// The signer must be one of the `owner`'s delegates.
+    DelegatedKeyValue {
+        /// The account owning the namespace to write into.
+        owner: PeerId,
+        /// The key.
+        key: String,
+        /// The value.
+        value: Vec<u8>,
+        /// The Timestamp.
+        timestamp: SystemTime,
+    },
+    /// Propose an account management transaction that needs the approval of multiple admins.
+    ///
+    /// The proposal is identified by the `TransactionHash` of this transaction.
+    /// It counts as approved by the proposer and is stored on chain until it is
+    /// approved by the number of admins set via `SetAdminThreshold`
+    /// (or until it expires after the `PROPOSAL_LIFETIME`).
+    /// Lowering the threshold executes the proposals that are approved by enough admins.
+    ProposeAccountChange {
+        /// The proposed `UpdateAccount`, `CreateAccount`, `DeleteAccount`,
+        /// `SetAdminThreshold`, `RotateKey` or `SetSchema` transaction.
+        operation: Box<Transaction>,
+        /// The timestamp of transaction creation.
+        timestamp: SystemTime,
+    },
+    /// Approve a proposed account management transaction.
+    ///
+    /// The proposed transaction is executed as soon as enough admins approved it.
+    ApproveAccountChange {
+        /// The `TransactionHash` of the `ProposeAccountChange` transaction.
+        proposal: TransactionHash,
+        /// The timestamp of transaction creation.
+        timestamp: SystemTime,
+    },
+    /// Set the number of admins needed to manage accounts.
+    ///
+    /// With a threshold above one, account management transactions
+    /// need to be proposed via `ProposeAccountChange`. (Default `1`).
+    SetAdminThreshold {
+        /// The number of admin approvals needed.
+        threshold: usize,
+        /// The timestamp of transaction creation.
+        timestamp: SystemTime,
+    },
+    /// Replace the key of an account.
+    ///
+    /// The account keeps its permissions and its namespace,
+    /// the values can be queried using either key.
+    /// Must be signed by the old key or by an admin.
+    /// Additionally, the new key must sign the `KeyRotation` (see `RotateKey::new`).
+    RotateKey {
+        /// The current ID of the account.
+        id: PeerId,
+        /// The new ID of the account.
+        new_id: PeerId,
+        /// The signature of the `KeyRotation` by the new key.
+        new_id_signature: Signature,
+        /// The timestamp of transaction creation.
+        timestamp: SystemTime,
+    },
+    /// Set the schema of the keys selected by `key` (for all accounts).
+    ///
+    /// A schema with the same `key` is replaced. Must be signed by an admin.
+    SetSchema {
+        /// The keys the schema applies to.
+        key: Permission,
+        /// The type of the values or `None` to remove the schema.
+        value_type: Option<ValueType>,
+        /// The timestamp of transaction creation.
+        timestamp: SystemTime,
+    },
+    /// Set a `key` to a blob that is stored off-chain.
+    ///
+    /// The payload has to be uploaded via `PutBlob` first.
+    /// The `BlobRef` is written as value of the `key`.
+    Blob {
+        /// The key.
+        key: String,
+        /// The hash of the payload.
+        hash: BlobHash,
+        /// The size of the payload in bytes.
+        size: u64,
+        /// The Timestamp.
+        timestamp: SystemTime,
+    },
+    /// Share a content key encrypting the values of the keys selected by `scope`.
+    ///
+    /// The values are encrypted by the clients, the RPUs only store the wrapped keys.
+    /// A later share for an overlapping scope replaces the key for new values.
+    ShareContentKey {
+        /// The keys (of the signer's namespace) whose values are encrypted.
+        scope: Permission,
+        /// The identifier of the content key.
+        key_id: ContentKeyId,
+        /// The content key wrapped for each reader (including the signer).
+        readers: Vec<WrappedKey>,
+        /// The timestamp of transaction creation.
+        timestamp: SystemTime,
+    },
+}
+
+impl Transaction {
+    /// Check whether the transaction manages accounts (and needs admin approval).
+    #[must_use]
+    pub fn is_account_management(&self) -> bool {
+        match self {
+            Self::UpdateAccount(_)
+            | Self::CreateAccount(_)
+            | Self::DeleteAccount(_)
+            | Self::SetAdminThreshold(_)
+            | Self::RotateKey(_)
+            | Self::SetSchema(_) => true,
+            Self::KeyValue(_)
+            | Self::DelegatedKeyValue(_)
+            | Self::ProposeAccountChange(_)
+            | Self::ApproveAccountChange(_)
+            | Self::Blob(_)
+            | Self::ShareContentKey(_) => false,
+        }
+    }
+
+    /// Get the timestamp of transaction creation (set by the client).
+    #[must_use]
+    pub fn timestamp(&self) -> SystemTime {
+        match self {
+            Self::KeyValue(params) => params.timestamp,
+            Self::UpdateAccount(params) => params.timestamp,
+            Self::CreateAccount(params) => params.timestamp,
+            Self::DeleteAccount(params) => params.timestamp,
+            Self::DelegatedKeyValue(params) => params.timestamp,
+            Self::ProposeAccountChange(params) => params.timestamp,
+            Self::ApproveAccountChange(params) => params.timestamp,
+            Self::SetAdminThreshold(params) => params.timestamp,
+            Self::RotateKey(params) => params.timestamp,
+            Self::SetSchema(params) => params.timestamp,
+            Self::Blob(params) => params.timestamp,
+            Self::ShareContentKey(params) => params.timestamp,
+        }
+    }
+
+    /// Get the number of bytes stored for this transaction (including off-chain payloads).
+    #[must_use]
+    pub fn payload_size(&self) -> u64 {
+        match self {
+            Self::KeyValue(params) => params.value.len() as u64,
+            Self::DelegatedKeyValue(params) => params.value.len() as u64,
+            Self::Blob(params) => params.size,
+            _ => 0,
+        }
+    }
+
+    /// Get the value written by this transaction (if it is signed by `signer`).
+    ///
+    /// Returns `None` if the transaction does not write a value.
+    #[must_use]
+    pub fn written_value<'a>(&'a self, signer: &'a PeerId) -> Option<WrittenValue<'a>> {
+        match self {
+            Self::KeyValue(params) => Some(WrittenValue {
+                namespace: signer,
+                key: &params.key,
+                value: Cow::Borrowed(&params.value),
+                timestamp: params.timestamp,
+            }),
+            Self::DelegatedKeyValue(params) => Some(WrittenValue {
+                namespace: &params.owner,
+                key: &params.key,
+                value: Cow::Borrowed(&params.value),
+                timestamp: params.timestamp,
+            }),
+            Self::Blob(params) => {
+                let blob_ref = BlobRef {
+                    hash: params.hash,
+                    size: params.size,
+                };
+                Some(WrittenValue {
+                    namespace: signer,
+                    key: &params.key,
+                    value: Cow::Owned(postcard::to_stdvec(&blob_ref).unwrap()),
+                    timestamp: params.timestamp,
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+impl transaction::RotateKey {
+    /// Create a transaction to rotate the key of the account `id` to the key of the `new_identity`.
+    pub fn new(id: PeerId, new_identity: &Identity) -> Result<Self, pinxit::Error> {
+        let new_id = new_identity.id().clone();
+        let new_id_signature = new_identity.sign(KeyRotation {
+            id: id.clone(),
+            new_id: new_id.clone(),
+        })?;
+        Ok(Self {
+            id,
+            new_id,
+            new_id_signature,
+            timestamp: SystemTime::now(),
+        })
+    }
+
+    /// Verify that the new key signed the `KeyRotation`.
+    pub fn verify_new_id_signature(&self) -> Result<(), pinxit::Error> {
+        let key_rotation = KeyRotation {
+            id: self.id.clone(),
+            new_id: self.new_id.clone(),
+        };
+        self.new_id.verify(key_rotation, &self.new_id_signature)
+    }
+}
+
+/// A value written by a `KeyValue`, `DelegatedKeyValue` or `Blob` transaction.
+#[derive(Debug, Clone)]
+pub struct WrittenValue<'a> {
+    /// The account owning the namespace the value is written to.
+    pub namespace: &'a PeerId,
+    /// The key.
+    pub key: &'a str,
+    /// The value (the encoded `BlobRef` for `Blob` transactions).
+    pub value: Cow<'a, [u8]>,
+    /// The Timestamp.
+    pub timestamp: SystemTime,
 }
 
 /// A trait signifying that a transaction can be written into the Account-tree in the `DataStorage`.
@@ -382,3 +1069,8 @@ pub trait AccountTransaction {}
 impl AccountTransaction for transaction::UpdateAccount {}
 impl AccountTransaction for transaction::CreateAccount {}
 impl AccountTransaction for transaction::DeleteAccount {}
+impl AccountTransaction for transaction::ProposeAccountChange {}
+impl AccountTransaction for transaction::ApproveAccountChange {}
+impl AccountTransaction for transaction::SetAdminThreshold {}
+impl AccountTransaction for transaction::RotateKey {}
+impl AccountTransaction for transaction::SetSchema {}
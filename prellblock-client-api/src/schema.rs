@@ -0,0 +1,273 @@
+//! Schemas declaring the type of the values written to a key.
+
+use crate::account::Permission;
+use serde::{Deserialize, Serialize};
+
+/// Binds the keys selected by a `Permission` to the type of their values.
+///
+/// Schemas are managed by admins via `SetSchema` transactions.
+/// Values that do not match the schema of their key are rejected.
+///
+/// # Example
+/// ```
+/// use prellblock_client_api::{
+///     account::{Matching, Permission},
+///     schema::{value_type_of, DecodedValue, Schema, ValueType},
+/// };
+///
+/// let schemas = vec![
+///     Schema::new(Permission::new("speed", Matching::Exact), ValueType::Float),
+///     Schema::new(Permission::new("wagon_", Matching::Prefix), ValueType::Integer),
+/// ];
+///
+/// let value_type = value_type_of(&schemas, "wagon_count").unwrap();
+/// let value = postcard::to_stdvec(&12_i32).unwrap();
+/// assert_eq!(value_type.decode(&value), Some(DecodedValue::Integer(12)));
+/// let value = postcard::to_stdvec(&-3_i8).unwrap();
+/// assert_eq!(value_type.decode(&value), Some(DecodedValue::Integer(-3)));
+/// assert!(!ValueType::Float.validate(&value));
+/// assert_eq!(value_type_of(&schemas, "driver"), None);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Schema {
+    /// The keys this schema applies to.
+    pub key: Permission,
+    /// The type of the values.
+    #[serde(rename = "type")]
+    pub value_type: ValueType,
+}
+
+impl Schema {
+    /// Create a new `Schema` for the keys selected by `key`.
+    #[must_use]
+    pub const fn new(key: Permission, value_type: ValueType) -> Self {
+        Self { key, value_type }
+    }
+}
+
+/// Get the type of the values of `key` (the first matching schema applies).
+///
+/// Returns `None` if there is no schema for the `key`.
+#[must_use]
+pub fn value_type_of<'a>(
+    schemas: impl IntoIterator<Item = &'a Schema>,
+    key: &str,
+) -> Option<ValueType> {
+    schemas
+        .into_iter()
+        .find(|schema| schema.key.matches(key))
+        .map(|schema| schema.value_type)
+}
+
+/// The type of a value (as encoded by `Client::send_key_value`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ValueType {
+    /// An empty value (e.g. a postcard-encoded `()`).
+    Unit,
+    /// A postcard-encoded signed integer (`i8` to `i64`).
+    Integer,
+    /// A postcard-encoded unsigned integer (`u8` to `u64`).
+    Unsigned,
+    /// A postcard-encoded `f32` or `f64`.
+    Float,
+    /// A postcard-encoded string.
+    String,
+    /// A postcard-encoded string containing a JSON document.
+    Json,
+    /// A postcard-encoded byte sequence containing a CBOR document.
+    Cbor,
+}
+
+impl ValueType {
+    /// Check whether the `value` is of this type.
+    #[must_use]
+    pub fn validate(self, value: &[u8]) -> bool {
+        self.decode(value).is_some()
+    }
+
+    /// Decode a numeric `value` of this type (e.g. to aggregate it).
+    ///
+    /// Returns `None` if this type is not numeric or the `value` is not of this type.
+    ///
+    /// # Example
+    /// ```
+    /// use prellblock_client_api::schema::ValueType;
+    ///
+    /// let value = postcard::to_stdvec(&-42_i32).unwrap();
+    /// assert_eq!(ValueType::Integer.decode_number(&value), Some(-42.0));
+    /// let value = postcard::to_stdvec(&7_u16).unwrap();
+    /// assert_eq!(ValueType::Unsigned.decode_number(&value), Some(7.0));
+    /// assert_eq!(ValueType::Float.decode_number(&value), None);
+    /// assert_eq!(ValueType::String.decode_number(&value), None);
+    /// ```
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn decode_number(self, value: &[u8]) -> Option<f64> {
+        match self.decode(value)? {
+            DecodedValue::Integer(value) => Some(value as f64),
+            DecodedValue::Unsigned(value) => Some(value as f64),
+            DecodedValue::Float(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Decode a `value` of this type.
+    ///
+    /// Returns `None` if the `value` is not of this type.
+    #[must_use]
+    pub fn decode(self, value: &[u8]) -> Option<DecodedValue> {
+        let decoded = match self {
+            Self::Unit => {
+                if value.is_empty() {
+                    DecodedValue::Unit
+                } else {
+                    return None;
+                }
+            }
+            Self::Integer => DecodedValue::Integer(decode_signed(value)?),
+            Self::Unsigned => DecodedValue::Unsigned(decode_unsigned(value)?),
+            // The length of the value is the only difference between both encodings.
+            Self::Float => match value.len() {
+                4 => DecodedValue::Float(f64::from(decode_all::<f32>(value)?)),
+                8 => DecodedValue::Float(decode_all(value)?),
+                _ => return None,
+            },
+            Self::String => DecodedValue::String(decode_all(value)?),
+            Self::Json => {
+                let document: String = decode_all(value)?;
+                DecodedValue::Json(serde_json::from_str(&document).ok()?)
+            }
+            Self::Cbor => {
+                let document: Vec<u8> = decode_all(value)?;
+                DecodedValue::Cbor(serde_cbor::from_slice(&document).ok()?)
+            }
+        };
+        Some(decoded)
+    }
+}
+
+/// A value decoded according to its `ValueType`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DecodedValue {
+    /// An empty value.
+    Unit,
+    /// A signed integer.
+    Integer(i64),
+    /// An unsigned integer.
+    Unsigned(u64),
+    /// A floating point number.
+    Float(f64),
+    /// A string.
+    String(String),
+    /// A JSON document.
+    Json(serde_json::Value),
+    /// A CBOR document.
+    Cbor(serde_cbor::Value),
+}
+
+/// Decode a postcard-encoded signed integer (`i8` to `i64`, selected by the length of the `value`).
+pub(crate) fn decode_signed(value: &[u8]) -> Option<i64> {
+    match value.len() {
+        1 => decode_all::<i8>(value).map(i64::from),
+        2 => decode_all::<i16>(value).map(i64::from),
+        4 => decode_all::<i32>(value).map(i64::from),
+        8 => decode_all(value),
+        _ => None,
+    }
+}
+
+/// Decode a postcard-encoded unsigned integer (`u8` to `u64`, selected by the length of the `value`).
+pub(crate) fn decode_unsigned(value: &[u8]) -> Option<u64> {
+    match value.len() {
+        1 => decode_all::<u8>(value).map(u64::from),
+        2 => decode_all::<u16>(value).map(u64::from),
+        4 => decode_all::<u32>(value).map(u64::from),
+        8 => decode_all(value),
+        _ => None,
+    }
+}
+
+/// Decode a postcard-encoded `value` that must not contain any trailing bytes.
+pub(crate) fn decode_all<'a, T: Deserialize<'a>>(value: &'a [u8]) -> Option<T> {
+    match postcard::take_from_bytes(value) {
+        Ok((decoded, rest)) if rest.is_empty() => Some(decoded),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::account::Matching;
+
+    fn encode<T: Serialize>(value: &T) -> Vec<u8> {
+        postcard::to_stdvec(value).unwrap()
+    }
+
+    #[test]
+    fn integers_of_all_sizes_are_decoded() {
+        assert_eq!(
+            ValueType::Integer.decode(&encode(&-1_i8)),
+            Some(DecodedValue::Integer(-1))
+        );
+        assert_eq!(
+            ValueType::Integer.decode(&encode(&i64::MIN)),
+            Some(DecodedValue::Integer(i64::MIN))
+        );
+        assert_eq!(
+            ValueType::Unsigned.decode(&encode(&u32::MAX)),
+            Some(DecodedValue::Unsigned(u64::from(u32::MAX)))
+        );
+        // There is no 3 byte integer.
+        assert_eq!(ValueType::Integer.decode(&[0, 0, 0]), None);
+        assert_eq!(ValueType::Unsigned.decode(&[]), None);
+    }
+
+    #[test]
+    fn floats_of_both_sizes_are_decoded() {
+        assert_eq!(
+            ValueType::Float.decode(&encode(&0.5_f32)),
+            Some(DecodedValue::Float(0.5))
+        );
+        assert_eq!(
+            ValueType::Float.decode(&encode(&-2.5_f64)),
+            Some(DecodedValue::Float(-2.5))
+        );
+        assert!(!ValueType::Float.validate(&encode(&1_u16)));
+    }
+
+    #[test]
+    fn documents_must_be_valid() {
+        let json = encode(&r#"{"speed": 42}"#);
+        assert!(ValueType::Json.validate(&json));
+        assert!(ValueType::String.validate(&json));
+        assert!(!ValueType::Json.validate(&encode(&"{")));
+
+        let cbor = encode(&serde_cbor::to_vec(&("speed", 42)).unwrap());
+        assert!(ValueType::Cbor.validate(&cbor));
+        assert!(!ValueType::Cbor.validate(&encode(&vec![0xff_u8])));
+    }
+
+    #[test]
+    fn trailing_bytes_are_rejected() {
+        let mut value = encode(&"speed");
+        assert!(ValueType::String.validate(&value));
+        value.push(0);
+        assert!(!ValueType::String.validate(&value));
+
+        assert!(ValueType::Unit.validate(&encode(&())));
+        assert!(!ValueType::Unit.validate(&[0]));
+    }
+
+    #[test]
+    fn the_first_matching_schema_applies() {
+        let schemas = vec![
+            Schema::new(Permission::new("speed", Matching::Exact), ValueType::Float),
+            Schema::new(Permission::new("s", Matching::Prefix), ValueType::String),
+        ];
+        assert_eq!(value_type_of(&schemas, "speed"), Some(ValueType::Float));
+        assert_eq!(value_type_of(&schemas, "speed_2"), Some(ValueType::String));
+        assert_eq!(value_type_of(&schemas, "wagon"), None);
+    }
+}
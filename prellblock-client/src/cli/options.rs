@@ -7,6 +7,11 @@ pub struct Opt {
     pub private_key_file: String,
     /// The address of the receiving RPU's address.
     pub turi_address: SocketAddr,
+    /// Verify all read blocks and values, starting at the given genesis transactions file.
+    ///
+    /// The RPU is not trusted in this mode.
+    #[structopt(long)]
+    pub genesis_transactions: Option<String>,
     #[structopt(subcommand)]
     pub cmd: Cmd,
 }
@@ -16,6 +21,12 @@ pub struct Opt {
 pub enum Cmd {
     /// Set a single key value pair.
     Set(cmd::Set),
+    /// Set a key to the content of a file (stored off-chain as blob).
+    #[structopt(name = "put_blob")]
+    PutBlob(cmd::PutBlob),
+    /// Encrypt the values of the keys matching a scope for the given readers.
+    #[structopt(name = "share_key")]
+    ShareKey(cmd::ShareKey),
     /// Run a benchmark.
     #[structopt(name = "bench")]
     Benchmark(cmd::Benchmark),
@@ -28,26 +39,67 @@ pub enum Cmd {
     /// Delete an account.
     #[structopt(name = "delete_account")]
     DeleteAccount(cmd::DeleteAccount),
+    /// Set the number of admins needed to manage accounts.
+    #[structopt(name = "set_admin_threshold")]
+    SetAdminThreshold(cmd::SetAdminThreshold),
+    /// Rotate the key of an account (its values stay readable by both IDs).
+    #[structopt(name = "rotate_key")]
+    RotateKey(cmd::RotateKey),
+    /// Set the type of the values of the keys matching a scope.
+    #[structopt(name = "set_schema")]
+    SetSchema(cmd::SetSchema),
+    /// Remove the schema of the keys matching a scope.
+    #[structopt(name = "delete_schema")]
+    DeleteSchema(cmd::DeleteSchema),
+    /// Get all schemas.
+    #[structopt(name = "get_schemas")]
+    GetSchemas,
+    /// Approve a proposed account management transaction.
+    #[structopt(name = "approve")]
+    Approve(cmd::Approve),
     /// Get values from the blockchain.
     ///
     /// Specifying only a filter returns the last recorded value.
     #[structopt(name = "get_value")]
     GetValue(cmd::GetValue),
+    /// List the keys an account has written.
+    #[structopt(name = "list_keys")]
+    ListKeys(cmd::ListKeys),
+    /// Download a blob into a file.
+    #[structopt(name = "get_blob")]
+    GetBlob(cmd::GetBlob),
     /// Get accounts from the blockchain.
     #[structopt(name = "get_account")]
     GetAccount(cmd::GetAccount),
+    /// List all accounts matching the given filters.
+    #[structopt(name = "list_accounts")]
+    ListAccounts(cmd::ListAccounts),
+    /// Look up the IDs of the accounts with the given name.
+    #[structopt(name = "find_account")]
+    FindAccount(cmd::FindAccount),
     /// Get blocks from the blockchain.
     #[structopt(name = "get_block")]
     GetBlock(cmd::GetBlock),
     /// Get the current block number (that is going to be committed).
     #[structopt(name = "current_block_number")]
     CurrentBlockNumber,
+    /// Get the status of a transaction.
+    #[structopt(name = "transaction_status")]
+    TransactionStatus(cmd::TransactionStatus),
+    /// Print newly committed values (and blocks) until interrupted.
+    Subscribe(cmd::Subscribe),
 }
 
 pub mod cmd {
     use pinxit::PeerId;
-    use prellblock_client::{consensus::BlockNumber, Filter, Span};
-    use std::str::FromStr;
+    use prellblock_client::{
+        account::{AccountKind, Matching},
+        blob::BlobHash,
+        consensus::{BlockNumber, TransactionHash},
+        schema::ValueType,
+        Aggregation, Filter, Span,
+    };
+    use std::{str::FromStr, time::Duration};
     use structopt::StructOpt;
 
     /// Transaction to set a key to a value.
@@ -57,6 +109,33 @@ pub mod cmd {
         pub key: String,
         /// The value of the corresponding key.
         pub value: String,
+        /// Wait until the transaction is committed (at most this duration, e.g. `10s`).
+        #[structopt(long, parse(try_from_str = humantime::parse_duration))]
+        pub wait: Option<Duration>,
+        /// Write into the namespace of this account (requires being one of its delegates).
+        #[structopt(long)]
+        pub owner: Option<PeerId>,
+    }
+
+    /// Transaction to set a key to a blob.
+    #[derive(StructOpt, Debug)]
+    pub struct PutBlob {
+        /// The key of this transaction.
+        pub key: String,
+        /// The path of the file to upload.
+        pub file: String,
+    }
+
+    /// Share a new content key for the keys matching a scope.
+    #[derive(StructOpt, Debug)]
+    pub struct ShareKey {
+        /// The scope selecting the keys.
+        pub scope: String,
+        /// The accounts that shall be able to decrypt the values.
+        pub readers: Vec<PeerId>,
+        /// How keys are matched against the scope (exact, prefix or glob).
+        #[structopt(long = "match", default_value = "exact")]
+        pub matching: ParseMatching,
     }
 
     /// Benchmark the blockchain.
@@ -81,6 +160,9 @@ pub mod cmd {
         pub peer_id: String,
         /// The filepath to a yaml-file cotaining the accounts permissions.
         pub permission_file: String,
+        /// Propose the transaction to be approved by other admins.
+        #[structopt(long)]
+        pub propose: bool,
     }
 
     /// Create a new account.
@@ -92,6 +174,9 @@ pub mod cmd {
         pub name: String,
         /// The filepath to a yaml-file cotaining the accounts permissions.
         pub permission_file: String,
+        /// Propose the transaction to be approved by other admins.
+        #[structopt(long)]
+        pub propose: bool,
     }
 
     /// Delete an account.
@@ -99,6 +184,66 @@ pub mod cmd {
     pub struct DeleteAccount {
         /// The public key of the account to delete.
         pub peer_id: String,
+        /// Propose the transaction to be approved by other admins.
+        #[structopt(long)]
+        pub propose: bool,
+    }
+
+    /// Set the number of admins needed to manage accounts.
+    #[derive(StructOpt, Debug)]
+    pub struct SetAdminThreshold {
+        /// The number of admin approvals needed.
+        pub threshold: usize,
+        /// Propose the transaction to be approved by other admins.
+        #[structopt(long)]
+        pub propose: bool,
+    }
+
+    /// Rotate the key of an account.
+    #[derive(StructOpt, Debug)]
+    pub struct RotateKey {
+        /// The current public key of the account.
+        pub peer_id: PeerId,
+        /// The private key file path of the new key (it has to sign the rotation).
+        pub new_private_key_file: String,
+        /// Propose the transaction to be approved by other admins.
+        #[structopt(long)]
+        pub propose: bool,
+    }
+
+    /// Set the type of the values of the keys matching a scope.
+    #[derive(StructOpt, Debug)]
+    pub struct SetSchema {
+        /// The scope selecting the keys.
+        pub scope: String,
+        /// The type of the values (unit, integer, unsigned, float, string, json or cbor).
+        pub value_type: ParseValueType,
+        /// How keys are matched against the scope (exact, prefix or glob).
+        #[structopt(long = "match", default_value = "exact")]
+        pub matching: ParseMatching,
+        /// Propose the transaction to be approved by other admins.
+        #[structopt(long)]
+        pub propose: bool,
+    }
+
+    /// Remove the schema of the keys matching a scope.
+    #[derive(StructOpt, Debug)]
+    pub struct DeleteSchema {
+        /// The scope of the schema.
+        pub scope: String,
+        /// How keys are matched against the scope (exact, prefix or glob).
+        #[structopt(long = "match", default_value = "exact")]
+        pub matching: ParseMatching,
+        /// Propose the transaction to be approved by other admins.
+        #[structopt(long)]
+        pub propose: bool,
+    }
+
+    /// Approve a proposed account management transaction.
+    #[derive(StructOpt, Debug)]
+    pub struct Approve {
+        /// The hash of the proposing transaction.
+        pub proposal: TransactionHash,
     }
 
     /// Update the permissions for a given account.
@@ -125,6 +270,32 @@ pub mod cmd {
         /// Valid examples are: 1 (skip every second value), 200ms (always skip 200ms).
         /// Dates won't be accepted.
         pub skip: Option<ParseSpan>,
+        /// Aggregate the values in buckets (min, max, mean, count, first or last).
+        #[structopt(long, requires = "bucket", conflicts_with = "skip")]
+        pub aggregate: Option<ParseAggregation>,
+        /// The duration of a bucket when aggregating (e.g. 1min).
+        #[structopt(long, parse(try_from_str = humantime::parse_duration))]
+        pub bucket: Option<Duration>,
+    }
+
+    /// List the keys an account has written.
+    #[derive(StructOpt, Debug)]
+    pub struct ListKeys {
+        /// The `PeerId` of the account.
+        pub peer_id: PeerId,
+    }
+
+    /// Download a blob into a file.
+    #[derive(StructOpt, Debug)]
+    pub struct GetBlob {
+        /// The `PeerId` of the account that has written the blob.
+        pub peer_id: PeerId,
+        /// The key the blob was written to.
+        pub key: String,
+        /// The hash of the blob (as printed by `put_blob`).
+        pub hash: BlobHash,
+        /// The path of the file to write.
+        pub file: String,
     }
 
     /// Update the permissions for a given account.
@@ -134,6 +305,30 @@ pub mod cmd {
         pub peer_ids: Vec<PeerId>,
     }
 
+    /// List all accounts matching the given filters.
+    #[derive(StructOpt, Debug)]
+    pub struct ListAccounts {
+        /// Only list accounts of this type (normal, block_reader, rpu or admin).
+        #[structopt(long = "type")]
+        pub kind: Option<ParseAccountKind>,
+        /// Only list accounts with a name starting with this prefix.
+        #[structopt(long)]
+        pub name_prefix: Option<String>,
+        /// Only list expired accounts.
+        #[structopt(long, conflicts_with = "active")]
+        pub expired: bool,
+        /// Only list active (not expired) accounts.
+        #[structopt(long)]
+        pub active: bool,
+    }
+
+    /// Look up the IDs of the accounts with the given name.
+    #[derive(StructOpt, Debug)]
+    pub struct FindAccount {
+        /// The name of the account.
+        pub name: String,
+    }
+
     /// Update the permissions for a given account.
     #[derive(StructOpt, Debug)]
     pub struct GetBlock {
@@ -144,6 +339,26 @@ pub mod cmd {
         pub filter: ParseFilter<BlockNumber>,
     }
 
+    /// Get the status of a transaction.
+    #[derive(StructOpt, Debug)]
+    pub struct TransactionStatus {
+        /// The hash of the transaction (printed when sending the transaction).
+        pub transaction_hash: TransactionHash,
+    }
+
+    /// Subscribe to newly committed values.
+    #[derive(StructOpt, Debug)]
+    pub struct Subscribe {
+        /// The `PeerId` to receive values from.
+        pub peer_id: PeerId,
+        /// A filter to select keys.
+        #[structopt(default_value = "..")]
+        pub filter: ParseFilter<String>,
+        /// Also print each committed block.
+        #[structopt(long)]
+        pub blocks: bool,
+    }
+
     #[derive(Debug)]
     pub struct ParseFilter<T>(pub Filter<T>);
 
@@ -191,6 +406,78 @@ pub mod cmd {
         }
     }
 
+    #[derive(Debug)]
+    pub struct ParseValueType(pub ValueType);
+
+    impl FromStr for ParseValueType {
+        type Err = String;
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            let value_type = match s {
+                "unit" => ValueType::Unit,
+                "integer" => ValueType::Integer,
+                "unsigned" => ValueType::Unsigned,
+                "float" => ValueType::Float,
+                "string" => ValueType::String,
+                "json" => ValueType::Json,
+                "cbor" => ValueType::Cbor,
+                _ => return Err(format!("Unknown value type {:?}", s)),
+            };
+            Ok(Self(value_type))
+        }
+    }
+
+    #[derive(Debug)]
+    pub struct ParseMatching(pub Matching);
+
+    impl FromStr for ParseMatching {
+        type Err = String;
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            let matching = match s {
+                "exact" => Matching::Exact,
+                "prefix" => Matching::Prefix,
+                "glob" => Matching::Glob,
+                _ => return Err(format!("Unknown matching {:?}", s)),
+            };
+            Ok(Self(matching))
+        }
+    }
+
+    #[derive(Debug)]
+    pub struct ParseAggregation(pub Aggregation);
+
+    impl FromStr for ParseAggregation {
+        type Err = String;
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            let aggregation = match s {
+                "min" => Aggregation::Min,
+                "max" => Aggregation::Max,
+                "mean" => Aggregation::Mean,
+                "count" => Aggregation::Count,
+                "first" => Aggregation::First,
+                "last" => Aggregation::Last,
+                _ => return Err(format!("Unknown aggregation {:?}", s)),
+            };
+            Ok(Self(aggregation))
+        }
+    }
+
+    #[derive(Debug)]
+    pub struct ParseAccountKind(pub AccountKind);
+
+    impl FromStr for ParseAccountKind {
+        type Err = String;
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            let kind = match s {
+                "normal" => AccountKind::Normal,
+                "block_reader" => AccountKind::BlockReader,
+                "rpu" => AccountKind::RPU,
+                "admin" => AccountKind::Admin,
+                _ => return Err(format!("Unknown account type {:?}", s)),
+            };
+            Ok(Self(kind))
+        }
+    }
+
     #[derive(Debug)]
     pub struct ParseSpan(pub Span);
 
@@ -2,17 +2,36 @@
 
 #![allow(clippy::future_not_send)]
 
-use balise::{client, Error};
+use crate::{BlockPages, ValuePages, VerificationError, Verifier};
+use balise::{
+    client::{self, Subscription},
+    Error,
+};
 use newtype_enum::{Enum, Variant};
 use pinxit::{Identity, PeerId, Signable, Signed};
 use prellblock_client_api::{
-    account::{Account, Permissions},
-    consensus::{Block, BlockNumber},
-    message, transaction, ClientMessage, Filter, GetAccount, GetBlock, GetCurrentBlockNumber,
-    GetValue, Query, ReadValues, Transaction,
+    account::{Account, Permission, Permissions},
+    blob::{BlobHash, BLOB_CHUNK_SIZE},
+    consensus::{Block, BlockNumber, GenesisTransactions, TransactionHash},
+    encryption::{self, ContentKey, ContentKeyGrant, ContentKeyId, EncryptedValue},
+    message,
+    schema::{Schema, ValueType},
+    transaction, AggregateQuery, Aggregates, ClientMessage, Cursor, Filter, GetAccount,
+    GetAggregate, GetBlob, GetBlock, GetContentKeys, GetCurrentBlockNumber, GetSchemas,
+    GetTransactionStatus, GetValue, GetValueProof, GetValueProofs, KeyInfo, ListAccounts, ListKeys,
+    Page, PutBlob, Query, ReadValues, Subscribe, SubscriptionEvent, Transaction,
+    TransactionReceipt, TransactionStatus, ValueProof, MAX_VALUE_PROOFS,
 };
 use serde::Serialize;
-use std::{net::SocketAddr, time::SystemTime};
+use std::{
+    collections::HashMap,
+    mem,
+    net::SocketAddr,
+    time::{Duration, SystemTime},
+};
+
+/// The number of `Block`s requested at once when verifying the chain.
+const SYNC_BATCH_SIZE: u64 = 100;
 
 /// A Client Instance.
 ///
@@ -33,6 +52,17 @@ use std::{net::SocketAddr, time::SystemTime};
 pub struct Client {
     rpu_client: client::Client<ClientMessage>,
     identity: Identity,
+    verifier: Option<Verifier>,
+    /// The content keys shared by other accounts (for decrypting, refreshed on unknown keys).
+    content_keys: HashMap<PeerId, ContentKeys>,
+}
+
+/// The content keys shared by an account.
+struct ContentKeys {
+    /// All grants of the account in the order they were shared.
+    grants: Vec<ContentKeyGrant>,
+    /// The keys that were shared with this client.
+    keys: HashMap<ContentKeyId, ContentKey>,
 }
 
 impl Client {
@@ -45,6 +75,43 @@ impl Client {
         Self {
             rpu_client: client::Client::new(turi_address),
             identity,
+            verifier: None,
+            content_keys: HashMap::new(),
+        }
+    }
+
+    /// Create a new client that verifies all read `Block`s and values.
+    ///
+    /// The RPU at `turi_address` is not trusted. Instead, all `Block`s starting at the
+    /// `genesis_transactions` are verified to be signed by a supermajority of RPUs.
+    /// Read values are verified via a `ValueProof` each.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use prellblock_client::{consensus::GenesisTransactions, Client};
+    ///
+    /// # async fn test() -> Result<(), Box<dyn std::error::Error>> {
+    /// let identity = "03d738c972f37a6fd9b33278ac0c50236e45637bcd5aeee82d8323655257d256".parse()?;
+    /// let genesis_transactions: GenesisTransactions =
+    ///     serde_yaml::from_str(&std::fs::read_to_string("config/genesis/genesis.yaml")?)?;
+    /// let mut client =
+    ///     Client::new_verifying("10.10.10.10:2480".parse().unwrap(), identity, genesis_transactions);
+    /// let blocks = client.query_block(..).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[must_use]
+    pub fn new_verifying(
+        turi_address: SocketAddr,
+        identity: Identity,
+        genesis_transactions: GenesisTransactions,
+    ) -> Self {
+        Self {
+            rpu_client: client::Client::new(turi_address),
+            identity,
+            verifier: Some(Verifier::new(genesis_transactions)),
+            content_keys: HashMap::new(),
         }
     }
 
@@ -58,7 +125,9 @@ impl Client {
     }
 
     /// Execute a transaction.
-    async fn execute<T>(&mut self, transaction: T) -> Result<(), Error>
+    ///
+    /// Returns the `TransactionHash` of the sent transaction.
+    async fn execute<T>(&mut self, transaction: T) -> Result<TransactionHash, Error>
     where
         T: Variant<Transaction> + Send,
     {
@@ -68,12 +137,35 @@ impl Client {
             .await
     }
 
+    /// Execute a transaction and wait until it is committed.
+    ///
+    /// Fails if the transaction got rejected or was not committed within `timeout`.
+    async fn execute_and_wait<T>(
+        &mut self,
+        transaction: T,
+        timeout: Duration,
+    ) -> Result<TransactionReceipt, Error>
+    where
+        T: Variant<Transaction> + Send,
+    {
+        let transaction = Transaction::from_variant(transaction);
+        self.rpu_client
+            .send_request(message::ExecuteAndWait(self.sign(transaction)?, timeout))
+            .await
+    }
+
     /// Send a key-value transaction.
-    pub async fn send_key_value<V>(&mut self, key: String, value: V) -> Result<(), Error>
+    pub async fn send_key_value<V>(
+        &mut self,
+        key: String,
+        value: V,
+    ) -> Result<TransactionHash, Error>
     where
         V: Serialize + Send,
     {
         let value = postcard::to_stdvec(&value)?;
+        let owner = self.identity.id().clone();
+        let value = self.encrypt_value(&owner, &key, value).await?;
         self.execute(transaction::KeyValue {
             key,
             value,
@@ -82,12 +174,317 @@ impl Client {
         .await
     }
 
+    /// Send a key-value transaction and wait until it is committed.
+    ///
+    /// The RPU caps the `timeout` to a maximum of one minute.
+    pub async fn send_key_value_and_wait<V>(
+        &mut self,
+        key: String,
+        value: V,
+        timeout: Duration,
+    ) -> Result<TransactionReceipt, Error>
+    where
+        V: Serialize + Send,
+    {
+        let value = postcard::to_stdvec(&value)?;
+        let owner = self.identity.id().clone();
+        let value = self.encrypt_value(&owner, &key, value).await?;
+        self.execute_and_wait(
+            transaction::KeyValue {
+                key,
+                value,
+                timestamp: SystemTime::now(),
+            },
+            timeout,
+        )
+        .await
+    }
+
+    /// Send a key-value transaction into the namespace of the `owner` account.
+    ///
+    /// This account must be one of the `owner`'s delegates.
+    pub async fn send_delegated_key_value<V>(
+        &mut self,
+        owner: PeerId,
+        key: String,
+        value: V,
+    ) -> Result<TransactionHash, Error>
+    where
+        V: Serialize + Send,
+    {
+        let value = postcard::to_stdvec(&value)?;
+        let value = self.encrypt_value(&owner, &key, value).await?;
+        self.execute(transaction::DelegatedKeyValue {
+            owner,
+            key,
+            value,
+            timestamp: SystemTime::now(),
+        })
+        .await
+    }
+
+    /// Send a key-value transaction into the namespace of the `owner` account
+    /// and wait until it is committed.
+    ///
+    /// The RPU caps the `timeout` to a maximum of one minute.
+    pub async fn send_delegated_key_value_and_wait<V>(
+        &mut self,
+        owner: PeerId,
+        key: String,
+        value: V,
+        timeout: Duration,
+    ) -> Result<TransactionReceipt, Error>
+    where
+        V: Serialize + Send,
+    {
+        let value = postcard::to_stdvec(&value)?;
+        let value = self.encrypt_value(&owner, &key, value).await?;
+        self.execute_and_wait(
+            transaction::DelegatedKeyValue {
+                owner,
+                key,
+                value,
+                timestamp: SystemTime::now(),
+            },
+            timeout,
+        )
+        .await
+    }
+
+    /// Share a new content key encrypting the values of the keys selected by `scope`.
+    ///
+    /// Values written afterwards (by this client) are encrypted, the `readers` can decrypt them.
+    /// The key is also shared with this account. Sharing a new key for the same `scope`
+    /// with fewer readers prevents the removed readers from reading new values.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use prellblock_client::Client;
+    /// use prellblock_client::account::{Matching, Permission};
+    ///
+    /// # async fn test(client: &mut Client)  -> Result<(), Box<dyn std::error::Error>>{
+    /// let reader = "4242424242424242424242424242424242424242424242424242424242424242".parse()?;
+    /// let scope = Permission::new("diagnostics_", Matching::Prefix);
+    /// client.share_content_key(scope, vec![reader]).await?;
+    /// client.send_key_value("diagnostics_engine".to_string(), 42).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn share_content_key(
+        &mut self,
+        scope: Permission,
+        readers: Vec<PeerId>,
+    ) -> Result<TransactionHash, Error> {
+        let content_key = ContentKey::generate();
+        let mut wrapped_keys = Vec::with_capacity(readers.len() + 1);
+        wrapped_keys.push(self.wrap_content_key(&content_key, self.identity.id())?);
+        for reader in readers
+            .iter()
+            .filter(|reader| *reader != self.identity.id())
+        {
+            wrapped_keys.push(self.wrap_content_key(&content_key, reader)?);
+        }
+
+        let transaction_hash = self
+            .execute(transaction::ShareContentKey {
+                scope,
+                key_id: content_key.id(),
+                readers: wrapped_keys,
+                timestamp: SystemTime::now(),
+            })
+            .await?;
+
+        Ok(transaction_hash)
+    }
+
+    fn wrap_content_key(
+        &self,
+        content_key: &ContentKey,
+        reader: &PeerId,
+    ) -> Result<encryption::WrappedKey, Error> {
+        content_key
+            .wrap_for(&self.identity, reader)
+            .map_err(|err| Error::BoxError(err.into()))
+    }
+
+    /// Get the content keys shared by the `owner` (fetched from the RPU if not cached).
+    async fn shared_content_keys(
+        &mut self,
+        owner: &PeerId,
+        refresh: bool,
+    ) -> Result<&ContentKeys, Error> {
+        if refresh || !self.content_keys.contains_key(owner) {
+            let message = GetContentKeys {
+                owner: owner.clone(),
+            };
+            let grants: Vec<ContentKeyGrant> = self
+                .rpu_client
+                .send_request(message::GetContentKeys(self.sign(message)?))
+                .await?;
+            let keys = grants
+                .iter()
+                .filter_map(|grant| Some((grant.key_id, grant.unwrap(&self.identity)?)))
+                .collect();
+            self.content_keys
+                .insert(owner.clone(), ContentKeys { grants, keys });
+        }
+        Ok(&self.content_keys[owner])
+    }
+
+    /// Encrypt a `value` of the `owner`'s `key` if the values of the `key` are encrypted.
+    ///
+    /// The grants are fetched before each write, so the value is always encrypted
+    /// with the current content key of the `key` (the RPUs reject any other).
+    async fn encrypt_value(
+        &mut self,
+        owner: &PeerId,
+        key: &str,
+        value: Vec<u8>,
+    ) -> Result<Vec<u8>, Error> {
+        let content_keys = self.shared_content_keys(owner, true).await?;
+        match encryption::current_grant(&content_keys.grants, key) {
+            None => Ok(value),
+            Some(grant) => match content_keys.keys.get(&grant.key_id) {
+                Some(content_key) => Ok(content_key.encrypt(&value).to_bytes()),
+                None => Err(Error::BoxError(
+                    format!(
+                        "The content key {} for {:?} was not shared with this account.",
+                        grant.key_id, key
+                    )
+                    .into(),
+                )),
+            },
+        }
+    }
+
+    /// Decrypt a `value` of `peer_id`'s `key` (e.g. of a `SubscriptionEvent`).
+    ///
+    /// Values that are not encrypted or cannot be decrypted are returned unchanged.
+    pub async fn decrypt_value(
+        &mut self,
+        peer_id: &PeerId,
+        key: &str,
+        value: Vec<u8>,
+    ) -> Result<Vec<u8>, Error> {
+        let encrypted = match EncryptedValue::from_bytes(&value) {
+            Some(encrypted) => encrypted,
+            None => return Ok(value),
+        };
+
+        // The key could have been shared after the grants were fetched.
+        let content_keys = self.shared_content_keys(peer_id, false).await?;
+        let refresh = !content_keys.keys.contains_key(&encrypted.key_id);
+        let content_keys = self.shared_content_keys(peer_id, refresh).await?;
+        if !content_keys
+            .grants
+            .iter()
+            .any(|grant| grant.scope.matches(key))
+        {
+            return Ok(value);
+        }
+        Ok(content_keys
+            .keys
+            .get(&encrypted.key_id)
+            .and_then(|content_key| content_key.decrypt(&encrypted))
+            .unwrap_or(value))
+    }
+
+    /// Upload a blob and send a transaction setting `key` to it.
+    ///
+    /// Only the `BlobHash` and the size of the blob are stored on the blockchain.
+    /// The blob is uploaded in chunks of `BLOB_CHUNK_SIZE` bytes.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use prellblock_client::Client;
+    /// # async fn test(client: &mut Client)  -> Result<(), Box<dyn std::error::Error>>{
+    /// let recording = std::fs::read("recording.mp4")?;
+    /// client.send_blob("camera".to_string(), recording).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn send_blob(
+        &mut self,
+        key: String,
+        data: Vec<u8>,
+    ) -> Result<TransactionHash, Error> {
+        let size = data.len() as u64;
+        let hash = BlobHash::of(&data);
+        let mut chunks: Vec<&[u8]> = data.chunks(BLOB_CHUNK_SIZE as usize).collect();
+        if chunks.is_empty() {
+            // An empty blob is uploaded as one empty chunk.
+            chunks.push(&[]);
+        }
+        let mut complete = false;
+        for (index, chunk) in chunks.into_iter().enumerate() {
+            complete = self
+                .rpu_client
+                .send_request(message::PutBlob(self.sign(PutBlob {
+                    hash,
+                    size,
+                    offset: index as u64 * BLOB_CHUNK_SIZE,
+                    data: chunk.to_vec(),
+                })?))
+                .await?;
+        }
+        if !complete {
+            return Err(Error::BoxError(
+                format!("The RPU did not store blob {}.", hash).into(),
+            ));
+        }
+
+        self.execute(transaction::Blob {
+            key,
+            hash,
+            size,
+            timestamp: SystemTime::now(),
+        })
+        .await
+    }
+
+    /// Read the blob written to `peer_id`'s `key` with the given `hash`.
+    ///
+    /// The blob is read in chunks and verified against its `hash`.
+    /// Read the `BlobRef` written as value of the `key` to get the `hash`.
+    pub async fn query_blob(
+        &mut self,
+        peer_id: PeerId,
+        key: String,
+        hash: BlobHash,
+    ) -> Result<Vec<u8>, Error> {
+        let mut data = Vec::new();
+        loop {
+            let message = GetBlob {
+                peer_id: peer_id.clone(),
+                key: key.clone(),
+                hash,
+                offset: data.len() as u64,
+            };
+            let chunk = self
+                .rpu_client
+                .send_request(message::GetBlob(self.sign(message)?))
+                .await?;
+            data.extend_from_slice(&chunk.data);
+            if chunk.data.is_empty() || data.len() as u64 >= chunk.size {
+                break;
+            }
+        }
+
+        if BlobHash::of(&data) == hash {
+            Ok(data)
+        } else {
+            Err(Error::BoxError(
+                format!("The RPU sent an invalid payload of blob {}.", hash).into(),
+            ))
+        }
+    }
+
     /// Update a `target` account's `permissions`.
     pub async fn update_account(
         &mut self,
         target: PeerId,
         permissions: Permissions,
-    ) -> Result<(), Error> {
+    ) -> Result<TransactionHash, Error> {
         self.execute(transaction::UpdateAccount {
             id: target,
             permissions,
@@ -102,7 +499,7 @@ impl Client {
         account: PeerId,
         name: String,
         permissions: Permissions,
-    ) -> Result<(), Error> {
+    ) -> Result<TransactionHash, Error> {
         self.execute(transaction::CreateAccount {
             id: account,
             name,
@@ -113,7 +510,7 @@ impl Client {
     }
 
     /// Delete an account.
-    pub async fn delete_account(&mut self, account: PeerId) -> Result<(), Error> {
+    pub async fn delete_account(&mut self, account: PeerId) -> Result<TransactionHash, Error> {
         self.execute(transaction::DeleteAccount {
             id: account,
             timestamp: SystemTime::now(),
@@ -121,6 +518,124 @@ impl Client {
         .await
     }
 
+    /// Rotate the key of an account to the key of the `new_identity`.
+    ///
+    /// This can be done by the account itself (signed with the old key) or by an admin.
+    /// The values of the account can still be queried by both IDs.
+    pub async fn rotate_key(
+        &mut self,
+        account: PeerId,
+        new_identity: &Identity,
+    ) -> Result<TransactionHash, Error> {
+        let transaction = transaction::RotateKey::new(account, new_identity)
+            .map_err(|err| Error::BoxError(err.into()))?;
+        self.execute(transaction).await
+    }
+
+    /// Set the number of admins needed to manage accounts.
+    ///
+    /// If more than one admin is already needed, use `propose_account_change` instead.
+    pub async fn set_admin_threshold(
+        &mut self,
+        threshold: usize,
+    ) -> Result<TransactionHash, Error> {
+        self.execute(transaction::SetAdminThreshold {
+            threshold,
+            timestamp: SystemTime::now(),
+        })
+        .await
+    }
+
+    /// Set the schema of the keys selected by `key` (`None` removes the schema).
+    ///
+    /// If more than one admin is needed, use `propose_account_change` instead.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use prellblock_client::Client;
+    /// use prellblock_client::{
+    ///     account::{Matching, Permission},
+    ///     schema::ValueType,
+    /// };
+    ///
+    /// # async fn test(client: &mut Client)  -> Result<(), Box<dyn std::error::Error>>{
+    /// let key = Permission::new("speed", Matching::Exact);
+    /// client.set_schema(key, Some(ValueType::Float)).await?;
+    /// // Only floats can be written to `speed` from now on.
+    /// client.send_key_value("speed".to_string(), 42.0).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn set_schema(
+        &mut self,
+        key: Permission,
+        value_type: Option<ValueType>,
+    ) -> Result<TransactionHash, Error> {
+        self.execute(transaction::SetSchema {
+            key,
+            value_type,
+            timestamp: SystemTime::now(),
+        })
+        .await
+    }
+
+    /// Get all schemas (in the order they are matched against keys).
+    ///
+    /// Use `schema::value_type_of` to find the type of a key and decode its values.
+    pub async fn query_schemas(&mut self) -> Result<Vec<Schema>, Error> {
+        self.rpu_client
+            .send_request(message::GetSchemas(self.sign(GetSchemas)?))
+            .await
+    }
+
+    /// Propose an account management transaction that needs the approval of multiple admins.
+    ///
+    /// Returns the `TransactionHash` identifying the proposal.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use prellblock_client::Client;
+    /// use prellblock_client::transaction;
+    /// use std::time::SystemTime;
+    ///
+    /// # async fn test(client: &mut Client)  -> Result<(), Box<dyn std::error::Error>>{
+    /// let peer_id = "4242424242424242424242424242424242424242424242424242424242424242".parse()?;
+    /// let proposal = client
+    ///     .propose_account_change(transaction::DeleteAccount {
+    ///         id: peer_id,
+    ///         timestamp: SystemTime::now(),
+    ///     })
+    ///     .await?;
+    /// // Other admins approve the proposal via `client.approve_account_change(proposal)`.
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn propose_account_change<T>(
+        &mut self,
+        operation: T,
+    ) -> Result<TransactionHash, Error>
+    where
+        T: Variant<Transaction> + Send,
+    {
+        self.execute(transaction::ProposeAccountChange {
+            operation: Box::new(Transaction::from_variant(operation)),
+            timestamp: SystemTime::now(),
+        })
+        .await
+    }
+
+    /// Approve a proposed account management transaction.
+    pub async fn approve_account_change(
+        &mut self,
+        proposal: TransactionHash,
+    ) -> Result<TransactionHash, Error> {
+        self.execute(transaction::ApproveAccountChange {
+            proposal,
+            timestamp: SystemTime::now(),
+        })
+        .await
+    }
+
     /// Query one or multiple accounts.
     ///
     /// All accounts `Accounts` matching the `peer_ids` will be returned.
@@ -132,6 +647,35 @@ impl Client {
             .await
     }
 
+    /// List all accounts matching the `filter` together with their `PeerId`s.
+    ///
+    /// This can be used to look up accounts by their name.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use prellblock_client::Client;
+    /// use prellblock_client::ListAccounts;
+    ///
+    /// # async fn test(client: &mut Client)  -> Result<(), Box<dyn std::error::Error>>{
+    /// let filter = ListAccounts {
+    ///     name: Some("ICE 4711".to_string()),
+    ///     ..ListAccounts::default()
+    /// };
+    /// for (peer_id, account) in client.list_accounts(filter).await? {
+    ///     println!("{}: {:?}", peer_id, account);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn list_accounts(
+        &mut self,
+        filter: ListAccounts,
+    ) -> Result<Vec<(PeerId, Account)>, Error> {
+        self.rpu_client
+            .send_request(message::ListAccounts(self.sign(filter)?))
+            .await
+    }
+
     /// Query the value(s) of specific key-value pairs.
     ///
     /// If the requested data in `filter` or `query` does not exist in a account,
@@ -161,14 +705,151 @@ impl Client {
         filter: impl Into<Filter<String>>,
         query: Query,
     ) -> Result<ReadValues, Error> {
+        let mut values = ReadValues::new();
+        let mut pages = self.value_pages(peer_ids, filter, query);
+        while let Some(page) = pages.next().await {
+            for (peer_id, values_of_peer) in page? {
+                let merged_values_of_peer = values.entry(peer_id).or_default();
+                for (key, values_by_key) in values_of_peer {
+                    merged_values_of_peer
+                        .entry(key)
+                        .or_default()
+                        .extend(values_by_key);
+                }
+            }
+        }
+
+        Ok(values)
+    }
+
+    /// Query the values page by page.
+    ///
+    /// This is the same as `query_values`, but only one page is held in memory at once.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use prellblock_client::{Client, Query};
+    /// # async fn test(client: &mut Client)  -> Result<(), Box<dyn std::error::Error>>{
+    /// let peer_id = "4242424242424242424242424242424242424242424242424242424242424242".parse()?;
+    /// let mut pages = client.value_pages(vec![peer_id], "speed".to_string(), Query::AllValues);
+    /// while let Some(values) = pages.next().await {
+    ///     println!("{:?}", values?);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn value_pages(
+        &mut self,
+        peer_ids: Vec<PeerId>,
+        filter: impl Into<Filter<String>>,
+        query: Query,
+    ) -> ValuePages<'_> {
+        ValuePages::new(self, peer_ids, filter.into(), query)
+    }
+
+    /// Query a single page of values.
+    ///
+    /// Pass `None` as `cursor` to get the first page and the `next` cursor
+    /// of the previous page to get the following ones.
+    pub async fn query_values_page(
+        &mut self,
+        peer_ids: Vec<PeerId>,
+        filter: impl Into<Filter<String>>,
+        query: Query,
+        cursor: Option<Cursor>,
+    ) -> Result<Page<ReadValues>, Error> {
         let message = GetValue {
             peer_ids,
             filter: filter.into(),
             query,
+            cursor,
         };
-        self.rpu_client
+        let mut page = self
+            .rpu_client
             .send_request(message::GetValue(self.sign(message)?))
-            .await
+            .await?;
+
+        if self.verifier.is_some() {
+            self.verify_values(&page.items).await?;
+        }
+
+        // Values are decrypted after they were verified.
+        for (peer_id, values_of_peer) in &mut page.items {
+            for (key, values_by_key) in values_of_peer {
+                for (value, _, _) in values_by_key.values_mut() {
+                    *value = self.decrypt_value(peer_id, key, mem::take(value)).await?;
+                }
+            }
+        }
+
+        Ok(page)
+    }
+
+    /// Aggregate the values of specific keys in buckets (on the RPU).
+    ///
+    /// Only keys with a numeric schema are aggregated. Aggregated values are not signed,
+    /// so they cannot be verified and are not available in verifying mode.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use prellblock_client::Client;
+    /// use prellblock_client::{AggregateQuery, Aggregation};
+    /// use std::time::Duration;
+    ///
+    /// # async fn test(client: &mut Client)  -> Result<(), Box<dyn std::error::Error>>{
+    /// // Query the mean speed of every minute in the last hour.
+    /// let peer_id = "4242424242424242424242424242424242424242424242424242424242424242".parse()?;
+    /// let query = AggregateQuery {
+    ///     span: Duration::from_secs(60 * 60).into(),
+    ///     end: 0.into(),
+    ///     bucket: Duration::from_secs(60),
+    ///     aggregation: Aggregation::Mean,
+    /// };
+    /// client.query_aggregates(vec![peer_id], "speed".to_string(), query).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn query_aggregates(
+        &mut self,
+        peer_ids: Vec<PeerId>,
+        filter: impl Into<Filter<String>>,
+        query: AggregateQuery,
+    ) -> Result<Aggregates, Error> {
+        if self.verifier.is_some() {
+            return Err(Error::BoxError(
+                "Aggregated values cannot be verified.".into(),
+            ));
+        }
+
+        let filter = filter.into();
+        let mut aggregates = Aggregates::new();
+        let mut cursor = None;
+        loop {
+            let message = GetAggregate {
+                peer_ids: peer_ids.clone(),
+                filter: filter.clone(),
+                query: query.clone(),
+                cursor,
+            };
+            let page = self
+                .rpu_client
+                .send_request(message::GetAggregate(self.sign(message)?))
+                .await?;
+            for (peer_id, aggregates_of_peer) in page.items {
+                let merged_aggregates_of_peer = aggregates.entry(peer_id).or_default();
+                for (key, aggregates_by_key) in aggregates_of_peer {
+                    merged_aggregates_of_peer
+                        .entry(key)
+                        .or_default()
+                        .extend(aggregates_by_key);
+                }
+            }
+
+            cursor = page.next;
+            if cursor.is_none() {
+                return Ok(aggregates);
+            }
+        }
     }
 
     /// Query the current value of specific key-value pairs.
@@ -193,6 +874,74 @@ impl Client {
             .await
     }
 
+    /// Get a proof that a value is included in a `Block`.
+    ///
+    /// The value is identified by the writing account, its `key` and its (client) `timestamp`
+    /// as returned by `query_values`. Returns `None` if the value was not found.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use prellblock_client::Client;
+    /// # async fn test(client: &mut Client)  -> Result<(), Box<dyn std::error::Error>>{
+    /// let peer_id: pinxit::PeerId = "4242424242424242424242424242424242424242424242424242424242424242".parse()?;
+    /// let values = client.query_current_value(vec![peer_id.clone()], "speed".to_string()).await?;
+    /// for (_, (_, timestamp, _)) in &values[&peer_id]["speed"] {
+    ///     let proof = client.query_value_proof(peer_id.clone(), "speed".to_string(), *timestamp).await?;
+    ///     let block_hash = proof.and_then(|proof| proof.verify_inclusion());
+    ///     println!("Value is included in block {:?}", block_hash);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn query_value_proof(
+        &mut self,
+        peer_id: PeerId,
+        key: String,
+        timestamp: SystemTime,
+    ) -> Result<Option<ValueProof>, Error> {
+        let message = GetValueProof {
+            peer_id: peer_id.clone(),
+            key: key.clone(),
+            timestamp,
+        };
+        let proof = self
+            .rpu_client
+            .send_request(message::GetValueProof(self.sign(message)?))
+            .await?;
+
+        if let Some(proof) = &proof {
+            let transaction = proof.transaction.unverified_ref();
+            if let Some(written) = transaction.written_value(proof.transaction.signer()) {
+                self.verify_value_proof(&peer_id, &key, &written.value, timestamp, proof)
+                    .await?;
+            }
+        }
+
+        Ok(proof)
+    }
+
+    /// List the keys (time series) written by the account `peer_id`.
+    ///
+    /// Keys you are not allowed to read are omitted.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use prellblock_client::Client;
+    /// # async fn test(client: &mut Client)  -> Result<(), Box<dyn std::error::Error>>{
+    /// let peer_id: pinxit::PeerId = "4242424242424242424242424242424242424242424242424242424242424242".parse()?;
+    /// for key_info in client.list_keys(peer_id).await? {
+    ///     println!("{}: {} values", key_info.key, key_info.count);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn list_keys(&mut self, peer_id: PeerId) -> Result<Vec<KeyInfo>, Error> {
+        let message = ListKeys { peer_id };
+        self.rpu_client
+            .send_request(message::ListKeys(self.sign(message)?))
+            .await
+    }
+
     /// Retrieve blocks from the chain.
     ///
     /// Nonexisting blocks specified by the `filter` will be ignored (no error will be returned).
@@ -212,14 +961,172 @@ impl Client {
         &mut self,
         filter: impl Into<Filter<BlockNumber>>,
     ) -> Result<Vec<Block>, Error> {
-        let message = GetBlock {
-            filter: filter.into(),
-        };
+        let mut blocks = Vec::new();
+        let mut pages = self.block_pages(filter);
+        while let Some(page) = pages.next().await {
+            blocks.extend(page?);
+        }
+
+        Ok(blocks)
+    }
+
+    /// Retrieve blocks from the chain page by page.
+    ///
+    /// This is the same as `query_block`, but only one page is held in memory at once.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use prellblock_client::Client;
+    /// use prellblock_client::consensus::BlockNumber;
+    ///
+    /// # async fn test(client: &mut Client)  -> Result<(), Box<dyn std::error::Error>>{
+    /// let mut pages = client.block_pages(BlockNumber::new(0)..);
+    /// while let Some(blocks) = pages.next().await {
+    ///     for block in blocks? {
+    ///         println!("{:?}", block.hash());
+    ///     }
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn block_pages(&mut self, filter: impl Into<Filter<BlockNumber>>) -> BlockPages<'_> {
+        BlockPages::new(self, filter.into())
+    }
+
+    /// Retrieve a single page of blocks.
+    ///
+    /// Pass `None` as `cursor` to get the first page and the `next` cursor
+    /// of the previous page to get the following ones.
+    pub async fn query_block_page(
+        &mut self,
+        filter: impl Into<Filter<BlockNumber>>,
+        cursor: Option<Cursor>,
+    ) -> Result<Page<Vec<Block>>, Error> {
+        let mut page = self.query_block_unverified(filter.into(), cursor).await?;
+
+        if self.verifier.is_some() {
+            page.items.sort_by_key(Block::block_number);
+            for block in &page.items {
+                self.sync_verifier(block.block_number()).await?;
+                self.verifier_mut()?
+                    .verify(block)
+                    .map_err(verification_error)?;
+            }
+        }
+
+        Ok(page)
+    }
+
+    async fn query_block_unverified(
+        &mut self,
+        filter: Filter<BlockNumber>,
+        cursor: Option<Cursor>,
+    ) -> Result<Page<Vec<Block>>, Error> {
+        let message = GetBlock { filter, cursor };
         self.rpu_client
             .send_request(message::GetBlock(self.sign(message)?))
             .await
     }
 
+    fn verifier_mut(&mut self) -> Result<&mut Verifier, Error> {
+        self.verifier
+            .as_mut()
+            .ok_or_else(|| Error::BoxError("The client is not in verifying mode.".into()))
+    }
+
+    /// Verify all `Block`s before `block_number` (if not already done).
+    async fn sync_verifier(&mut self, block_number: BlockNumber) -> Result<(), Error> {
+        loop {
+            let start = self.verifier_mut()?.block_number();
+            if start >= block_number {
+                return Ok(());
+            }
+
+            let end = (start + SYNC_BATCH_SIZE).min(block_number);
+            // Only the first page is used, the rest is requested in the next iteration.
+            let page = self
+                .query_block_unverified((start..end).into(), None)
+                .await?;
+
+            let verifier = self.verifier_mut()?;
+            for block in &page.items {
+                verifier.verify(block).map_err(verification_error)?;
+            }
+            if verifier.block_number() == start {
+                return Err(verification_error(VerificationError::MissingBlock(start)));
+            }
+        }
+    }
+
+    /// Verify read values by requesting their `ValueProof`s
+    /// (in batches of `MAX_VALUE_PROOFS`).
+    async fn verify_values(&mut self, values: &ReadValues) -> Result<(), Error> {
+        let values: Vec<_> = values
+            .iter()
+            .flat_map(|(peer_id, values_of_peer)| {
+                values_of_peer.iter().flat_map(move |(key, values_by_key)| {
+                    values_by_key
+                        .values()
+                        .map(move |(value, timestamp, _)| (peer_id, key, value, *timestamp))
+                })
+            })
+            .collect();
+
+        for batch in values.chunks(MAX_VALUE_PROOFS) {
+            let message = GetValueProofs {
+                values: batch
+                    .iter()
+                    .map(|(peer_id, key, _, timestamp)| GetValueProof {
+                        peer_id: (*peer_id).clone(),
+                        key: (*key).clone(),
+                        timestamp: *timestamp,
+                    })
+                    .collect(),
+            };
+            let proofs = self
+                .rpu_client
+                .send_request(message::GetValueProofs(self.sign(message)?))
+                .await?;
+            if proofs.len() != batch.len() {
+                return Err(Error::BoxError(
+                    "The number of value proofs does not match.".into(),
+                ));
+            }
+
+            for ((peer_id, key, value, timestamp), proof) in batch.iter().zip(proofs) {
+                let proof = proof.ok_or_else(|| {
+                    verification_error(VerificationError::InvalidValueProof(
+                        (*peer_id).clone(),
+                        (*key).clone(),
+                        *timestamp,
+                    ))
+                })?;
+                self.verify_value_proof(peer_id, key, value, *timestamp, &proof)
+                    .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Verify a `ValueProof` (if in verifying mode).
+    async fn verify_value_proof(
+        &mut self,
+        peer_id: &PeerId,
+        key: &str,
+        value: &[u8],
+        timestamp: SystemTime,
+        proof: &ValueProof,
+    ) -> Result<(), Error> {
+        if self.verifier.is_none() {
+            return Ok(());
+        }
+        self.sync_verifier(proof.header.height + 1).await?;
+        self.verifier_mut()?
+            .verify_value_proof(peer_id, key, value, timestamp, proof)
+            .map_err(verification_error)
+    }
+
     /// Retrieve the current block number.
     ///
     /// # Example
@@ -238,4 +1145,63 @@ impl Client {
             ))
             .await
     }
+
+    /// Retrieve the status of a transaction sent earlier.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use prellblock_client::Client;
+    /// # async fn test(client: &mut Client)  -> Result<(), Box<dyn std::error::Error>>{
+    /// let transaction_hash = client.send_key_value("speed".to_string(), 42).await?;
+    /// let status = client.transaction_status(transaction_hash).await?;
+    /// println!("Status of transaction {}: {:?}", transaction_hash, status);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn transaction_status(
+        &mut self,
+        transaction_hash: TransactionHash,
+    ) -> Result<TransactionStatus, Error> {
+        let message = GetTransactionStatus { transaction_hash };
+        self.rpu_client
+            .send_request(message::GetTransactionStatus(self.sign(message)?))
+            .await
+    }
+
+    /// Subscribe to newly committed values of the given peers.
+    ///
+    /// The RPU pushes a `SubscriptionEvent` for every committed value matching the `filter`.
+    /// If `blocks` is set, an event for each committed block is pushed, too.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use prellblock_client::Client;
+    /// # async fn test(client: &mut Client)  -> Result<(), Box<dyn std::error::Error>>{
+    /// let peer_id = "4242424242424242424242424242424242424242424242424242424242424242".parse()?;
+    /// let mut subscription = client.subscribe(vec![peer_id], "speed".to_string(), false).await?;
+    /// while let Some(event) = subscription.recv().await {
+    ///     println!("{:?}", event?);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn subscribe(
+        &mut self,
+        peer_ids: Vec<PeerId>,
+        filter: impl Into<Filter<String>>,
+        blocks: bool,
+    ) -> Result<Subscription<SubscriptionEvent>, Error> {
+        let message = Subscribe {
+            peer_ids,
+            filter: filter.into(),
+            blocks,
+        };
+        self.rpu_client
+            .subscribe(message::Subscribe(self.sign(message)?))
+            .await
+    }
+}
+
+fn verification_error(err: VerificationError) -> Error {
+    Error::BoxError(err.into())
 }
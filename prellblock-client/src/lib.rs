@@ -4,6 +4,15 @@
 //! A Library Crate for external Clients - Malte (TM)
 
 mod client;
+mod pages;
+mod verifier;
 
+pub use balise::client::Subscription;
 pub use client::Client;
-pub use prellblock_client_api::{account, consensus, Filter, Query, Span};
+pub use pages::{BlockPages, ValuePages};
+pub use prellblock_client_api::{
+    account, blob, consensus, encryption, schema, transaction, AggregateQuery, Aggregates,
+    Aggregation, Cursor, Filter, KeyInfo, ListAccounts, Page, Query, Span, SubscriptionEvent,
+    TransactionReceipt, TransactionStatus, ValueProof,
+};
+pub use verifier::{VerificationError, Verifier};
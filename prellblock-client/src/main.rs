@@ -10,12 +10,24 @@
 mod cli;
 
 use cli::prelude::*;
-use prellblock_client::{account::Permissions, Client, Query};
+use pinxit::{Identity, PeerId};
+use prellblock_client::{
+    account::{Permission, Permissions},
+    blob::BlobHash,
+    consensus::{GenesisTransactions, TransactionHash},
+    schema::{self, ValueType},
+    transaction, AggregateQuery, Client, Filter, ListAccounts, Query, SubscriptionEvent,
+};
 use rand::{
     rngs::{OsRng, StdRng},
     RngCore, SeedableRng,
 };
-use std::{fs, net::SocketAddr, str, time::Instant};
+use std::{
+    fs,
+    net::SocketAddr,
+    str,
+    time::{Instant, SystemTime},
+};
 use structopt::StructOpt;
 
 #[tokio::main]
@@ -28,18 +40,43 @@ async fn main() {
 
     let identity_bytes =
         fs::read_to_string(opt.private_key_file).expect("Could not open private key file.");
-    let client = create_client(opt.turi_address, &identity_bytes);
+    let client = if let Some(genesis_transactions) = opt.genesis_transactions {
+        let genesis_transactions_data =
+            fs::read_to_string(genesis_transactions).expect("Could not open genesis file.");
+        let genesis_transactions: GenesisTransactions =
+            serde_yaml::from_str(&genesis_transactions_data).expect("Invalid genesis file.");
+        let identity = identity_bytes
+            .parse()
+            .expect("Cannot read identity. Wrong format?");
+        Client::new_verifying(opt.turi_address, identity, genesis_transactions)
+    } else {
+        create_client(opt.turi_address, &identity_bytes)
+    };
 
     match opt.cmd {
         Cmd::Set(cmd) => main_set(client, cmd).await,
+        Cmd::PutBlob(cmd) => main_put_blob(client, cmd).await,
+        Cmd::ShareKey(cmd) => main_share_key(client, cmd).await,
         Cmd::Benchmark(cmd) => main_benchmark(identity_bytes, opt.turi_address, cmd).await,
         Cmd::UpdateAccount(cmd) => main_update_account(client, cmd).await,
         Cmd::CreateAccount(cmd) => main_create_account(client, cmd).await,
         Cmd::DeleteAccount(cmd) => main_delete_account(client, cmd).await,
+        Cmd::SetAdminThreshold(cmd) => main_set_admin_threshold(client, cmd).await,
+        Cmd::RotateKey(cmd) => main_rotate_key(client, cmd).await,
+        Cmd::SetSchema(cmd) => main_set_schema(client, cmd).await,
+        Cmd::DeleteSchema(cmd) => main_delete_schema(client, cmd).await,
+        Cmd::GetSchemas => main_get_schemas(client).await,
+        Cmd::Approve(cmd) => main_approve(client, cmd).await,
         Cmd::GetValue(cmd) => main_get_value(client, cmd).await,
+        Cmd::ListKeys(cmd) => main_list_keys(client, cmd).await,
+        Cmd::GetBlob(cmd) => main_get_blob(client, cmd).await,
         Cmd::GetAccount(cmd) => main_get_account(client, cmd).await,
+        Cmd::ListAccounts(cmd) => main_list_accounts(client, cmd).await,
+        Cmd::FindAccount(cmd) => main_find_account(client, cmd).await,
         Cmd::GetBlock(cmd) => main_get_block(client, cmd).await,
         Cmd::CurrentBlockNumber => main_current_block_number(client).await,
+        Cmd::TransactionStatus(cmd) => main_transaction_status(client, cmd).await,
+        Cmd::Subscribe(cmd) => main_subscribe(client, cmd).await,
     }
 }
 
@@ -51,12 +88,70 @@ fn create_client(turi_address: SocketAddr, identity: &str) -> Client {
 }
 
 async fn main_set(mut client: Client, cmd: cmd::Set) {
-    let cmd::Set { key, value } = cmd;
+    let cmd::Set {
+        key,
+        value,
+        wait,
+        owner,
+    } = cmd;
 
     // execute the test client
-    match client.send_key_value(key, value).await {
-        Err(err) => log::error!("Failed to send transaction: {}", err),
-        Ok(()) => log::debug!("Transaction ok!"),
+    if let Some(timeout) = wait {
+        let result = match owner {
+            Some(owner) => {
+                client
+                    .send_delegated_key_value_and_wait(owner, key, value, timeout)
+                    .await
+            }
+            None => client.send_key_value_and_wait(key, value, timeout).await,
+        };
+        match result {
+            Err(err) => log::error!("Failed to execute transaction: {}", err),
+            Ok(receipt) => log::info!(
+                "Transaction {} committed in block #{} (index {}).",
+                receipt.transaction_hash,
+                receipt.block_number,
+                receipt.index,
+            ),
+        }
+    } else {
+        let result = match owner {
+            Some(owner) => client.send_delegated_key_value(owner, key, value).await,
+            None => client.send_key_value(key, value).await,
+        };
+        match result {
+            Err(err) => log::error!("Failed to send transaction: {}", err),
+            Ok(transaction_hash) => log::info!("Transaction {} sent.", transaction_hash),
+        }
+    }
+}
+
+async fn main_put_blob(mut client: Client, cmd: cmd::PutBlob) {
+    let cmd::PutBlob { key, file } = cmd;
+
+    let data = fs::read(file).expect("Could not read blob file.");
+    let hash = BlobHash::of(&data);
+    match client.send_blob(key, data).await {
+        Err(err) => log::error!("Failed to send blob: {}", err),
+        Ok(transaction_hash) => log::info!(
+            "Blob {} uploaded, transaction {} sent.",
+            hash,
+            transaction_hash
+        ),
+    }
+}
+
+async fn main_share_key(mut client: Client, cmd: cmd::ShareKey) {
+    let cmd::ShareKey {
+        scope,
+        readers,
+        matching,
+    } = cmd;
+
+    let scope = Permission::new(scope, matching.0);
+    match client.share_content_key(scope, readers).await {
+        Err(err) => log::error!("Failed to share content key: {}", err),
+        Ok(transaction_hash) => log::info!("Transaction {} sent.", transaction_hash),
     }
 }
 
@@ -88,7 +183,7 @@ async fn main_benchmark(identity: String, turi_address: SocketAddr, cmd: cmd::Be
                 let value = str::from_utf8(&value[..size]).unwrap();
                 match client.send_key_value(key, value).await {
                     Err(err) => log::error!("Failed to send transaction: {}", err),
-                    Ok(()) => log::debug!("Transaction ok!"),
+                    Ok(transaction_hash) => log::debug!("Transaction {} ok!", transaction_hash),
                 }
             }
             start.elapsed()
@@ -122,6 +217,7 @@ async fn main_update_account(mut client: Client, cmd: cmd::UpdateAccount) {
     let cmd::UpdateAccount {
         peer_id,
         permission_file,
+        propose,
     } = cmd;
 
     // TestCLI: 256cdb0197402705f96d39eab7dd3d47a39cb75673a58852d83f666973d80e01
@@ -133,10 +229,18 @@ async fn main_update_account(mut client: Client, cmd: cmd::UpdateAccount) {
     let permissions: Permissions =
         serde_yaml::from_str(&permission_file_content).expect("Invalid permission file content");
 
-    match client.update_account(peer_id, permissions).await {
-        Err(err) => log::error!("Failed to send transaction: {}", err),
-        Ok(()) => log::debug!("Transaction ok!"),
-    }
+    let result = if propose {
+        client
+            .propose_account_change(transaction::UpdateAccount {
+                id: peer_id,
+                permissions,
+                timestamp: SystemTime::now(),
+            })
+            .await
+    } else {
+        client.update_account(peer_id, permissions).await
+    };
+    log_account_management_result(result, propose);
 }
 
 async fn main_create_account(mut client: Client, cmd: cmd::CreateAccount) {
@@ -144,6 +248,7 @@ async fn main_create_account(mut client: Client, cmd: cmd::CreateAccount) {
         peer_id,
         name,
         permission_file,
+        propose,
     } = cmd;
 
     let peer_id = peer_id.parse().expect("Invalid account id given.");
@@ -153,18 +258,149 @@ async fn main_create_account(mut client: Client, cmd: cmd::CreateAccount) {
     let permissions: Permissions =
         serde_yaml::from_str(&permission_file_content).expect("Invalid permission file content.");
 
-    match client.create_account(peer_id, name, permissions).await {
-        Err(err) => log::error!("Failed to send transaction: {}", err),
-        Ok(()) => log::debug!("Transaction ok!"),
-    }
+    let result = if propose {
+        client
+            .propose_account_change(transaction::CreateAccount {
+                id: peer_id,
+                name,
+                permissions,
+                timestamp: SystemTime::now(),
+            })
+            .await
+    } else {
+        client.create_account(peer_id, name, permissions).await
+    };
+    log_account_management_result(result, propose);
 }
 
 async fn main_delete_account(mut client: Client, cmd: cmd::DeleteAccount) {
-    let cmd::DeleteAccount { peer_id } = cmd;
+    let cmd::DeleteAccount { peer_id, propose } = cmd;
     let peer_id = peer_id.parse().expect("Invalid account id given.");
-    match client.delete_account(peer_id).await {
+    let result = if propose {
+        client
+            .propose_account_change(transaction::DeleteAccount {
+                id: peer_id,
+                timestamp: SystemTime::now(),
+            })
+            .await
+    } else {
+        client.delete_account(peer_id).await
+    };
+    log_account_management_result(result, propose);
+}
+
+async fn main_set_admin_threshold(mut client: Client, cmd: cmd::SetAdminThreshold) {
+    let cmd::SetAdminThreshold { threshold, propose } = cmd;
+    let result = if propose {
+        client
+            .propose_account_change(transaction::SetAdminThreshold {
+                threshold,
+                timestamp: SystemTime::now(),
+            })
+            .await
+    } else {
+        client.set_admin_threshold(threshold).await
+    };
+    log_account_management_result(result, propose);
+}
+
+async fn main_rotate_key(mut client: Client, cmd: cmd::RotateKey) {
+    let cmd::RotateKey {
+        peer_id,
+        new_private_key_file,
+        propose,
+    } = cmd;
+    let new_identity: Identity = fs::read_to_string(new_private_key_file)
+        .expect("Could not open new private key file.")
+        .parse()
+        .expect("Cannot read new identity. Wrong format?");
+    let result = if propose {
+        let transaction = transaction::RotateKey::new(peer_id, &new_identity)
+            .expect("Could not sign the key rotation.");
+        client.propose_account_change(transaction).await
+    } else {
+        client.rotate_key(peer_id, &new_identity).await
+    };
+    log_account_management_result(result, propose);
+}
+
+async fn main_set_schema(mut client: Client, cmd: cmd::SetSchema) {
+    let cmd::SetSchema {
+        scope,
+        value_type,
+        matching,
+        propose,
+    } = cmd;
+    let key = Permission::new(scope, matching.0);
+    change_schema(&mut client, key, Some(value_type.0), propose).await;
+}
+
+async fn main_delete_schema(mut client: Client, cmd: cmd::DeleteSchema) {
+    let cmd::DeleteSchema {
+        scope,
+        matching,
+        propose,
+    } = cmd;
+    let key = Permission::new(scope, matching.0);
+    change_schema(&mut client, key, None, propose).await;
+}
+
+async fn change_schema(
+    client: &mut Client,
+    key: Permission,
+    value_type: Option<ValueType>,
+    propose: bool,
+) {
+    let result = if propose {
+        client
+            .propose_account_change(transaction::SetSchema {
+                key,
+                value_type,
+                timestamp: SystemTime::now(),
+            })
+            .await
+    } else {
+        client.set_schema(key, value_type).await
+    };
+    log_account_management_result(result, propose);
+}
+
+async fn main_get_schemas(mut client: Client) {
+    match client.query_schemas().await {
+        Ok(schemas) => {
+            if schemas.is_empty() {
+                log::warn!("No schemas set.");
+            }
+            for schema in schemas {
+                log::info!(
+                    "{:?} ({:?}): {:?}",
+                    schema.key.scope,
+                    schema.key.matching,
+                    schema.value_type
+                );
+            }
+        }
+        Err(err) => log::error!("Failed to retrieve schemas: {}", err),
+    }
+}
+
+async fn main_approve(mut client: Client, cmd: cmd::Approve) {
+    let cmd::Approve { proposal } = cmd;
+    match client.approve_account_change(proposal).await {
+        Err(err) => log::error!("Failed to send transaction: {}", err),
+        Ok(transaction_hash) => log::debug!("Transaction {} ok!", transaction_hash),
+    }
+}
+
+fn log_account_management_result(result: Result<TransactionHash, balise::Error>, propose: bool) {
+    match result {
         Err(err) => log::error!("Failed to send transaction: {}", err),
-        Ok(()) => log::debug!("Transaction ok!"),
+        Ok(transaction_hash) if propose => log::info!(
+            "Proposal {} sent. Other admins can approve it using `approve {}`.",
+            transaction_hash,
+            transaction_hash
+        ),
+        Ok(transaction_hash) => log::debug!("Transaction {} ok!", transaction_hash),
     }
 }
 
@@ -175,14 +411,36 @@ async fn main_get_value(mut client: Client, cmd: cmd::GetValue) {
         span,
         end,
         skip,
+        aggregate,
+        bucket,
     } = cmd;
 
+    if let (Some(aggregation), Some(bucket)) = (aggregate, bucket) {
+        let query = AggregateQuery {
+            span: span.0,
+            end: end.0,
+            bucket,
+            aggregation: aggregation.0,
+        };
+        main_get_aggregate(client, peer_id, filter.0, query).await;
+        return;
+    }
+
     let query = Query::Range {
         span: span.0,
         end: end.0,
         skip: skip.map(|skip| skip.0),
     };
 
+    // Values are decoded according to the schema of their key.
+    let schemas = match client.query_schemas().await {
+        Ok(schemas) => schemas,
+        Err(err) => {
+            log::warn!("Failed to retrieve schemas: {}", err);
+            Vec::new()
+        }
+    };
+
     match client.query_values(vec![peer_id], filter.0, query).await {
         Ok(values) => {
             if values.is_empty() {
@@ -201,18 +459,102 @@ async fn main_get_value(mut client: Client, cmd: cmd::GetValue) {
                     } else {
                         log::info!("  Key {:?}:", key);
                     }
+                    let value_type = schema::value_type_of(&schemas, &key);
                     for (timestamp, (value, client_time, signature)) in values_by_key {
+                        match value_type.and_then(|ty| ty.decode(&value)) {
+                            Some(decoded) => log::info!(
+                                "    {} (Client Timestamp: {}): {:?}",
+                                humantime::format_rfc3339_millis(timestamp),
+                                humantime::format_rfc3339_millis(client_time),
+                                (decoded, signature)
+                            ),
+                            None => log::info!(
+                                "    {} (Client Timestamp: {}): {:?}",
+                                humantime::format_rfc3339_millis(timestamp),
+                                humantime::format_rfc3339_millis(client_time),
+                                (value, signature)
+                            ),
+                        }
+                    }
+                }
+            }
+        }
+        Err(err) => log::error!("Failed to retrieve values: {}", err),
+    }
+}
+
+async fn main_get_aggregate(
+    mut client: Client,
+    peer_id: PeerId,
+    filter: Filter<String>,
+    query: AggregateQuery,
+) {
+    match client.query_aggregates(vec![peer_id], filter, query).await {
+        Ok(aggregates) => {
+            if aggregates.is_empty() {
+                log::warn!("No values retrieved.");
+            }
+
+            for (peer_id, aggregates_of_peer) in aggregates {
+                if aggregates_of_peer.is_empty() {
+                    log::warn!("No numeric values retrieved for peer {}.", peer_id);
+                } else {
+                    log::info!("The aggregated values of peer {} are:", peer_id);
+                }
+                for (key, aggregates_by_key) in aggregates_of_peer {
+                    log::info!("  Key {:?}:", key);
+                    let mut aggregates_by_key: Vec<_> = aggregates_by_key.into_iter().collect();
+                    aggregates_by_key.sort_by_key(|(start, _)| *start);
+                    for (start, value) in aggregates_by_key {
                         log::info!(
-                            "    {} (Client Timestamp: {}): {:?}",
-                            humantime::format_rfc3339_millis(timestamp),
-                            humantime::format_rfc3339_millis(client_time),
-                            (value, signature)
+                            "    {} (Bucket): {}",
+                            humantime::format_rfc3339_millis(start),
+                            value
                         );
                     }
                 }
             }
         }
-        Err(err) => log::error!("Failed to retrieve values: {}", err),
+        Err(err) => log::error!("Failed to retrieve aggregated values: {}", err),
+    }
+}
+
+async fn main_list_keys(mut client: Client, cmd: cmd::ListKeys) {
+    let cmd::ListKeys { peer_id } = cmd;
+
+    match client.list_keys(peer_id).await {
+        Ok(keys) => {
+            if keys.is_empty() {
+                log::warn!("No keys retrieved.");
+            }
+            for key_info in keys {
+                log::info!(
+                    "{:?}: {} values ({} - {})",
+                    key_info.key,
+                    key_info.count,
+                    humantime::format_rfc3339_millis(key_info.first_write),
+                    humantime::format_rfc3339_millis(key_info.last_write),
+                );
+            }
+        }
+        Err(err) => log::error!("Failed to list keys: {}", err),
+    }
+}
+
+async fn main_get_blob(mut client: Client, cmd: cmd::GetBlob) {
+    let cmd::GetBlob {
+        peer_id,
+        key,
+        hash,
+        file,
+    } = cmd;
+
+    match client.query_blob(peer_id, key, hash).await {
+        Ok(data) => {
+            fs::write(&file, &data).expect("Could not write blob file.");
+            log::info!("Blob {} ({} bytes) written to {}.", hash, data.len(), file);
+        }
+        Err(err) => log::error!("Failed to retrieve blob: {}", err),
     }
 }
 
@@ -234,21 +576,84 @@ async fn main_get_account(mut client: Client, cmd: cmd::GetAccount) {
     }
 }
 
+async fn main_list_accounts(mut client: Client, cmd: cmd::ListAccounts) {
+    let cmd::ListAccounts {
+        kind,
+        name_prefix,
+        expired,
+        active,
+    } = cmd;
+    let expired = match (expired, active) {
+        (true, _) => Some(true),
+        (_, true) => Some(false),
+        _ => None,
+    };
+    let filter = ListAccounts {
+        kind: kind.map(|kind| kind.0),
+        name: None,
+        name_prefix,
+        expired,
+    };
+
+    match client.list_accounts(filter).await {
+        Ok(accounts) => {
+            if accounts.is_empty() {
+                log::warn!("No accounts found.");
+            }
+            for (peer_id, account) in accounts {
+                log::info!("{} ({}): {:#?}", account.name, peer_id, account);
+            }
+        }
+        Err(err) => log::error!("Failed to list accounts: {}", err),
+    }
+}
+
+async fn main_find_account(mut client: Client, cmd: cmd::FindAccount) {
+    let cmd::FindAccount { name } = cmd;
+    let filter = ListAccounts {
+        name: Some(name.clone()),
+        ..ListAccounts::default()
+    };
+
+    match client.list_accounts(filter).await {
+        Ok(accounts) => {
+            if accounts.is_empty() {
+                log::warn!("No account named {:?} found.", name);
+            }
+            for (peer_id, _) in accounts {
+                log::info!("{}: {}", name, peer_id);
+            }
+        }
+        Err(err) => log::error!("Failed to look up account: {}", err),
+    }
+}
+
 async fn main_get_block(mut client: Client, cmd: cmd::GetBlock) {
     let cmd::GetBlock { filter } = cmd;
 
-    match client.query_block(filter.0).await {
-        Ok(block_vec) => {
-            if block_vec.is_empty() {
-                log::warn!("No blocks retrieved for the given range.");
-            } else {
-                log::info!("The retrieved blocks are:");
+    // Print the blocks page by page, so large ranges don't need to fit into memory.
+    let mut retrieved_any = false;
+    let mut pages = client.block_pages(filter.0);
+    while let Some(page) = pages.next().await {
+        match page {
+            Ok(block_vec) => {
+                if !retrieved_any && !block_vec.is_empty() {
+                    log::info!("The retrieved blocks are:");
+                    retrieved_any = true;
+                }
+                for block in block_vec {
+                    log::info!("{:#?}", block);
+                }
             }
-            for block in block_vec {
-                log::info!("{:#?}", block);
+            Err(err) => {
+                log::error!("Failed to retrieve blocks: {}", err);
+                return;
             }
         }
-        Err(err) => log::error!("Failed to retrieve blocks: {}", err),
+    }
+
+    if !retrieved_any {
+        log::warn!("No blocks retrieved for the given range.");
     }
 }
 
@@ -262,3 +667,68 @@ async fn main_current_block_number(mut client: Client) {
         ),
     }
 }
+
+async fn main_transaction_status(mut client: Client, cmd: cmd::TransactionStatus) {
+    let cmd::TransactionStatus { transaction_hash } = cmd;
+
+    match client.transaction_status(transaction_hash).await {
+        Err(err) => log::error!("Failed to retrieve transaction status: {}", err),
+        Ok(status) => log::info!(
+            "The status of transaction {} is: {:?}.",
+            transaction_hash,
+            status
+        ),
+    }
+}
+
+async fn main_subscribe(mut client: Client, cmd: cmd::Subscribe) {
+    let cmd::Subscribe {
+        peer_id,
+        filter,
+        blocks,
+    } = cmd;
+
+    let mut subscription = match client.subscribe(vec![peer_id], filter.0, blocks).await {
+        Ok(subscription) => subscription,
+        Err(err) => {
+            log::error!("Failed to subscribe: {}", err);
+            return;
+        }
+    };
+
+    while let Some(event) = subscription.recv().await {
+        match event {
+            Ok(SubscriptionEvent::Value {
+                peer_id,
+                key,
+                value,
+                timestamp,
+                block_number,
+                ..
+            }) => match client.decrypt_value(&peer_id, &key, value).await {
+                Ok(value) => log::info!(
+                    "Block #{}: {} set {:?} to {:?} (Client Timestamp: {}).",
+                    block_number,
+                    peer_id,
+                    key,
+                    value,
+                    humantime::format_rfc3339_millis(timestamp),
+                ),
+                Err(err) => log::error!("Failed to decrypt value: {}", err),
+            },
+            Ok(SubscriptionEvent::Block {
+                block_number,
+                block_hash,
+                transactions,
+                ..
+            }) => log::info!(
+                "Block #{} ({}) with {} transactions committed.",
+                block_number,
+                block_hash,
+                transactions,
+            ),
+            Err(err) => log::error!("Subscription failed: {}", err),
+        }
+    }
+    log::info!("Subscription ended.");
+}
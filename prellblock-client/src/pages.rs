@@ -0,0 +1,113 @@
+//! Helpers for iterating over the pages of paginated requests.
+
+#![allow(clippy::future_not_send)]
+
+use crate::Client;
+use balise::Error;
+use pinxit::PeerId;
+use prellblock_client_api::{
+    consensus::{Block, BlockNumber},
+    Cursor, Filter, Query, ReadValues,
+};
+
+/// The pages of a `GetValue` request, created by `Client::value_pages`.
+pub struct ValuePages<'a> {
+    client: &'a mut Client,
+    peer_ids: Vec<PeerId>,
+    filter: Filter<String>,
+    query: Query,
+    cursor: Option<Cursor>,
+    done: bool,
+}
+
+impl<'a> ValuePages<'a> {
+    pub(crate) fn new(
+        client: &'a mut Client,
+        peer_ids: Vec<PeerId>,
+        filter: Filter<String>,
+        query: Query,
+    ) -> Self {
+        Self {
+            client,
+            peer_ids,
+            filter,
+            query,
+            cursor: None,
+            done: false,
+        }
+    }
+
+    /// Get the next page of values.
+    ///
+    /// Returns `None` after the last page or an error.
+    pub async fn next(&mut self) -> Option<Result<ReadValues, Error>> {
+        if self.done {
+            return None;
+        }
+
+        let result = self
+            .client
+            .query_values_page(
+                self.peer_ids.clone(),
+                self.filter.clone(),
+                self.query.clone(),
+                self.cursor.take(),
+            )
+            .await;
+        match result {
+            Ok(page) => {
+                self.cursor = page.next;
+                self.done = self.cursor.is_none();
+                Some(Ok(page.items))
+            }
+            Err(err) => {
+                self.done = true;
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+/// The pages of a `GetBlock` request, created by `Client::block_pages`.
+pub struct BlockPages<'a> {
+    client: &'a mut Client,
+    filter: Filter<BlockNumber>,
+    cursor: Option<Cursor>,
+    done: bool,
+}
+
+impl<'a> BlockPages<'a> {
+    pub(crate) fn new(client: &'a mut Client, filter: Filter<BlockNumber>) -> Self {
+        Self {
+            client,
+            filter,
+            cursor: None,
+            done: false,
+        }
+    }
+
+    /// Get the next page of blocks.
+    ///
+    /// Returns `None` after the last page or an error.
+    pub async fn next(&mut self) -> Option<Result<Vec<Block>, Error>> {
+        if self.done {
+            return None;
+        }
+
+        let result = self
+            .client
+            .query_block_page(self.filter.clone(), self.cursor.take())
+            .await;
+        match result {
+            Ok(page) => {
+                self.cursor = page.next;
+                self.done = self.cursor.is_none();
+                Some(Ok(page.items))
+            }
+            Err(err) => {
+                self.done = true;
+                Some(Err(err))
+            }
+        }
+    }
+}
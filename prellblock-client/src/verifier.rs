@@ -0,0 +1,218 @@
+//! A light client verifying the data received from a (possibly compromised) RPU.
+
+use err_derive::Error;
+use pinxit::PeerId;
+use prellblock::world_state::WorldState;
+use prellblock_client_api::{
+    account::AccountType,
+    consensus::{
+        supermajority_reached, Block, BlockApproval, BlockHash, BlockNumber, Body,
+        GenesisTransactions, LeaderTerm, SignatureList, StateRoot,
+    },
+    ValueProof,
+};
+use std::{convert::TryInto, time::SystemTime};
+
+/// An error of the `verifier` module.
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum VerificationError {
+    /// The `Block` is not the next one to verify.
+    #[error(display = "Expected block #{}, but got block #{}.", 0, 1)]
+    UnexpectedBlock(BlockNumber, BlockNumber),
+
+    /// The `Block`'s previous block hash does not match the hash of the previous block.
+    #[error(display = "Block #{} does not match the previous block hash.", 0)]
+    PrevBlockHashDoesNotMatch(BlockNumber),
+
+    /// The `Block` does not match the already verified block.
+    #[error(display = "Block #{} does not match the verified block.", 0)]
+    BlockHashDoesNotMatch(BlockNumber),
+
+    /// The RPU did not send a requested `Block`.
+    #[error(display = "Block #{} is missing.", 0)]
+    MissingBlock(BlockNumber),
+
+    /// The `SignatureList` contains multiple signatures of the same peer.
+    #[error(display = "Block #{} contains duplicate signatures.", 0)]
+    DuplicateSignatures(BlockNumber),
+
+    /// The `Block` is not signed by a supermajority of RPUs.
+    #[error(display = "Block #{} is not signed by a supermajority of RPUs.", 0)]
+    NotEnoughSignatures(BlockNumber),
+
+    /// The `Block` is signed by an account that is not an RPU.
+    #[error(display = "Block #{} is signed by {}, which is not an RPU.", 0, 1)]
+    NotAnRPU(BlockNumber, PeerId),
+
+    /// A signature could not be verified.
+    #[error(display = "{}", 0)]
+    InvalidSignature(#[error(from)] pinxit::Error),
+
+    /// The `ValueProof` does not prove the requested value.
+    #[error(
+        display = "The value of {} ({:?} at {:?}) could not be proven.",
+        0,
+        1,
+        2
+    )]
+    InvalidValueProof(PeerId, String, SystemTime),
+}
+
+/// Verifies `Block`s received from a (possibly compromised) RPU.
+///
+/// Starting at the trusted genesis transactions, all `Block`s are verified in order.
+/// The set of RPUs is tracked by applying each `Block` to a `WorldState` (like RPUs do).
+/// A `Block` is accepted if it is signed by a supermajority of the RPUs.
+#[derive(Debug)]
+pub struct Verifier {
+    world_state: WorldState,
+    block_hashes: Vec<BlockHash>,
+}
+
+impl Verifier {
+    /// Create a new verifier trusting the given `genesis_transactions`.
+    #[must_use]
+    pub fn new(genesis_transactions: GenesisTransactions) -> Self {
+        let genesis_block = Block {
+            body: Body::new(
+                LeaderTerm::default(),
+                BlockNumber::default(),
+                BlockHash::default(),
+                genesis_transactions.timestamp,
+                StateRoot::default(),
+                genesis_transactions.transactions,
+            ),
+            signatures: SignatureList::default(),
+        };
+
+        let mut verifier = Self {
+            world_state: WorldState::default(),
+            block_hashes: Vec::new(),
+        };
+        verifier.apply(genesis_block);
+        verifier
+    }
+
+    /// The number of verified `Block`s (the number of the next block to verify).
+    #[must_use]
+    pub fn block_number(&self) -> BlockNumber {
+        BlockNumber::new(self.block_hashes.len() as u64)
+    }
+
+    /// Get the hash of an already verified `Block`.
+    #[must_use]
+    pub fn block_hash(&self, block_number: BlockNumber) -> Option<&BlockHash> {
+        let index: usize = u64::from(block_number).try_into().ok()?;
+        self.block_hashes.get(index)
+    }
+
+    /// Verify a `Block`.
+    ///
+    /// The `Block` must either be the next one (it is applied afterwards)
+    /// or an already verified one.
+    pub fn verify(&mut self, block: &Block) -> Result<(), VerificationError> {
+        let block_number = block.block_number();
+        if let Some(block_hash) = self.block_hash(block_number) {
+            return if *block_hash == block.hash() {
+                Ok(())
+            } else {
+                Err(VerificationError::BlockHashDoesNotMatch(block_number))
+            };
+        }
+
+        if block_number != self.block_number() {
+            return Err(VerificationError::UnexpectedBlock(
+                self.block_number(),
+                block_number,
+            ));
+        }
+
+        if block.body.prev_block_hash() != self.world_state.last_block_hash {
+            return Err(VerificationError::PrevBlockHashDoesNotMatch(block_number));
+        }
+
+        let approval = BlockApproval {
+            leader_term: block.body.leader_term(),
+            block_number,
+            block_hash: block.hash(),
+        };
+        self.verify_rpu_majority_signatures(&approval, &block.signatures)?;
+
+        self.apply(block.clone());
+        Ok(())
+    }
+
+    /// Verify the `proof` of a value read from the blockchain.
+    ///
+    /// The `Block` containing the value must already be verified.
+    /// Values written by a rotated key are proven for every ID of the account.
+    pub fn verify_value_proof(
+        &self,
+        peer_id: &PeerId,
+        key: &str,
+        value: &[u8],
+        timestamp: SystemTime,
+        proof: &ValueProof,
+    ) -> Result<(), VerificationError> {
+        let invalid_proof =
+            || VerificationError::InvalidValueProof(peer_id.clone(), key.to_string(), timestamp);
+
+        let transaction = proof.transaction.verify_ref()?;
+        match transaction.written_value(transaction.signer()) {
+            Some(written)
+                if self.world_state.namespace_of(written.namespace)
+                    == self.world_state.namespace_of(peer_id)
+                    && written.key == key
+                    && *written.value == *value
+                    && written.timestamp == timestamp => {}
+            _ => return Err(invalid_proof()),
+        }
+
+        let block_number = proof.header.height;
+        match (proof.verify_inclusion(), self.block_hash(block_number)) {
+            (Some(block_hash), Some(verified_block_hash)) if block_hash == *verified_block_hash => {
+                Ok(())
+            }
+            _ => Err(invalid_proof()),
+        }
+    }
+
+    /// Verify the signatures like `Core::verify_rpu_majority_signatures` does on the RPUs.
+    fn verify_rpu_majority_signatures(
+        &self,
+        approval: &BlockApproval,
+        signatures: &SignatureList,
+    ) -> Result<(), VerificationError> {
+        let block_number = approval.block_number;
+        if !signatures.is_unique() {
+            return Err(VerificationError::DuplicateSignatures(block_number));
+        }
+
+        // There is no consensus with less than four RPUs.
+        let rpus = self.world_state.peers.len();
+        if rpus < 4 || !supermajority_reached(signatures.len(), rpus) {
+            return Err(VerificationError::NotEnoughSignatures(block_number));
+        }
+
+        for (peer_id, signature) in signatures {
+            match self.world_state.accounts.get(peer_id) {
+                Some(account) if matches!(account.account_type, AccountType::RPU { .. }) => {}
+                _ => return Err(VerificationError::NotAnRPU(block_number, peer_id.clone())),
+            }
+            peer_id.verify(approval, signature)?;
+        }
+
+        Ok(())
+    }
+
+    /// Apply a verified `Block` to the `WorldState` (to track the RPUs and their account changes).
+    fn apply(&mut self, block: Block) {
+        let block_hash = block.hash();
+        // The previous block hash was checked before.
+        self.world_state
+            .apply_block(block)
+            .expect("The block does not follow the last verified block.");
+        self.block_hashes.push(block_hash);
+    }
+}
@@ -2,8 +2,8 @@
 
 use crate::{data_broadcaster::Broadcaster, peer::message};
 use pinxit::Signed;
-use prellblock_client_api::Transaction;
-use std::{mem, sync::Arc, time::Duration};
+use prellblock_client_api::{consensus::TransactionHash, Transaction};
+use std::{collections::HashSet, mem, sync::Arc, time::Duration};
 use tokio::{
     sync::{mpsc, Mutex},
     time::timeout,
@@ -16,6 +16,8 @@ const MAX_TIME_BETWEEN_BATCHES: Duration = Duration::from_millis(400);
 pub struct Batcher {
     broadcaster: Arc<Broadcaster>,
     bucket: Mutex<Vec<Signed<Transaction>>>,
+    /// The hashes of the transactions of the batch that is currently sent.
+    sending: Mutex<HashSet<TransactionHash>>,
     notifier: mpsc::Sender<()>,
 }
 
@@ -27,6 +29,7 @@ impl Batcher {
         let batcher = Self {
             broadcaster,
             bucket: Mutex::default(),
+            sending: Mutex::default(),
             notifier,
         };
         let batcher = Arc::new(batcher);
@@ -50,6 +53,16 @@ impl Batcher {
         }
     }
 
+    /// Check whether the transaction identified by `transaction_hash` is waiting
+    /// in the bucket or is currently sent to the other RPUs.
+    pub async fn contains(&self, transaction_hash: &TransactionHash) -> bool {
+        let bucket = self.bucket.lock().await;
+        bucket
+            .iter()
+            .any(|transaction| TransactionHash::of(transaction) == *transaction_hash)
+            || self.sending.lock().await.contains(transaction_hash)
+    }
+
     async fn periodically_send_to_broadcaster(self: Arc<Self>, mut receiver: mpsc::Receiver<()>) {
         loop {
             let timeout_result = timeout(MAX_TIME_BETWEEN_BATCHES, receiver.recv()).await;
@@ -61,10 +74,15 @@ impl Batcher {
                 was_timeout = true;
             }
 
-            let transactions = mem::take(&mut *self.bucket.lock().await);
-            if transactions.is_empty() {
-                continue;
-            }
+            let transactions = {
+                let mut bucket = self.bucket.lock().await;
+                if bucket.is_empty() {
+                    continue;
+                }
+                // Remember the batch until it is sent (it is not in the bucket anymore).
+                *self.sending.lock().await = bucket.iter().map(TransactionHash::of).collect();
+                mem::take(&mut *bucket)
+            };
             log::trace!(
                 "Start sending batch with {} transactions (Timeout: {}).",
                 transactions.len(),
@@ -76,6 +94,7 @@ impl Batcher {
                 Ok(_) => log::debug!("Batch sent successfully"),
                 Err(err) => log::error!("Error while sending Batch: {}", err),
             };
+            self.sending.lock().await.clear();
         }
     }
 }
@@ -0,0 +1,740 @@
+//! Offline integrity check of the `BlockStorage` (e.g. after a power loss).
+
+use super::{
+    count_from_bytes, peer_id_from_bytes, system_time_to_bytes, value_id, BlockStorage, Error,
+};
+use crate::{
+    consensus::{Block, BlockNumber},
+    transaction_checker::{PermissionError, TransactionCheck},
+    world_state::WorldState,
+};
+use pinxit::{PeerId, Signature};
+use serde::{Deserialize, Serialize};
+use sled::{IVec, Tree};
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    hash::{Hash, Hasher},
+    ops::Range,
+    str,
+    time::{Duration, SystemTime},
+};
+
+/// The temporary tree of the values written by the blocks (while checking the time series).
+const EXPECTED_VALUES_TREE_NAME: &[u8] = b"check_expected_values";
+
+/// An inconsistency of the `BlockStorage` found by `BlockStorage::check`.
+#[derive(Debug, err_derive::Error)]
+#[non_exhaustive]
+pub enum Inconsistency {
+    /// A `Block` could not be read (the following blocks are not checked).
+    #[error(display = "A block could not be read: {}", 0)]
+    UnreadableBlock(Error),
+
+    /// Blocks are missing (the following blocks are not checked).
+    #[error(display = "Block #{} is missing.", 0)]
+    MissingBlock(BlockNumber),
+
+    /// The height of the `Block` does not fit the previous block (the following blocks are not checked).
+    #[error(display = "Expected block #{}, but got block #{}.", 0, 1)]
+    BlockHeightDoesNotFit(BlockNumber, BlockNumber),
+
+    /// The `Block`'s previous block hash does not match (the following blocks are not checked).
+    #[error(display = "Block #{} does not match the previous block hash.", 0)]
+    PrevBlockHashDoesNotMatch(BlockNumber),
+
+    /// The `Block` is not signed by a supermajority of the RPUs at its height.
+    #[error(display = "Block #{} is not signed correctly: {}", 0, 1)]
+    InvalidSignatures(BlockNumber, String),
+
+    /// The `Block` could not be applied to the `WorldState` (the following blocks are not checked).
+    #[error(display = "Block #{} could not be applied: {}", 0, 1)]
+    UnappliableBlock(BlockNumber, String),
+
+    /// The `StateRoot` of the `Block` does not match the replayed `WorldState`.
+    #[error(display = "The state root of block #{} does not match.", 0)]
+    StateRootDoesNotMatch(BlockNumber),
+
+    /// A value written by a `Block` is missing in its time series.
+    #[error(
+        display = "The value of {} ({:?} at {:?}) is missing in its time series.",
+        0,
+        1,
+        2
+    )]
+    MissingValue(PeerId, String, SystemTime),
+
+    /// A value of a time series was not written by any `Block`.
+    #[error(
+        display = "The value of {} ({:?} at {:?}) was not written by any block.",
+        0,
+        1,
+        2
+    )]
+    UnexpectedValue(PeerId, String, SystemTime),
+
+    /// A value of a time series could not be read.
+    #[error(display = "A value of {} ({:?}) could not be read: {}", 0, 1, 2)]
+    UnreadableValue(PeerId, String, Error),
+
+    /// The stored number of values of a time series does not match its length.
+    #[error(
+        display = "The time series of {} ({:?}) is counted with {} instead of {} values.",
+        0,
+        1,
+        2,
+        3
+    )]
+    ValueCountDoesNotMatch(PeerId, String, u64, u64),
+
+    /// The stored number of values of a time series cannot be decoded.
+    #[error(display = "The value count of {} ({:?}) is corrupt.", 0, 1)]
+    CorruptValueCount(PeerId, String),
+}
+
+/// The result of `BlockStorage::check`.
+#[derive(Debug, Default)]
+pub struct CheckReport {
+    /// The number of checked `Block`s.
+    pub blocks: u64,
+    /// The number of checked values.
+    pub values: u64,
+    /// The block the check started at (`None` if it started at the genesis block).
+    ///
+    /// The blocks before a snapshot are missing after a snapshot synchronization.
+    pub snapshot: Option<BlockNumber>,
+    /// The inconsistencies found.
+    pub inconsistencies: Vec<Inconsistency>,
+}
+
+impl CheckReport {
+    /// Whether no inconsistencies were found.
+    #[must_use]
+    pub fn is_ok(&self) -> bool {
+        self.inconsistencies.is_empty()
+    }
+}
+
+/// A value written by the blocks (stored in the `EXPECTED_VALUES_TREE_NAME` tree).
+#[derive(Serialize, Deserialize)]
+struct ExpectedValue {
+    namespace: PeerId,
+    key: String,
+    value: Vec<u8>,
+    timestamp: SystemTime,
+    signature: Signature,
+    /// The timestamp of the block (the write time if the value is restored).
+    block_timestamp: SystemTime,
+    /// The number of times the value was written.
+    count: u64,
+}
+
+/// The values of a time series with the same `value_count_id`.
+struct StoredValue {
+    timestamp: SystemTime,
+    /// The write times of the values in the time series.
+    write_times: Vec<IVec>,
+    /// Whether the value is known to not belong to an unchecked block (so it can be removed).
+    removable: bool,
+}
+
+impl BlockStorage {
+    /// Check the integrity of the `BlockStorage`.
+    ///
+    /// The chain of blocks (heights and previous block hashes) is checked
+    /// and the `WorldState` is replayed to verify the signatures and state root of each block.
+    /// The time series have to contain exactly the values written by the blocks
+    /// and their stored number of values has to match.
+    /// If `rebuild_indexes` is set, the indexes of the transactions, values and blobs
+    /// are rebuilt from the blocks and the time series are repaired.
+    ///
+    /// This must not be run while the RPU is running.
+    pub fn check(&self, rebuild_indexes: bool) -> Result<CheckReport, Error> {
+        let mut report = CheckReport::default();
+
+        // The blocks before a snapshot are missing after a snapshot synchronization.
+        // Their indexes cannot be rebuilt (so they are kept).
+        let block_count = match self.read(..).next_back() {
+            Some(last_block) => u64::from(last_block?.block_number()) + 1,
+            None => 0,
+        };
+        if rebuild_indexes && self.blocks.len() as u64 == block_count {
+            self.transactions.clear()?;
+            self.values.clear()?;
+            self.blob_refs.clear()?;
+            self.blob_ref_counts.clear()?;
+        }
+
+        // The written values are collected on disk (there may be more than fit into memory).
+        let expected = self.database.open_tree(EXPECTED_VALUES_TREE_NAME)?;
+        expected.clear()?;
+
+        let checked = self.check_blocks(&mut report, &expected, rebuild_indexes)?;
+
+        for namespace in self.accounts.iter().keys() {
+            let namespace = peer_id_from_bytes(&namespace?);
+            for key in self.database.open_tree(namespace.as_bytes())?.iter().keys() {
+                let key = key?;
+                let key = str::from_utf8(&key).unwrap();
+                self.check_time_series(
+                    &mut report,
+                    &expected,
+                    &checked,
+                    &namespace,
+                    key,
+                    rebuild_indexes,
+                )?;
+            }
+        }
+
+        // The time series of the remaining values are not listed in their namespace.
+        while let Some((_, data)) = expected.first()? {
+            let value: ExpectedValue = postcard::from_bytes(&data)?;
+            self.check_time_series(
+                &mut report,
+                &expected,
+                &checked,
+                &value.namespace,
+                &value.key,
+                rebuild_indexes,
+            )?;
+        }
+
+        self.database.drop_tree(EXPECTED_VALUES_TREE_NAME)?;
+        Ok(report)
+    }
+
+    /// Check the chain of blocks and collect the values written by them in `expected`.
+    ///
+    /// Returns the range of the blocks whose values were collected.
+    fn check_blocks(
+        &self,
+        report: &mut CheckReport,
+        expected: &Tree,
+        rebuild_indexes: bool,
+    ) -> Result<Range<BlockNumber>, Error> {
+        let mut world_state = WorldState::default();
+        let mut start = BlockNumber::default();
+
+        for block in self.read(..) {
+            let block = match block {
+                Ok(block) => block,
+                Err(err) => {
+                    report
+                        .inconsistencies
+                        .push(Inconsistency::UnreadableBlock(err));
+                    break;
+                }
+            };
+            let block_number = block.block_number();
+
+            if block_number > world_state.block_number {
+                // Continue at the snapshot of a snapshot synchronization.
+                let snapshot = if self
+                    .snapshot_blocks
+                    .contains_key(block_number.to_be_bytes())?
+                {
+                    self.read_snapshot(block_number + 1)?
+                } else {
+                    None
+                };
+                match snapshot {
+                    Some(snapshot) if snapshot.block_number == block_number + 1 => {
+                        world_state = snapshot;
+                        start = world_state.block_number;
+                        report.snapshot = Some(block_number);
+                        report.blocks += 1;
+                        continue;
+                    }
+                    _ => {
+                        report
+                            .inconsistencies
+                            .push(Inconsistency::MissingBlock(world_state.block_number));
+                        break;
+                    }
+                }
+            }
+            if block_number != world_state.block_number {
+                report
+                    .inconsistencies
+                    .push(Inconsistency::BlockHeightDoesNotFit(
+                        world_state.block_number,
+                        block_number,
+                    ));
+                break;
+            }
+            if block.body.prev_block_hash() != world_state.last_block_hash {
+                report
+                    .inconsistencies
+                    .push(Inconsistency::PrevBlockHashDoesNotMatch(block_number));
+                break;
+            }
+
+            // The genesis block is not signed.
+            if block_number > BlockNumber::default() {
+                if let Err(reason) =
+                    world_state.verify_block_signatures(&block.header(), &block.signatures)
+                {
+                    report
+                        .inconsistencies
+                        .push(Inconsistency::InvalidSignatures(block_number, reason));
+                }
+            }
+
+            // Invalid transactions are not expected by the `WorldState` (it panics on them).
+            // The genesis transactions are not checked (they create the first accounts).
+            let verified = if block_number > BlockNumber::default() {
+                verify_transactions(&world_state, &block).map_err(|err| err.to_string())
+            } else {
+                Ok(())
+            };
+            if let Err(reason) = verified.and_then(|()| {
+                world_state
+                    .apply_block(block.clone())
+                    .map_err(|err| err.to_string())
+            }) {
+                report
+                    .inconsistencies
+                    .push(Inconsistency::UnappliableBlock(block_number, reason));
+                break;
+            }
+            if block_number > BlockNumber::default()
+                && world_state.state_root() != block.body.state_root()
+            {
+                report
+                    .inconsistencies
+                    .push(Inconsistency::StateRootDoesNotMatch(block_number));
+            }
+
+            // Values are filed under the namespaces after applying the block (like in `write_block`).
+            for transaction in block.body.transactions() {
+                let signer = transaction.signer();
+                if let Some(written) = transaction.unverified_ref().written_value(signer) {
+                    let namespace = world_state.namespace_of(written.namespace);
+                    let id = value_count_id(
+                        namespace,
+                        written.key,
+                        &written.value,
+                        written.timestamp,
+                        transaction.signature(),
+                    )?;
+                    let mut value = match expected.get(&id)? {
+                        Some(data) => postcard::from_bytes(&data)?,
+                        None => ExpectedValue {
+                            namespace: namespace.clone(),
+                            key: written.key.to_string(),
+                            value: written.value.to_vec(),
+                            timestamp: written.timestamp,
+                            signature: transaction.signature().clone(),
+                            block_timestamp: block.body.timestamp(),
+                            count: 0,
+                        },
+                    };
+                    value.count += 1;
+                    expected.insert(id, postcard::to_stdvec(&value)?)?;
+                }
+            }
+
+            if rebuild_indexes {
+                self.write_block_indexes(&block, &world_state)?;
+            }
+            report.blocks += 1;
+        }
+
+        Ok(start..world_state.block_number)
+    }
+
+    /// Check that the time series `key` of the `namespace` contains the `expected` values
+    /// and that its number of values is stored correctly.
+    ///
+    /// The checked values are removed from `expected`.
+    /// Values that were not written by the `checked` blocks are skipped.
+    /// If `repair` is set, missing values are restored and unexpected values are removed.
+    fn check_time_series(
+        &self,
+        report: &mut CheckReport,
+        expected: &Tree,
+        checked: &Range<BlockNumber>,
+        namespace: &PeerId,
+        key: &str,
+        repair: bool,
+    ) -> Result<(), Error> {
+        let all_checked = checked.start == BlockNumber::default()
+            && u64::from(checked.end) == self.blocks.len() as u64;
+
+        let time_series_name = [namespace.as_bytes(), key.as_bytes()].join(&0);
+        let time_series = self.database.open_tree(time_series_name)?;
+        let length = time_series.len() as u64;
+
+        let mut stored = HashMap::new();
+        for result in time_series.iter() {
+            let (write_time, data) = result?;
+            let (value, timestamp, signature): (Vec<u8>, SystemTime, Signature) =
+                match postcard::from_bytes(&data) {
+                    Ok(value) => value,
+                    Err(err) => {
+                        report.inconsistencies.push(Inconsistency::UnreadableValue(
+                            namespace.clone(),
+                            key.to_string(),
+                            err.into(),
+                        ));
+                        if repair {
+                            time_series.remove(write_time)?;
+                        }
+                        continue;
+                    }
+                };
+
+            // The values of unchecked blocks cannot be checked.
+            let mut removable = all_checked;
+            if !all_checked {
+                if let Some(location) = self.values.get(value_id(namespace, key, timestamp)?)? {
+                    let (block_number, _): (BlockNumber, usize) = postcard::from_bytes(&location)?;
+                    if !checked.contains(&block_number) {
+                        continue;
+                    }
+                    removable = true;
+                }
+            }
+
+            let id = value_count_id(namespace, key, &value, timestamp, &signature)?;
+            let value = stored.entry(id).or_insert_with(|| StoredValue {
+                timestamp,
+                write_times: Vec::new(),
+                removable,
+            });
+            value.write_times.push(write_time);
+            report.values += 1;
+        }
+
+        let prefix = postcard::to_stdvec(&(namespace, key))?;
+        for result in expected.scan_prefix(prefix) {
+            let (id, data) = result?;
+            let value: ExpectedValue = postcard::from_bytes(&data)?;
+            expected.remove(&id)?;
+
+            let write_times = stored
+                .remove(&id[..])
+                .map_or_else(Vec::new, |stored| stored.write_times);
+            let count = write_times.len() as u64;
+            if count < value.count {
+                report.inconsistencies.push(Inconsistency::MissingValue(
+                    namespace.clone(),
+                    key.to_string(),
+                    value.timestamp,
+                ));
+                if repair {
+                    self.restore_value(&time_series, &value)?;
+                }
+            } else if count > value.count {
+                report.inconsistencies.push(Inconsistency::UnexpectedValue(
+                    namespace.clone(),
+                    key.to_string(),
+                    value.timestamp,
+                ));
+                if repair {
+                    // The value was written by a block, so the duplicates can always be removed.
+                    for write_time in write_times.into_iter().skip(value.count as usize) {
+                        time_series.remove(write_time)?;
+                    }
+                }
+            }
+        }
+
+        for (_, value) in stored {
+            report.inconsistencies.push(Inconsistency::UnexpectedValue(
+                namespace.clone(),
+                key.to_string(),
+                value.timestamp,
+            ));
+            if repair && value.removable {
+                for write_time in value.write_times {
+                    time_series.remove(write_time)?;
+                }
+            }
+        }
+
+        let namespace_tree = self.database.open_tree(namespace.as_bytes())?;
+        let stored_length = namespace_tree
+            .get(key)?
+            .map_or(Ok(0), |count| count_from_bytes(&count));
+        if let Ok(stored_length) = stored_length {
+            if stored_length != length {
+                report
+                    .inconsistencies
+                    .push(Inconsistency::ValueCountDoesNotMatch(
+                        namespace.clone(),
+                        key.to_string(),
+                        stored_length,
+                        length,
+                    ));
+            }
+        } else {
+            report
+                .inconsistencies
+                .push(Inconsistency::CorruptValueCount(
+                    namespace.clone(),
+                    key.to_string(),
+                ));
+        }
+        if repair {
+            let length = time_series.len() as u64;
+            if length > 0 {
+                self.accounts.insert(namespace.as_bytes(), &[])?;
+                namespace_tree.insert(key, length.to_be_bytes().to_vec())?;
+            } else {
+                namespace_tree.remove(key)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Write a missing `value` back into its `time_series` (at the time of its block).
+    fn restore_value(&self, time_series: &Tree, value: &ExpectedValue) -> Result<(), Error> {
+        // Other values must not be overwritten.
+        let mut write_time = value.block_timestamp;
+        while time_series.contains_key(system_time_to_bytes(write_time))? {
+            write_time += Duration::from_nanos(1);
+        }
+        self.write_value(
+            &value.namespace,
+            &value.key,
+            &value.value,
+            value.timestamp,
+            &value.signature,
+            write_time,
+        )
+    }
+}
+
+/// Verify the transactions of a `block` against the `WorldState` before it
+/// (like the RPUs do before committing a block).
+fn verify_transactions(world_state: &WorldState, block: &Block) -> Result<(), PermissionError> {
+    let mut check = TransactionCheck::new(world_state.clone());
+    check.set_block_timestamp(block.body.timestamp());
+    for transaction in block.body.transactions() {
+        check.verify_permissions_and_apply(transaction.verify_ref()?)?;
+    }
+    Ok(())
+}
+
+/// Identify a value (by its transaction signature) to count how often it is written.
+///
+/// The id starts with the encoded namespace and key (so the values of a time series can be scanned).
+fn value_count_id(
+    namespace: &PeerId,
+    key: &str,
+    value: &[u8],
+    timestamp: SystemTime,
+    signature: &Signature,
+) -> Result<Vec<u8>, Error> {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    Ok(postcard::to_stdvec(&(
+        namespace,
+        key,
+        timestamp,
+        signature,
+        hasher.finish(),
+    ))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        consensus::{Body, LeaderTerm, SignatureList, StateRoot},
+        world_state::Permissions,
+    };
+    use pinxit::{Identity, Signable, Signed};
+    use prellblock_client_api::{
+        account::AccountType,
+        consensus::{BlockApproval, GenesisTransactions},
+        transaction, Transaction,
+    };
+    use sled::Config;
+
+    fn at(seconds: u64) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(seconds)
+    }
+
+    fn create_account(
+        admin: &Identity,
+        identity: &Identity,
+        account_type: AccountType,
+    ) -> Signed<Transaction> {
+        Transaction::CreateAccount(transaction::CreateAccount {
+            id: identity.id().clone(),
+            name: "account".to_string(),
+            permissions: Permissions {
+                account_type: Some(account_type),
+                expire_at: None,
+                has_writing_rights: Some(true),
+                writing_scopes: None,
+                delegates: None,
+                reading_rights: None,
+                limits: None,
+            },
+            timestamp: at(1000),
+        })
+        .sign(admin)
+        .unwrap()
+    }
+
+    fn key_value(writer: &Identity, value: Vec<u8>) -> Signed<Transaction> {
+        Transaction::KeyValue(transaction::KeyValue {
+            key: "speed".to_string(),
+            value,
+            timestamp: at(1001),
+        })
+        .sign(writer)
+        .unwrap()
+    }
+
+    /// A `BlockStorage` with four RPUs, an admin and a writer (created in the genesis block).
+    struct Chain {
+        block_storage: BlockStorage,
+        rpus: Vec<Identity>,
+        admin: Identity,
+        writer: Identity,
+        world_state: WorldState,
+    }
+
+    impl Chain {
+        fn new() -> Self {
+            let rpus: Vec<_> = (0..4).map(|_| Identity::generate()).collect();
+            let admin = Identity::generate();
+            let writer = Identity::generate();
+
+            let mut transactions: Vec<_> = rpus
+                .iter()
+                .zip((3000..).step_by(2))
+                .map(|(identity, port): (_, u16)| {
+                    let account_type = AccountType::RPU {
+                        turi_address: ([127, 0, 0, 1], port).into(),
+                        peer_address: ([127, 0, 0, 1], port + 1).into(),
+                    };
+                    create_account(&admin, identity, account_type)
+                })
+                .collect();
+            transactions.push(create_account(&admin, &admin, AccountType::Admin));
+            transactions.push(create_account(&admin, &writer, AccountType::Normal));
+            let genesis_transactions = GenesisTransactions {
+                transactions,
+                timestamp: at(1000),
+            };
+            let block_storage =
+                BlockStorage::open(&Config::new().temporary(true), Some(genesis_transactions))
+                    .unwrap();
+
+            let mut world_state = WorldState::default();
+            let genesis_block = block_storage.read(..).next().unwrap().unwrap();
+            world_state.apply_block(genesis_block).unwrap();
+            Self {
+                block_storage,
+                rpus,
+                admin,
+                writer,
+                world_state,
+            }
+        }
+
+        /// Write the next block with the `transactions` signed by the first `signers` RPUs.
+        ///
+        /// The block has the `state_root` after applying it (if `valid_state_root` is set).
+        fn write_block(
+            &mut self,
+            transactions: Vec<Signed<Transaction>>,
+            signers: usize,
+            valid_state_root: bool,
+        ) {
+            let body = |state_root| {
+                Body::new(
+                    LeaderTerm::default(),
+                    self.world_state.block_number,
+                    self.world_state.last_block_hash,
+                    at(1002),
+                    state_root,
+                    transactions.clone(),
+                )
+            };
+            let mut state_root = StateRoot::default();
+            if valid_state_root {
+                let mut world_state = self.world_state.clone();
+                world_state
+                    .apply_block(Block {
+                        body: body(state_root),
+                        signatures: SignatureList::default(),
+                    })
+                    .unwrap();
+                state_root = world_state.state_root();
+            }
+
+            let body = body(state_root);
+            let approval = BlockApproval {
+                leader_term: body.leader_term(),
+                block_number: body.height(),
+                block_hash: body.hash(),
+            };
+            let signatures = self.rpus[..signers]
+                .iter()
+                .map(|identity| (identity.id().clone(), identity.sign(&approval).unwrap()))
+                .collect();
+            let block = Block { body, signatures };
+            self.block_storage
+                .write_block(&block, &self.world_state)
+                .unwrap();
+            self.world_state.last_block_hash = block.hash();
+            self.world_state.block_number += 1;
+        }
+    }
+
+    #[test]
+    fn valid_chains_are_consistent() {
+        let mut chain = Chain::new();
+        chain.write_block(vec![key_value(&chain.writer, vec![1])], 3, true);
+
+        let report = chain.block_storage.check(false).unwrap();
+        assert!(report.is_ok(), "{:?}", report.inconsistencies);
+        assert_eq!(report.blocks, 2);
+        assert_eq!(report.values, 1);
+    }
+
+    #[test]
+    fn blocks_without_a_supermajority_are_reported() {
+        let mut chain = Chain::new();
+        chain.write_block(vec![key_value(&chain.writer, vec![1])], 2, true);
+
+        let report = chain.block_storage.check(false).unwrap();
+        assert!(matches!(
+            report.inconsistencies[..],
+            [Inconsistency::InvalidSignatures(..)]
+        ));
+    }
+
+    #[test]
+    fn blocks_with_another_state_root_are_reported() {
+        let mut chain = Chain::new();
+        chain.write_block(vec![key_value(&chain.writer, vec![1])], 3, false);
+
+        let report = chain.block_storage.check(false).unwrap();
+        assert!(matches!(
+            report.inconsistencies[..],
+            [Inconsistency::StateRootDoesNotMatch(..)]
+        ));
+    }
+
+    #[test]
+    fn unappliable_blocks_are_reported() {
+        let mut chain = Chain::new();
+        // The `WorldState` would panic on an account that already exists.
+        let transaction = create_account(&chain.admin, &chain.writer, AccountType::Normal);
+        chain.write_block(vec![transaction], 3, false);
+
+        let report = chain.block_storage.check(false).unwrap();
+        assert!(matches!(
+            report.inconsistencies[..],
+            [Inconsistency::UnappliableBlock(..)]
+        ));
+    }
+}
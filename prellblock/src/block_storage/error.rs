@@ -18,6 +18,33 @@ pub enum Error {
     #[error(display = "Block height does not fit the previous block height.")]
     BlockHeightDoesNotFit,
 
+    /// The `BlockStorage` was written in a format that is not supported anymore.
+    #[error(
+        display = "The block storage has the unsupported format version {} (it has to be recreated).",
+        0
+    )]
+    UnsupportedFormat(u32),
+
+    /// The stored format version of the `BlockStorage` cannot be decoded.
+    #[error(display = "The format version of the block storage is corrupt.")]
+    CorruptFormatVersion,
+
+    /// A stored counter (e.g. the number of values of a time series) cannot be decoded.
+    #[error(display = "A counter of the block storage is corrupt.")]
+    CorruptCounter,
+
+    /// A chunk of a blob upload does not fit the size of the blob.
+    #[error(display = "The chunk does not fit the blob.")]
+    InvalidBlobChunk,
+
+    /// The uploaded payload of a blob does not match its hash.
+    #[error(display = "The uploaded payload does not match the hash of the blob.")]
+    BlobHashDoesNotMatch,
+
+    /// The bucket duration of an `AggregateQuery` is zero.
+    #[error(display = "The bucket of an aggregate query must not be empty.")]
+    EmptyBucket,
+
     /// The `Block` could not be encoded correctly.
     #[error(display = "{}", 0)]
     Encoding(#[error(from)] postcard::Error),
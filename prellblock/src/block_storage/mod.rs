@@ -1,18 +1,28 @@
 //! The `BlockStorage` is a permantent storage for validated Blocks persisted on disk.
 
+mod check;
 mod error;
 
+pub use check::{CheckReport, Inconsistency};
 pub use error::Error;
 
 use crate::{
     consensus::{Block, BlockHash, BlockNumber, Body},
     transaction_checker::AccountChecker,
+    world_state::WorldState,
 };
-use pinxit::{PeerId, Signature};
+use pinxit::{PeerId, Signature, Signed};
 use prellblock_client_api::{
-    consensus::{GenesisTransactions, LeaderTerm, SignatureList},
-    Filter, Query, ReadValuesOfPeer, ReadValuesOfSeries, Span, Transaction,
+    blob::{BlobChunk, BlobHash, BLOB_CHUNK_SIZE},
+    consensus::{
+        BlockHeader, GenesisTransactions, LeaderTerm, MerkleHash, MerkleProof, MerkleTree,
+        SignatureList, StateRoot, TransactionHash,
+    },
+    schema::ValueType,
+    AggregateQuery, AggregatesOfPeer, AggregatesOfSeries, Aggregation, Filter, KeyInfo, PutBlob,
+    Query, ReadValuesOfPeer, ReadValuesOfSeries, Span, Transaction, TransactionStatus, ValueProof,
 };
+use serde::{Deserialize, Serialize};
 use sled::{Config, Db, Tree};
 use std::{
     collections::HashMap,
@@ -23,8 +33,47 @@ use std::{
     time::{Duration, SystemTime},
 };
 
+/// The version of the storage format (stores without a version are version `0`).
+///
+/// Version `1` hashes blocks via their `BlockHeader` (with the Merkle root of the transactions
+/// and the state root). Version `2` counts the values of each time series
+/// (version `1` stores are migrated by counting their values).
+const FORMAT_VERSION: u32 = 2;
+const FORMAT_VERSION_KEY: &[u8] = b"format_version";
+
 const BLOCKS_TREE_NAME: &[u8] = b"blocks";
 const ACCOUNTS_TREE_NAME: &[u8] = b"accounts";
+const TRANSACTIONS_TREE_NAME: &[u8] = b"transactions";
+const REJECTED_TRANSACTIONS_TREE_NAME: &[u8] = b"rejected_transactions";
+const REJECTIONS_TREE_NAME: &[u8] = b"rejections";
+const VALUES_TREE_NAME: &[u8] = b"values";
+const BLOBS_TREE_NAME: &[u8] = b"blobs";
+const BLOB_REFS_TREE_NAME: &[u8] = b"blob_refs";
+const BLOB_REF_COUNTS_TREE_NAME: &[u8] = b"blob_ref_counts";
+const UNREFERENCED_BLOBS_TREE_NAME: &[u8] = b"unreferenced_blobs";
+const BLOB_UPLOADS_TREE_NAME: &[u8] = b"blob_uploads";
+const BLOB_UPLOAD_CHUNKS_TREE_NAME: &[u8] = b"blob_upload_chunks";
+const BLOB_UPLOAD_CHUNK_COUNTS_TREE_NAME: &[u8] = b"blob_upload_chunk_counts";
+const SNAPSHOTS_TREE_NAME: &[u8] = b"snapshots";
+const SNAPSHOT_BLOCKS_TREE_NAME: &[u8] = b"snapshot_blocks";
+const HEADERS_TREE_NAME: &[u8] = b"headers";
+const STAGED_INDEX_TREE_NAME: &[u8] = b"staged_index";
+
+/// Rejected transactions are remembered for this number of blocks (older ones are removed).
+const REJECTIONS_TO_KEEP: u64 = 10_000;
+
+/// Payloads of blobs that are not referenced by a committed `Blob` transaction
+/// within this time after their upload are removed.
+const UNREFERENCED_BLOB_LIFETIME: Duration = Duration::from_secs(60 * 60);
+
+/// The number of `WorldState` snapshots kept (older ones are removed).
+const SNAPSHOTS_TO_KEEP: usize = 3;
+
+/// The maximum size of a `SnapshotChunk`.
+const SNAPSHOT_CHUNK_SIZE: u64 = 1024 * 1024;
+
+/// A value read from a time series: the value, the client timestamp and the signature.
+type ReadValue = (Vec<u8>, SystemTime, Signature);
 
 /// A `BlockStorage` provides persistent storage on disk.
 ///
@@ -34,6 +83,29 @@ pub struct BlockStorage {
     database: Db,
     blocks: Tree,
     accounts: Tree,
+    transactions: Tree,
+    rejected_transactions: Tree,
+    rejections: Tree,
+    values: Tree,
+    blobs: Tree,
+    blob_refs: Tree,
+    /// The number of committed `Blob` transactions referencing each blob.
+    blob_ref_counts: Tree,
+    /// The upload time of the blobs that are not referenced (yet).
+    unreferenced_blobs: Tree,
+    /// The start time of the uploads that are not complete (yet).
+    blob_uploads: Tree,
+    /// The chunks of the uploads that are not complete (keyed by upload and index).
+    blob_upload_chunks: Tree,
+    /// The number of chunks received for each upload.
+    blob_upload_chunk_counts: Tree,
+    snapshots: Tree,
+    /// The blocks a snapshot synchronization continued at (the blocks before are not stored).
+    snapshot_blocks: Tree,
+    /// The `HeaderEntry`s of the blocks before a snapshot synchronization (which are not stored).
+    headers: Tree,
+    /// The pages of `IndexEntry`s of a snapshot synchronization (until it is imported).
+    staged_index: Tree,
 }
 
 impl BlockStorage {
@@ -49,32 +121,106 @@ impl BlockStorage {
             .snapshot_after_ops(100)
             .use_compression(false) // TODO: set this to `true`.
             .compression_factor(20);
+        Self::open(&config, genesis_transactions)
+    }
 
+    /// Open a `BlockStorage` with a sled `config` (like `new`).
+    fn open(
+        config: &Config,
+        genesis_transactions: Option<GenesisTransactions>,
+    ) -> Result<Self, Error> {
         let database = config.open()?;
         let blocks = database.open_tree(BLOCKS_TREE_NAME)?;
+
+        // Blocks of other versions cannot be read (their hashes do not match).
+        let mut count_values = false;
+        if blocks.is_empty() {
+            database.insert(FORMAT_VERSION_KEY, &FORMAT_VERSION.to_be_bytes())?;
+        } else {
+            let version = match database.get(FORMAT_VERSION_KEY)? {
+                Some(version) => match version.as_ref().try_into() {
+                    Ok(version) => u32::from_be_bytes(version),
+                    Err(_) => return Err(Error::CorruptFormatVersion),
+                },
+                None => 0,
+            };
+            match version {
+                FORMAT_VERSION => {}
+                1 => count_values = true,
+                _ => return Err(Error::UnsupportedFormat(version)),
+            }
+        }
         let accounts = database.open_tree(ACCOUNTS_TREE_NAME)?;
+        let transactions = database.open_tree(TRANSACTIONS_TREE_NAME)?;
+        let rejected_transactions = database.open_tree(REJECTED_TRANSACTIONS_TREE_NAME)?;
+        let rejections = database.open_tree(REJECTIONS_TREE_NAME)?;
+        let values = database.open_tree(VALUES_TREE_NAME)?;
+        let blobs = database.open_tree(BLOBS_TREE_NAME)?;
+        let blob_refs = database.open_tree(BLOB_REFS_TREE_NAME)?;
+        let blob_ref_counts = database.open_tree(BLOB_REF_COUNTS_TREE_NAME)?;
+        let unreferenced_blobs = database.open_tree(UNREFERENCED_BLOBS_TREE_NAME)?;
+        let blob_uploads = database.open_tree(BLOB_UPLOADS_TREE_NAME)?;
+        let blob_upload_chunks = database.open_tree(BLOB_UPLOAD_CHUNKS_TREE_NAME)?;
+        let blob_upload_chunk_counts = database.open_tree(BLOB_UPLOAD_CHUNK_COUNTS_TREE_NAME)?;
+        let snapshots = database.open_tree(SNAPSHOTS_TREE_NAME)?;
+        let snapshot_blocks = database.open_tree(SNAPSHOT_BLOCKS_TREE_NAME)?;
+        let headers = database.open_tree(HEADERS_TREE_NAME)?;
+        let staged_index = database.open_tree(STAGED_INDEX_TREE_NAME)?;
 
         let block_storage = Self {
             database,
             blocks,
             accounts,
+            transactions,
+            rejected_transactions,
+            rejections,
+            values,
+            blobs,
+            blob_refs,
+            blob_ref_counts,
+            unreferenced_blobs,
+            blob_uploads,
+            blob_upload_chunks,
+            blob_upload_chunk_counts,
+            snapshots,
+            snapshot_blocks,
+            headers,
+            staged_index,
         };
 
+        // Version `1` did not count the values of the time series.
+        if count_values {
+            log::info!(
+                "Migrating the block storage to format version {}.",
+                FORMAT_VERSION
+            );
+            block_storage.count_values()?;
+            block_storage
+                .database
+                .insert(FORMAT_VERSION_KEY, &FORMAT_VERSION.to_be_bytes())?;
+        }
+
         // Apply genesis block if `BlockStorage` is empty.
         if block_storage.blocks.is_empty() {
             let genesis_transactions = genesis_transactions
                 .expect("No genesis transactions were given, but BlockStorage is empty.");
             let genesis_block = Block {
-                body: Body {
-                    leader_term: LeaderTerm::default(),
-                    height: BlockNumber::default(),
-                    prev_block_hash: BlockHash::default(),
-                    timestamp: genesis_transactions.timestamp,
-                    transactions: genesis_transactions.transactions,
-                },
+                body: Body::new(
+                    LeaderTerm::default(),
+                    BlockNumber::default(),
+                    BlockHash::default(),
+                    genesis_transactions.timestamp,
+                    StateRoot::default(),
+                    genesis_transactions.transactions,
+                ),
                 signatures: SignatureList::default(),
             };
-            block_storage.write_block(&genesis_block)?;
+            // Genesis transactions are applied without checks (like in the `WorldState`).
+            let mut world_state = WorldState::default();
+            for transaction in genesis_block.body.transactions() {
+                world_state.apply_transaction(transaction.clone());
+            }
+            block_storage.write_block(&genesis_block, &world_state)?;
         }
 
         Ok(block_storage)
@@ -83,19 +229,20 @@ impl BlockStorage {
     /// Write a value to the store.
     ///
     /// The data will be accessible by the block number?.
-    pub fn write_block(&self, block: &Block) -> Result<(), Error> {
+    /// Values are filed under the namespaces of the `world_state` after applying the `block`.
+    pub fn write_block(&self, block: &Block, world_state: &WorldState) -> Result<(), Error> {
         let (last_block_hash, block_number) = if let Some(last_block) = self.read(..).next_back() {
             let last_block = last_block?;
-            (last_block.hash(), last_block.body.height + 1)
+            (last_block.hash(), last_block.body.height() + 1)
         } else {
             (BlockHash::default(), BlockNumber::default())
         };
 
-        if block.body.prev_block_hash != last_block_hash {
+        if block.body.prev_block_hash() != last_block_hash {
             return Err(Error::BlockHashDoesNotMatch);
         }
 
-        if block.body.height != block_number {
+        if block.body.height() != block_number {
             return Err(Error::BlockHeightDoesNotFit);
         }
 
@@ -104,21 +251,67 @@ impl BlockStorage {
             .insert(block.block_number().to_be_bytes(), value)?;
         log::trace!("Writing block #{}: {:#?}", block.block_number(), block);
 
-        for transaction in &block.body.transactions {
-            match transaction.unverified_ref() {
-                Transaction::KeyValue(params) => {
-                    self.write_value(
-                        transaction.signer(),
-                        &params.key,
-                        &params.value,
-                        params.timestamp,
-                        transaction.signature(),
-                    )?;
+        for transaction in block.body.transactions() {
+            // Values are filed under the namespace owner (which is not the signer for delegated writes).
+            // Rotated keys keep writing into the namespace of their original ID.
+            // Account permissions are saved in the `WorldState`.
+            let signer = transaction.signer();
+            if let Some(written) = transaction.unverified_ref().written_value(signer) {
+                let namespace = world_state.namespace_of(written.namespace);
+                self.write_value(
+                    namespace,
+                    written.key,
+                    &written.value,
+                    written.timestamp,
+                    transaction.signature(),
+                    SystemTime::now(),
+                )?;
+            }
+        }
+
+        self.write_block_indexes(block, world_state)?;
+        self.prune_blobs(SystemTime::now())
+    }
+
+    /// Write the indexes of the transactions of a `block`
+    /// (where the transactions and values were committed and which blobs can be read).
+    ///
+    /// Values are filed under the namespaces of the `world_state` after applying the `block`.
+    fn write_block_indexes(&self, block: &Block, world_state: &WorldState) -> Result<(), Error> {
+        for (index, transaction) in block.body.transactions().iter().enumerate() {
+            self.write_transaction_index(transaction, (block.block_number(), index), world_state)?;
+        }
+
+        Ok(())
+    }
+
+    /// Write the indexes of a `transaction` committed at `location` (block number and index).
+    fn write_transaction_index(
+        &self,
+        transaction: &Signed<Transaction>,
+        location: (BlockNumber, usize),
+        world_state: &WorldState,
+    ) -> Result<(), Error> {
+        // Remember where the transaction was committed.
+        let location = postcard::to_stdvec(&location)?;
+        self.transactions
+            .insert(TransactionHash::of(transaction).as_bytes(), &location[..])?;
+
+        let signer = transaction.signer();
+        if let Some(written) = transaction.unverified_ref().written_value(signer) {
+            let namespace = world_state.namespace_of(written.namespace);
+
+            // Remember where the value was committed (to create proofs).
+            let value_id = value_id(namespace, written.key, written.timestamp)?;
+            self.values.insert(value_id, location)?;
+
+            // Remember which blobs can be read via the key.
+            if let Transaction::Blob(params) = transaction.unverified_ref() {
+                let blob_ref_id =
+                    blob_ref_id(namespace, written.key, &params.hash, written.timestamp)?;
+                if self.blob_refs.insert(blob_ref_id, &[])?.is_none() {
+                    self.count_blob_ref(&params.hash, true)?;
                 }
-                // We don't need to do anything here. Account permissions are saved in the `WorldState`.
-                Transaction::UpdateAccount(_)
-                | Transaction::CreateAccount(_)
-                | Transaction::DeleteAccount(_) => {}
             }
         }
 
@@ -128,6 +321,8 @@ impl BlockStorage {
     /// Write the peer's id to the peer tree.
     /// Write the key to the timeseries tree of the peer.
     /// Write the transaction to the general transaction tree.
+    ///
+    /// The `write_time` is the time the value is written to the `BlockStorage` (of any RPU).
     fn write_value(
         &self,
         peer_id: &PeerId,
@@ -135,29 +330,763 @@ impl BlockStorage {
         value: &[u8],
         timestamp: SystemTime,
         signature: &Signature,
+        write_time: SystemTime,
     ) -> Result<(), Error> {
         // Add the peer to the account db.
         self.accounts.insert(peer_id.as_bytes(), &[])?;
 
-        // Add the value name the time_series tree.
-        self.database
-            .open_tree(peer_id.as_bytes())?
-            .insert(key, &[])?;
-
         // Insert value with timestamp of receival and the client's timestamp into the time_series tree.
+        // The key is added to the namespace of the peer when counting the value.
         let time_series_name = [peer_id.as_bytes(), key.as_bytes()].join(&0);
-        let write_time = SystemTime::now();
 
         // Write time has to be the first one because it is used when reading.
         let time = system_time_to_bytes(write_time);
         let data = postcard::to_stdvec(&(value, timestamp, signature))?;
-        self.database
+        if self
+            .database
             .open_tree(time_series_name)?
-            .insert(time, data)?;
+            .insert(time, data)?
+            .is_none()
+        {
+            self.count_value(peer_id, key, true)?;
+        }
+
+        Ok(())
+    }
+
+    /// Count a value added to (or removed from) the time series `key` of `peer_id`.
+    ///
+    /// The keys of a namespace are stored with the number of values of their time series
+    /// (so that they do not need to be counted when listing the keys).
+    fn count_value(&self, peer_id: &PeerId, key: &str, added: bool) -> Result<(), Error> {
+        let namespace = self.database.open_tree(peer_id.as_bytes())?;
+        update_count(&namespace, key, added, true)?;
+        Ok(())
+    }
+
+    /// Store the number of values of all time series in their namespaces.
+    ///
+    /// This is needed to migrate stores of version `1` (which did not count the values).
+    fn count_values(&self) -> Result<(), Error> {
+        for peer_id in self.accounts.iter().keys() {
+            let peer_id = peer_id?;
+            let namespace = self.database.open_tree(&peer_id)?;
+            for key in namespace.iter().keys() {
+                let key = key?;
+                let time_series_name = [&peer_id[..], &key[..]].join(&0);
+                let count = self.database.open_tree(time_series_name)?.len() as u64;
+                namespace.insert(key, &count.to_be_bytes())?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Get a proof that the value of `peer_id`'s `key` at `timestamp` is included in a `Block`.
+    ///
+    /// The `peer_id` has to be the namespace (the original ID) of the account.
+    /// Returns `None` if the value is not known to the `BlockStorage`.
+    pub fn value_proof(
+        &self,
+        peer_id: &PeerId,
+        key: &str,
+        timestamp: SystemTime,
+    ) -> Result<Option<ValueProof>, Error> {
+        let mut proofs = self.value_proofs(&[Some((peer_id, key, timestamp))])?;
+        Ok(proofs.pop().unwrap())
+    }
+
+    /// Get the proofs of several values (like `value_proof`).
+    ///
+    /// The proofs are returned in the same order, `None` if the value is not known
+    /// (or not given). The `Block`s are only read once for consecutive values.
+    pub fn value_proofs(
+        &self,
+        values: &[Option<(&PeerId, &str, SystemTime)>],
+    ) -> Result<Vec<Option<ValueProof>>, Error> {
+        let mut proofs = Vec::with_capacity(values.len());
+        let mut last_block: Option<(Block, MerkleTree)> = None;
+        for value in values {
+            let location = match value {
+                Some((peer_id, key, timestamp)) => {
+                    self.values.get(value_id(peer_id, key, *timestamp)?)?
+                }
+                None => None,
+            };
+            let location = match location {
+                Some(location) => location,
+                None => {
+                    proofs.push(None);
+                    continue;
+                }
+            };
+            let (block_number, index): (BlockNumber, usize) = postcard::from_bytes(&location)?;
+
+            if last_block.as_ref().map(|(block, _)| block.block_number()) != Some(block_number) {
+                last_block = match self.read(block_number..=block_number).next() {
+                    Some(block) => {
+                        let block = block?;
+                        let merkle_tree = block.body.merkle_tree();
+                        Some((block, merkle_tree))
+                    }
+                    None => None,
+                };
+            }
+            let (block, merkle_tree) = match &last_block {
+                Some(last_block) => last_block,
+                None => {
+                    proofs.push(None);
+                    continue;
+                }
+            };
+
+            let proof = merkle_tree.proof(index);
+            let transaction = block.body.transactions().get(index).cloned();
+            proofs.push(match (proof, transaction) {
+                (Some(proof), Some(transaction)) => Some(ValueProof {
+                    transaction,
+                    header: block.header(),
+                    proof,
+                    signatures: block.signatures.clone(),
+                }),
+                _ => None,
+            });
+        }
+
+        Ok(proofs)
+    }
+
+    /// Store the payload of a blob and return its hash.
+    ///
+    /// Payloads are stored even if no `Blob` transaction references them (yet).
+    /// They are removed if they are still not referenced after the `UNREFERENCED_BLOB_LIFETIME`.
+    pub fn write_blob(&self, data: &[u8]) -> Result<BlobHash, Error> {
+        let hash = BlobHash::of(data);
+        self.blobs.insert(hash.as_bytes(), data)?;
+        if !self.blob_ref_counts.contains_key(hash.as_bytes())? {
+            self.unreferenced_blobs.insert(
+                hash.as_bytes(),
+                system_time_to_bytes(SystemTime::now()).as_ref(),
+            )?;
+        }
+        Ok(hash)
+    }
+
+    /// Store a `chunk` of the payload of a blob uploaded by `uploader`.
+    ///
+    /// The payload has to be split into chunks of `BLOB_CHUNK_SIZE` bytes (the last one can be
+    /// shorter), which can be stored in any order. Once all chunks were received, the payload
+    /// is checked against its hash and stored (like `write_blob`).
+    /// Returns whether the payload is complete.
+    pub fn write_blob_chunk(&self, uploader: &PeerId, chunk: &PutBlob) -> Result<bool, Error> {
+        let chunk_count = blob_chunk_count(chunk.size);
+        let index = chunk.offset / BLOB_CHUNK_SIZE;
+        let chunk_size = chunk.size.saturating_sub(chunk.offset).min(BLOB_CHUNK_SIZE);
+        if chunk.offset % BLOB_CHUNK_SIZE != 0
+            || index >= chunk_count
+            || chunk.data.len() as u64 != chunk_size
+        {
+            return Err(Error::InvalidBlobChunk);
+        }
+        if self.has_blob(&chunk.hash)? {
+            return Ok(true);
+        }
+
+        let upload_id = postcard::to_stdvec(&(uploader, &chunk.hash, chunk.size))?;
+        if !self.blob_uploads.contains_key(&upload_id)? {
+            self.blob_uploads
+                .insert(&upload_id, system_time_to_bytes(SystemTime::now()).as_ref())?;
+        }
+        let chunk_id = [&upload_id[..], &index.to_be_bytes()].concat();
+        if self
+            .blob_upload_chunks
+            .insert(chunk_id, &chunk.data[..])?
+            .is_some()
+        {
+            // The chunk was already received.
+            return Ok(false);
+        }
+        let received = update_count(&self.blob_upload_chunk_counts, &upload_id, true, false)?;
+        if received < chunk_count {
+            return Ok(false);
+        }
+
+        // All chunks were received (the chunks are ordered by their index).
+        let mut data = Vec::with_capacity(chunk.size as usize);
+        for chunk_data in self.blob_upload_chunks.scan_prefix(&upload_id).values() {
+            data.extend_from_slice(&chunk_data?);
+        }
+        self.remove_blob_upload(&upload_id)?;
+        if BlobHash::of(&data) != chunk.hash {
+            return Err(Error::BlobHashDoesNotMatch);
+        }
+        self.write_blob(&data)?;
+        Ok(true)
+    }
+
+    /// Remove the chunks of an upload.
+    fn remove_blob_upload(&self, upload_id: &[u8]) -> Result<(), Error> {
+        for chunk_id in self.blob_upload_chunks.scan_prefix(upload_id).keys() {
+            self.blob_upload_chunks.remove(chunk_id?)?;
+        }
+        self.blob_upload_chunk_counts.remove(upload_id)?;
+        self.blob_uploads.remove(upload_id)?;
+        Ok(())
+    }
+
+    /// Check whether the payload of a blob is stored.
+    pub fn has_blob(&self, hash: &BlobHash) -> Result<bool, Error> {
+        Ok(self.blobs.contains_key(hash.as_bytes())?)
+    }
+
+    /// Get the size of the stored payload of a blob.
+    ///
+    /// Returns `None` if the payload is not stored.
+    pub fn blob_size(&self, hash: &BlobHash) -> Result<Option<u64>, Error> {
+        Ok(self
+            .blobs
+            .get(hash.as_bytes())?
+            .map(|data| data.len() as u64))
+    }
+
+    /// Count a reference of a committed `Blob` transaction to a blob (or remove one).
+    ///
+    /// Blobs without references are removed by `prune_blobs` after a while.
+    fn count_blob_ref(&self, hash: &BlobHash, added: bool) -> Result<(), Error> {
+        let count = update_count(&self.blob_ref_counts, hash.as_bytes(), added, false)?;
+        if count > 0 {
+            self.unreferenced_blobs.remove(hash.as_bytes())?;
+        } else {
+            self.unreferenced_blobs.insert(
+                hash.as_bytes(),
+                system_time_to_bytes(SystemTime::now()).as_ref(),
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Remove the payloads of blobs that were not referenced within the `UNREFERENCED_BLOB_LIFETIME`
+    /// before `now` (and the chunks of uploads that were not completed within this time).
+    fn prune_blobs(&self, now: SystemTime) -> Result<(), Error> {
+        let oldest = now
+            .checked_sub(UNREFERENCED_BLOB_LIFETIME)
+            .unwrap_or(SystemTime::UNIX_EPOCH);
+        for result in self.unreferenced_blobs.iter() {
+            let (hash, upload_time) = result?;
+            if system_time_from_bytes(&upload_time) < oldest {
+                log::debug!("Removing unreferenced blob {:?}.", hash);
+                self.blobs.remove(&hash)?;
+                self.unreferenced_blobs.remove(hash)?;
+            }
+        }
+        for result in self.blob_uploads.iter() {
+            let (upload_id, start_time) = result?;
+            if system_time_from_bytes(&start_time) < oldest {
+                log::debug!("Removing incomplete blob upload {:?}.", upload_id);
+                self.remove_blob_upload(&upload_id)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Read the chunk of the payload of a blob starting at `offset`.
+    ///
+    /// Returns `None` if the payload is not stored.
+    pub fn read_blob_chunk(
+        &self,
+        hash: &BlobHash,
+        offset: u64,
+    ) -> Result<Option<BlobChunk>, Error> {
+        let data = match self.blobs.get(hash.as_bytes())? {
+            Some(data) => data,
+            None => return Ok(None),
+        };
+        let size = data.len() as u64;
+        let start = offset.min(size);
+        let end = offset.saturating_add(BLOB_CHUNK_SIZE).min(size);
+        Ok(Some(BlobChunk {
+            data: data[start as usize..end as usize].to_vec(),
+            size,
+        }))
+    }
+
+    /// Check whether a blob was written to `peer_id`'s `key` by a committed `Blob` transaction.
+    ///
+    /// The `peer_id` has to be the namespace (the original ID) of the account.
+    pub fn has_blob_ref(
+        &self,
+        peer_id: &PeerId,
+        key: &str,
+        hash: &BlobHash,
+    ) -> Result<bool, Error> {
+        let prefix = postcard::to_stdvec(&(peer_id, key, hash))?;
+        match self.blob_refs.scan_prefix(prefix).next() {
+            Some(result) => {
+                result?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Persist a snapshot of the `world_state` (tagged with its `block_number`).
+    ///
+    /// Only the newest `SNAPSHOTS_TO_KEEP` snapshots are kept.
+    pub fn write_snapshot(&self, world_state: &WorldState) -> Result<(), Error> {
+        let value = postcard::to_stdvec(world_state)?;
+        self.snapshots
+            .insert(world_state.block_number.to_be_bytes(), value)?;
+        log::debug!(
+            "Wrote WorldState snapshot at block #{}.",
+            world_state.block_number
+        );
+
+        while self.snapshots.len() > SNAPSHOTS_TO_KEEP {
+            self.snapshots.pop_min()?;
+        }
+        Ok(())
+    }
+
+    /// Read the newest valid snapshot of the `WorldState` with at most `max_block_number` blocks applied.
+    ///
+    /// A snapshot is valid if the hash of its last block matches the block in the `BlockStorage`
+    /// and its state root matches the state root of this block.
+    /// Snapshots that cannot be decoded, do not belong to the chain or are corrupt are skipped.
+    pub fn read_snapshot(
+        &self,
+        max_block_number: BlockNumber,
+    ) -> Result<Option<WorldState>, Error> {
+        for value in self
+            .snapshots
+            .range(map_range_bound(..=max_block_number, |v| v.to_be_bytes()))
+            .values()
+            .rev()
+        {
+            let world_state: WorldState = match postcard::from_bytes(&value?) {
+                Ok(world_state) => world_state,
+                Err(err) => {
+                    log::warn!("Skipping unreadable WorldState snapshot: {}", err);
+                    continue;
+                }
+            };
+            if world_state.block_number == BlockNumber::default() {
+                continue;
+            }
+
+            let last_block_number = world_state.block_number - 1;
+            let last_block = match self.read(last_block_number..=last_block_number).next() {
+                Some(block) => block?,
+                None => {
+                    log::warn!(
+                        "Skipping WorldState snapshot at block #{} (block is not stored).",
+                        world_state.block_number
+                    );
+                    continue;
+                }
+            };
+            if last_block.hash() != world_state.last_block_hash {
+                log::warn!(
+                    "Skipping WorldState snapshot at block #{} (block hash does not match).",
+                    world_state.block_number
+                );
+            } else if last_block_number > BlockNumber::default()
+                && last_block.body.state_root() != world_state.state_root()
+            {
+                // The genesis block has the default state root.
+                log::warn!(
+                    "Skipping WorldState snapshot at block #{} (state root does not match).",
+                    world_state.block_number
+                );
+            } else {
+                return Ok(Some(world_state));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Read the chunk of an encoded snapshot starting at `offset`.
+    ///
+    /// The snapshot with `block_number` blocks applied is read (or the newest one if `None`).
+    /// Returns `None` if there is no such snapshot.
+    pub fn read_snapshot_chunk(
+        &self,
+        block_number: Option<BlockNumber>,
+        offset: u64,
+    ) -> Result<Option<SnapshotChunk>, Error> {
+        let snapshot = match block_number {
+            Some(block_number) => self
+                .snapshots
+                .get(block_number.to_be_bytes())?
+                .map(|data| (block_number, data)),
+            None => self.snapshots.last()?.map(|(key, data)| {
+                let block_number = u64::from_be_bytes(key.as_ref().try_into().unwrap());
+                (BlockNumber::new(block_number), data)
+            }),
+        };
+        let (block_number, data) = match snapshot {
+            Some(snapshot) => snapshot,
+            None => return Ok(None),
+        };
+
+        let size = data.len() as u64;
+        let start = offset.min(size);
+        let end = offset.saturating_add(SNAPSHOT_CHUNK_SIZE).min(size);
+        Ok(Some(SnapshotChunk {
+            block_number,
+            data: data[start as usize..end as usize].to_vec(),
+            size,
+        }))
+    }
+
+    /// Continue the chain at a verified `block` and its snapshot `world_state`
+    /// (after a snapshot synchronization).
+    ///
+    /// The blocks between the last stored block and the `block` are not stored
+    /// (the `block` is marked, so that `check` can tell this gap from missing blocks).
+    /// Their verified `headers` are stored instead (to serve other RPUs and verifiers)
+    /// and their values are read from the index staged via `stage_index`.
+    ///
+    /// If the import fails, everything written so far is removed again
+    /// (so that replaying the blocks does not write the values twice).
+    pub fn import_snapshot(
+        &self,
+        block: &Block,
+        world_state: &WorldState,
+        headers: &[HeaderEntry],
+    ) -> Result<(), Error> {
+        let result = self.try_import_snapshot(block, world_state, headers);
+        if result.is_err() {
+            self.rollback_import(block, world_state, headers)?;
+        }
+        self.staged_index.clear()?;
+        result
+    }
+
+    fn try_import_snapshot(
+        &self,
+        block: &Block,
+        world_state: &WorldState,
+        headers: &[HeaderEntry],
+    ) -> Result<(), Error> {
+        for page in self.staged_index.iter().values() {
+            let entries: Vec<IndexEntry> = postcard::from_bytes(&page?)?;
+            self.write_index(&entries, world_state)?;
+        }
+        for entry in headers {
+            let value = postcard::to_stdvec(entry)?;
+            self.headers
+                .insert(entry.header.height.to_be_bytes(), value)?;
+        }
+        self.write_snapshot_block(block, world_state)
+    }
+
+    /// Remove the values, headers and the snapshot of a failed `import_snapshot`.
+    fn rollback_import(
+        &self,
+        block: &Block,
+        world_state: &WorldState,
+        headers: &[HeaderEntry],
+    ) -> Result<(), Error> {
+        for page in self.staged_index.iter().values() {
+            let entries: Vec<IndexEntry> = postcard::from_bytes(&page?)?;
+            self.remove_index(&entries, world_state)?;
+        }
+        for entry in headers {
+            self.headers.remove(entry.header.height.to_be_bytes())?;
+        }
+        let key = block.block_number().to_be_bytes();
+        if self.snapshot_blocks.remove(key)?.is_some() {
+            self.blocks.remove(key)?;
+            self.snapshots.remove(key)?;
+        }
+        Ok(())
+    }
+
+    fn write_snapshot_block(&self, block: &Block, world_state: &WorldState) -> Result<(), Error> {
+        if let Some(last_block) = self.read(..).next_back() {
+            if last_block?.block_number() >= block.block_number() {
+                return Err(Error::BlockHeightDoesNotFit);
+            }
+        }
+        if world_state.last_block_hash != block.hash() {
+            return Err(Error::BlockHashDoesNotMatch);
+        }
+
+        // The marker is written first (so that a failed import can be rolled back).
+        self.snapshot_blocks
+            .insert(block.block_number().to_be_bytes(), &[])?;
+        let value = postcard::to_stdvec(&block)?;
+        self.blocks
+            .insert(block.block_number().to_be_bytes(), value)?;
+        self.write_snapshot(world_state)
+    }
+
+    /// Read the `HeaderEntry`s of at most `budget` blocks before the block `end` (in ascending order).
+    ///
+    /// The headers of blocks before a snapshot synchronization are read from the stored headers.
+    /// The headers are used to verify the RPUs of a snapshot and the entries read via `read_index`.
+    pub fn read_headers(&self, end: BlockNumber, budget: u64) -> Result<Vec<HeaderEntry>, Error> {
+        let start = u64::from(end).saturating_sub(budget);
+        let mut entries = Vec::new();
+        for height in start..u64::from(end) {
+            let height = BlockNumber::new(height);
+            if let Some(block) = self.read(height..=height).next() {
+                entries.push(HeaderEntry::of(&block?));
+            } else if let Some(value) = self.headers.get(height.to_be_bytes())? {
+                entries.push(postcard::from_bytes(&value)?);
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Read the values of the time series committed up to the block `max_block_number`
+    /// (to synchronize the indexes of another RPU).
+    ///
+    /// Each entry contains the transaction that has written the value and its inclusion proof.
+    /// Values of blocks that are not stored (before a snapshot) are skipped.
+    ///
+    /// Reading starts at `position` (if given) and stops after `budget` entries were read.
+    /// If there are more entries to read, the position to continue at is returned.
+    pub fn read_index(
+        &self,
+        max_block_number: BlockNumber,
+        position: Option<IndexPosition>,
+        budget: usize,
+    ) -> Result<(Vec<IndexEntry>, Option<IndexPosition>), Error> {
+        let (start, mut series_position) = match position {
+            Some(position) => (
+                Bound::Included(position.namespace.as_bytes().to_vec()),
+                Some((position.key, position.next)),
+            ),
+            None => (Bound::Unbounded, None),
+        };
+
+        let mut entries = Vec::new();
+        let mut last_block: Option<(Block, MerkleTree)> = None;
+        for namespace in self.accounts.range((start, Bound::Unbounded)).keys() {
+            let namespace = peer_id_from_bytes(&namespace?);
+
+            // The position only applies to the first namespace and key.
+            let (start, mut next) = match series_position.take() {
+                Some((key, next)) => (Bound::Included(key), Some(next)),
+                None => (Bound::Unbounded, None),
+            };
+            for key in self
+                .database
+                .open_tree(namespace.as_bytes())?
+                .range((start, Bound::Unbounded))
+                .keys()
+            {
+                let key = key?;
+                let key = str::from_utf8(&key).unwrap();
+                let start = map_bound(next.take().map_or(Bound::Unbounded, Bound::Included), |v| {
+                    system_time_to_bytes(v)
+                });
+
+                let time_series_name = [namespace.as_bytes(), key.as_bytes()].join(&0);
+                for result in self
+                    .database
+                    .open_tree(time_series_name)?
+                    .range((start, Bound::Unbounded))
+                {
+                    let (write_time, data) = result?;
+                    let write_time = system_time_from_bytes(&write_time);
+                    if entries.len() >= budget {
+                        let position = IndexPosition {
+                            namespace,
+                            key: key.to_string(),
+                            next: write_time,
+                        };
+                        return Ok((entries, Some(position)));
+                    }
+
+                    let (_, timestamp, _): (Vec<u8>, SystemTime, Signature) =
+                        postcard::from_bytes(&data)?;
+                    let location = match self.values.get(value_id(&namespace, key, timestamp)?)? {
+                        Some(location) => location,
+                        None => continue,
+                    };
+                    let (block_number, index): (BlockNumber, usize) =
+                        postcard::from_bytes(&location)?;
+                    // Values of later blocks are synchronized with their blocks.
+                    if block_number > max_block_number {
+                        continue;
+                    }
+
+                    if last_block.as_ref().map(|(block, _)| block.block_number())
+                        != Some(block_number)
+                    {
+                        last_block = match self.read(block_number..=block_number).next() {
+                            Some(block) => {
+                                let block = block?;
+                                let merkle_tree = block.body.merkle_tree();
+                                Some((block, merkle_tree))
+                            }
+                            None => None,
+                        };
+                    }
+                    let (block, merkle_tree) = match &last_block {
+                        Some(last_block) => last_block,
+                        None => continue,
+                    };
+                    let (transaction, proof) = match (
+                        block.body.transactions().get(index),
+                        merkle_tree.proof(index),
+                    ) {
+                        (Some(transaction), Some(proof)) => (transaction.clone(), proof),
+                        _ => continue,
+                    };
+
+                    entries.push(IndexEntry {
+                        write_time,
+                        location: (block_number, index),
+                        transaction,
+                        proof,
+                    });
+                }
+            }
+        }
+
+        Ok((entries, None))
+    }
+
+    /// Write the `entries` read via `read_index` (from another RPU).
+    ///
+    /// The entries have to be verified via `IndexEntry::is_included_in`
+    /// (and the signatures of their transactions) before.
+    /// Values are filed under the namespaces of the snapshot `world_state` (which do not change anymore).
+    /// Values that are already known are skipped (so an interrupted synchronization can be repeated).
+    pub fn write_index(
+        &self,
+        entries: &[IndexEntry],
+        world_state: &WorldState,
+    ) -> Result<(), Error> {
+        for entry in entries {
+            let transaction = &entry.transaction;
+            if let Some(written) = transaction
+                .unverified_ref()
+                .written_value(transaction.signer())
+            {
+                let namespace = world_state.namespace_of(written.namespace);
+                if self
+                    .values
+                    .contains_key(value_id(namespace, written.key, written.timestamp)?)?
+                {
+                    continue;
+                }
+                self.write_value(
+                    namespace,
+                    written.key,
+                    &written.value,
+                    written.timestamp,
+                    transaction.signature(),
+                    entry.write_time,
+                )?;
+            }
+            self.write_transaction_index(transaction, entry.location, world_state)?;
+        }
+        Ok(())
+    }
+
+    /// Stage a page of verified `entries` (read via `read_index`) for `import_snapshot`.
+    pub fn stage_index(&self, entries: &[IndexEntry]) -> Result<(), Error> {
+        let id = self.database.generate_id()?;
+        let value = postcard::to_stdvec(entries)?;
+        self.staged_index.insert(id.to_be_bytes(), value)?;
+        Ok(())
+    }
+
+    /// Remove the entries staged for an import that was not finished.
+    pub fn clear_staged_index(&self) -> Result<(), Error> {
+        self.staged_index.clear()?;
+        Ok(())
+    }
+
+    /// Remove the `entries` written via `write_index` (when an import fails).
+    fn remove_index(&self, entries: &[IndexEntry], world_state: &WorldState) -> Result<(), Error> {
+        for entry in entries {
+            let transaction = &entry.transaction;
+            self.transactions
+                .remove(TransactionHash::of(transaction).as_bytes())?;
+
+            let signer = transaction.signer();
+            if let Some(written) = transaction.unverified_ref().written_value(signer) {
+                let namespace = world_state.namespace_of(written.namespace);
+                self.values
+                    .remove(value_id(namespace, written.key, written.timestamp)?)?;
+                if let Transaction::Blob(params) = transaction.unverified_ref() {
+                    let blob_ref_id =
+                        blob_ref_id(namespace, written.key, &params.hash, written.timestamp)?;
+                    if self.blob_refs.remove(blob_ref_id)?.is_some() {
+                        self.count_blob_ref(&params.hash, false)?;
+                    }
+                }
+                let time_series_name = [namespace.as_bytes(), written.key.as_bytes()].join(&0);
+                if self
+                    .database
+                    .open_tree(time_series_name)?
+                    .remove(system_time_to_bytes(entry.write_time))?
+                    .is_some()
+                {
+                    self.count_value(namespace, written.key, false)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Remember the `reason` why the transaction with the given `transaction_hash`
+    /// was rejected while committing the block `block_number`.
+    ///
+    /// Rejections are forgotten after `REJECTIONS_TO_KEEP` blocks.
+    pub fn write_rejected_transaction(
+        &self,
+        transaction_hash: &TransactionHash,
+        block_number: BlockNumber,
+        reason: &str,
+    ) -> Result<(), Error> {
+        self.rejected_transactions
+            .insert(transaction_hash.as_bytes(), reason)?;
+        self.rejections
+            .insert(rejection_id(block_number, transaction_hash), &[])?;
 
+        // Remove the rejections of old blocks.
+        if u64::from(block_number) > REJECTIONS_TO_KEEP {
+            let end = block_number - REJECTIONS_TO_KEEP;
+            for key in self.rejections.range(..end.to_be_bytes()).keys() {
+                let key = key?;
+                self.rejected_transactions.remove(&key[8..])?;
+                self.rejections.remove(key)?;
+            }
+        }
         Ok(())
     }
 
+    /// Get the status of a transaction that was either committed or rejected.
+    ///
+    /// Returns `None` if the transaction is not known to the `BlockStorage`.
+    pub fn transaction_status(
+        &self,
+        transaction_hash: &TransactionHash,
+    ) -> Result<Option<TransactionStatus>, Error> {
+        if let Some(location) = self.transactions.get(transaction_hash.as_bytes())? {
+            let (block_number, index) = postcard::from_bytes(&location)?;
+            return Ok(Some(TransactionStatus::Committed {
+                block_number,
+                index,
+            }));
+        }
+
+        if let Some(reason) = self
+            .rejected_transactions
+            .get(transaction_hash.as_bytes())?
+        {
+            let reason = String::from_utf8_lossy(&reason).into_owned();
+            return Ok(Some(TransactionStatus::Rejected { reason }));
+        }
+
+        Ok(None)
+    }
+
     /// Read a range of blocks from the store.
     pub fn read<R>(&self, range: R) -> impl DoubleEndedIterator<Item = Result<Block, Error>>
     where
@@ -180,40 +1109,191 @@ impl BlockStorage {
     }
 
     /// Read transactions filtered by a `Filter` and a `Query` from `Blockstorage`.
+    ///
+    /// The values of rotated keys are read from the namespace of the account.
+    /// Reading starts at `position` (if given) and stops after `budget` values were read.
+    /// The remaining `budget` is updated. If there are more values to read,
+    /// the position to continue at is returned.
     pub fn read_transactions(
         &self,
         account_checker: &AccountChecker,
         peer_id: &PeerId,
         filter: Filter<&str>,
         query: &Query,
-    ) -> Result<ReadValuesOfPeer, Error> {
-        self.database
-            .open_tree(peer_id.as_bytes())?
-            .range(filter)
+        position: Option<ReadPosition>,
+        budget: &mut usize,
+    ) -> Result<(ReadValuesOfPeer, Option<ReadPosition>), Error> {
+        self.read_series(
+            account_checker,
+            peer_id,
+            filter,
+            position,
+            budget,
+            |_, time_series_name, position, budget| {
+                self.read_transactions_inner(time_series_name, query, position, budget)
+                    .map(Some)
+            },
+        )
+    }
+
+    /// Aggregate the values filtered by a `Filter` in the buckets of an `AggregateQuery`.
+    ///
+    /// The values are decoded according to the schema of their key (in the `world_state`),
+    /// keys without a numeric schema are omitted. Each bucket counts as a single value
+    /// of the `budget` (see `read_transactions`).
+    #[allow(clippy::too_many_arguments)]
+    pub fn read_aggregates(
+        &self,
+        account_checker: &AccountChecker,
+        world_state: &WorldState,
+        peer_id: &PeerId,
+        filter: Filter<&str>,
+        query: &AggregateQuery,
+        position: Option<ReadPosition>,
+        budget: &mut usize,
+    ) -> Result<(AggregatesOfPeer, Option<ReadPosition>), Error> {
+        self.read_series(
+            account_checker,
+            peer_id,
+            filter,
+            position,
+            budget,
+            |key, time_series_name, position, budget| match world_state.value_type_of(key) {
+                Some(
+                    value_type @ (ValueType::Integer | ValueType::Unsigned | ValueType::Float),
+                ) => self
+                    .aggregate_transactions_inner(
+                        time_series_name,
+                        query,
+                        value_type,
+                        position,
+                        budget,
+                    )
+                    .map(Some),
+                _ => Ok(None),
+            },
+        )
+    }
+
+    /// Read the time series of `peer_id` selected by a `Filter` with `read_series`.
+    ///
+    /// Time series for which `read_series` returns `None` are omitted.
+    /// See `read_transactions` for `position` and `budget`.
+    fn read_series<T>(
+        &self,
+        account_checker: &AccountChecker,
+        peer_id: &PeerId,
+        filter: Filter<&str>,
+        position: Option<ReadPosition>,
+        budget: &mut usize,
+        mut read_series: impl FnMut(
+            &str,
+            &[u8],
+            Option<SeriesPosition>,
+            &mut usize,
+        ) -> Result<Option<(T, Option<SeriesPosition>)>, Error>,
+    ) -> Result<(HashMap<String, T>, Option<ReadPosition>), Error> {
+        let (start, mut series_position) = match position {
+            Some(position) => (Bound::Included(position.key), position.series),
+            None => (map_bound(filter.start_bound(), |v| (*v).to_string()), None),
+        };
+        let end = map_bound(filter.end_bound(), |v| (*v).to_string());
+
+        let namespace = account_checker.namespace_of(peer_id);
+        let mut values = HashMap::new();
+        for key in self
+            .database
+            .open_tree(namespace.as_bytes())?
+            .range((start, end))
             .keys()
-            .filter_map(|key| {
-                let inner = || {
-                    let key = key?;
-                    let key = str::from_utf8(&key).unwrap();
-                    if !account_checker.is_allowed_to_read_key(peer_id, key) {
-                        return Ok(None);
-                    }
-                    let time_series_name = [peer_id.as_bytes(), key.as_bytes()].join(&0);
-                    let transactions = self.read_transactions_inner(&time_series_name, query)?;
-                    let key = key.into();
-                    Ok(Some((key, transactions)))
+        {
+            // The position in the time series only applies to the first key.
+            let series_position = series_position.take();
+            let key = key?;
+            let key = str::from_utf8(&key).unwrap();
+            if !account_checker.is_allowed_to_read_key(peer_id, key) {
+                continue;
+            }
+
+            if *budget == 0 {
+                let position = ReadPosition {
+                    key: key.to_string(),
+                    series: None,
                 };
-                inner().transpose()
-            })
-            .collect()
+                return Ok((values, Some(position)));
+            }
+
+            let time_series_name = [namespace.as_bytes(), key.as_bytes()].join(&0);
+            let (transactions, series) =
+                match read_series(key, &time_series_name, series_position, budget)? {
+                    Some(result) => result,
+                    None => continue,
+                };
+            values.insert(key.to_string(), transactions);
+
+            if let Some(series) = series {
+                let position = ReadPosition {
+                    key: key.to_string(),
+                    series: Some(series),
+                };
+                return Ok((values, Some(position)));
+            }
+        }
+
+        Ok((values, None))
+    }
+
+    /// List the keys (time series) written by `peer_id`.
+    ///
+    /// The keys of rotated keys are read from the namespace of the account.
+    /// Keys the `account_checker`'s account is not allowed to read are omitted.
+    pub fn read_keys(
+        &self,
+        account_checker: &AccountChecker,
+        peer_id: &PeerId,
+    ) -> Result<Vec<KeyInfo>, Error> {
+        let namespace = account_checker.namespace_of(peer_id);
+        let mut keys = Vec::new();
+        for result in self.database.open_tree(namespace.as_bytes())?.iter() {
+            let (key, count) = result?;
+            let key = str::from_utf8(&key).unwrap();
+            if !account_checker.is_allowed_to_read_key(peer_id, key) {
+                continue;
+            }
+
+            let time_series_name = [namespace.as_bytes(), key.as_bytes()].join(&0);
+            let mut iter = self.read_time_series(&time_series_name, ..)?;
+            let first_write = match iter.next().transpose()? {
+                Some((write_time, _)) => write_time,
+                // All values of the key were removed (by `pop_block`).
+                None => continue,
+            };
+            let last_write = match iter.next_back().transpose()? {
+                Some((write_time, _)) => write_time,
+                None => first_write,
+            };
+
+            keys.push(KeyInfo {
+                key: key.to_string(),
+                first_write,
+                last_write,
+                count: count_from_bytes(&count)?,
+            });
+        }
+
+        Ok(keys)
     }
 
     /// Get a all transactions of a `time_series`, filtered by a `Query`, in a `HashMap`.
+    ///
+    /// See `read_transactions` for `position` and `budget`.
     fn read_transactions_inner(
         &self,
         time_series_name: &[u8],
         query: &Query,
-    ) -> Result<ReadValuesOfSeries, Error> {
+        position: Option<SeriesPosition>,
+        budget: &mut usize,
+    ) -> Result<(ReadValuesOfSeries, Option<SeriesPosition>), Error> {
         let mut transactions = HashMap::new();
 
         match query {
@@ -226,26 +1306,31 @@ impl BlockStorage {
                     .transpose()?
                 {
                     transactions.insert(key, value);
+                    *budget -= 1;
                 }
             }
             // Get all values in this series.
             Query::AllValues => {
-                for result in self.read_time_series(time_series_name, ..)? {
+                let start = match &position {
+                    Some(position) => Bound::Included(position.next),
+                    None => Bound::Unbounded,
+                };
+                for result in self.read_time_series(time_series_name, (start, Bound::Unbounded))? {
                     let (key, value) = result?;
+                    if *budget == 0 {
+                        let position = SeriesPosition {
+                            next: key,
+                            span: None,
+                        };
+                        return Ok((transactions, Some(position)));
+                    }
                     transactions.insert(key, value);
+                    *budget -= 1;
                 }
             }
             // Get all values of a give `Range`.
             Query::Range { span, end, skip } => {
-                let mut skip_end = 0;
-                let end = match *end {
-                    Span::Count(count) => {
-                        skip_end = count;
-                        Bound::Unbounded
-                    }
-                    Span::Time(time) => Bound::Excluded(time),
-                    Span::Duration(duration) => Bound::Excluded(SystemTime::now() - duration),
-                };
+                let (end, mut span, skip_end) = range_end(*span, *end, position);
 
                 let mut iter = self
                     .read_time_series(time_series_name, ((Bound::Unbounded), end))?
@@ -257,24 +1342,19 @@ impl BlockStorage {
                     iter.next().transpose()?;
                 }
 
-                let mut span = *span;
                 while let Some((key, value)) = iter.next().transpose()? {
-                    match &mut span {
-                        Span::Count(count) => {
-                            if *count == 0 {
-                                break;
-                            } else {
-                                *count -= 1;
-                            }
-                        }
-                        Span::Time(time) => {
-                            if key < *time {
-                                break;
-                            }
-                        }
-                        Span::Duration(duration) => span = Span::Time(key - *duration),
+                    if *budget == 0 {
+                        let position = SeriesPosition {
+                            next: key,
+                            span: Some(span),
+                        };
+                        return Ok((transactions, Some(position)));
+                    }
+                    if !take_from_span(&mut span, key) {
+                        break;
                     }
                     transactions.insert(key, value);
+                    *budget -= 1;
                     // Skip items according to `skip`
                     if let Some(skip) = skip {
                         match skip {
@@ -299,7 +1379,84 @@ impl BlockStorage {
             }
         }
 
-        Ok(transactions)
+        Ok((transactions, None))
+    }
+
+    /// Aggregate the values of a `time_series` in the buckets of an `AggregateQuery`.
+    ///
+    /// The values are decoded as `value_type`, values that cannot be decoded are ignored.
+    /// See `read_transactions` for `position` and `budget`.
+    fn aggregate_transactions_inner(
+        &self,
+        time_series_name: &[u8],
+        query: &AggregateQuery,
+        value_type: ValueType,
+        position: Option<SeriesPosition>,
+        budget: &mut usize,
+    ) -> Result<(AggregatesOfSeries, Option<SeriesPosition>), Error> {
+        let mut aggregates = HashMap::new();
+
+        let bucket_nanos = query.bucket.as_nanos();
+        if bucket_nanos == 0 {
+            return Err(Error::EmptyBucket);
+        }
+        let bucket_of = |key: SystemTime| {
+            let since_epoch = key
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_nanos();
+            bucket_start(since_epoch - since_epoch % bucket_nanos)
+        };
+
+        let (end, mut span, skip_end) = range_end(query.span, query.end, position);
+
+        let mut iter = self
+            .read_time_series(time_series_name, ((Bound::Unbounded), end))?
+            .rev();
+
+        // Skip to last wanted value
+        for _ in 0..skip_end {
+            iter.next().transpose()?;
+        }
+
+        // The values are read backwards, so the newest bucket is the first one.
+        let mut current: Option<(SystemTime, Bucket)> = None;
+        while let Some((key, (value, _, _))) = iter.next().transpose()? {
+            let start = bucket_of(key);
+            if current
+                .as_ref()
+                .map_or(true, |(current, _)| *current != start)
+            {
+                // Buckets are never split into multiple pages.
+                if let Some((current, bucket)) = current.take() {
+                    aggregates.insert(current, bucket.value(query.aggregation));
+                    *budget -= 1;
+                }
+                if *budget == 0 {
+                    let position = SeriesPosition {
+                        next: key,
+                        span: Some(span),
+                    };
+                    return Ok((aggregates, Some(position)));
+                }
+            }
+            if !take_from_span(&mut span, key) {
+                break;
+            }
+            if let Some(value) = value_type.decode_number(&value) {
+                match &mut current {
+                    Some((_, bucket)) => bucket.add(value),
+                    None => current = Some((start, Bucket::new(value))),
+                }
+            }
+        }
+
+        if let Some((current, bucket)) = current {
+            aggregates.insert(current, bucket.value(query.aggregation));
+            *budget -= 1;
+        }
+
+        Ok((aggregates, None))
     }
 
     // Read a timeseries from `BlockStorage` and transform the raw data into a `Transaction` tuple.
@@ -309,10 +1466,7 @@ impl BlockStorage {
         &self,
         time_series_name: &[u8],
         range: R,
-    ) -> Result<
-        impl DoubleEndedIterator<Item = Result<(SystemTime, (Vec<u8>, SystemTime, Signature)), Error>>,
-        Error,
-    >
+    ) -> Result<impl DoubleEndedIterator<Item = Result<(SystemTime, ReadValue), Error>>, Error>
     where
         R: RangeBounds<SystemTime>,
     {
@@ -323,29 +1477,69 @@ impl BlockStorage {
             .map(|result| {
                 let (key, value) = result?;
                 let key = system_time_from_bytes(&key);
-                let value: (Vec<u8>, SystemTime, Signature) = postcard::from_bytes(&value)?;
-                Ok((key, value))
+                let (value, timestamp, signature): (Vec<u8>, SystemTime, Signature) =
+                    postcard::from_bytes(&value)?;
+                Ok((key, (value, timestamp, signature)))
             });
         Ok(iter)
     }
 
     /// Remove the last block (at the end of the chain) and return it.
-    pub fn pop_block(&self) -> Result<Option<Block>, Error> {
+    ///
+    /// The `world_state` must not be rolled back yet (to know the namespaces of the values).
+    pub fn pop_block(&self, world_state: &WorldState) -> Result<Option<Block>, Error> {
         if let Some((_, value)) = self.blocks.pop_max()? {
             let block: Block = postcard::from_bytes(&value)?;
 
+            // Snapshots including the block are not valid anymore.
+            for key in self
+                .snapshots
+                .range(map_range_bound(block.block_number() + 1.., |v| {
+                    v.to_be_bytes()
+                }))
+                .keys()
+            {
+                self.snapshots.remove(key?)?;
+            }
+
+            // The transactions rejected while committing the block could be valid now.
+            for key in self
+                .rejections
+                .scan_prefix(block.block_number().to_be_bytes())
+                .keys()
+            {
+                let key = key?;
+                self.rejected_transactions.remove(&key[8..])?;
+                self.rejections.remove(key)?;
+            }
+
             // update value tree
-            for transaction in &block.body.transactions {
-                match transaction.unverified_ref() {
-                    Transaction::KeyValue(params) => {
-                        let peer_id = transaction.signer();
-                        let time_series_name = [peer_id.as_bytes(), params.key.as_bytes()].join(&0);
-                        self.database.open_tree(time_series_name)?.pop_max()?;
+            for transaction in block.body.transactions() {
+                self.transactions
+                    .remove(TransactionHash::of(transaction).as_bytes())?;
+
+                // Account permissions are rolled back in the `WorldState`.
+                let signer = transaction.signer();
+                if let Some(written) = transaction.unverified_ref().written_value(signer) {
+                    let peer_id = world_state.namespace_of(written.namespace);
+                    self.values
+                        .remove(value_id(peer_id, written.key, written.timestamp)?)?;
+                    if let Transaction::Blob(params) = transaction.unverified_ref() {
+                        let blob_ref_id =
+                            blob_ref_id(peer_id, written.key, &params.hash, written.timestamp)?;
+                        if self.blob_refs.remove(blob_ref_id)?.is_some() {
+                            self.count_blob_ref(&params.hash, false)?;
+                        }
+                    }
+                    let time_series_name = [peer_id.as_bytes(), written.key.as_bytes()].join(&0);
+                    if self
+                        .database
+                        .open_tree(time_series_name)?
+                        .pop_max()?
+                        .is_some()
+                    {
+                        self.count_value(peer_id, written.key, false)?;
                     }
-                    // We don't need to do anything here. Account permissions are rolled back in the `WorldState`.
-                    Transaction::UpdateAccount(_)
-                    | Transaction::DeleteAccount(_)
-                    | Transaction::CreateAccount(_) => {}
                 }
             }
 
@@ -356,6 +1550,335 @@ impl BlockStorage {
     }
 }
 
+/// A chunk of an encoded `WorldState` snapshot.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SnapshotChunk {
+    /// The number of blocks applied to the snapshot.
+    pub block_number: BlockNumber,
+    /// The data of the chunk.
+    pub data: Vec<u8>,
+    /// The size of the whole encoded snapshot.
+    pub size: u64,
+}
+
+/// The `BlockHeader` of a block with its signatures (to synchronize another RPU).
+///
+/// The transactions are only included if the block changes accounts
+/// (so that the RPUs at each height can be verified).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HeaderEntry {
+    /// The header of the block.
+    pub header: BlockHeader,
+    /// The signatures of the block.
+    pub signatures: SignatureList,
+    /// The transactions of the block (if it changes accounts).
+    pub transactions: Option<Vec<Signed<Transaction>>>,
+}
+
+impl HeaderEntry {
+    /// Get the `HeaderEntry` of a `block`.
+    #[must_use]
+    pub fn of(block: &Block) -> Self {
+        let transactions = block.body.transactions();
+        Self {
+            header: block.header(),
+            signatures: block.signatures.clone(),
+            transactions: if transactions
+                .iter()
+                .any(|t| changes_accounts(t.unverified_ref()))
+            {
+                Some(transactions.to_vec())
+            } else {
+                None
+            },
+        }
+    }
+
+    /// Rebuild the block (if the transactions are included).
+    ///
+    /// The hash of the block has to be compared to the hash of the `header`.
+    #[must_use]
+    pub fn block(&self) -> Option<Block> {
+        let transactions = self.transactions.clone()?;
+        Some(Block {
+            body: Body::new(
+                self.header.leader_term,
+                self.header.height,
+                self.header.prev_block_hash,
+                self.header.timestamp,
+                self.header.state_root,
+                transactions,
+            ),
+            signatures: self.signatures.clone(),
+        })
+    }
+}
+
+/// Check whether a `transaction` changes accounts (and thus possibly the RPUs).
+#[must_use]
+pub fn changes_accounts(transaction: &Transaction) -> bool {
+    transaction.is_account_management()
+        || matches!(
+            transaction,
+            Transaction::ProposeAccountChange(_) | Transaction::ApproveAccountChange(_)
+        )
+}
+
+/// A value of a time series (to synchronize the indexes of another RPU).
+///
+/// The value is given by the transaction that has written it,
+/// which can be verified against the `BlockHeader` of its block.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndexEntry {
+    /// The time the value was written to the `BlockStorage`.
+    write_time: SystemTime,
+    /// The block number and index of the transaction that wrote the value.
+    location: (BlockNumber, usize),
+    /// The transaction that wrote the value.
+    transaction: Signed<Transaction>,
+    /// The inclusion proof of the transaction.
+    proof: MerkleProof,
+}
+
+impl IndexEntry {
+    /// The number of the block that contains the transaction.
+    #[must_use]
+    pub const fn block_number(&self) -> BlockNumber {
+        self.location.0
+    }
+
+    /// The transaction that wrote the value.
+    #[must_use]
+    pub const fn transaction(&self) -> &Signed<Transaction> {
+        &self.transaction
+    }
+
+    /// Check whether the transaction is included at its location in the block
+    /// with the given `transactions_root`.
+    #[must_use]
+    pub fn is_included_in(&self, transactions_root: &MerkleHash) -> bool {
+        self.proof.index == self.location.1
+            && self
+                .proof
+                .root(&TransactionHash::of(&self.transaction))
+                .as_ref()
+                == Some(transactions_root)
+    }
+}
+
+/// The position to continue reading the indexes at.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndexPosition {
+    /// The namespace to continue at.
+    namespace: PeerId,
+    /// The key to continue at.
+    key: String,
+    /// The (RPU write) time of the next value to read.
+    next: SystemTime,
+}
+
+/// The position to continue reading the values of a peer at.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReadPosition {
+    /// The key to continue at.
+    key: String,
+    /// The position in the time series of `key` or `None` to start at the beginning.
+    series: Option<SeriesPosition>,
+}
+
+/// The position to continue reading a time series at.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct SeriesPosition {
+    /// The (RPU write) time of the next value to read.
+    next: SystemTime,
+    /// The remaining `Span` of a `Query::Range` (or an `AggregateQuery`).
+    span: Option<Span>,
+}
+
+/// The values of a bucket of an `AggregateQuery` (which are read backwards).
+struct Bucket {
+    count: u64,
+    sum: f64,
+    min: f64,
+    max: f64,
+    first: f64,
+    last: f64,
+}
+
+impl Bucket {
+    /// Create a new bucket containing the newest `value`.
+    const fn new(value: f64) -> Self {
+        Self {
+            count: 1,
+            sum: value,
+            min: value,
+            max: value,
+            first: value,
+            last: value,
+        }
+    }
+
+    /// Add a `value` that is older than all values in the bucket.
+    fn add(&mut self, value: f64) {
+        self.count += 1;
+        self.sum += value;
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+        self.first = value;
+    }
+
+    /// Get the `aggregation` of the bucket.
+    #[allow(clippy::cast_precision_loss)]
+    fn value(&self, aggregation: Aggregation) -> f64 {
+        match aggregation {
+            Aggregation::Min => self.min,
+            Aggregation::Max => self.max,
+            Aggregation::Mean => self.sum / self.count as f64,
+            Aggregation::Count => self.count as f64,
+            Aggregation::First => self.first,
+            Aggregation::Last => self.last,
+        }
+    }
+}
+
+/// Get the end bound of a `Query::Range` (or an `AggregateQuery`), the remaining `Span`
+/// and the number of values to skip at the end.
+fn range_end(
+    span: Span,
+    end: Span,
+    position: Option<SeriesPosition>,
+) -> (Bound<SystemTime>, Span, usize) {
+    match position {
+        // Continue at the position (the `end` was already applied).
+        Some(SeriesPosition {
+            next,
+            span: Some(span),
+        }) => (Bound::Included(next), span, 0),
+        _ => match end {
+            Span::Count(count) => (Bound::Unbounded, span, count),
+            Span::Time(time) => (Bound::Excluded(time), span, 0),
+            Span::Duration(duration) => (Bound::Excluded(SystemTime::now() - duration), span, 0),
+        },
+    }
+}
+
+/// Update the remaining `span` when reading the value at `key` (backwards).
+///
+/// Returns `false` if the value is not part of the `span`.
+fn take_from_span(span: &mut Span, key: SystemTime) -> bool {
+    match span {
+        Span::Count(count) => {
+            if *count == 0 {
+                false
+            } else {
+                *count -= 1;
+                true
+            }
+        }
+        Span::Time(time) => key >= *time,
+        Span::Duration(duration) => {
+            *span = Span::Time(key - *duration);
+            true
+        }
+    }
+}
+
+/// Get the start of the bucket at `nanos` since the UNIX epoch.
+#[allow(clippy::cast_possible_truncation)]
+fn bucket_start(nanos: u128) -> SystemTime {
+    let duration = Duration::new(
+        (nanos / 1_000_000_000) as u64,
+        (nanos % 1_000_000_000) as u32,
+    );
+    SystemTime::UNIX_EPOCH + duration
+}
+
+/// The identifier of a value in the values tree.
+fn value_id(peer_id: &PeerId, key: &str, timestamp: SystemTime) -> Result<Vec<u8>, Error> {
+    Ok(postcard::to_stdvec(&(peer_id, key, timestamp))?)
+}
+
+/// The identifier of a rejection in the rejections tree (ordered by the `block_number`).
+fn rejection_id(block_number: BlockNumber, transaction_hash: &TransactionHash) -> Vec<u8> {
+    [
+        block_number.to_be_bytes().as_ref(),
+        transaction_hash.as_bytes(),
+    ]
+    .concat()
+}
+
+/// The number of chunks of `BLOB_CHUNK_SIZE` bytes a blob of `size` bytes is uploaded in.
+///
+/// Empty blobs are uploaded in a single empty chunk.
+const fn blob_chunk_count(size: u64) -> u64 {
+    if size == 0 {
+        1
+    } else {
+        (size + BLOB_CHUNK_SIZE - 1) / BLOB_CHUNK_SIZE
+    }
+}
+
+/// The identifier of a blob written to a key in the blob refs tree.
+///
+/// The identifiers of all writes of the same blob to a key share a common prefix.
+fn blob_ref_id(
+    peer_id: &PeerId,
+    key: &str,
+    hash: &BlobHash,
+    timestamp: SystemTime,
+) -> Result<Vec<u8>, Error> {
+    Ok(postcard::to_stdvec(&(peer_id, key, hash, timestamp))?)
+}
+
+/// Get the `PeerId` of a namespace stored in the accounts tree.
+fn peer_id_from_bytes(bytes: &[u8]) -> PeerId {
+    let hex: String = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+    hex.parse()
+        .expect("The accounts tree contains an invalid peer id.")
+}
+
+/// Get a counter (e.g. the number of values of a time series) stored as big-endian bytes.
+fn count_from_bytes(bytes: &[u8]) -> Result<u64, Error> {
+    match bytes.try_into() {
+        Ok(bytes) => Ok(u64::from_be_bytes(bytes)),
+        Err(_) => Err(Error::CorruptCounter),
+    }
+}
+
+/// Increment (or decrement) the counter stored at `key` in a `tree` and return its new value.
+///
+/// A counter of zero is removed unless `keep_zero` is set.
+fn update_count(
+    tree: &Tree,
+    key: impl AsRef<[u8]>,
+    added: bool,
+    keep_zero: bool,
+) -> Result<u64, Error> {
+    let mut result = Ok(0);
+    tree.update_and_fetch(key, |count| {
+        let old_count = match count.map_or(Ok(0), count_from_bytes) {
+            Ok(count) => count,
+            Err(err) => {
+                // Keep the corrupt counter (it can be repaired by the fsck mode).
+                result = Err(err);
+                return count.map(<[u8]>::to_vec);
+            }
+        };
+        let count = if added {
+            old_count + 1
+        } else {
+            old_count.saturating_sub(1)
+        };
+        result = Ok(count);
+        if count == 0 && !keep_zero {
+            None
+        } else {
+            Some(count.to_be_bytes().to_vec())
+        }
+    })?;
+    result
+}
+
 fn map_range_bound<T, R, U>(range_bound: R, mut f: impl FnMut(&T) -> U) -> impl RangeBounds<U>
 where
     R: RangeBounds<T>,
@@ -394,3 +1917,518 @@ fn system_time_from_bytes(bytes: &[u8]) -> SystemTime {
         SystemTime::UNIX_EPOCH - duration
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pinxit::{Identity, Signable};
+    use prellblock_client_api::transaction;
+
+    const BUCKET: Duration = Duration::from_secs(4);
+
+    /// A `BlockStorage` (with an empty genesis block) that is removed when it is dropped.
+    fn block_storage() -> BlockStorage {
+        let genesis_transactions = GenesisTransactions {
+            transactions: Vec::new(),
+            timestamp: SystemTime::UNIX_EPOCH,
+        };
+        BlockStorage::open(&Config::new().temporary(true), Some(genesis_transactions)).unwrap()
+    }
+
+    fn at(millis: u64) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_millis(millis)
+    }
+
+    /// Write the `values` to the time series `speed` (one per second, starting at second `1000`).
+    fn write_values(block_storage: &BlockStorage, identity: &Identity, values: &[Vec<u8>]) {
+        let signature = Transaction::KeyValue(transaction::KeyValue {
+            key: "speed".to_string(),
+            value: Vec::new(),
+            timestamp: SystemTime::UNIX_EPOCH,
+        })
+        .sign(identity)
+        .unwrap()
+        .signature()
+        .clone();
+        for (index, value) in values.iter().enumerate() {
+            let write_time = at(1_000_000 + 1000 * index as u64);
+            block_storage
+                .write_value(
+                    identity.id(),
+                    "speed",
+                    value,
+                    write_time,
+                    &signature,
+                    write_time,
+                )
+                .unwrap();
+        }
+    }
+
+    fn numbers(count: u32) -> Vec<Vec<u8>> {
+        (0..count)
+            .map(|value| postcard::to_stdvec(&f64::from(value)).unwrap())
+            .collect()
+    }
+
+    fn aggregate(
+        block_storage: &BlockStorage,
+        identity: &Identity,
+        aggregation: Aggregation,
+        position: Option<SeriesPosition>,
+        budget: &mut usize,
+    ) -> (Vec<(SystemTime, f64)>, Option<SeriesPosition>) {
+        let time_series_name = [identity.id().as_bytes(), "speed".as_bytes()].join(&0);
+        let query = AggregateQuery {
+            span: 100.into(),
+            end: 0.into(),
+            bucket: BUCKET,
+            aggregation,
+        };
+        let (values, position) = block_storage
+            .aggregate_transactions_inner(
+                &time_series_name,
+                &query,
+                ValueType::Float,
+                position,
+                budget,
+            )
+            .unwrap();
+        let mut values: Vec<_> = values.into_iter().collect();
+        values.sort_by_key(|(start, _)| *start);
+        (values, position)
+    }
+
+    #[test]
+    fn values_of_version_1_stores_are_counted() {
+        let block_storage = block_storage();
+        let identity = Identity::generate();
+        write_values(&block_storage, &identity, &numbers(3));
+        let namespace = block_storage
+            .database
+            .open_tree(identity.id().as_bytes())
+            .unwrap();
+        assert_eq!(
+            count_from_bytes(&namespace.get("speed").unwrap().unwrap()).unwrap(),
+            3
+        );
+
+        // Version `1` stored the keys without a count.
+        namespace.insert("speed", &[]).unwrap();
+        block_storage.count_values().unwrap();
+        assert_eq!(
+            count_from_bytes(&namespace.get("speed").unwrap().unwrap()).unwrap(),
+            3
+        );
+    }
+
+    #[test]
+    fn corrupt_counters_are_rejected() {
+        let block_storage = block_storage();
+        let identity = Identity::generate();
+        let namespace = block_storage
+            .database
+            .open_tree(identity.id().as_bytes())
+            .unwrap();
+        namespace.insert("speed", &[1, 2]).unwrap();
+
+        let result = block_storage.count_value(identity.id(), "speed", true);
+        assert!(matches!(result, Err(Error::CorruptCounter)));
+        assert_eq!(&*namespace.get("speed").unwrap().unwrap(), &[1, 2]);
+    }
+
+    #[test]
+    fn buckets_are_aligned_to_the_epoch() {
+        assert_eq!(bucket_start(0), SystemTime::UNIX_EPOCH);
+        assert_eq!(bucket_start(4_500_000_000), at(4500));
+
+        let block_storage = block_storage();
+        let identity = Identity::generate();
+        write_values(&block_storage, &identity, &numbers(10));
+
+        let (values, position) =
+            aggregate(&block_storage, &identity, Aggregation::Count, None, &mut 10);
+        assert!(position.is_none());
+        assert_eq!(
+            values,
+            vec![
+                (at(1_000_000), 4.0),
+                (at(1_004_000), 4.0),
+                (at(1_008_000), 2.0)
+            ]
+        );
+    }
+
+    #[test]
+    fn aggregations_of_buckets() {
+        let block_storage = block_storage();
+        let identity = Identity::generate();
+        write_values(&block_storage, &identity, &numbers(10));
+
+        let expected = [
+            (Aggregation::Min, [0.0, 4.0, 8.0]),
+            (Aggregation::Max, [3.0, 7.0, 9.0]),
+            (Aggregation::Mean, [1.5, 5.5, 8.5]),
+            (Aggregation::Count, [4.0, 4.0, 2.0]),
+            (Aggregation::First, [0.0, 4.0, 8.0]),
+            (Aggregation::Last, [3.0, 7.0, 9.0]),
+        ];
+        for (aggregation, expected) in &expected {
+            let (values, _) = aggregate(&block_storage, &identity, *aggregation, None, &mut 10);
+            let values: Vec<_> = values.into_iter().map(|(_, value)| value).collect();
+            assert_eq!(values, expected, "{:?}", aggregation);
+        }
+    }
+
+    #[test]
+    fn undecodable_values_are_ignored() {
+        let block_storage = block_storage();
+        let identity = Identity::generate();
+        let mut values = numbers(4);
+        values[1] = b"not a number".to_vec();
+        write_values(&block_storage, &identity, &values);
+
+        let (values, _) = aggregate(&block_storage, &identity, Aggregation::Mean, None, &mut 10);
+        assert_eq!(values, vec![(at(1_000_000), 5.0 / 3.0)]);
+    }
+
+    #[test]
+    fn values_are_decoded_by_their_schema() {
+        let block_storage = block_storage();
+        let identity = Identity::generate();
+        let values: Vec<_> = [-2_i8, 3, -4]
+            .iter()
+            .map(|value| postcard::to_stdvec(value).unwrap())
+            .collect();
+        write_values(&block_storage, &identity, &values);
+
+        let query = AggregateQuery {
+            span: 100.into(),
+            end: 0.into(),
+            bucket: BUCKET,
+            aggregation: Aggregation::Min,
+        };
+        let time_series_name = [identity.id().as_bytes(), "speed".as_bytes()].join(&0);
+        let aggregate = |value_type| {
+            let (values, _) = block_storage
+                .aggregate_transactions_inner(&time_series_name, &query, value_type, None, &mut 10)
+                .unwrap();
+            values.into_iter().collect::<Vec<_>>()
+        };
+        assert_eq!(aggregate(ValueType::Integer), vec![(at(1_000_000), -4.0)]);
+        // The same bytes are other numbers when decoded as unsigned integers.
+        assert_eq!(aggregate(ValueType::Unsigned), vec![(at(1_000_000), 3.0)]);
+        assert_eq!(aggregate(ValueType::Float), vec![]);
+    }
+
+    #[test]
+    fn buckets_are_not_split_into_pages() {
+        let block_storage = block_storage();
+        let identity = Identity::generate();
+        write_values(&block_storage, &identity, &numbers(10));
+
+        // The newest buckets are read first.
+        let mut budget = 2;
+        let (values, position) = aggregate(
+            &block_storage,
+            &identity,
+            Aggregation::Count,
+            None,
+            &mut budget,
+        );
+        assert_eq!(budget, 0);
+        assert_eq!(values, vec![(at(1_004_000), 4.0), (at(1_008_000), 2.0)]);
+        assert!(position.is_some());
+
+        let mut budget = 2;
+        let (values, position) = aggregate(
+            &block_storage,
+            &identity,
+            Aggregation::Count,
+            position,
+            &mut budget,
+        );
+        assert_eq!(budget, 1);
+        assert_eq!(values, vec![(at(1_000_000), 4.0)]);
+        assert!(position.is_none());
+    }
+
+    #[test]
+    fn empty_buckets_are_rejected() {
+        let block_storage = block_storage();
+        let identity = Identity::generate();
+        let query = AggregateQuery {
+            span: 100.into(),
+            end: 0.into(),
+            bucket: Duration::default(),
+            aggregation: Aggregation::Count,
+        };
+        let result = block_storage.aggregate_transactions_inner(
+            &[identity.id().as_bytes(), "speed".as_bytes()].join(&0),
+            &query,
+            ValueType::Float,
+            None,
+            &mut 10,
+        );
+        assert!(matches!(result, Err(Error::EmptyBucket)));
+    }
+
+    /// The `WorldState` after the genesis block (with a recorded transaction).
+    fn genesis_world_state(block_storage: &BlockStorage) -> WorldState {
+        let genesis_block = block_storage.read(..).next().unwrap().unwrap();
+        let mut world_state = WorldState::default();
+        world_state.apply_block(genesis_block).unwrap();
+
+        let identity = Identity::generate();
+        let transaction = Transaction::KeyValue(transaction::KeyValue {
+            key: "speed".to_string(),
+            value: vec![42],
+            timestamp: at(1_000_000),
+        });
+        world_state.record_transaction(identity.id(), &transaction);
+        world_state
+    }
+
+    #[test]
+    fn snapshots_round_trip() {
+        let block_storage = block_storage();
+        let world_state = genesis_world_state(&block_storage);
+        block_storage.write_snapshot(&world_state).unwrap();
+
+        let snapshot = block_storage
+            .read_snapshot(world_state.block_number)
+            .unwrap()
+            .unwrap();
+        assert_eq!(snapshot.block_number, world_state.block_number);
+        assert_eq!(snapshot.last_block_hash, world_state.last_block_hash);
+        assert_eq!(snapshot.state_root(), world_state.state_root());
+
+        // The snapshot is newer than the requested block number.
+        let block_number = world_state.block_number - 1;
+        assert!(block_storage.read_snapshot(block_number).unwrap().is_none());
+    }
+
+    #[test]
+    fn snapshots_of_other_chains_are_skipped() {
+        let block_storage = block_storage();
+        let mut world_state = genesis_world_state(&block_storage);
+        world_state.last_block_hash = BlockHash::default();
+        block_storage.write_snapshot(&world_state).unwrap();
+
+        assert!(block_storage
+            .read_snapshot(world_state.block_number)
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn snapshots_with_another_state_root_are_skipped() {
+        let block_storage = block_storage();
+        let mut world_state = genesis_world_state(&block_storage);
+        let genesis_block = block_storage.read(..).next().unwrap().unwrap();
+
+        // The snapshot is only valid with the state root of its last block.
+        for state_root in &[StateRoot::default(), world_state.state_root()] {
+            let block = Block {
+                body: Body::new(
+                    LeaderTerm::default(),
+                    BlockNumber::new(1),
+                    genesis_block.hash(),
+                    at(1_000_000),
+                    *state_root,
+                    Vec::new(),
+                ),
+                signatures: SignatureList::default(),
+            };
+            world_state.block_number = BlockNumber::new(2);
+            world_state.last_block_hash = block.hash();
+            block_storage.blocks.clear().unwrap();
+            block_storage.snapshots.clear().unwrap();
+            block_storage
+                .write_snapshot_block(&block, &world_state)
+                .unwrap();
+
+            let snapshot = block_storage
+                .read_snapshot(world_state.block_number)
+                .unwrap();
+            assert_eq!(snapshot.is_some(), *state_root == world_state.state_root());
+        }
+    }
+
+    #[test]
+    fn snapshot_chunks_round_trip() {
+        let block_storage = block_storage();
+        let world_state = genesis_world_state(&block_storage);
+        block_storage.write_snapshot(&world_state).unwrap();
+        let encoded = postcard::to_stdvec(&world_state).unwrap();
+
+        let chunk = block_storage.read_snapshot_chunk(None, 0).unwrap().unwrap();
+        assert_eq!(chunk.block_number, world_state.block_number);
+        assert_eq!(chunk.size, encoded.len() as u64);
+        assert_eq!(chunk.data, encoded);
+        let snapshot: WorldState = postcard::from_bytes(&chunk.data).unwrap();
+        assert_eq!(snapshot.state_root(), world_state.state_root());
+
+        let chunk = block_storage
+            .read_snapshot_chunk(Some(world_state.block_number), 10)
+            .unwrap()
+            .unwrap();
+        assert_eq!(chunk.data, &encoded[10..]);
+
+        let chunk = block_storage
+            .read_snapshot_chunk(Some(world_state.block_number), chunk.size + 1)
+            .unwrap()
+            .unwrap();
+        assert!(chunk.data.is_empty());
+
+        let block_number = world_state.block_number + 1;
+        assert!(block_storage
+            .read_snapshot_chunk(Some(block_number), 0)
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn only_the_newest_snapshots_are_kept() {
+        let block_storage = block_storage();
+        let mut world_state = genesis_world_state(&block_storage);
+        for block_number in 1..=5 {
+            world_state.block_number = BlockNumber::new(block_number);
+            block_storage.write_snapshot(&world_state).unwrap();
+        }
+
+        assert_eq!(block_storage.snapshots.len(), SNAPSHOTS_TO_KEEP);
+        let chunk = block_storage.read_snapshot_chunk(None, 0).unwrap().unwrap();
+        assert_eq!(chunk.block_number, BlockNumber::new(5));
+        assert!(block_storage
+            .read_snapshot_chunk(Some(BlockNumber::new(2)), 0)
+            .unwrap()
+            .is_none());
+    }
+
+    fn blob_chunk(data: &[u8], index: usize) -> PutBlob {
+        let chunk_size = BLOB_CHUNK_SIZE as usize;
+        PutBlob {
+            hash: BlobHash::of(data),
+            size: data.len() as u64,
+            offset: (index * chunk_size) as u64,
+            data: data.chunks(chunk_size).nth(index).unwrap_or(&[]).to_vec(),
+        }
+    }
+
+    #[test]
+    fn blob_chunks_are_stored_in_any_order() {
+        let block_storage = block_storage();
+        let identity = Identity::generate();
+        let data: Vec<u8> = (0..=u8::MAX)
+            .cycle()
+            .take(2 * BLOB_CHUNK_SIZE as usize + 5)
+            .collect();
+        let hash = BlobHash::of(&data);
+
+        for index in &[2, 0] {
+            let chunk = blob_chunk(&data, *index);
+            assert!(!block_storage
+                .write_blob_chunk(identity.id(), &chunk)
+                .unwrap());
+        }
+        // Duplicate chunks are ignored.
+        let chunk = blob_chunk(&data, 0);
+        assert!(!block_storage
+            .write_blob_chunk(identity.id(), &chunk)
+            .unwrap());
+        assert!(!block_storage.has_blob(&hash).unwrap());
+
+        let chunk = blob_chunk(&data, 1);
+        assert!(block_storage
+            .write_blob_chunk(identity.id(), &chunk)
+            .unwrap());
+        assert_eq!(
+            block_storage.blob_size(&hash).unwrap(),
+            Some(data.len() as u64)
+        );
+        let chunk = block_storage
+            .read_blob_chunk(&hash, BLOB_CHUNK_SIZE)
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            chunk.data,
+            &data[BLOB_CHUNK_SIZE as usize..2 * BLOB_CHUNK_SIZE as usize]
+        );
+        assert!(block_storage.blob_uploads.is_empty());
+        assert!(block_storage.blob_upload_chunks.is_empty());
+        assert!(block_storage.blob_upload_chunk_counts.is_empty());
+
+        // Empty blobs are uploaded as one empty chunk.
+        let chunk = blob_chunk(&[], 0);
+        assert!(block_storage
+            .write_blob_chunk(identity.id(), &chunk)
+            .unwrap());
+        assert_eq!(
+            block_storage.blob_size(&BlobHash::of(&[])).unwrap(),
+            Some(0)
+        );
+    }
+
+    #[test]
+    fn invalid_blob_chunks_are_rejected() {
+        let block_storage = block_storage();
+        let identity = Identity::generate();
+        let data = vec![0xff; BLOB_CHUNK_SIZE as usize + 5];
+
+        let mut misaligned = blob_chunk(&data, 1);
+        misaligned.offset -= 1;
+        let mut too_short = blob_chunk(&data, 0);
+        too_short.data.pop();
+        let mut too_long = blob_chunk(&data, 1);
+        too_long.data.push(0);
+        let mut beyond_the_end = blob_chunk(&data, 1);
+        beyond_the_end.offset += BLOB_CHUNK_SIZE;
+        for chunk in &[misaligned, too_short, too_long, beyond_the_end] {
+            let result = block_storage.write_blob_chunk(identity.id(), chunk);
+            assert!(matches!(result, Err(Error::InvalidBlobChunk)));
+        }
+
+        // The payload is only stored if it matches its hash.
+        let mut other_data = data.clone();
+        other_data[0] = 0;
+        let mut chunk = blob_chunk(&other_data, 0);
+        chunk.hash = BlobHash::of(&data);
+        assert!(!block_storage
+            .write_blob_chunk(identity.id(), &chunk)
+            .unwrap());
+        let result = block_storage.write_blob_chunk(identity.id(), &blob_chunk(&data, 1));
+        assert!(matches!(result, Err(Error::BlobHashDoesNotMatch)));
+        assert!(!block_storage.has_blob(&BlobHash::of(&data)).unwrap());
+        assert!(block_storage.blob_uploads.is_empty());
+        assert!(block_storage.blob_upload_chunks.is_empty());
+    }
+
+    #[test]
+    fn unreferenced_blobs_and_incomplete_uploads_are_pruned() {
+        let block_storage = block_storage();
+        let identity = Identity::generate();
+        let unreferenced = block_storage.write_blob(&[1, 2, 3]).unwrap();
+        let referenced = block_storage.write_blob(&[4, 5, 6]).unwrap();
+        block_storage.count_blob_ref(&referenced, true).unwrap();
+        let incomplete = vec![0xff; BLOB_CHUNK_SIZE as usize + 5];
+        block_storage
+            .write_blob_chunk(identity.id(), &blob_chunk(&incomplete, 0))
+            .unwrap();
+
+        let now = SystemTime::now();
+        block_storage.prune_blobs(now).unwrap();
+        assert!(block_storage.has_blob(&unreferenced).unwrap());
+        assert!(block_storage.has_blob(&referenced).unwrap());
+        assert_eq!(block_storage.blob_uploads.len(), 1);
+
+        block_storage
+            .prune_blobs(now + UNREFERENCED_BLOB_LIFETIME + Duration::from_secs(1))
+            .unwrap();
+        assert!(!block_storage.has_blob(&unreferenced).unwrap());
+        assert!(block_storage.has_blob(&referenced).unwrap());
+        assert!(block_storage.blob_uploads.is_empty());
+        assert!(block_storage.blob_upload_chunks.is_empty());
+        assert!(block_storage.blob_upload_chunk_counts.is_empty());
+    }
+}
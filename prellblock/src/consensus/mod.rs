@@ -7,6 +7,6 @@ pub use praftbft::{
     ConsensusMessage, ConsensusResponse, Error, PRaftBFT as Consensus, Queue, RingBuffer,
 };
 pub(crate) use prellblock_client_api::consensus::{
-    Block, BlockHash, BlockNumber, Body, LeaderTerm, SignatureList,
+    Block, BlockHash, BlockNumber, Body, LeaderTerm, SignatureList, StateRoot,
 };
 pub use transaction_applier::TransactionApplier;
@@ -3,14 +3,14 @@ use crate::{
     block_storage::BlockStorage,
     consensus::{LeaderTerm, SignatureList, TransactionApplier},
     peer::{message as peer_message, Sender},
-    transaction_checker::TransactionChecker,
+    transaction_checker::{TransactionChecker, MAX_CLOCK_SKEW},
     world_state::WorldStateService,
 };
 use futures::{stream::FuturesUnordered, StreamExt};
 use newtype_enum::Enum;
-use pinxit::{Identity, PeerId, Signable, Signed, Verified};
-use prellblock_client_api::Transaction;
-use std::net::SocketAddr;
+use pinxit::{Identity, PeerId, Signable, Verified};
+use prellblock_client_api::consensus::supermajority_reached;
+use std::{net::SocketAddr, time::SystemTime};
 use tokio::sync::{Mutex, Notify};
 
 #[derive(Debug)]
@@ -20,7 +20,7 @@ pub struct Core {
     pub(super) world_state: WorldStateService,
     pub(super) transaction_applier: TransactionApplier,
     pub(super) transaction_checker: TransactionChecker,
-    pub(super) queue: Mutex<Queue<Signed<Transaction>>>,
+    pub(super) queue: Mutex<Queue>,
     pub(super) notify_censorship_checker: Notify,
     pub(super) notify_leader: Notify,
 }
@@ -82,6 +82,27 @@ impl Core {
         Ok(())
     }
 
+    /// Verify that a block `timestamp` is not before the previous block and not in the future.
+    pub fn verify_block_timestamp(&self, timestamp: SystemTime) -> Result<(), Error> {
+        if let Some(last_block_timestamp) = self.world_state.get().last_block_timestamp {
+            if timestamp < last_block_timestamp {
+                return Err(Error::BlockTimestampNotMonotonic(
+                    timestamp,
+                    last_block_timestamp,
+                ));
+            }
+        }
+
+        let is_in_future = SystemTime::now()
+            .checked_add(MAX_CLOCK_SKEW)
+            .map_or(false, |latest| timestamp > latest);
+        if is_in_future {
+            return Err(Error::BlockTimestampInFuture(timestamp));
+        }
+
+        Ok(())
+    }
+
     #[allow(clippy::future_not_send)]
     pub async fn send_message<M>(
         &self,
@@ -185,13 +206,3 @@ where
     let response = response.verify()?;
     response.try_map(|response| response.into_variant().ok_or(Error::UnexpectedResponse))
 }
-
-/// Check whether a number represents a supermajority (>2/3) compared
-/// to the total number of peers (`peer_count`) in the consenus.
-pub fn supermajority_reached(response_len: usize, peer_count: usize) -> bool {
-    if peer_count < 4 {
-        panic!("Cannot find consensus for less than four peers.");
-    }
-    let supermajority = peer_count * 2 / 3 + 1;
-    response_len >= supermajority
-}
@@ -7,6 +7,7 @@ use crate::{
 use err_derive::Error;
 use pinxit::PeerId;
 use prellblock_client_api::Transaction;
+use std::time::SystemTime;
 
 /// An error of the `praftbft` consensus.
 #[derive(Debug, Error)]
@@ -85,6 +86,18 @@ pub enum Error {
     )]
     BadInvalidTransactionIndex(usize),
 
+    /// The block timestamp is before the timestamp of the previous block.
+    #[error(
+        display = "The block timestamp {:?} is before the previous block timestamp {:?}.",
+        0,
+        1
+    )]
+    BlockTimestampNotMonotonic(SystemTime, SystemTime),
+
+    /// The block timestamp is in the future.
+    #[error(display = "The block timestamp {:?} is in the future.", 0)]
+    BlockTimestampInFuture(SystemTime),
+
     /// The ack message does not match the request.
     #[error(display = "The ack message does not match the request.")]
     AckDoesNotMatch,
@@ -108,6 +121,45 @@ pub enum Error {
     )]
     PrevBlockHashDoesNotMatch(BlockHash, BlockHash),
 
+    /// The `StateRoot` of the `Block` does not match the resulting `WorldState`.
+    #[error(
+        display = "The StateRoot of block #{} does not match the resulting WorldState.",
+        0
+    )]
+    StateRootDoesNotMatch(BlockNumber),
+
+    /// A synchronized `WorldState` snapshot could not be decoded.
+    #[error(display = "The snapshot could not be decoded.")]
+    InvalidSnapshot,
+
+    /// A synchronized `WorldState` snapshot does not belong to the sent block.
+    #[error(display = "The snapshot does not match block #{}.", 0)]
+    SnapshotDoesNotMatch(BlockNumber),
+
+    /// A synchronized `WorldState` snapshot exceeds the `MAX_SNAPSHOT_SIZE`.
+    #[error(display = "The snapshot is too large ({} bytes).", 0)]
+    SnapshotTooLarge(u64),
+
+    /// The synchronized headers of the blocks before a snapshot do not form a chain.
+    #[error(display = "The header of block #{} does not match the chain.", 0)]
+    HeaderDoesNotMatch(BlockNumber),
+
+    /// The signatures of a synchronized block (or header) are not valid
+    /// for the RPUs at its height.
+    #[error(display = "The signatures of block #{} are invalid: {}", 0, 1)]
+    InvalidBlockSignatures(BlockNumber, String),
+
+    /// The RPUs of a synchronized `WorldState` snapshot do not match the verified history.
+    #[error(
+        display = "The RPUs of the snapshot at block #{} do not match the verified history.",
+        0
+    )]
+    RPUsDoNotMatch(BlockNumber),
+
+    /// A synchronized index entry is not included in its block.
+    #[error(display = "An index entry is not included in block #{}.", 0)]
+    IndexEntryNotIncluded(BlockNumber),
+
     // ----------------------------------------------------------------
     // Errors with the block number.
     // ----------------------------------------------------------------
@@ -6,7 +6,7 @@ pub use state::Phase;
 
 use super::{
     message::{consensus_message as message, consensus_response as response},
-    Core, Error, ErrorVerify, InvalidTransaction, NotifyMap, ViewChange,
+    Core, Error, ErrorVerify, InvalidTransaction, NotifyMap, RejectedTransaction, ViewChange,
 };
 use crate::consensus::{BlockNumber, LeaderTerm};
 use pinxit::PeerId;
@@ -43,6 +43,26 @@ impl Follower {
         self.state.lock().await
     }
 
+    /// Get the number of the current block (the number of applied blocks).
+    pub async fn block_number(&self) -> BlockNumber {
+        self.state.lock().await.block_number
+    }
+
+    /// Wait until the current block number is at least `block_number`.
+    ///
+    /// Blocks applied before calling this are not missed
+    /// (even if several blocks were applied at once by a synchronization).
+    pub async fn wait_for_block(&self, block_number: BlockNumber) {
+        let mut state = self.state.lock().await;
+        if state.block_number >= block_number {
+            return;
+        }
+        // Register while holding the lock, so we won't miss the block being applied.
+        let wait = state.block_changed.wait(block_number);
+        drop(state);
+        wait.await;
+    }
+
     /// Wait until we reached the block number the message is at.
     async fn state_in_block(
         &self,
@@ -115,12 +135,16 @@ impl Follower {
         message.block_number.verify(state.block_number)?;
 
         let metadata = message.metadata.clone();
-        let (body, invalid_transactions) = self
+        let (body, rejected_transactions) = self
             .view_change
             .request_view_change_on_error(async {
                 // Validate the Block Hash.
                 let block_hash = message.block_hash;
-                let body = state.body_with(message.valid_transactions, message.timestamp);
+                let body = state.body_with(
+                    message.valid_transactions,
+                    message.timestamp,
+                    message.state_root,
+                );
                 if body.hash() != block_hash {
                     return Err(Error::BlockNotMatchingHash);
                 }
@@ -141,20 +165,32 @@ impl Follower {
                     &message.ackprepare_signatures,
                 )?;
 
-                if body.transactions.is_empty() {
+                if body.transactions().is_empty() {
                     // Empty blocks are not allowed.
                     return Err(Error::EmptyBlock);
                 }
 
+                // The transactions are checked against the block timestamp.
+                self.verify_block_timestamp(body.timestamp())?;
+
                 // Check for transaction validity.
-                self.stateful_validate(&body.transactions, &message.invalid_transactions)?;
+                let (rejected_transactions, state_root) = self.stateful_validate(
+                    body.transactions(),
+                    &message.invalid_transactions,
+                    body.timestamp(),
+                )?;
+
+                // The block has to commit to the resulting `WorldState`.
+                if state_root != body.state_root() {
+                    return Err(Error::StateRootDoesNotMatch(body.height()));
+                }
 
-                Ok((body, message.invalid_transactions))
+                Ok((body, rejected_transactions))
             })
             .await?;
 
         // All checks passed, update our state.
-        state.append(body, invalid_transactions);
+        state.append(body, rejected_transactions);
 
         // There could be a commit message for this block number that arrived first.
         // We then need to apply the commit (or at least check).
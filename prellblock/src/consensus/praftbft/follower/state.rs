@@ -1,7 +1,11 @@
-use super::{message, Core, Error, InvalidTransaction, NotifyMap};
-use crate::consensus::{Block, BlockHash, BlockNumber, Body, LeaderTerm, SignatureList};
+use super::{message, Core, Error, NotifyMap, RejectedTransaction};
+use crate::{
+    block_storage::HeaderEntry,
+    consensus::{Block, BlockHash, BlockNumber, Body, LeaderTerm, SignatureList, StateRoot},
+    world_state::WorldState,
+};
 use pinxit::{PeerId, Signed};
-use prellblock_client_api::Transaction;
+use prellblock_client_api::{consensus::TransactionHash, Transaction};
 use std::{ops::Deref, sync::Arc, time::SystemTime};
 
 #[derive(Debug)]
@@ -22,7 +26,7 @@ pub struct State {
     /// The hash of the current block. (Set in prepare phase)
     pub block_hash: Option<BlockHash>,
     /// The body of the current block. (Set in append phase)
-    pub block_content: Option<(Body, Vec<InvalidTransaction>)>,
+    pub block_content: Option<(Body, Vec<RejectedTransaction>)>,
     /// Wheter an rollback is currently allowed (only once after a leader change)
     pub rollback_possible: bool,
 
@@ -47,6 +51,7 @@ pub enum Phase {
 impl State {
     pub fn new(core: Arc<Core>) -> Self {
         let world_state = core.world_state.get();
+        let rollback_possible = core.world_state.can_rollback();
         Self {
             core,
             leader_term: LeaderTerm::default(),
@@ -56,7 +61,7 @@ impl State {
             last_block_hash: world_state.last_block_hash,
             block_hash: None,
             block_content: None,
-            rollback_possible: world_state.block_number > BlockNumber::default(),
+            rollback_possible,
             buffered_commit_message: None,
         }
     }
@@ -81,14 +86,20 @@ impl State {
     }
 
     /// Create a body with the given `transactions`.
-    pub fn body_with(&self, transactions: Vec<Signed<Transaction>>, timestamp: SystemTime) -> Body {
-        Body {
-            leader_term: self.leader_term,
-            height: self.block_number,
-            prev_block_hash: self.last_block_hash,
+    pub fn body_with(
+        &self,
+        transactions: Vec<Signed<Transaction>>,
+        timestamp: SystemTime,
+        state_root: StateRoot,
+    ) -> Body {
+        Body::new(
+            self.leader_term,
+            self.block_number,
+            self.last_block_hash,
             timestamp,
+            state_root,
             transactions,
-        }
+        )
     }
 
     /// Create a body signed by `ackappend_signatures`.
@@ -110,9 +121,9 @@ impl State {
     /// Move to the append phase.
     ///
     /// Panics if not in prepare phase.
-    pub fn append(&mut self, body: Body, invalid_transactions: Vec<InvalidTransaction>) {
+    pub fn append(&mut self, body: Body, rejected_transactions: Vec<RejectedTransaction>) {
         assert_eq!(self.phase(), Phase::Prepare);
-        self.block_content = Some((body, invalid_transactions))
+        self.block_content = Some((body, rejected_transactions))
     }
 
     /// Commit a block using a list of ackappend `signatures`.
@@ -129,16 +140,28 @@ impl State {
 
         // We are sure that these transactions are really invalid and therefore
         // they can be removed from the queue without losing good transactions.
-        let (_, invalid_transactions) = self.block_content.as_ref().unwrap();
-        if !invalid_transactions.is_empty() {
+        let (_, rejected_transactions) = self.block_content.as_ref().unwrap();
+        if !rejected_transactions.is_empty() {
             log::warn!(
                 "Removing invalid transactions from queue: {:#?}",
-                invalid_transactions
+                rejected_transactions
             );
             self.queue
                 .lock()
                 .await
-                .remove_all(invalid_transactions.iter().map(|(_, tx)| tx));
+                .remove_all(rejected_transactions.iter().map(|(tx, _)| tx));
+
+            // Remember the rejections to answer status requests of clients.
+            // They are only needed for status requests, so failing to write them is not fatal.
+            for (transaction, reason) in rejected_transactions {
+                if let Err(err) = self.block_storage.write_rejected_transaction(
+                    &TransactionHash::of(transaction),
+                    self.block_number,
+                    reason,
+                ) {
+                    log::warn!("Could not write rejected transaction: {}", err);
+                }
+            }
         }
 
         // Must be called at last because it resets the state.
@@ -155,7 +178,7 @@ impl State {
         self.queue
             .lock()
             .await
-            .remove_all(block.body.transactions.iter());
+            .remove_all(block.body.transactions().iter());
 
         // Applies block.
         self.transaction_applier.apply_block(block).await;
@@ -173,6 +196,40 @@ impl State {
         self.block_changed.notify_all(&self.block_number);
     }
 
+    /// Continue at a verified snapshot `world_state` after the `block`.
+    ///
+    /// The verified `headers` of the blocks before are stored instead of the blocks.
+    pub async fn apply_snapshot(
+        &mut self,
+        block: Block,
+        world_state: WorldState,
+        headers: &[HeaderEntry],
+    ) -> Result<(), Error> {
+        self.block_storage
+            .import_snapshot(&block, &world_state, headers)?;
+
+        // Remove committed transactions from our queue.
+        self.queue
+            .lock()
+            .await
+            .remove_all(block.body.transactions().iter());
+
+        // Setup next round.
+        self.block_number = world_state.block_number;
+        self.last_block_hash = world_state.last_block_hash;
+        self.world_state.reset(world_state).await;
+        self.block_hash = None;
+        self.block_content = None;
+        // The blocks before the snapshot are not known.
+        self.rollback_possible = false;
+
+        self.buffered_commit_message = None;
+
+        // Notify waiting tasks
+        self.block_changed.notify_all(&self.block_number);
+        Ok(())
+    }
+
     /// Set a new `leader_term`.
     pub fn new_leader_term(&mut self, leader_term: LeaderTerm, new_view_signatures: SignatureList) {
         self.leader_term = leader_term;
@@ -198,11 +255,18 @@ impl State {
 
         // BlockStorage remove topmost block.
         // Double Unwrap should be fine because there needs to be some block.
-        let last_block = self.block_storage.pop_block().unwrap().unwrap();
+        let last_block = self
+            .block_storage
+            .pop_block(&self.world_state.get())
+            .unwrap()
+            .unwrap();
         assert_eq!(last_block.block_number() + 1, self.block_number);
 
         // The transactions may not be lost.
-        self.queue.lock().await.extend(last_block.body.transactions);
+        self.queue
+            .lock()
+            .await
+            .extend(last_block.body.into_transactions());
 
         // We ignore all invalid transactions during rolllback. They will be lost.
         // (They would be lost anyway after a restart.)
@@ -1,14 +1,21 @@
-use super::{Error, Follower, InvalidTransaction};
+use super::{Error, Follower, InvalidTransaction, RejectedTransaction};
+use crate::consensus::StateRoot;
 use pinxit::{verify_signed_batch_iter, Signed};
 use prellblock_client_api::Transaction;
+use std::time::SystemTime;
 
 impl Follower {
     /// Stateful validate transactions sent by the leader.
+    ///
+    /// The transactions are checked against the `timestamp` of the block.
+    /// Returns the transactions marked as invalid together with the reason they were rejected
+    /// and the `StateRoot` of the `WorldState` after applying the valid transactions.
     pub(super) fn stateful_validate(
         &self,
         valid_transactions: &[Signed<Transaction>],
         invalid_transactions: &[InvalidTransaction],
-    ) -> Result<(), Error> {
+        timestamp: SystemTime,
+    ) -> Result<(Vec<RejectedTransaction>, StateRoot), Error> {
         let number_of_valid_transactions = valid_transactions.len();
         let mut valid_transactions = verify_signed_batch_iter(valid_transactions.iter())?;
 
@@ -24,6 +31,8 @@ impl Follower {
             .zip(verify_signed_batch_iter(invalid_transactions_iter)?);
 
         let mut check = self.transaction_checker.check();
+        check.set_block_timestamp(timestamp);
+        let mut rejected_transactions = Vec::new();
 
         let mut index = 0;
         loop {
@@ -47,13 +56,16 @@ impl Follower {
             // Otherwise the leader tries to trick followers into dropping valid transactions
             // from the queue (which is like censorship).
             if let Some((_, verified_invalid_transaction)) = invalid_item {
-                if check
-                    .verify_permissions_and_apply(verified_invalid_transaction)
-                    .is_ok()
-                {
-                    return Err(Error::CensorshipDetected(
-                        (*verified_invalid_transaction).clone().into(),
-                    ));
+                match check.verify_permissions_and_apply(verified_invalid_transaction) {
+                    Ok(()) => {
+                        return Err(Error::CensorshipDetected(
+                            (*verified_invalid_transaction).clone().into(),
+                        ));
+                    }
+                    Err(err) => {
+                        let transaction: &Signed<Transaction> = verified_invalid_transaction.into();
+                        rejected_transactions.push((transaction.clone(), err.to_string()));
+                    }
                 }
             } else {
                 break;
@@ -64,6 +76,6 @@ impl Follower {
         assert_eq!(valid_transactions.len(), 0);
         assert_eq!(invalid_transactions.len(), 0);
 
-        Ok(())
+        Ok((rejected_transactions, check.state_root()))
     }
 }
@@ -1,12 +1,33 @@
 use super::{super::message::Metadata, message, response, Error, ErrorVerify, Follower, State};
-use crate::consensus::{Block, BlockNumber, LeaderTerm};
-use pinxit::PeerId;
+use crate::{
+    block_storage::{changes_accounts, HeaderEntry, IndexEntry},
+    consensus::{Block, BlockNumber, LeaderTerm, SignatureList},
+    transaction_checker::TransactionCheck,
+    world_state::WorldState,
+};
+use pinxit::{verify_signed_batch_iter, PeerId, Signed};
+use prellblock_client_api::{
+    consensus::{BlockHeader, MerkleHash},
+    Transaction,
+};
 use rand::Rng;
 use std::net::SocketAddr;
 use tokio::sync::{MutexGuard, SemaphorePermit};
 
 const SYNCHRONIZATION_BLOCK_THRESHOLD: u64 = 3;
 
+/// The maximum number of blocks sent in a single `SynchronizationResponse`.
+const MAX_SYNCHRONIZATION_BLOCKS: u64 = 1000;
+
+/// The maximum number of headers sent in a single `HeaderResponse`.
+const MAX_SYNCHRONIZATION_HEADERS: u64 = 10_000;
+
+/// The maximum number of index entries sent in a single `IndexResponse`.
+const MAX_INDEX_ENTRIES: usize = 10_000;
+
+/// The maximum size of a synchronized `WorldState` snapshot.
+const MAX_SNAPSHOT_SIZE: u64 = 256 * 1024 * 1024;
+
 impl Follower {
     /// Synchronize if there is only one instance of synchronisation running.
     pub async fn synchronize_if_needed(
@@ -78,9 +99,54 @@ impl Follower {
     async fn synchronize(
         &self,
         synchronizer_permit: SemaphorePermit<'_>,
-        state: MutexGuard<'_, State>,
+        mut state: MutexGuard<'_, State>,
         peer_address: SocketAddr,
     ) -> Result<MutexGuard<'_, State>, Error> {
+        // A new RPU (that only knows the genesis block) starts at the newest snapshot of the peer.
+        if state.block_number == BlockNumber::new(1) {
+            match self.fetch_snapshot(peer_address).await {
+                Ok(Some((block, world_state))) => {
+                    if let Err(err) = self
+                        .import_snapshot(&mut state, peer_address, block, world_state)
+                        .await
+                    {
+                        log::warn!(
+                            "Could not import the snapshot (replaying all blocks instead): {}",
+                            err
+                        );
+                    }
+                }
+                Ok(None) => {}
+                Err(err) => log::warn!(
+                    "Could not synchronize from a snapshot (replaying all blocks instead): {}",
+                    err
+                ),
+            }
+        }
+
+        // The blocks are sent in multiple responses.
+        loop {
+            let block_number = state.block_number;
+            let (next_state, complete) = self.synchronize_blocks(state, peer_address).await?;
+            state = next_state;
+            if complete || state.block_number == block_number {
+                break;
+            }
+        }
+
+        log::trace!("Done synchronizing.");
+        drop(synchronizer_permit);
+        Ok(state)
+    }
+
+    /// Fetch the blocks after our current block.
+    ///
+    /// Returns whether all blocks of the peer were received.
+    async fn synchronize_blocks<'a>(
+        &'a self,
+        state: MutexGuard<'a, State>,
+        peer_address: SocketAddr,
+    ) -> Result<(MutexGuard<'a, State>, bool), Error> {
         let request = message::SynchronizationRequest {
             leader_term: state.leader_term,
             block_number: state.block_number,
@@ -108,6 +174,7 @@ impl Follower {
 
         if let Some(first_block) = response.blocks.first() {
             if state.rollback_possible
+                && self.world_state.can_rollback()
                 && first_block.block_number() + 1 == state.block_number
                 && first_block.hash() != state.last_block_hash
             {
@@ -124,26 +191,190 @@ impl Follower {
             "Received {} blocks while synchronizing.",
             response.blocks.len()
         );
+        let complete = (response.blocks.len() as u64) < MAX_SYNCHRONIZATION_BLOCKS;
         for block in response.blocks {
             log::trace!("Applying synchronized block: {:#?}", block);
-            if block.body.height < state.block_number {
+            if block.body.height() < state.block_number {
                 continue;
             }
 
             self.apply_synchronized_block(&mut state, block).await?;
         }
 
-        log::trace!("Done synchronizing.");
-        drop(synchronizer_permit);
-        Ok(state)
+        Ok((state, complete))
+    }
+
+    /// Download the newest snapshot of a peer and verify that it belongs to its block.
+    ///
+    /// The last block applied to the snapshot has to commit to the snapshot via its `StateRoot`
+    /// (its signatures are verified with the history of the RPUs in `import_snapshot`).
+    /// Returns `None` if the peer has no snapshot after the genesis block.
+    async fn fetch_snapshot(
+        &self,
+        peer_address: SocketAddr,
+    ) -> Result<Option<(Block, WorldState)>, Error> {
+        let mut snapshot_block_number = None;
+        let mut block = None;
+        let mut data = Vec::new();
+        loop {
+            let request = message::SnapshotRequest {
+                block_number: snapshot_block_number,
+                offset: data.len() as u64,
+            };
+            let response = self.send_message(peer_address, request).await?.into_inner();
+            let chunk = match response.chunk {
+                Some(chunk) => chunk,
+                None if snapshot_block_number.is_none() => return Ok(None),
+                None => return Err(Error::InvalidSnapshot),
+            };
+            if snapshot_block_number.is_none() {
+                // We already know the genesis block (see `synchronize`).
+                if chunk.block_number <= BlockNumber::new(1) {
+                    return Ok(None);
+                }
+                block = response.block;
+                snapshot_block_number = Some(chunk.block_number);
+            }
+            if chunk.size > MAX_SNAPSHOT_SIZE {
+                return Err(Error::SnapshotTooLarge(chunk.size));
+            }
+
+            if chunk.data.is_empty() {
+                break;
+            }
+            data.extend(chunk.data);
+            if data.len() as u64 > MAX_SNAPSHOT_SIZE {
+                return Err(Error::SnapshotTooLarge(data.len() as u64));
+            }
+            log::trace!(
+                "Received {} of {} bytes of the snapshot.",
+                data.len(),
+                chunk.size
+            );
+            if data.len() as u64 >= chunk.size {
+                break;
+            }
+        }
+
+        let block = block.ok_or(Error::InvalidSnapshot)?;
+        let world_state: WorldState =
+            postcard::from_bytes(&data).map_err(|_| Error::InvalidSnapshot)?;
+
+        if world_state.block_number != block.block_number() + 1
+            || world_state.last_block_hash != block.hash()
+        {
+            return Err(Error::SnapshotDoesNotMatch(block.block_number()));
+        }
+        if world_state.state_root() != block.body.state_root() {
+            return Err(Error::StateRootDoesNotMatch(block.block_number()));
+        }
+
+        Ok(Some((block, world_state)))
+    }
+
+    /// Download the time-series indexes up to the snapshot's `block` and continue at the snapshot.
+    ///
+    /// The history of the RPUs up to the `block` is verified first (see `verify_history`).
+    /// Each index entry is verified against the header of its block
+    /// and the signature of its transaction is checked.
+    async fn import_snapshot(
+        &self,
+        state: &mut State,
+        peer_address: SocketAddr,
+        block: Block,
+        world_state: WorldState,
+    ) -> Result<(), Error> {
+        log::info!(
+            "Synchronizing from snapshot at block #{}.",
+            block.block_number()
+        );
+
+        let headers = self.fetch_headers(state, peer_address, &block).await?;
+        let transactions_roots =
+            verify_history(self.world_state.get(), &headers, &block, &world_state)?;
+
+        // The entries are only written once all of them are verified.
+        self.block_storage.clear_staged_index()?;
+        let mut position = None;
+        loop {
+            let request = message::IndexRequest {
+                block_number: block.block_number(),
+                position,
+            };
+            let response = self.send_message(peer_address, request).await?.into_inner();
+            verify_signed_batch_iter(response.entries.iter().map(IndexEntry::transaction))?;
+            for entry in &response.entries {
+                let block_number = entry.block_number();
+                let is_included = transactions_roots
+                    .get(u64::from(block_number) as usize)
+                    .map_or(false, |transactions_root| {
+                        entry.is_included_in(transactions_root)
+                    });
+                if !is_included {
+                    return Err(Error::IndexEntryNotIncluded(block_number));
+                }
+            }
+            self.block_storage.stage_index(&response.entries)?;
+            position = match response.next {
+                Some(next) => Some(next),
+                None => break,
+            };
+        }
+
+        // The genesis block is already stored.
+        state
+            .apply_snapshot(block, world_state, &headers[1..])
+            .await
+    }
+
+    /// Download the headers of the blocks before the snapshot's `block`
+    /// and verify that they form a chain from our genesis block to it.
+    ///
+    /// Returns the headers of all these blocks (by block number).
+    async fn fetch_headers(
+        &self,
+        state: &State,
+        peer_address: SocketAddr,
+        block: &Block,
+    ) -> Result<Vec<HeaderEntry>, Error> {
+        // The chain is verified backwards, starting at the block.
+        let mut headers = Vec::new();
+        let mut first_block_hash = block.hash();
+        let mut prev_block_hash = block.body.prev_block_hash();
+        let mut end = block.block_number();
+        while end > BlockNumber::default() {
+            let request = message::HeaderRequest { end };
+            let response = self.send_message(peer_address, request).await?.into_inner();
+            if response.headers.is_empty() {
+                return Err(Error::HeaderDoesNotMatch(end - 1));
+            }
+            for entry in response.headers.into_iter().rev() {
+                let block_hash = entry.header.hash();
+                if entry.header.height + 1 != end || block_hash != prev_block_hash {
+                    return Err(Error::HeaderDoesNotMatch(end - 1));
+                }
+                first_block_hash = block_hash;
+                prev_block_hash = entry.header.prev_block_hash;
+                end = entry.header.height;
+                headers.push(entry);
+            }
+        }
+
+        // We only know the genesis block (see `synchronize`).
+        if first_block_hash != state.last_block_hash {
+            return Err(Error::HeaderDoesNotMatch(BlockNumber::default()));
+        }
+
+        headers.reverse();
+        Ok(headers)
     }
 
     async fn apply_synchronized_block(&self, state: &mut State, block: Block) -> Result<(), Error> {
-        block.body.height.verify(state.block_number)?;
+        block.body.height().verify(state.block_number)?;
 
-        if block.body.prev_block_hash != state.last_block_hash {
+        if block.body.prev_block_hash() != state.last_block_hash {
             return Err(Error::PrevBlockHashDoesNotMatch(
-                block.body.prev_block_hash,
+                block.body.prev_block_hash(),
                 state.last_block_hash,
             ));
         }
@@ -153,21 +384,27 @@ impl Follower {
         self.verify_rpu_majority_signatures(
             response::AckAppend {
                 metadata: Metadata {
-                    leader_term: block.body.leader_term,
-                    block_number: block.body.height,
+                    leader_term: block.body.leader_term(),
+                    block_number: block.body.height(),
                     block_hash,
                 },
             },
             &block.signatures,
         )?;
 
-        let data = &block.body.transactions;
+        let data = block.body.transactions();
         if data.is_empty() {
             return Err(Error::EmptyBlock);
         }
 
         // Validate Transactions
-        self.transaction_checker.verify(data)?;
+        self.verify_block_timestamp(block.body.timestamp())?;
+        let state_root = self
+            .transaction_checker
+            .verify(data, block.body.timestamp())?;
+        if state_root != block.body.state_root() {
+            return Err(Error::StateRootDoesNotMatch(block.body.height()));
+        }
 
         // Persist the blocks after all checks have passed.
         state.apply_block(block_hash, block).await;
@@ -206,9 +443,12 @@ impl Follower {
         if start_block_number > BlockNumber::default() {
             start_block_number -= 1;
         }
+        // Large numbers of blocks are sent in multiple responses.
+        let end_block_number =
+            current_block_number.min(start_block_number + MAX_SYNCHRONIZATION_BLOCKS);
         let mut blocks_iter = self
             .block_storage
-            .read(start_block_number..=current_block_number);
+            .read(start_block_number..=end_block_number);
 
         let first_block = match blocks_iter.next() {
             Some(Ok(first_block)) if message.block_hash != first_block.hash() => {
@@ -225,4 +465,363 @@ impl Follower {
         log::trace!("Sending {} blocks to {}.", blocks.len(), peer_id);
         Ok(response::SynchronizationResponse { new_view, blocks })
     }
+
+    pub fn handle_snapshot_request(
+        &self,
+        peer_id: &PeerId,
+        message: message::SnapshotRequest,
+    ) -> Result<response::SnapshotResponse, Error> {
+        log::trace!("Request by {} for a snapshot chunk.", peer_id);
+
+        let chunk = self
+            .block_storage
+            .read_snapshot_chunk(message.block_number, message.offset)?;
+
+        // The block is only sent with the first chunk.
+        let block = match &chunk {
+            Some(chunk) if message.offset == 0 && chunk.block_number > BlockNumber::default() => {
+                let block_number = chunk.block_number - 1;
+                self.block_storage
+                    .read(block_number..=block_number)
+                    .next()
+                    .transpose()?
+            }
+            _ => None,
+        };
+        Ok(response::SnapshotResponse { block, chunk })
+    }
+
+    pub fn handle_header_request(
+        &self,
+        peer_id: &PeerId,
+        message: message::HeaderRequest,
+    ) -> Result<response::HeaderResponse, Error> {
+        log::trace!(
+            "Request by {} for the headers before block #{}.",
+            peer_id,
+            message.end
+        );
+
+        let headers = self
+            .block_storage
+            .read_headers(message.end, MAX_SYNCHRONIZATION_HEADERS)?;
+        Ok(response::HeaderResponse { headers })
+    }
+
+    pub fn handle_index_request(
+        &self,
+        peer_id: &PeerId,
+        message: message::IndexRequest,
+    ) -> Result<response::IndexResponse, Error> {
+        log::trace!("Request by {} for the indexes.", peer_id);
+
+        let (entries, next) = self.block_storage.read_index(
+            message.block_number,
+            message.position,
+            MAX_INDEX_ENTRIES,
+        )?;
+        Ok(response::IndexResponse { entries, next })
+    }
+}
+
+/// Verify the history of the RPUs from the genesis `world_state` up to the snapshot's `block`.
+///
+/// The `headers` of the blocks before the `block` (starting at the genesis block)
+/// have to form a chain to the `block` (see `fetch_headers`).
+/// Each block has to be signed by a supermajority of the RPUs at its height.
+/// The RPUs are tracked by applying the transactions that change accounts,
+/// which are sent with the headers of their blocks.
+/// The RPUs after the `block` have to match the RPUs of the `snapshot`
+/// (so that no blocks changing the RPUs were left out).
+///
+/// Returns the Merkle roots of the transactions of all blocks (by block number).
+fn verify_history(
+    world_state: WorldState,
+    headers: &[HeaderEntry],
+    block: &Block,
+    snapshot: &WorldState,
+) -> Result<Vec<MerkleHash>, Error> {
+    let mut check = TransactionCheck::new(world_state);
+    let mut transactions_roots = Vec::with_capacity(headers.len() + 1);
+    for (index, entry) in headers.iter().enumerate() {
+        transactions_roots.push(entry.header.transactions_root);
+        // The genesis block is not signed (its transactions are already applied).
+        if index == 0 {
+            continue;
+        }
+
+        let transactions = match entry.block() {
+            Some(block) if block.hash() != entry.header.hash() => {
+                return Err(Error::HeaderDoesNotMatch(entry.header.height));
+            }
+            Some(block) => Some(block.body.into_transactions()),
+            None => None,
+        };
+        verify_block(
+            &mut check,
+            &entry.header,
+            &entry.signatures,
+            transactions.as_deref(),
+        )?;
+    }
+
+    let header = block.header();
+    transactions_roots.push(header.transactions_root);
+    verify_block(
+        &mut check,
+        &header,
+        &block.signatures,
+        Some(block.body.transactions()),
+    )?;
+
+    if check.world_state().peers != snapshot.peers {
+        return Err(Error::RPUsDoNotMatch(block.block_number()));
+    }
+    Ok(transactions_roots)
+}
+
+/// Verify the `signatures` of a block against the RPUs of the `check`
+/// and apply the `transactions` of the block that change accounts.
+fn verify_block(
+    check: &mut TransactionCheck,
+    header: &BlockHeader,
+    signatures: &SignatureList,
+    transactions: Option<&[Signed<Transaction>]>,
+) -> Result<(), Error> {
+    check
+        .world_state()
+        .verify_block_signatures(header, signatures)
+        .map_err(|reason| Error::InvalidBlockSignatures(header.height, reason))?;
+
+    check.set_block_timestamp(header.timestamp);
+    for transaction in transactions.unwrap_or_default() {
+        if changes_accounts(transaction.unverified_ref()) {
+            check.verify_permissions_and_apply(transaction.verify_ref()?)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        consensus::{BlockHash, Body, StateRoot},
+        world_state::{Account, Permissions},
+    };
+    use pinxit::{Identity, Signable};
+    use prellblock_client_api::{account::AccountType, consensus::BlockApproval, transaction};
+    use std::{
+        sync::Arc,
+        time::{Duration, SystemTime},
+    };
+
+    fn rpu(port: u16) -> AccountType {
+        AccountType::RPU {
+            turi_address: ([127, 0, 0, 1], port).into(),
+            peer_address: ([127, 0, 0, 1], port + 1).into(),
+        }
+    }
+
+    /// A genesis `WorldState` with the `rpus` (at ports from `3000`) and an `admin`.
+    fn genesis(rpus: &[Identity], admin: &Identity) -> WorldState {
+        let mut world_state = WorldState::default();
+        for (identity, port) in rpus.iter().zip((3000..).step_by(2)) {
+            add_rpu(&mut world_state, identity, port);
+        }
+        let mut account = Account::new("admin".to_string());
+        account.account_type = AccountType::Admin;
+        world_state
+            .accounts
+            .insert(admin.id().clone(), Arc::new(account));
+        world_state
+    }
+
+    fn add_rpu(world_state: &mut WorldState, identity: &Identity, port: u16) {
+        let mut account = Account::new("rpu".to_string());
+        account.account_type = rpu(port);
+        world_state
+            .peers
+            .push_back((identity.id().clone(), ([127, 0, 0, 1], port + 1).into()));
+        world_state
+            .accounts
+            .insert(identity.id().clone(), Arc::new(account));
+    }
+
+    fn genesis_entry() -> HeaderEntry {
+        let body = Body::new(
+            LeaderTerm::default(),
+            BlockNumber::default(),
+            BlockHash::default(),
+            SystemTime::UNIX_EPOCH + Duration::from_secs(1000),
+            StateRoot::default(),
+            Vec::new(),
+        );
+        HeaderEntry {
+            header: body.header(),
+            signatures: SignatureList::default(),
+            transactions: None,
+        }
+    }
+
+    /// The block after `prev` with the `transactions` signed by the `signers`.
+    fn block(
+        prev: &BlockHeader,
+        transactions: Vec<Signed<Transaction>>,
+        signers: &[&Identity],
+    ) -> Block {
+        let body = Body::new(
+            LeaderTerm::default(),
+            prev.height + 1,
+            prev.hash(),
+            prev.timestamp + Duration::from_secs(1),
+            StateRoot::default(),
+            transactions,
+        );
+        let approval = BlockApproval {
+            leader_term: body.leader_term(),
+            block_number: body.height(),
+            block_hash: body.hash(),
+        };
+        let signatures = signers
+            .iter()
+            .map(|identity| (identity.id().clone(), identity.sign(&approval).unwrap()))
+            .collect();
+        Block { body, signatures }
+    }
+
+    fn create_rpu(admin: &Identity, identity: &Identity, port: u16) -> Signed<Transaction> {
+        Transaction::CreateAccount(transaction::CreateAccount {
+            id: identity.id().clone(),
+            name: "rpu".to_string(),
+            permissions: Permissions {
+                account_type: Some(rpu(port)),
+                expire_at: None,
+                has_writing_rights: None,
+                writing_scopes: None,
+                delegates: None,
+                reading_rights: None,
+                limits: None,
+            },
+            timestamp: SystemTime::UNIX_EPOCH + Duration::from_secs(1000),
+        })
+        .sign(admin)
+        .unwrap()
+    }
+
+    /// A history where block `1` adds an RPU that signs the snapshot's block `2`.
+    struct History {
+        rpus: Vec<Identity>,
+        new_rpu: Identity,
+        genesis: WorldState,
+        headers: Vec<HeaderEntry>,
+        block: Block,
+        snapshot: WorldState,
+    }
+
+    fn history() -> History {
+        let rpus: Vec<_> = (0..4).map(|_| Identity::generate()).collect();
+        let admin = Identity::generate();
+        let new_rpu = Identity::generate();
+        let genesis = genesis(&rpus, &admin);
+
+        let genesis_entry = genesis_entry();
+        let block_1 = block(
+            &genesis_entry.header,
+            vec![create_rpu(&admin, &new_rpu, 4000)],
+            &[&rpus[0], &rpus[1], &rpus[2]],
+        );
+        let block = block(
+            &block_1.header(),
+            Vec::new(),
+            &[&rpus[1], &rpus[2], &rpus[3], &new_rpu],
+        );
+
+        let mut snapshot = genesis.clone();
+        add_rpu(&mut snapshot, &new_rpu, 4000);
+        History {
+            rpus,
+            new_rpu,
+            genesis,
+            headers: vec![genesis_entry, HeaderEntry::of(&block_1)],
+            block,
+            snapshot,
+        }
+    }
+
+    #[test]
+    fn signatures_of_rpus_added_after_genesis_are_accepted() {
+        let history = history();
+        let transactions_roots = verify_history(
+            history.genesis,
+            &history.headers,
+            &history.block,
+            &history.snapshot,
+        )
+        .unwrap();
+        assert_eq!(transactions_roots.len(), 3);
+        assert_eq!(
+            transactions_roots[2],
+            history.block.header().transactions_root
+        );
+    }
+
+    #[test]
+    fn left_out_account_changes_are_rejected() {
+        let mut history = history();
+        history.headers[1].transactions = None;
+        let result = verify_history(
+            history.genesis,
+            &history.headers,
+            &history.block,
+            &history.snapshot,
+        );
+        assert!(matches!(result, Err(Error::InvalidBlockSignatures(..))));
+    }
+
+    #[test]
+    fn tampered_account_changes_are_rejected() {
+        let mut history = history();
+        let other = Identity::generate();
+        history.headers[1].transactions = Some(vec![create_rpu(&other, &other, 5000)]);
+        let result = verify_history(
+            history.genesis,
+            &history.headers,
+            &history.block,
+            &history.snapshot,
+        );
+        assert!(matches!(result, Err(Error::HeaderDoesNotMatch(..))));
+    }
+
+    #[test]
+    fn blocks_without_a_supermajority_of_rpus_are_rejected() {
+        let history = history();
+        let other = Identity::generate();
+        let signers: &[&[&Identity]] = &[
+            &[&history.rpus[1], &history.rpus[2], &history.new_rpu],
+            &[&history.rpus[1], &history.rpus[2], &history.new_rpu, &other],
+        ];
+        for signers in signers {
+            let block = block(&history.headers[1].header, Vec::new(), signers);
+            let result = verify_history(
+                history.genesis.clone(),
+                &history.headers,
+                &block,
+                &history.snapshot,
+            );
+            assert!(matches!(result, Err(Error::InvalidBlockSignatures(..))));
+        }
+    }
+
+    #[test]
+    fn snapshots_with_other_rpus_are_rejected() {
+        let history = history();
+        let result = verify_history(
+            history.genesis.clone(),
+            &history.headers,
+            &history.block,
+            &history.genesis,
+        );
+        assert!(matches!(result, Err(Error::RPUsDoNotMatch(..))));
+    }
 }
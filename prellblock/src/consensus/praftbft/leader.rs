@@ -3,7 +3,7 @@ use super::{
     Core, Error, Follower, InvalidTransaction, ViewChange, MAX_TRANSACTIONS_PER_BLOCK,
 };
 use crate::{
-    consensus::{BlockHash, BlockNumber, Body, LeaderTerm, SignatureList},
+    consensus::{BlockHash, BlockNumber, Body, LeaderTerm, SignatureList, StateRoot},
     transaction_checker::TransactionCheck,
 };
 use pinxit::{verify_signed_batch, Signed};
@@ -178,18 +178,27 @@ impl Leader {
             }
         }
 
+        // Block timestamps must not decrease (even if the clock is adjusted).
+        let timestamp = match self.world_state.get().last_block_timestamp {
+            Some(last_block_timestamp) => SystemTime::now().max(last_block_timestamp),
+            None => SystemTime::now(),
+        };
+
         // Also applies valid transactions onto the leader's virutal world state.
-        let (valid_transactions, invalid_transactions) = self.stateful_validate(transactions)?;
+        let (valid_transactions, invalid_transactions) =
+            self.stateful_validate(transactions, timestamp)?;
 
-        let body = Body {
-            leader_term: self.leader_term,
-            height: self.block_number,
-            prev_block_hash: self.last_block_hash,
-            timestamp: SystemTime::now(),
-            transactions: valid_transactions,
-        };
+        let body = Body::new(
+            self.leader_term,
+            self.block_number,
+            self.last_block_hash,
+            timestamp,
+            self.transaction_check.state_root(),
+            valid_transactions,
+        );
 
         let block_hash = body.hash();
+        let state_root = body.state_root();
 
         let ackprepare_signatures = self.prepare(block_hash).await?;
         log::trace!(
@@ -201,10 +210,11 @@ impl Leader {
         let ackappend_signatures = self
             .append(
                 block_hash,
-                body.transactions,
+                body.into_transactions(),
                 invalid_transactions,
                 ackprepare_signatures,
-                body.timestamp,
+                timestamp,
+                state_root,
             )
             .await?;
         log::trace!(
@@ -241,6 +251,7 @@ impl Leader {
         invalid_transactions: Vec<(usize, Signed<Transaction>)>,
         ackprepare_signatures: SignatureList,
         timestamp: SystemTime,
+        state_root: StateRoot,
     ) -> Result<SignatureList, Error> {
         self.phase = Phase::Append;
 
@@ -251,6 +262,7 @@ impl Leader {
             invalid_transactions,
             ackprepare_signatures,
             timestamp,
+            state_root,
         };
 
         self.broadcast_until_majority(message, move |ack| ack.metadata.verify(&metadata))
@@ -289,8 +301,10 @@ impl Leader {
     fn stateful_validate(
         &mut self,
         transactions: Vec<Signed<Transaction>>,
+        timestamp: SystemTime,
     ) -> Result<(Vec<Signed<Transaction>>, Vec<InvalidTransaction>), Error> {
         let verified_transactions = verify_signed_batch(transactions)?;
+        self.transaction_check.set_block_timestamp(timestamp);
 
         let mut valid_transactions = Vec::new();
         let mut invalid_transactions = Vec::new();
@@ -1,5 +1,8 @@
 use super::{InvalidTransaction, Metadata};
-use crate::consensus::{BlockHash, BlockNumber, LeaderTerm, SignatureList};
+use crate::{
+    block_storage::IndexPosition,
+    consensus::{BlockHash, BlockNumber, LeaderTerm, SignatureList, StateRoot},
+};
 use newtype_enum::newtype_enum;
 use pinxit::Signed;
 use prellblock_client_api::Transaction;
@@ -31,6 +34,8 @@ pub enum ConsensusMessage {
         invalid_transactions: Vec<InvalidTransaction>,
         /// The timestamp of when the proposed Block was created by the leader.
         timestamp: SystemTime,
+        /// The `StateRoot` of the `WorldState` after applying the valid transactions.
+        state_root: StateRoot,
     },
 
     /// A `ConsensusMessage` signalizing the Followers to Store the Block in the `BlockStorage` together with the `ACKAPPEND`-Signatures.
@@ -66,6 +71,28 @@ pub enum ConsensusMessage {
         /// The block hash of the topmost block we have.
         block_hash: BlockHash,
     },
+
+    /// A Request for a chunk of a `WorldState` snapshot (to synchronize a new RPU).
+    SnapshotRequest {
+        /// The number of blocks applied to the snapshot (`None` for the newest snapshot).
+        block_number: Option<BlockNumber>,
+        /// The offset of the chunk in the encoded snapshot.
+        offset: u64,
+    },
+
+    /// A Request for the headers of the blocks before `end` (to verify the indexes of a snapshot).
+    HeaderRequest {
+        /// The number of the block after the requested headers.
+        end: BlockNumber,
+    },
+
+    /// A Request for the time-series indexes up to the block of a snapshot.
+    IndexRequest {
+        /// The number of the last block whose values are requested.
+        block_number: BlockNumber,
+        /// The position to continue reading the indexes at.
+        position: Option<IndexPosition>,
+    },
 }
 
 impl Deref for consensus_message::Prepare {
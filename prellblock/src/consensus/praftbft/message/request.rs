@@ -28,3 +28,15 @@ impl Request for consensus_message::NewView {
 impl Request for consensus_message::SynchronizationRequest {
     type Response = consensus_response::SynchronizationResponse;
 }
+
+impl Request for consensus_message::SnapshotRequest {
+    type Response = consensus_response::SnapshotResponse;
+}
+
+impl Request for consensus_message::HeaderRequest {
+    type Response = consensus_response::HeaderResponse;
+}
+
+impl Request for consensus_message::IndexRequest {
+    type Response = consensus_response::IndexResponse;
+}
@@ -1,5 +1,8 @@
 use super::Metadata;
-use crate::consensus::{Block, LeaderTerm, SignatureList};
+use crate::{
+    block_storage::{HeaderEntry, IndexEntry, IndexPosition, SnapshotChunk},
+    consensus::{Block, LeaderTerm, SignatureList},
+};
 use newtype_enum::newtype_enum;
 use serde::{Deserialize, Serialize};
 
@@ -28,6 +31,28 @@ pub enum ConsensusResponse {
         blocks: Vec<Block>,
     },
 
+    /// A Response to a `SnapshotRequest`.
+    SnapshotResponse {
+        /// The last `Block` applied to the snapshot (only sent with the first chunk).
+        block: Option<Block>,
+        /// The requested chunk (`None` if there is no such snapshot).
+        chunk: Option<SnapshotChunk>,
+    },
+
+    /// A Response to a `HeaderRequest`.
+    HeaderResponse {
+        /// The headers of the blocks before the requested block (in ascending order).
+        headers: Vec<HeaderEntry>,
+    },
+
+    /// A Response to an `IndexRequest`.
+    IndexResponse {
+        /// The values of the time series.
+        entries: Vec<IndexEntry>,
+        /// The position to continue reading at (`None` if all entries were sent).
+        next: Option<IndexPosition>,
+    },
+
     /// An empty response.
     Ok,
 }
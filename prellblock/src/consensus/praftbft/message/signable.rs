@@ -1,5 +1,6 @@
 use super::{ConsensusMessage, ConsensusResponse, Metadata};
 use crate::consensus::SignatureList;
+use prellblock_client_api::consensus::BlockApproval;
 
 use pinxit::Signable;
 use serde::Serialize;
@@ -42,6 +43,15 @@ impl Signable for ConsensusResponse {
     type SignableData = Vec<u8>;
     type Error = postcard::Error;
     fn signable_data(&self) -> Result<Self::SignableData, Self::Error> {
-        SignableData::ConsensusResponse(self).signable_data()
+        match self {
+            // The `AckAppend` signatures are stored in the block and verified by clients.
+            Self::AckAppend(response) => BlockApproval {
+                leader_term: response.metadata.leader_term,
+                block_number: response.metadata.block_number,
+                block_hash: response.metadata.block_hash,
+            }
+            .signable_data(),
+            _ => SignableData::ConsensusResponse(self).signable_data(),
+        }
     }
 }
@@ -16,7 +16,7 @@ pub use ring_buffer::RingBuffer;
 
 use self::core::Core;
 use super::TransactionApplier;
-use crate::{block_storage::BlockStorage, world_state::WorldStateService};
+use crate::{block_storage::BlockStorage, consensus::BlockNumber, world_state::WorldStateService};
 use censorship_checker::CensorshipChecker;
 use error::ErrorVerify;
 use follower::Follower;
@@ -25,7 +25,7 @@ use message::Request;
 use newtype_enum::Enum;
 use notify::NotifyMap;
 use pinxit::{Identity, Signable, Signed};
-use prellblock_client_api::Transaction;
+use prellblock_client_api::{consensus::TransactionHash, Transaction, TransactionStatus};
 use std::sync::Arc;
 use view_change::ViewChange;
 
@@ -33,6 +33,9 @@ const MAX_TRANSACTIONS_PER_BLOCK: usize = 4000;
 
 type InvalidTransaction = (usize, Signed<Transaction>);
 
+/// An invalid transaction together with the reason why it was rejected.
+type RejectedTransaction = (Signed<Transaction>, String);
+
 /// See the [paper](https://www.scs.stanford.edu/17au-cs244b/labs/projects/clow_jiang.pdf).
 #[derive(Debug)]
 #[must_use]
@@ -101,6 +104,61 @@ impl PRaftBFT {
         }
     }
 
+    /// Get the status of the transaction identified by `transaction_hash`.
+    pub async fn transaction_status(
+        &self,
+        transaction_hash: &TransactionHash,
+    ) -> Result<TransactionStatus, Error> {
+        if let Some(status) = self
+            .core
+            .block_storage
+            .transaction_status(transaction_hash)?
+        {
+            return Ok(status);
+        }
+
+        let is_pending = self
+            .core
+            .queue
+            .lock()
+            .await
+            .iter()
+            .any(|transaction| TransactionHash::of(transaction) == *transaction_hash);
+
+        if is_pending {
+            Ok(TransactionStatus::Pending)
+        } else {
+            Ok(TransactionStatus::Unknown)
+        }
+    }
+
+    /// Wait until the current block number is at least `block_number`.
+    pub async fn wait_for_block(&self, block_number: BlockNumber) {
+        self.follower.wait_for_block(block_number).await
+    }
+
+    /// Wait until the transaction identified by `transaction_hash` is committed or rejected.
+    ///
+    /// Returns the final status of the transaction.
+    pub async fn wait_for_transaction(
+        &self,
+        transaction_hash: &TransactionHash,
+    ) -> Result<TransactionStatus, Error> {
+        loop {
+            // Take the block number before checking the status,
+            // so we won't miss a block being applied in between.
+            let block_number = self.follower.block_number().await;
+
+            match self.transaction_status(transaction_hash).await? {
+                status @ TransactionStatus::Committed { .. }
+                | status @ TransactionStatus::Rejected { .. } => return Ok(status),
+                TransactionStatus::Pending | TransactionStatus::Unknown => {
+                    self.follower.wait_for_block(block_number + 1).await
+                }
+            }
+        }
+    }
+
     /// Process the incoming `ConsensusMessages`.
     pub async fn handle_message(
         self: &Arc<Self>,
@@ -135,6 +193,9 @@ impl PRaftBFT {
             ViewChange(message) => self.view_change.handle_view_change(peer_id, signature, message.new_leader_term)?,
             NewView(message) => self.follower.handle_new_view_message(peer_id, message).await?,
             SynchronizationRequest(message) => self.follower.handle_synchronization_request(peer_id, message).await?,
+            SnapshotRequest(message) => self.follower.handle_snapshot_request(&peer_id, message)?,
+            HeaderRequest(message) => self.follower.handle_header_request(&peer_id, message)?,
+            IndexRequest(message) => self.follower.handle_index_request(&peer_id, message)?,
         };
 
         Ok(response.sign(&self.core.identity)?)
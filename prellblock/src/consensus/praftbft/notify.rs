@@ -9,9 +9,28 @@ use std::{
     task::{Context, Poll, Waker},
 };
 
-type NotifyMapInner<T> = Arc<Mutex<HashMap<T, slab::Slab<Option<Waker>>>>>;
+type NotifyMapInner<T> = Arc<Mutex<HashMap<T, slab::Slab<Waiter>>>>;
+
+/// The state of a registered `Wait`.
+#[derive(Debug)]
+enum Waiter {
+    /// Not notified yet (the waker is set once the `Wait` is polled).
+    Pending(Option<Waker>),
+    /// The state was reached.
+    Notified,
+}
+
+impl Waiter {
+    fn notify(&mut self) {
+        if let Self::Pending(Some(waker)) = std::mem::replace(self, Self::Notified) {
+            waker.wake();
+        }
+    }
+}
 
 /// A structure that can notify multiple tasks once a given state is reached.
+///
+/// States are ordered: reaching a state also reaches all states before it.
 #[derive(Debug)]
 pub struct NotifyMap<T>
 where
@@ -33,27 +52,43 @@ where
 
 impl<T> NotifyMap<T>
 where
-    T: Eq + Hash,
+    T: Ord + Hash + Clone,
 {
     /// Wait until a given `state` is reached.
     ///
     /// The returned `Wait` future will resolve once
-    /// `notify_all(state)` is called or the `NotifyMap` is dropped.
+    /// `notify_all(state)` (or a later state) is called or the `NotifyMap` is dropped.
+    ///
+    /// The `Wait` is registered right away (not when it is polled first),
+    /// so no notification after calling this is missed.
     pub fn wait(&mut self, state: T) -> Wait<T> {
+        let index = self
+            .inner
+            .lock()
+            .unwrap()
+            .entry(state.clone())
+            .or_default()
+            .insert(Waiter::Pending(None));
         Wait {
-            index: 0,
+            index,
             inner: self.inner.clone(),
             state,
         }
     }
 
-    /// Notify all futures waiting for a given `state`.
+    /// Notify all futures waiting for a given `state` or an earlier one.
+    ///
+    /// States can be skipped (e.g. when several blocks are applied at once).
     pub fn notify_all(&mut self, state: &T) {
-        if let Some(wakers) = self.inner.lock().unwrap().get_mut(state) {
-            for (_, waker) in wakers {
-                if let Some(waker) = waker.take() {
-                    waker.wake();
-                }
+        for (_, waiters) in self
+            .inner
+            .lock()
+            .unwrap()
+            .iter_mut()
+            .filter(|(waiting_for, _)| *waiting_for <= state)
+        {
+            for (_, waiter) in waiters {
+                waiter.notify();
             }
         }
     }
@@ -65,11 +100,9 @@ where
 {
     fn drop(&mut self) {
         // Notify all futurues waiting for any state.
-        for wakers in self.inner.lock().unwrap().values_mut() {
-            for (_, waker) in wakers {
-                if let Some(waker) = waker.take() {
-                    waker.wake();
-                }
+        for waiters in self.inner.lock().unwrap().values_mut() {
+            for (_, waiter) in waiters {
+                waiter.notify();
             }
         }
     }
@@ -80,8 +113,7 @@ pub struct Wait<T>
 where
     T: Hash + Eq,
 {
-    // `0` if no waker is registered
-    // `1 + key` otherwise
+    /// The index of our `Waiter` in the waiter list of the `state`.
     index: usize,
     inner: NotifyMapInner<T>,
     state: T,
@@ -92,8 +124,16 @@ where
     T: Hash + Eq + Clone,
 {
     fn clone(&self) -> Self {
+        let mut inner = self.inner.lock().unwrap();
+        let waiters = inner.get_mut(&self.state).unwrap();
+        let waiter = match waiters[self.index] {
+            Waiter::Pending(_) => Waiter::Pending(None),
+            Waiter::Notified => Waiter::Notified,
+        };
+        let index = waiters.insert(waiter);
+        drop(inner);
         Self {
-            index: 0,
+            index,
             inner: self.inner.clone(),
             state: self.state.clone(),
         }
@@ -102,39 +142,19 @@ where
 
 impl<T> Future for Wait<T>
 where
-    T: Hash + Eq + Clone + Unpin,
+    T: Hash + Eq,
 {
     type Output = ();
-    fn poll(mut self: Pin<&mut Self>, ctx: &mut Context) -> Poll<Self::Output> {
+    fn poll(self: Pin<&mut Self>, ctx: &mut Context) -> Poll<Self::Output> {
         let mut inner = self.inner.lock().unwrap();
-        match (self.index.checked_sub(1), inner.get_mut(&self.state)) {
-            // A waker is already registered.
-            (Some(index), Some(wakers)) => {
-                match &mut wakers[index] {
-                    // Waker was notified.
-                    None => return Poll::Ready(()),
-                    // A waker is already configured, no need to clone the waker.
-                    Some(waker) if waker.will_wake(ctx.waker()) => {}
-                    // Setup a new waker, the old one is no longer valid.
-                    Some(waker) => *waker = ctx.waker().clone(),
-                }
-            }
-            // A waker is already registered, but the waker list is removed.
-            (Some(_), None) => unreachable!(),
-            // No waker is registered and the wakers list already exists.
-            (None, Some(wakers)) => {
-                let index = wakers.insert(Some(ctx.waker().clone()));
-                drop(inner);
-                self.index = 1 + index;
-            }
-            // No waker is registered and there is no wakers list.
-            (None, None) => {
-                let mut wakers = slab::Slab::new();
-                let index = wakers.insert(Some(ctx.waker().clone()));
-                inner.insert(self.state.clone(), wakers);
-                drop(inner);
-                self.index = 1 + index;
-            }
+        // The waiter list is kept as long as a `Wait` is registered.
+        match &mut inner.get_mut(&self.state).unwrap()[self.index] {
+            // Waiter was notified.
+            Waiter::Notified => return Poll::Ready(()),
+            // A waker is already configured, no need to clone the waker.
+            Waiter::Pending(Some(waker)) if waker.will_wake(ctx.waker()) => {}
+            // Setup a new waker, the old one is no longer valid.
+            Waiter::Pending(waker) => *waker = Some(ctx.waker().clone()),
         }
         Poll::Pending
     }
@@ -145,16 +165,65 @@ where
     T: Hash + Eq,
 {
     fn drop(&mut self) {
-        // Remove our waker from the waker list.
-        if let Some(index) = self.index.checked_sub(1) {
-            let mut inner = self.inner.lock().unwrap();
-            let wakers = inner.get_mut(&self.state).unwrap();
-            wakers.remove(index);
-
-            // Remove the wakers list if it's empty.
-            if wakers.is_empty() {
-                inner.remove(&self.state);
-            }
+        // Remove our waiter from the waiter list.
+        let mut inner = self.inner.lock().unwrap();
+        let waiters = inner.get_mut(&self.state).unwrap();
+        waiters.remove(self.index);
+
+        // Remove the waiter list if it's empty.
+        if waiters.is_empty() {
+            inner.remove(&self.state);
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::task::noop_waker;
+
+    fn is_ready(wait: &mut Wait<u64>) -> bool {
+        let waker = noop_waker();
+        let mut ctx = Context::from_waker(&waker);
+        Pin::new(wait).poll(&mut ctx).is_ready()
+    }
+
+    #[test]
+    fn notifications_before_the_first_poll_are_not_missed() {
+        let mut notify_map = NotifyMap::default();
+        let mut wait = notify_map.wait(1);
+        notify_map.notify_all(&1);
+        assert!(is_ready(&mut wait));
+    }
+
+    #[test]
+    fn skipped_states_are_notified() {
+        let mut notify_map = NotifyMap::default();
+        let mut wait_1 = notify_map.wait(1);
+        let mut wait_3 = notify_map.wait(3);
+        let mut wait_5 = notify_map.wait(5);
+        assert!(!is_ready(&mut wait_1));
+
+        notify_map.notify_all(&4);
+        assert!(is_ready(&mut wait_1));
+        assert!(is_ready(&mut wait_3));
+        assert!(!is_ready(&mut wait_5));
+
+        let mut clone = wait_3.clone();
+        assert!(is_ready(&mut clone));
+
+        drop(notify_map);
+        assert!(is_ready(&mut wait_5));
+    }
+
+    #[test]
+    fn dropped_waits_are_removed() {
+        let mut notify_map = NotifyMap::default();
+        let wait = notify_map.wait(1);
+        let clone = wait.clone();
+        drop(wait);
+        assert_eq!(notify_map.inner.lock().unwrap()[&1].len(), 1);
+        drop(clone);
+        assert!(notify_map.inner.lock().unwrap().is_empty());
+    }
+}
@@ -1,45 +1,68 @@
-use std::{collections::VecDeque, ops::Deref, time::Instant};
+use pinxit::Signed;
+use prellblock_client_api::{consensus::TransactionHash, Transaction};
+use std::{
+    collections::{HashSet, VecDeque},
+    ops::Deref,
+    time::Instant,
+};
 
-/// A queue of elements that have an associated insertion time (`inserted`).
+/// A queue of transactions that have an associated insertion time (`inserted`).
+///
+/// Transactions that are already in the queue are not inserted again.
 ///
 /// ```
+/// # use pinxit::{Identity, Signable};
 /// # use prellblock::consensus::Queue;
-///
+/// # use prellblock_client_api::{consensus::TransactionHash, transaction, Transaction};
+/// # use std::time::SystemTime;
+/// #
+/// # let identity = Identity::generate();
+/// # let transaction = |value: u8| {
+/// #     Transaction::KeyValue(transaction::KeyValue {
+/// #         key: "key".to_string(),
+/// #         value: vec![value],
+/// #         timestamp: SystemTime::UNIX_EPOCH,
+/// #     })
+/// #     .sign(&identity)
+/// #     .unwrap()
+/// # };
 /// let mut queue = Queue::default();
 ///
-/// queue.insert(4);
-/// queue.insert(1);
-/// queue.insert(3);
-/// queue.insert(2);
+/// queue.insert(transaction(4));
+/// queue.insert(transaction(1));
+/// queue.insert(transaction(3));
+/// queue.insert(transaction(2));
+/// queue.insert(transaction(1));
 ///
-/// assert_eq!(queue.remove(&3), Some(3));
-/// assert_eq!(queue.remove(&3), None);
+/// assert!(queue.remove(&transaction(3)).is_some());
+/// assert!(queue.remove(&transaction(3)).is_none());
 ///
 /// queue.peek().unwrap().inserted().elapsed();
 ///
-/// let data: Vec<_> = queue.collect();
-/// assert_eq!(data, [4, 1, 2]);
+/// let data: Vec<_> = queue.map(|transaction| TransactionHash::of(&transaction)).collect();
+/// let expected: Vec<_> = [4, 1, 2]
+///     .iter()
+///     .map(|&value| TransactionHash::of(&transaction(value)))
+///     .collect();
+/// assert_eq!(data, expected);
 /// ```
-#[derive(Debug)]
-pub struct Queue<T> {
-    entries: VecDeque<Entry<T>>,
+#[derive(Debug, Default)]
+pub struct Queue {
+    entries: VecDeque<Entry>,
+    /// The hashes of all queued transactions (to detect duplicates quickly).
+    hashes: HashSet<TransactionHash>,
 }
 
-impl<T> Default for Queue<T> {
-    fn default() -> Self {
-        Self {
-            entries: VecDeque::new(),
+impl Queue {
+    /// Insert a `transaction` into the queue (if it is not already queued).
+    pub fn insert(&mut self, transaction: Signed<Transaction>) {
+        let hash = TransactionHash::of(&transaction);
+        if self.hashes.insert(hash) {
+            self.entries.push_back(Entry::new(hash, transaction))
         }
     }
-}
-
-impl<T> Queue<T> {
-    /// Insert an `item` into the queue.
-    pub fn insert(&mut self, item: T) {
-        self.entries.push_back(Entry::new(item))
-    }
 
-    /// Get the number of items in the queue.
+    /// Get the number of transactions in the queue.
     #[must_use]
     pub fn len(&self) -> usize {
         self.entries.len()
@@ -51,71 +74,78 @@ impl<T> Queue<T> {
         self.entries.is_empty()
     }
 
+    /// Get an iterator over all transactions in the queue (in insertion order).
+    pub fn iter(&self) -> impl Iterator<Item = &Signed<Transaction>> {
+        self.entries.iter().map(|entry| &entry.item)
+    }
+
     /// Get a reference to the first `Entry` of the queue.
     ///
     /// Use `entry.inserted()` to get the insetion time.
     ///
-    /// Entry implements `Deref<Target=T>` to access the `item`.
+    /// Entry implements `Deref<Target=Signed<Transaction>>` to access the transaction.
     #[must_use]
-    pub fn peek(&self) -> Option<&Entry<T>> {
+    pub fn peek(&self) -> Option<&Entry> {
         self.entries.front()
     }
 
-    /// Remove an `item` from the queue.
+    /// Remove a `transaction` from the queue.
     ///
-    /// **Note:** This needs to scan the whole queue
-    /// and therefore has an `O(n)` runtime.
-    pub fn remove(&mut self, item: &T) -> Option<T>
-    where
-        T: Eq,
-    {
+    /// **Note:** If the transaction is queued, this needs to scan
+    /// the whole queue and therefore has an `O(n)` runtime.
+    pub fn remove(&mut self, transaction: &Signed<Transaction>) -> Option<Signed<Transaction>> {
+        let hash = TransactionHash::of(transaction);
+        if !self.hashes.remove(&hash) {
+            return None;
+        }
         self.entries
             .iter()
-            .position(|entry| entry.item == *item)
+            .position(|entry| entry.hash == hash)
             .and_then(|index| self.entries.remove(index))
             .map(|entry| entry.item)
     }
 
-    /// Remove all items in `iter` from the queue.
-    ///
-    /// **Note:** This needs to scan the whole queue
-    /// and therefore has an `O(n * m)` runtime.
-    pub fn remove_all<'a>(&mut self, iter: impl Iterator<Item = &'a T>)
-    where
-        T: Eq + 'a,
-    {
-        for item in iter {
-            self.remove(item);
+    /// Remove all transactions in `iter` from the queue.
+    pub fn remove_all<'a>(&mut self, iter: impl Iterator<Item = &'a Signed<Transaction>>) {
+        for transaction in iter {
+            self.remove(transaction);
         }
     }
 }
 
-impl<T> Iterator for Queue<T> {
-    type Item = T;
+impl Iterator for Queue {
+    type Item = Signed<Transaction>;
     fn next(&mut self) -> Option<Self::Item> {
-        self.entries.pop_front().map(|entry| entry.item)
+        let entry = self.entries.pop_front()?;
+        self.hashes.remove(&entry.hash);
+        Some(entry.item)
     }
 }
 
-impl<T> Extend<T> for Queue<T> {
+impl Extend<Signed<Transaction>> for Queue {
+    /// Insert all transactions (a transaction can arrive from several RPUs, duplicates are dropped).
     fn extend<I>(&mut self, iter: I)
     where
-        I: IntoIterator<Item = T>,
+        I: IntoIterator<Item = Signed<Transaction>>,
     {
-        self.entries.extend(iter.into_iter().map(Entry::new));
+        for transaction in iter {
+            self.insert(transaction);
+        }
     }
 }
 
 #[derive(Debug)]
-pub struct Entry<T> {
+pub struct Entry {
     inserted: Instant,
-    item: T,
+    hash: TransactionHash,
+    item: Signed<Transaction>,
 }
 
-impl<T> Entry<T> {
-    fn new(item: T) -> Self {
+impl Entry {
+    fn new(hash: TransactionHash, item: Signed<Transaction>) -> Self {
         Self {
             inserted: Instant::now(),
+            hash,
             item,
         }
     }
@@ -125,9 +155,43 @@ impl<T> Entry<T> {
     }
 }
 
-impl<T> Deref for Entry<T> {
-    type Target = T;
+impl Deref for Entry {
+    type Target = Signed<Transaction>;
     fn deref(&self) -> &Self::Target {
         &self.item
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pinxit::{Identity, Signable};
+    use prellblock_client_api::transaction;
+    use std::time::SystemTime;
+
+    fn transaction(identity: &Identity, value: u8) -> Signed<Transaction> {
+        Transaction::KeyValue(transaction::KeyValue {
+            key: "key".to_string(),
+            value: vec![value],
+            timestamp: SystemTime::UNIX_EPOCH,
+        })
+        .sign(identity)
+        .unwrap()
+    }
+
+    #[test]
+    fn transactions_from_several_rpus_are_queued_once() {
+        let identity = Identity::generate();
+        let mut queue = Queue::default();
+        queue.extend(vec![transaction(&identity, 1), transaction(&identity, 2)]);
+        queue.extend(vec![transaction(&identity, 2), transaction(&identity, 1)]);
+        assert_eq!(queue.len(), 2);
+
+        // Taken transactions can be queued again (e.g. after a leader change).
+        let first = queue.next().unwrap();
+        queue.insert(first);
+        queue.insert(transaction(&identity, 1));
+        assert_eq!(queue.len(), 2);
+        assert_eq!(queue.hashes.len(), 2);
+    }
+}
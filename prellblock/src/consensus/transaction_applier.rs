@@ -3,6 +3,9 @@
 use super::Block;
 use crate::{block_storage::BlockStorage, world_state::WorldStateService};
 
+/// A snapshot of the `WorldState` is persisted every `SNAPSHOT_INTERVAL` blocks.
+const SNAPSHOT_INTERVAL: u64 = 1000;
+
 /// Helps to apply transactions onto the `BlockStorage` and `WorldState`.
 #[derive(Debug)]
 pub struct TransactionApplier {
@@ -22,23 +25,20 @@ impl TransactionApplier {
 
     /// Applies a given to both the `world_state` and the `block_storage`.
     pub async fn apply_block(&self, block: Block) {
-        // Write Block to BlockStorage
-        self.apply_to_block_storage(&block);
+        let mut world_state = self.world_state.get_writable().await;
         // Write Block to WorldState
-        self.apply_to_worldstate(block).await;
-    }
-
-    /// Applies a given block to the `BlockStorage`.
-    pub fn apply_to_block_storage(&self, block: &Block) {
+        world_state.apply_block(block.clone()).unwrap();
         // Write Block to BlockStorage
-        self.block_storage.write_block(block).unwrap();
-    }
-
-    /// Applies a given block to the `WorldState`.
-    pub async fn apply_to_worldstate(&self, block: Block) {
-        // Write Block to WorldState
-        let mut world_state = self.world_state.get_writable().await;
-        world_state.apply_block(block).unwrap();
+        // (values are filed under the namespaces known after applying the block).
+        self.block_storage
+            .write_block(&block, &world_state)
+            .unwrap();
+        if u64::from(world_state.block_number) % SNAPSHOT_INTERVAL == 0 {
+            // The snapshot only speeds up the next start, so failing to write it is not fatal.
+            if let Err(err) = self.block_storage.write_snapshot(&world_state) {
+                log::warn!("Could not write WorldState snapshot: {}", err);
+            }
+        }
         world_state.save();
     }
 }
@@ -42,6 +42,12 @@ struct Opt {
     config: String,
     /// The path to the genesis transactions file (only needed for the first start).
     genesis_transactions: Option<String>,
+    /// Check the integrity of the block storage (without starting the RPU).
+    #[structopt(long)]
+    fsck: bool,
+    /// Rebuild the indexes and repair the stored values of the block storage while checking it.
+    #[structopt(long, requires = "fsck")]
+    rebuild_indexes: bool,
 }
 
 #[tokio::main]
@@ -66,6 +72,12 @@ async fn main() {
         None
     };
 
+    if opt.fsck {
+        let block_storage = BlockStorage::new(&private_config.block_path, None).unwrap();
+        fsck(&block_storage, opt.rebuild_indexes);
+        return;
+    }
+
     let hex_identity =
         fs::read_to_string(&private_config.identity).expect("Could not load identity file.");
     let identity: Identity = hex_identity.parse().expect("Identity could not be loaded.");
@@ -80,9 +92,13 @@ async fn main() {
     let broadcaster = Broadcaster::new(world_state.clone());
     let broadcaster = Arc::new(broadcaster);
 
-    let batcher = Batcher::new(broadcaster);
+    let batcher = Batcher::new(broadcaster.clone());
 
-    let reader = Reader::new(block_storage, world_state.clone());
+    let reader = Reader::new(
+        block_storage.clone(),
+        world_state.clone(),
+        consensus.clone(),
+    );
 
     // if configured correctly, the addresses for `Turi` and `PeerInbox` are in the `world_state`
     let rpu_account = world_state
@@ -106,11 +122,21 @@ async fn main() {
     let turi_task = {
         let private_config = private_config.clone();
         let transaction_checker = transaction_checker.clone();
+        let consensus = consensus.clone();
+        let block_storage = block_storage.clone();
 
         tokio::spawn(async move {
             let tls_identity = load_identity_from_env(private_config.tls_id).await?;
             let mut listener = TcpListener::bind(turi_address).await?;
-            let turi = Turi::new(tls_identity, batcher, reader, transaction_checker);
+            let turi = Turi::new(
+                tls_identity,
+                batcher,
+                reader,
+                transaction_checker,
+                consensus,
+                block_storage,
+                broadcaster,
+            );
             turi.serve(&mut listener).await
         })
     };
@@ -121,7 +147,13 @@ async fn main() {
     let calculator = Calculator::new();
     let calculator = Arc::new(calculator.into());
 
-    let peer_inbox = PeerInbox::new(calculator, data_storage, consensus, transaction_checker);
+    let peer_inbox = PeerInbox::new(
+        calculator,
+        data_storage,
+        consensus,
+        transaction_checker,
+        block_storage,
+    );
     let peer_inbox = Arc::new(peer_inbox);
 
     // execute the receiver in a new thread
@@ -149,3 +181,36 @@ async fn load_identity_from_env(tls_identity_path: String) -> Result<TlsIdentity
     let password = env::var("TLS_PASSWORD").unwrap_or_else(|_| "prellblock".to_string());
     balise::server::load_identity(tls_identity_path, &password).await
 }
+
+/// Check the integrity of the `block_storage` and print the result.
+///
+/// Exits with a failure code if inconsistencies were found.
+fn fsck(block_storage: &BlockStorage, rebuild_indexes: bool) {
+    let report = block_storage
+        .check(rebuild_indexes)
+        .expect("Could not check the block storage.");
+
+    if let Some(block_number) = report.snapshot {
+        println!(
+            "Started at the snapshot of block #{} (the previous blocks are not stored).",
+            block_number
+        );
+    }
+    println!(
+        "Checked {} blocks and {} values.",
+        report.blocks, report.values
+    );
+    if rebuild_indexes {
+        println!("Rebuilt the indexes and repaired the stored values.");
+    }
+
+    if report.is_ok() {
+        println!("No inconsistencies found.");
+    } else {
+        for inconsistency in &report.inconsistencies {
+            println!("{}", inconsistency);
+        }
+        println!("Found {} inconsistencies.", report.inconsistencies.len());
+        std::process::exit(1);
+    }
+}
@@ -13,7 +13,10 @@ pub use sender::Sender;
 use crate::consensus::{ConsensusMessage, ConsensusResponse};
 use balise::define_api;
 use pinxit::Signed;
-use prellblock_client_api::Transaction;
+use prellblock_client_api::{
+    blob::{BlobChunk, BlobHash},
+    PutBlob, Transaction,
+};
 use serde::{Deserialize, Serialize};
 use std::fmt::Debug;
 
@@ -40,5 +43,13 @@ define_api! {
 
         /// Messages exchanged by the consensus.
         Consensus(Signed<ConsensusMessage>) => Signed<ConsensusResponse>,
+
+        /// A chunk of a blob uploaded by a client (to replicate it).
+        ///
+        /// Returns whether all chunks of the payload were received.
+        StoreBlob(Signed<PutBlob>) => bool,
+
+        /// Get the chunk of the payload of a blob at an offset (or `None` if it is not stored).
+        FetchBlob(BlobHash, u64) => Option<BlobChunk>,
     }
 }
@@ -1,12 +1,13 @@
 use super::{message, Calculator, Pong};
 use crate::{
+    block_storage::BlockStorage,
     consensus::{Consensus, ConsensusResponse},
     data_storage::DataStorage,
     transaction_checker::TransactionChecker,
     BoxError,
 };
 use pinxit::{verify_signed_batch_iter, Signed, VerifiedRef};
-use prellblock_client_api::Transaction;
+use prellblock_client_api::{blob::BlobChunk, Transaction};
 use std::sync::{Arc, Mutex};
 
 type ArcMut<T> = Arc<Mutex<T>>;
@@ -17,6 +18,7 @@ pub struct PeerInbox {
     data_storage: Arc<DataStorage>,
     consensus: Arc<Consensus>,
     transaction_checker: TransactionChecker,
+    block_storage: BlockStorage,
 }
 
 impl PeerInbox {
@@ -27,12 +29,14 @@ impl PeerInbox {
         data_storage: Arc<DataStorage>,
         consensus: Arc<Consensus>,
         transaction_checker: TransactionChecker,
+        block_storage: BlockStorage,
     ) -> Self {
         Self {
             calculator,
             data_storage,
             consensus,
             transaction_checker,
+            block_storage,
         }
     }
 
@@ -59,6 +63,22 @@ impl PeerInbox {
                     params.timestamp,
                 )?;
             }
+            Transaction::DelegatedKeyValue(params) => {
+                log::debug!(
+                    "Client {} set {} to {:?} for {} (via another RPU)",
+                    &transaction.signer(),
+                    params.key,
+                    params.value,
+                    params.owner,
+                );
+
+                self.data_storage.write_key_value(
+                    &params.owner,
+                    &params.key,
+                    &params.value,
+                    params.timestamp,
+                )?;
+            }
             Transaction::UpdateAccount(params) => {
                 log::debug!(
                     "Client {} updates account {}: {:#?}",
@@ -89,6 +109,71 @@ impl PeerInbox {
                 self.data_storage
                     .write_account_transaction(transaction.signer(), params)?;
             }
+            Transaction::ProposeAccountChange(params) => {
+                log::debug!(
+                    "Client {} proposes {:#?}",
+                    &transaction.signer(),
+                    params.operation,
+                );
+                self.data_storage
+                    .write_account_transaction(transaction.signer(), params)?;
+            }
+            Transaction::ApproveAccountChange(params) => {
+                log::debug!(
+                    "Client {} approves proposal {}.",
+                    &transaction.signer(),
+                    params.proposal,
+                );
+                self.data_storage
+                    .write_account_transaction(transaction.signer(), params)?;
+            }
+            Transaction::SetAdminThreshold(params) => {
+                log::debug!(
+                    "Client {} sets the admin threshold to {}.",
+                    &transaction.signer(),
+                    params.threshold,
+                );
+                self.data_storage
+                    .write_account_transaction(transaction.signer(), params)?;
+            }
+            Transaction::RotateKey(params) => {
+                log::debug!(
+                    "Client {} rotates the key of account {} to {}.",
+                    &transaction.signer(),
+                    params.id,
+                    params.new_id,
+                );
+                self.data_storage
+                    .write_account_transaction(transaction.signer(), params)?;
+            }
+            Transaction::SetSchema(params) => {
+                log::debug!(
+                    "Client {} sets the schema of {:?} to {:?}.",
+                    &transaction.signer(),
+                    params.key,
+                    params.value_type,
+                );
+                self.data_storage
+                    .write_account_transaction(transaction.signer(), params)?;
+            }
+            Transaction::Blob(params) => {
+                log::debug!(
+                    "Client {} set {} to blob {} ({} bytes) (via another RPU)",
+                    &transaction.signer(),
+                    params.key,
+                    params.hash,
+                    params.size,
+                );
+            }
+            Transaction::ShareContentKey(params) => {
+                log::debug!(
+                    "Client {} shares content key {} for {:?} with {} readers (via another RPU)",
+                    &transaction.signer(),
+                    params.key_id,
+                    params.scope,
+                    params.readers.len(),
+                );
+            }
         }
         Ok(())
     }
@@ -113,6 +198,29 @@ impl PeerInbox {
         Ok(())
     }
 
+    /// Store a chunk of a blob uploaded by a client to another RPU.
+    pub fn handle_store_blob(&self, params: message::StoreBlob) -> Result<bool, BoxError> {
+        let message::StoreBlob(message) = params;
+        let message = message.verify()?;
+
+        // The uploader needs to be allowed to write blobs of this size.
+        self.transaction_checker
+            .account_checker(message.signer().clone())?
+            .verify_blob_size(message.size)?;
+
+        Ok(self
+            .block_storage
+            .write_blob_chunk(message.signer(), &message)?)
+    }
+
+    /// Get a chunk of the payload of a blob (if it is stored by this RPU).
+    pub fn handle_fetch_blob(
+        &self,
+        params: &message::FetchBlob,
+    ) -> Result<Option<BlobChunk>, BoxError> {
+        Ok(self.block_storage.read_blob_chunk(&params.0, params.1)?)
+    }
+
     /// Handle an add `Add` message, return a `usize` as a `Result`.
     pub fn handle_add(&self, params: &message::Add) -> Result<usize, BoxError> {
         Ok(self.calculator.lock().unwrap().add(params.0, params.1))
@@ -38,6 +38,8 @@ impl Receiver {
                 Ping(_) => self.peer_inbox.handle_ping(),
                 ExecuteBatch(params) => self.peer_inbox.handle_execute_batch(params).await,
                 Consensus(params) => self.peer_inbox.handle_consensus(params).await,
+                StoreBlob(params) => self.peer_inbox.handle_store_blob(params),
+                FetchBlob(params) => self.peer_inbox.handle_fetch_blob(&params),
             }),
             tls_identity,
         )?;
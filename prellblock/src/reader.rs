@@ -1,12 +1,68 @@
 //! A server for communicating between RPUs.
 
 use crate::{
-    block_storage::BlockStorage, transaction_checker::TransactionChecker,
-    world_state::WorldStateService, BoxError,
+    block_storage::{BlockStorage, ReadPosition},
+    consensus::{Block, Consensus},
+    peer::{message as peer_message, Sender},
+    transaction_checker::{AccountChecker, TransactionChecker},
+    world_state::WorldStateService,
+    BoxError,
+};
+use balise::server::{Publisher, Subscription};
+use pinxit::PeerId;
+use prellblock_client_api::{
+    blob::{BlobHash, MAX_BLOB_SIZE},
+    consensus::BlockNumber,
+    message, ClientMessage, Cursor, Page, Query, Span, Subscribe, SubscriptionEvent,
+    MAX_VALUE_PROOFS,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    ops::{Bound, RangeBounds},
+    sync::Arc,
+    time::SystemTime,
 };
-use prellblock_client_api::{message, ClientMessage};
 
 type Response<R> = Result<<R as balise::Request<ClientMessage>>::Response, BoxError>;
+type SubscriptionResponse<R> =
+    Result<Subscription<<R as balise::Request<ClientMessage>>::Response>, BoxError>;
+
+/// The number of events that are buffered for each subscription.
+const SUBSCRIPTION_BUFFER_SIZE: usize = 64;
+
+/// The maximum number of values in a single page of a `GetValue` response
+/// (or buckets of a `GetAggregate` response).
+const MAX_VALUES_PER_PAGE: usize = 10_000;
+
+/// The maximum number of blocks in a single page of a `GetBlock` response.
+const MAX_BLOCKS_PER_PAGE: usize = 64;
+
+/// The position to continue a `GetValue` (or `GetAggregate`) request at
+/// (sent to the client as a `Cursor`).
+#[derive(Debug, Serialize, Deserialize)]
+struct ValueCursor {
+    /// The index of the peer in the requested `peer_ids`.
+    peer_index: usize,
+    /// The position in the values of the peer.
+    position: Option<ReadPosition>,
+    /// The time the first page was read at (`Span::Duration` ends are relative to it).
+    time: SystemTime,
+}
+
+impl ValueCursor {
+    /// Decode the `cursor` of a request (or start at the first page).
+    fn new(cursor: Option<&Cursor>) -> Result<Self, BoxError> {
+        match cursor {
+            Some(cursor) => Ok(postcard::from_bytes(cursor.as_bytes())?),
+            None => Ok(Self {
+                peer_index: 0,
+                position: None,
+                time: SystemTime::now(),
+            }),
+        }
+    }
+}
 
 /// The `Reader` component responds to read queries.
 #[derive(Clone)]
@@ -14,16 +70,22 @@ pub struct Reader {
     block_storage: BlockStorage,
     world_state: WorldStateService,
     transaction_checker: TransactionChecker,
+    consensus: Arc<Consensus>,
 }
 
 impl Reader {
     /// Create a new reader instance.
     #[must_use]
-    pub fn new(block_storage: BlockStorage, world_state: WorldStateService) -> Self {
+    pub fn new(
+        block_storage: BlockStorage,
+        world_state: WorldStateService,
+        consensus: Arc<Consensus>,
+    ) -> Self {
         Self {
             block_storage,
             world_state: world_state.clone(),
             transaction_checker: TransactionChecker::new(world_state),
+            consensus,
         }
     }
 
@@ -40,23 +102,253 @@ impl Reader {
 
         let message = message.into_inner();
         let filter = message.filter;
-        let query = message.query;
+        let cursor = ValueCursor::new(message.cursor.as_ref())?;
+        // The end of the range must not move while paginating.
+        let query = fix_end(message.query, cursor.time);
 
-        #[allow(clippy::filter_map)]
-        message
-            .peer_ids
-            .into_iter()
-            .filter(|peer_id| account_checker.is_allowed_to_read_any_key(peer_id))
-            .map(|peer_id| {
-                let transactions = self.block_storage.read_transactions(
+        read_page(
+            &account_checker,
+            message.peer_ids,
+            cursor,
+            |peer_id, position, budget| {
+                Ok(self.block_storage.read_transactions(
+                    &account_checker,
+                    peer_id,
+                    filter.as_deref(),
+                    &query,
+                    position,
+                    budget,
+                )?)
+            },
+        )
+    }
+
+    pub(crate) async fn handle_get_aggregate(
+        &self,
+        params: message::GetAggregate,
+    ) -> Response<message::GetAggregate> {
+        let message::GetAggregate(message) = params;
+        let message = message.verify()?;
+
+        let account_checker = self
+            .transaction_checker
+            .account_checker(message.signer().clone())?;
+
+        let message = message.into_inner();
+        let filter = message.filter;
+        let cursor = ValueCursor::new(message.cursor.as_ref())?;
+        // The end of the range must not move while paginating.
+        let mut query = message.query;
+        query.end = fix_span(query.end, cursor.time);
+
+        // The values are decoded according to the schemas.
+        let world_state = self.world_state.get();
+        read_page(
+            &account_checker,
+            message.peer_ids,
+            cursor,
+            |peer_id, position, budget| {
+                Ok(self.block_storage.read_aggregates(
                     &account_checker,
-                    &peer_id,
+                    &world_state,
+                    peer_id,
                     filter.as_deref(),
                     &query,
-                )?;
-                Ok((peer_id, transactions))
+                    position,
+                    budget,
+                )?)
+            },
+        )
+    }
+
+    pub(crate) async fn handle_get_value_proof(
+        &self,
+        params: message::GetValueProof,
+    ) -> Response<message::GetValueProof> {
+        let message::GetValueProof(message) = params;
+        let message = message.verify()?;
+
+        let account_checker = self
+            .transaction_checker
+            .account_checker(message.signer().clone())?;
+
+        // Values the account is not allowed to read are treated as not found.
+        if !account_checker.is_allowed_to_read_key(&message.peer_id, &message.key) {
+            return Ok(None);
+        }
+
+        let namespace = account_checker.namespace_of(&message.peer_id);
+        let proof = self
+            .block_storage
+            .value_proof(namespace, &message.key, message.timestamp)?;
+
+        Ok(proof)
+    }
+
+    pub(crate) async fn handle_get_value_proofs(
+        &self,
+        params: message::GetValueProofs,
+    ) -> Response<message::GetValueProofs> {
+        let message::GetValueProofs(message) = params;
+        let message = message.verify()?;
+
+        if message.values.len() > MAX_VALUE_PROOFS {
+            return Err(format!(
+                "At most {} proofs can be requested at once.",
+                MAX_VALUE_PROOFS
+            )
+            .into());
+        }
+
+        let account_checker = self
+            .transaction_checker
+            .account_checker(message.signer().clone())?;
+
+        // Values the account is not allowed to read are treated as not found.
+        let values: Vec<_> = message
+            .values
+            .iter()
+            .map(|value| {
+                if account_checker.is_allowed_to_read_key(&value.peer_id, &value.key) {
+                    let namespace = account_checker.namespace_of(&value.peer_id);
+                    Some((namespace, value.key.as_str(), value.timestamp))
+                } else {
+                    None
+                }
             })
-            .collect()
+            .collect();
+
+        Ok(self.block_storage.value_proofs(&values)?)
+    }
+
+    pub(crate) async fn handle_get_blob(
+        &self,
+        params: message::GetBlob,
+    ) -> Response<message::GetBlob> {
+        let message::GetBlob(message) = params;
+        let message = message.verify()?;
+
+        let account_checker = self
+            .transaction_checker
+            .account_checker(message.signer().clone())?;
+
+        // Blobs the account is not allowed to read are treated as not found.
+        let namespace = account_checker.namespace_of(&message.peer_id);
+        if !account_checker.is_allowed_to_read_key(&message.peer_id, &message.key)
+            || !self
+                .block_storage
+                .has_blob_ref(namespace, &message.key, &message.hash)?
+        {
+            return Err(format!("Blob {} not found.", message.hash).into());
+        }
+
+        if !self.block_storage.has_blob(&message.hash)? {
+            self.fetch_blob(&message.hash).await?;
+        }
+
+        match self
+            .block_storage
+            .read_blob_chunk(&message.hash, message.offset)?
+        {
+            Some(chunk) => Ok(chunk),
+            None => Err(format!("Blob {} not found.", message.hash).into()),
+        }
+    }
+
+    /// Fetch the payload of a blob that is missing locally from the other RPUs.
+    async fn fetch_blob(&self, hash: &BlobHash) -> Result<(), BoxError> {
+        let peers = self.world_state.get().peers;
+        for (peer_id, peer_address) in peers {
+            let mut sender = Sender::new(peer_address);
+            match fetch_blob_from(&mut sender, hash).await {
+                // The payload is only stored if it matches the hash.
+                Ok(Some(data)) if BlobHash::of(&data) == *hash => {
+                    self.block_storage.write_blob(&data)?;
+                    return Ok(());
+                }
+                Ok(Some(_)) => {
+                    log::warn!("RPU {} sent an invalid payload of blob {}.", peer_id, hash)
+                }
+                Ok(None) => {}
+                Err(err) => log::warn!(
+                    "Could not fetch blob {} from RPU {}: {}",
+                    hash,
+                    peer_id,
+                    err
+                ),
+            }
+        }
+        Err(format!("The payload of blob {} is not available.", hash).into())
+    }
+
+    pub(crate) async fn handle_list_keys(
+        &self,
+        params: message::ListKeys,
+    ) -> Response<message::ListKeys> {
+        let message::ListKeys(message) = params;
+        let message = message.verify()?;
+
+        let account_checker = self
+            .transaction_checker
+            .account_checker(message.signer().clone())?;
+
+        if !account_checker.is_allowed_to_read_any_key(&message.peer_id) {
+            return Ok(Vec::new());
+        }
+
+        let keys = self
+            .block_storage
+            .read_keys(&account_checker, &message.peer_id)?;
+
+        Ok(keys)
+    }
+
+    pub(crate) async fn handle_get_schemas(
+        &self,
+        params: message::GetSchemas,
+    ) -> Response<message::GetSchemas> {
+        let message::GetSchemas(message) = params;
+        let message = message.verify()?;
+
+        // Any account can read the schemas.
+        self.transaction_checker
+            .account_checker(message.signer().clone())?;
+
+        let schemas = self.world_state.get().schemas.into_iter().collect();
+        Ok(schemas)
+    }
+
+    pub(crate) async fn handle_get_content_keys(
+        &self,
+        params: message::GetContentKeys,
+    ) -> Response<message::GetContentKeys> {
+        let message::GetContentKeys(message) = params;
+        let message = message.verify()?;
+
+        // Any account can get the grants (the keys can only be unwrapped by their readers).
+        let account_checker = self
+            .transaction_checker
+            .account_checker(message.signer().clone())?;
+
+        let namespace = account_checker.namespace_of(&message.owner);
+        let grants = self
+            .world_state
+            .get()
+            .content_keys
+            .get(namespace)
+            .map_or_else(Vec::new, |grants| {
+                grants
+                    .iter()
+                    .map(|grant| {
+                        let mut grant = grant.clone();
+                        grant
+                            .readers
+                            .retain(|wrapped| wrapped.reader == *message.signer());
+                        grant
+                    })
+                    .collect()
+            });
+        Ok(grants)
     }
 
     pub(crate) async fn handle_get_account(
@@ -85,6 +377,31 @@ impl Reader {
         Ok(accounts)
     }
 
+    pub(crate) async fn handle_list_accounts(
+        &self,
+        params: message::ListAccounts,
+    ) -> Response<message::ListAccounts> {
+        let message::ListAccounts(message) = params;
+        let message = message.verify()?;
+
+        self.transaction_checker
+            .account_checker(message.signer().clone())?
+            .verify_is_admin()?;
+
+        let world_state = self.world_state.get();
+        let mut accounts: Vec<_> = world_state
+            .accounts
+            .iter()
+            .filter(|(_, account)| message.matches(account))
+            .map(|(peer_id, account)| (peer_id.clone(), (**account).clone()))
+            .collect();
+        accounts.sort_by(|(a_id, a), (b_id, b)| {
+            (&a.name, a_id.as_bytes()).cmp(&(&b.name, b_id.as_bytes()))
+        });
+
+        Ok(accounts)
+    }
+
     pub(crate) async fn handle_get_block(
         &self,
         params: message::GetBlock,
@@ -97,9 +414,29 @@ impl Reader {
             .verify_can_read_blocks()?;
 
         let message = message.into_inner();
-        let blocks: Result<_, _> = self.block_storage.read(message.filter).collect();
+        let start = match &message.cursor {
+            Some(cursor) => Bound::Included(postcard::from_bytes(cursor.as_bytes())?),
+            None => cloned_bound(message.filter.start_bound()),
+        };
+        let range = (start, cloned_bound(message.filter.end_bound()));
+
+        // Read one more block to know whether there is a next page.
+        let mut blocks = self
+            .block_storage
+            .read(range)
+            .take(MAX_BLOCKS_PER_PAGE + 1)
+            .collect::<Result<Vec<_>, _>>()?;
 
-        Ok(blocks?)
+        let next = if blocks.len() > MAX_BLOCKS_PER_PAGE {
+            let next_block_number: BlockNumber = blocks.pop().unwrap().block_number();
+            Some(Cursor::new(postcard::to_stdvec(&next_block_number)?))
+        } else {
+            None
+        };
+        Ok(Page {
+            items: blocks,
+            next,
+        })
     }
 
     /// The function will return the current blocknumber,
@@ -120,4 +457,233 @@ impl Reader {
 
         Ok(block_number)
     }
+
+    /// Subscribe to newly committed values (and blocks).
+    ///
+    /// The events are pushed to the client by a separate task.
+    pub(crate) async fn handle_subscribe(
+        &self,
+        params: message::Subscribe,
+    ) -> SubscriptionResponse<message::Subscribe> {
+        let message::Subscribe(message) = params;
+        let message = message.verify()?;
+        let peer_id = message.signer().clone();
+
+        let account_checker = self.transaction_checker.account_checker(peer_id.clone())?;
+        if message.blocks {
+            account_checker.verify_can_read_blocks()?;
+        }
+
+        let (publisher, subscription) = Subscription::channel(SUBSCRIPTION_BUFFER_SIZE);
+        let reader = self.clone();
+        let subscribe = message.into_inner();
+        tokio::spawn(async move {
+            if let Err(err) = reader.publish(&peer_id, &subscribe, publisher).await {
+                log::debug!("Subscription of {} ended: {}", peer_id, err);
+            }
+        });
+
+        Ok(subscription)
+    }
+
+    /// Publish all events matching a subscription of `peer_id` until the client disconnects.
+    async fn publish(
+        &self,
+        peer_id: &PeerId,
+        subscribe: &Subscribe,
+        mut publisher: Publisher<SubscriptionEvent>,
+    ) -> Result<(), BoxError> {
+        let mut next_block_number = self.world_state.get().block_number;
+        loop {
+            // The permissions are checked for each block, they could have changed.
+            let account_checker = self.transaction_checker.account_checker(peer_id.clone())?;
+            if subscribe.blocks {
+                account_checker.verify_can_read_blocks()?;
+            }
+
+            let block_number = self.world_state.get().block_number;
+            let mut events = Vec::new();
+            for block in self.block_storage.read(next_block_number..block_number) {
+                events.extend(subscription_events(&account_checker, subscribe, &block?));
+            }
+            next_block_number = block_number;
+
+            for event in &events {
+                publisher.publish(event).await?;
+            }
+
+            // Blocks applied while publishing are read right away.
+            tokio::select! {
+                _ = self.consensus.wait_for_block(block_number + 1) => {}
+                // Stop once the client disconnected (instead of at the next event).
+                _ = publisher.closed() => return Ok(()),
+            }
+        }
+    }
+}
+
+/// Read a page of the values of the `peer_ids` with `read_peer`, starting at the `cursor`.
+///
+/// The number of values per page is limited to `MAX_VALUES_PER_PAGE`.
+fn read_page<T>(
+    account_checker: &AccountChecker,
+    peer_ids: Vec<PeerId>,
+    mut cursor: ValueCursor,
+    mut read_peer: impl FnMut(
+        &PeerId,
+        Option<ReadPosition>,
+        &mut usize,
+    ) -> Result<(T, Option<ReadPosition>), BoxError>,
+) -> Result<Page<HashMap<PeerId, T>>, BoxError> {
+    let mut budget = MAX_VALUES_PER_PAGE;
+    let mut values = HashMap::new();
+    let mut next = None;
+    for (peer_index, peer_id) in peer_ids.into_iter().enumerate() {
+        if peer_index < cursor.peer_index || !account_checker.is_allowed_to_read_any_key(&peer_id) {
+            continue;
+        }
+
+        if budget == 0 {
+            next = Some(ValueCursor {
+                peer_index,
+                position: None,
+                time: cursor.time,
+            });
+            break;
+        }
+
+        let position = if peer_index == cursor.peer_index {
+            cursor.position.take()
+        } else {
+            None
+        };
+        let (transactions, position) = read_peer(&peer_id, position, &mut budget)?;
+        values.insert(peer_id, transactions);
+
+        if position.is_some() {
+            next = Some(ValueCursor {
+                peer_index,
+                position,
+                time: cursor.time,
+            });
+            break;
+        }
+    }
+
+    let next = match next {
+        Some(next) => Some(Cursor::new(postcard::to_stdvec(&next)?)),
+        None => None,
+    };
+    Ok(Page {
+        items: values,
+        next,
+    })
+}
+
+/// Fetch the whole payload of a blob chunk by chunk from the RPU of `sender`.
+///
+/// Returns `None` if the payload is not stored by the RPU.
+async fn fetch_blob_from(
+    sender: &mut Sender,
+    hash: &BlobHash,
+) -> Result<Option<Vec<u8>>, BoxError> {
+    let mut data = Vec::new();
+    loop {
+        let offset = data.len() as u64;
+        let chunk = match sender
+            .send_request(peer_message::FetchBlob(*hash, offset))
+            .await?
+        {
+            Some(chunk) => chunk,
+            None => return Ok(None),
+        };
+        if chunk.size > MAX_BLOB_SIZE || (chunk.data.is_empty() && offset < chunk.size) {
+            return Err(format!("Invalid chunk of blob {} at {}.", hash, offset).into());
+        }
+        data.extend_from_slice(&chunk.data);
+        if data.len() as u64 >= chunk.size {
+            return Ok(Some(data));
+        }
+    }
+}
+
+/// Replace a `Span::Duration` end of a `query` by the `Span::Time` relative to `time`.
+fn fix_end(query: Query, time: SystemTime) -> Query {
+    match query {
+        Query::Range { span, end, skip } => Query::Range {
+            span,
+            end: fix_span(end, time),
+            skip,
+        },
+        query => query,
+    }
+}
+
+/// Replace a `Span::Duration` `end` by the `Span::Time` relative to `time`.
+fn fix_span(end: Span, time: SystemTime) -> Span {
+    match end {
+        Span::Duration(duration) => {
+            Span::Time(time.checked_sub(duration).unwrap_or(SystemTime::UNIX_EPOCH))
+        }
+        end => end,
+    }
+}
+
+fn cloned_bound<T: Clone>(bound: Bound<&T>) -> Bound<T> {
+    match bound {
+        Bound::Included(v) => Bound::Included(v.clone()),
+        Bound::Excluded(v) => Bound::Excluded(v.clone()),
+        Bound::Unbounded => Bound::Unbounded,
+    }
+}
+
+/// Get all events of a `block` matching a subscription.
+fn subscription_events<'a>(
+    account_checker: &'a AccountChecker,
+    subscribe: &'a Subscribe,
+    block: &'a Block,
+) -> impl Iterator<Item = SubscriptionEvent> + 'a {
+    let block_number = block.block_number();
+    let block_event = if subscribe.blocks {
+        Some(SubscriptionEvent::Block {
+            block_number,
+            block_hash: block.hash(),
+            timestamp: block.body.timestamp(),
+            transactions: block.body.transactions().len(),
+        })
+    } else {
+        None
+    };
+
+    let value_events = block
+        .body
+        .transactions
+        .iter()
+        .filter_map(move |transaction| {
+            let written = transaction
+                .unverified_ref()
+                .written_value(transaction.signer())?;
+            // Events of rotated keys are reported under the subscribed ID of the account.
+            let namespace = account_checker.namespace_of(written.namespace);
+            let peer_id = subscribe
+                .peer_ids
+                .iter()
+                .find(|peer_id| account_checker.namespace_of(peer_id) == namespace)?;
+            if subscribe.filter.as_deref().contains(&written.key)
+                && account_checker.is_allowed_to_read_key(peer_id, written.key)
+            {
+                Some(SubscriptionEvent::Value {
+                    peer_id: peer_id.clone(),
+                    key: written.key.to_string(),
+                    value: written.value.to_vec(),
+                    timestamp: written.timestamp,
+                    signature: transaction.signature().clone(),
+                    block_number,
+                })
+            } else {
+                None
+            }
+        });
+
+    block_event.into_iter().chain(value_events)
 }
@@ -1,13 +1,27 @@
 //! Module to check permissions of transactions.
 
-use crate::world_state::{WorldState, WorldStateService};
+use crate::world_state::{Usage, WorldState, WorldStateService};
 use err_derive::Error;
+use im::HashMap;
 use pinxit::{verify_signed_batch_iter, PeerId, Signed, VerifiedRef};
 use prellblock_client_api::{
-    account::{Account, AccountType, ReadingPermission},
+    account::{Account, AccountType, Permission, ReadingPermission},
+    blob::MAX_BLOB_SIZE,
+    consensus::{StateRoot, TransactionHash},
+    encryption::{self, EncryptedValue},
+    schema::ValueType,
     Transaction,
 };
-use std::sync::Arc;
+use std::{
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+/// How long before the block timestamp a transaction may have been created.
+pub const MAX_TRANSACTION_AGE: Duration = Duration::from_secs(5 * 60);
+
+/// How far a timestamp may be in the future (to tolerate clocks that are not exactly in sync).
+pub const MAX_CLOCK_SKEW: Duration = Duration::from_secs(30);
 
 /// An error of the `permission_checker` module.
 #[derive(Debug, Error)]
@@ -17,6 +31,22 @@ pub enum PermissionError {
     #[error(display = "The account {} is not allowed to write.", 0)]
     WriteDenied(PeerId),
 
+    /// A transaction tried to write a value with a key outside of the account's writing scopes.
+    #[error(display = "The account {} is not allowed to write the key {:?}.", 0, 1)]
+    WriteKeyDenied(PeerId, String),
+
+    /// A content key was shared for a scope outside of the account's writing scopes.
+    #[error(
+        display = "The account {} is not allowed to write all keys of the scope {:?}.",
+        0,
+        1
+    )]
+    WriteScopeDenied(PeerId, String),
+
+    /// A transaction tried to write into the namespace of an account that did not delegate to the signer.
+    #[error(display = "The account {} is not a delegate of {}.", 0, 1)]
+    NotADelegate(PeerId, PeerId),
+
     /// The account was not found.
     #[error(display = "The account {} was not found.", 0)]
     AccountNotFound(PeerId),
@@ -44,6 +74,81 @@ pub enum PermissionError {
     /// The account to be created already exists.
     #[error(display = "The account {} already exists.", 0)]
     AccountAlreadyExists(PeerId),
+
+    /// The account management transaction needs to be proposed and approved by multiple admins.
+    #[error(
+        display = "Account management needs the approval of {} admins, use a proposal.",
+        0
+    )]
+    ApprovalRequired(usize),
+
+    /// Only account management transactions can be proposed.
+    #[error(display = "The proposed transaction does not manage accounts.")]
+    InvalidProposal,
+
+    /// The proposal to approve was not found (or is already executed).
+    #[error(display = "The proposal {} was not found.", 0)]
+    ProposalNotFound(TransactionHash),
+
+    /// The admin already approved the proposal.
+    #[error(display = "The account {} already approved the proposal {}.", 0, 1)]
+    AlreadyApproved(PeerId, TransactionHash),
+
+    /// The admin threshold is zero or larger than the number of admins.
+    #[error(display = "The admin threshold {} is invalid.", 0)]
+    InvalidAdminThreshold(usize),
+
+    /// The account sent more transactions per second than allowed.
+    #[error(
+        display = "The account {} exceeded its limit of {} transactions per second.",
+        0,
+        1
+    )]
+    RateLimitExceeded(PeerId, u32),
+
+    /// The value is larger than allowed for the account.
+    #[error(
+        display = "The value of {} bytes exceeds the limit of {} bytes per value of account {}.",
+        1,
+        2,
+        0
+    )]
+    ValueTooLarge(PeerId, u64, u64),
+
+    /// The account wrote more bytes within a day than allowed.
+    #[error(
+        display = "The account {} exceeded its quota of {} bytes per day.",
+        0,
+        1
+    )]
+    QuotaExceeded(PeerId, u64),
+
+    /// The transaction was already committed.
+    #[error(display = "The transaction {} was already committed.", 0)]
+    TransactionReplayed(TransactionHash),
+
+    /// The timestamp of the transaction is too long before the block timestamp.
+    #[error(display = "The timestamp of the transaction {} is too old.", 0)]
+    TransactionTooOld(TransactionHash),
+
+    /// The timestamp of the transaction is after the block timestamp.
+    #[error(display = "The timestamp of the transaction {} is in the future.", 0)]
+    TransactionFromFuture(TransactionHash),
+
+    /// The value does not match the schema of its key.
+    #[error(display = "The value of the key {:?} is not of type {:?}.", 0, 1)]
+    SchemaMismatch(String, ValueType),
+
+    /// The value of an encrypted key is not encrypted with the content key currently shared for it.
+    #[error(
+        display = "The value of the key {:?} is not encrypted with its current content key.",
+        0
+    )]
+    NotEncrypted(String),
+
+    /// A content key was shared without any readers.
+    #[error(display = "A content key must be shared with at least one reader.")]
+    NoReaders,
 }
 
 /// A `TransactionChecker` is used to check whether accounts are allowed to carry out transactions.
@@ -60,10 +165,14 @@ impl TransactionChecker {
     }
 
     /// Returns a `TransactionCheck` with the current world state as virtual clone.
+    ///
+    /// The transaction timestamps are checked against the current time
+    /// until a block timestamp is set via `TransactionCheck::set_block_timestamp`.
     #[must_use]
     pub fn check(&self) -> TransactionCheck {
         TransactionCheck {
             world_state: self.world_state.get(),
+            block_timestamp: SystemTime::now(),
         }
     }
 
@@ -75,19 +184,28 @@ impl TransactionChecker {
         self.check().verify_permissions_and_apply(transaction)
     }
 
-    /// Verify signatures of `Transaction`s
-    pub fn verify(&self, data: &[Signed<Transaction>]) -> Result<(), PermissionError> {
+    /// Verify signatures of `Transaction`s of a block created at `block_timestamp`.
+    ///
+    /// Returns the `StateRoot` of the `WorldState` after applying the transactions.
+    pub fn verify(
+        &self,
+        data: &[Signed<Transaction>],
+        block_timestamp: SystemTime,
+    ) -> Result<StateRoot, PermissionError> {
         let verified_transactions = verify_signed_batch_iter(data.iter())?;
         let mut check = self.check();
+        check.set_block_timestamp(block_timestamp);
         for tx in verified_transactions {
             check.verify_permissions_and_apply(tx)?;
         }
-        Ok(())
+        Ok(check.state_root())
     }
 
     /// Get an `AcccountChecker` that can be used to verify permissions of a single account.
+    ///
+    /// The expiry of the account is checked against the current time.
     pub fn account_checker(&self, peer_id: PeerId) -> Result<AccountChecker, PermissionError> {
-        AccountChecker::new(&self.world_state.get(), peer_id)
+        AccountChecker::new(&self.world_state.get(), peer_id, SystemTime::now())
     }
 }
 
@@ -95,18 +213,25 @@ impl TransactionChecker {
 pub struct AccountChecker {
     peer_id: PeerId,
     account: Arc<Account>,
+    namespaces: HashMap<PeerId, PeerId>,
 }
 
 impl AccountChecker {
-    fn new(world_state: &WorldState, peer_id: PeerId) -> Result<Self, PermissionError> {
+    /// Create an `AccountChecker` for an account that must not be expired at `time`.
+    fn new(
+        world_state: &WorldState,
+        peer_id: PeerId,
+        time: SystemTime,
+    ) -> Result<Self, PermissionError> {
         if let Some(account) = world_state.accounts.get(&peer_id) {
             // Return an error if the account is expired.
-            if account.expire_at.is_expired() {
+            if account.expire_at.is_expired_at(time) {
                 Err(PermissionError::AccountExpired(peer_id))
             } else {
                 Ok(Self {
                     peer_id,
                     account: account.clone(),
+                    namespaces: world_state.namespaces.clone(),
                 })
             }
         } else {
@@ -114,12 +239,25 @@ impl AccountChecker {
         }
     }
 
+    /// Get the namespace (the original ID) of an account ID (which could be rotated).
+    #[must_use]
+    pub fn namespace_of<'a>(&'a self, peer_id: &'a PeerId) -> &'a PeerId {
+        self.namespaces.get(peer_id).unwrap_or(peer_id)
+    }
+
+    /// Check whether any of the `peer_ids` refers to the same account as `peer_id`
+    /// (even if its key was rotated).
+    fn contains_account(&self, peer_ids: &[PeerId], peer_id: &PeerId) -> bool {
+        let namespace = self.namespace_of(peer_id);
+        peer_ids.iter().any(|id| self.namespace_of(id) == namespace)
+    }
+
     /// This checks whether the account is allowed to read any keys of a given `peer_id`.
     #[must_use]
     pub fn is_allowed_to_read_any_key(&self, peer_id: &PeerId) -> bool {
         for reading_permission in &self.account.reading_rights {
             if let ReadingPermission::Whitelist(rights) = reading_permission {
-                if rights.accounts.contains(peer_id) {
+                if self.contains_account(&rights.accounts, peer_id) {
                     return true;
                 }
             }
@@ -135,11 +273,11 @@ impl AccountChecker {
         for reading_permission in &self.account.reading_rights {
             match reading_permission {
                 ReadingPermission::Whitelist(rights) | ReadingPermission::Blacklist(rights) => {
-                    if !rights.accounts.contains(peer_id)
+                    if !self.contains_account(&rights.accounts, peer_id)
                         || !rights
                             .namespace
                             .iter()
-                            .any(|permission| permission.scope == key)
+                            .any(|permission| permission.matches(key))
                     {
                         continue;
                     }
@@ -155,6 +293,118 @@ impl AccountChecker {
         false
     }
 
+    /// Verify that the account is allowed to write into its own namespace.
+    pub fn verify_has_writing_rights(&self) -> Result<(), PermissionError> {
+        if self.account.writing_rights {
+            Ok(())
+        } else {
+            Err(PermissionError::WriteDenied(self.peer_id.clone()))
+        }
+    }
+
+    /// This checks whether the account is allowed to write the given `key` into its own namespace.
+    ///
+    /// An account without writing scopes is allowed to write all keys.
+    pub fn verify_is_allowed_to_write_key(&self, key: &str) -> Result<(), PermissionError> {
+        self.verify_has_writing_rights()?;
+
+        let scopes = &self.account.writing_scopes;
+        if scopes.is_empty() || scopes.iter().any(|scope| scope.matches(key)) {
+            Ok(())
+        } else {
+            Err(PermissionError::WriteKeyDenied(
+                self.peer_id.clone(),
+                key.to_string(),
+            ))
+        }
+    }
+
+    /// This checks whether the account is allowed to write all keys selected by the `scope`.
+    ///
+    /// An account without writing scopes is allowed to write all keys.
+    pub fn verify_is_allowed_to_write_scope(
+        &self,
+        scope: &Permission,
+    ) -> Result<(), PermissionError> {
+        self.verify_has_writing_rights()?;
+
+        let scopes = &self.account.writing_scopes;
+        if scopes.is_empty()
+            || scopes
+                .iter()
+                .any(|writing_scope| scope.is_covered_by(writing_scope))
+        {
+            Ok(())
+        } else {
+            Err(PermissionError::WriteScopeDenied(
+                self.peer_id.clone(),
+                scope.scope.clone(),
+            ))
+        }
+    }
+
+    /// Verify that the account is allowed to store a blob of `size` bytes.
+    ///
+    /// The size is limited by `MAX_BLOB_SIZE` and the account's `bytes_per_value`.
+    pub fn verify_blob_size(&self, size: u64) -> Result<(), PermissionError> {
+        self.verify_has_writing_rights()?;
+
+        let limit = self
+            .account
+            .limits
+            .bytes_per_value
+            .map_or(MAX_BLOB_SIZE, |limit| limit.min(MAX_BLOB_SIZE));
+        if size > limit {
+            return Err(PermissionError::ValueTooLarge(
+                self.peer_id.clone(),
+                size,
+                limit,
+            ));
+        }
+        Ok(())
+    }
+
+    /// Verify that the account stays within its `Limits` when sending the `transaction`.
+    ///
+    /// The `usage` must already include the `transaction`.
+    pub fn verify_limits(
+        &self,
+        transaction: &Transaction,
+        usage: &Usage,
+    ) -> Result<(), PermissionError> {
+        let limits = &self.account.limits;
+        if let Some(limit) = limits.bytes_per_value {
+            let size = transaction.payload_size();
+            if size > limit {
+                return Err(PermissionError::ValueTooLarge(
+                    self.peer_id.clone(),
+                    size,
+                    limit,
+                ));
+            }
+        }
+        self.verify_usage(usage)
+    }
+
+    /// Verify that the `usage` of the account is within its `Limits`.
+    pub fn verify_usage(&self, usage: &Usage) -> Result<(), PermissionError> {
+        let limits = &self.account.limits;
+        if let Some(limit) = limits.transactions_per_second {
+            if usage.transactions > limit {
+                return Err(PermissionError::RateLimitExceeded(
+                    self.peer_id.clone(),
+                    limit,
+                ));
+            }
+        }
+        if let Some(limit) = limits.bytes_per_day {
+            if usage.bytes > limit {
+                return Err(PermissionError::QuotaExceeded(self.peer_id.clone(), limit));
+            }
+        }
+        Ok(())
+    }
+
     /// This checks whether the account is allowed to read with admin priviliges.
     ///
     /// This is necessary for reading account information.
@@ -187,9 +437,45 @@ impl AccountChecker {
 #[derive(Debug)]
 pub struct TransactionCheck {
     world_state: WorldState,
+    block_timestamp: SystemTime,
 }
 
 impl TransactionCheck {
+    /// Create a `TransactionCheck` on a given `world_state` (e.g. one tracking the accounts of a
+    /// chain that is synchronized).
+    ///
+    /// A block timestamp has to be set via `set_block_timestamp` before checking transactions.
+    #[must_use]
+    pub fn new(world_state: WorldState) -> Self {
+        let block_timestamp = world_state
+            .last_block_timestamp
+            .unwrap_or(SystemTime::UNIX_EPOCH);
+        Self {
+            world_state,
+            block_timestamp,
+        }
+    }
+
+    /// Get the virtual `WorldState` (after applying the checked transactions).
+    #[must_use]
+    pub const fn world_state(&self) -> &WorldState {
+        &self.world_state
+    }
+
+    /// Get the `StateRoot` of the virtual `WorldState` (after applying the checked transactions).
+    #[must_use]
+    pub fn state_root(&self) -> StateRoot {
+        self.world_state.state_root()
+    }
+
+    /// Set the timestamp of the block the transactions are checked for.
+    ///
+    /// The `world_state` is moved to the block timestamp (like in `WorldState::apply_block`).
+    pub fn set_block_timestamp(&mut self, block_timestamp: SystemTime) {
+        self.block_timestamp = block_timestamp;
+        self.world_state.start_block(block_timestamp);
+    }
+
     /// Verify whether a given `transaction` issued by a `peer_id` is valid.
     ///
     /// This also applies the `transaction` to the `world_state`.
@@ -198,43 +484,647 @@ impl TransactionCheck {
         &mut self,
         transaction: VerifiedRef<Transaction>,
     ) -> Result<(), PermissionError> {
-        let account_checker = AccountChecker::new(&self.world_state, transaction.signer().clone())?;
+        // The expiry is checked at the block timestamp (to be deterministic).
+        let account_checker = AccountChecker::new(
+            &self.world_state,
+            transaction.signer().clone(),
+            self.block_timestamp,
+        )?;
+
+        // Values must be recorded near their claimed time.
+        // (Committed transactions are also only remembered for the `REPLAY_WINDOW`.)
+        let signed_transaction: &Signed<Transaction> = transaction.into();
+        let timestamp = transaction.timestamp();
+        let too_old = self
+            .block_timestamp
+            .checked_sub(MAX_TRANSACTION_AGE)
+            .map_or(false, |oldest| timestamp < oldest)
+            || self
+                .world_state
+                .replay_horizon
+                .map_or(false, |horizon| timestamp < horizon);
+        if too_old {
+            return Err(PermissionError::TransactionTooOld(TransactionHash::of(
+                signed_transaction,
+            )));
+        }
+        let too_new = self
+            .block_timestamp
+            .checked_add(MAX_CLOCK_SKEW)
+            .map_or(false, |newest| timestamp > newest);
+        if too_new {
+            return Err(PermissionError::TransactionFromFuture(TransactionHash::of(
+                signed_transaction,
+            )));
+        }
+        if self
+            .world_state
+            .is_replayed(transaction.signer(), &transaction)
+        {
+            return Err(PermissionError::TransactionReplayed(TransactionHash::of(
+                signed_transaction,
+            )));
+        }
+
+        // The usage is based on the timestamps of the transactions (to be deterministic).
+        let usage = self
+            .world_state
+            .usage_with(transaction.signer(), &transaction);
+        account_checker.verify_limits(&transaction, &usage)?;
+
+        self.verify_transaction_and_apply(account_checker, transaction)?;
+        self.world_state
+            .record_transaction(transaction.signer(), &transaction);
+        Ok(())
+    }
 
+    fn verify_transaction_and_apply(
+        &mut self,
+        account_checker: AccountChecker,
+        transaction: VerifiedRef<Transaction>,
+    ) -> Result<(), PermissionError> {
         match &*transaction {
-            Transaction::KeyValue { .. } => {
-                if account_checker.account.writing_rights {
-                    Ok(())
-                } else {
-                    Err(PermissionError::WriteDenied(account_checker.peer_id))
+            Transaction::KeyValue(params) => {
+                account_checker.verify_is_allowed_to_write_key(&params.key)?;
+                let namespace = account_checker.namespace_of(&account_checker.peer_id);
+                self.verify_schema(namespace, &params.key, &params.value)
+            }
+            Transaction::DelegatedKeyValue(params) => {
+                // The value is written on behalf of the owner (and within the owner's rights).
+                let owner_checker = AccountChecker::new(
+                    &self.world_state,
+                    params.owner.clone(),
+                    self.block_timestamp,
+                )?;
+                if !owner_checker
+                    .contains_account(&owner_checker.account.delegates, &account_checker.peer_id)
+                {
+                    return Err(PermissionError::NotADelegate(
+                        account_checker.peer_id,
+                        owner_checker.peer_id,
+                    ));
                 }
+                owner_checker.verify_is_allowed_to_write_key(&params.key)?;
+                let namespace = owner_checker.namespace_of(&owner_checker.peer_id);
+                self.verify_schema(namespace, &params.key, &params.value)
             }
-            Transaction::UpdateAccount(params) => {
+            Transaction::Blob(params) => {
+                account_checker.verify_is_allowed_to_write_key(&params.key)?;
+                account_checker.verify_blob_size(params.size)?;
+                // The schema applies to the `BlobRef` written as value.
+                let written = transaction
+                    .written_value(&account_checker.peer_id)
+                    .expect("blob transactions write a value");
+                let namespace = account_checker.namespace_of(&account_checker.peer_id);
+                self.verify_schema(namespace, &params.key, &written.value)
+            }
+            Transaction::ShareContentKey(params) => {
+                account_checker.verify_is_allowed_to_write_scope(&params.scope)?;
+                if params.readers.is_empty() {
+                    return Err(PermissionError::NoReaders);
+                }
+                // Later values of the scope must be encrypted instead of matching their schema.
+                self.apply(transaction)
+            }
+            // An account can always rotate its own key.
+            Transaction::RotateKey(params) if params.id == account_checker.peer_id => {
+                self.verify_account_management(&transaction)?;
+                self.apply(transaction)
+            }
+            Transaction::UpdateAccount(_)
+            | Transaction::CreateAccount(_)
+            | Transaction::DeleteAccount(_)
+            | Transaction::SetAdminThreshold(_)
+            | Transaction::RotateKey(_)
+            | Transaction::SetSchema(_) => {
                 account_checker.verify_is_admin()?;
+                let threshold = self.world_state.admin_threshold();
+                if threshold > 1 {
+                    return Err(PermissionError::ApprovalRequired(threshold));
+                }
+                self.verify_account_management(&transaction)?;
+                self.apply(transaction)
+            }
+            Transaction::ProposeAccountChange(params) => {
+                account_checker.verify_is_admin()?;
+                if !params.operation.is_account_management() {
+                    return Err(PermissionError::InvalidProposal);
+                }
+                self.apply(transaction)
+            }
+            Transaction::ApproveAccountChange(params) => {
+                account_checker.verify_is_admin()?;
+                let proposal = self
+                    .world_state
+                    .proposals
+                    .get(&params.proposal)
+                    .ok_or(PermissionError::ProposalNotFound(params.proposal))?;
+                if proposal.approvals.contains(&account_checker.peer_id) {
+                    return Err(PermissionError::AlreadyApproved(
+                        account_checker.peer_id,
+                        params.proposal,
+                    ));
+                }
+                self.apply(transaction)
+            }
+        }
+    }
+
+    /// Apply a verified `transaction` to the `world_state`.
+    ///
+    /// The proposals executed afterwards (see `WorldState::apply_transaction`)
+    /// are verified as well. If one of them is invalid, the `transaction` is rejected.
+    fn apply(&mut self, transaction: VerifiedRef<Transaction>) -> Result<(), PermissionError> {
+        let world_state = self.world_state.clone();
+        self.world_state
+            .apply_transaction_only(transaction.to_owned().into());
+        while let Some(proposal_id) = self.world_state.next_approved_proposal() {
+            let operation = self.world_state.proposals[&proposal_id].operation.clone();
+            if let Err(err) = self.verify_account_management(&operation) {
+                self.world_state = world_state;
+                return Err(err);
+            }
+            self.world_state.execute_proposal(&proposal_id);
+        }
+        Ok(())
+    }
+
+    /// Verify that the `value` matches the schema of its `key` (if there is one).
+    ///
+    /// The schema of encrypted values cannot be checked,
+    /// they must be encrypted with a content key shared for the `key` instead.
+    fn verify_schema(
+        &self,
+        namespace: &PeerId,
+        key: &str,
+        value: &[u8],
+    ) -> Result<(), PermissionError> {
+        if self.world_state.is_encrypted(namespace, key) {
+            return self.verify_encrypted(namespace, key, value);
+        }
+        match self.world_state.value_type_of(key) {
+            Some(value_type) if !value_type.validate(value) => {
+                Err(PermissionError::SchemaMismatch(key.to_string(), value_type))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Verify that the `value` of an encrypted `key` is encrypted with the content key
+    /// currently shared for it.
+    ///
+    /// Keys shared earlier for the `key` are rejected (they could be known by removed readers).
+    fn verify_encrypted(
+        &self,
+        namespace: &PeerId,
+        key: &str,
+        value: &[u8],
+    ) -> Result<(), PermissionError> {
+        let grants = self.world_state.content_keys.get(namespace);
+        let is_shared = match (EncryptedValue::from_bytes(value), grants) {
+            (Some(encrypted), Some(grants)) => encryption::current_grant(grants, key)
+                .map_or(false, |grant| grant.key_id == encrypted.key_id),
+            _ => false,
+        };
+        if is_shared {
+            Ok(())
+        } else {
+            Err(PermissionError::NotEncrypted(key.to_string()))
+        }
+    }
+
+    /// Verify that an account management transaction can be applied to the `world_state`.
+    ///
+    /// The admin permissions are checked by the caller.
+    fn verify_account_management(&self, transaction: &Transaction) -> Result<(), PermissionError> {
+        let admins = self
+            .world_state
+            .accounts
+            .values()
+            .filter(|account| account.account_type == AccountType::Admin)
+            .count();
+        let is_admin = |peer_id: &PeerId| {
+            self.world_state
+                .accounts
+                .get(peer_id)
+                .map_or(false, |account| account.account_type == AccountType::Admin)
+        };
+
+        // The accounts could not be managed anymore with less admins than the threshold.
+        let remaining_admins = match transaction {
+            Transaction::UpdateAccount(params) => {
                 if self.world_state.accounts.get(&params.id).is_none() {
                     return Err(PermissionError::AccountNotFound(params.id.clone()));
                 }
-                self.world_state
-                    .apply_transaction(transaction.to_owned().into());
-                Ok(())
+                match &params.permissions.account_type {
+                    Some(AccountType::Admin) | None => admins,
+                    Some(_) if is_admin(&params.id) => admins - 1,
+                    Some(_) => admins,
+                }
             }
             Transaction::CreateAccount(params) => {
-                account_checker.verify_is_admin()?;
-                if self.world_state.accounts.get(&params.id).is_some() {
+                // IDs of rotated keys cannot be reused (they refer to an existing namespace).
+                if self.world_state.is_known_id(&params.id) {
                     return Err(PermissionError::AccountAlreadyExists(params.id.clone()));
                 }
-                self.world_state
-                    .apply_transaction(transaction.to_owned().into());
-                Ok(())
+                admins
             }
             Transaction::DeleteAccount(params) => {
-                account_checker.verify_is_admin()?;
                 if self.world_state.accounts.get(&params.id).is_none() {
                     return Err(PermissionError::AccountNotFound(params.id.clone()));
                 }
-                self.world_state
-                    .apply_transaction(transaction.to_owned().into());
-                Ok(())
+                if is_admin(&params.id) {
+                    admins - 1
+                } else {
+                    admins
+                }
+            }
+            Transaction::SetAdminThreshold(params) => {
+                if params.threshold == 0 || params.threshold > admins {
+                    return Err(PermissionError::InvalidAdminThreshold(params.threshold));
+                }
+                admins
+            }
+            Transaction::RotateKey(params) => {
+                if self.world_state.accounts.get(&params.id).is_none() {
+                    return Err(PermissionError::AccountNotFound(params.id.clone()));
+                }
+                // IDs cannot be reused (they could refer to an existing namespace).
+                if self.world_state.is_known_id(&params.new_id) {
+                    return Err(PermissionError::AccountAlreadyExists(params.new_id.clone()));
+                }
+                // Nobody else may take over the account.
+                params.verify_new_id_signature()?;
+                admins
             }
+            Transaction::SetSchema(_) => admins,
+            _ => return Err(PermissionError::InvalidProposal),
+        };
+
+        let threshold = self.world_state.admin_threshold();
+        if remaining_admins < threshold {
+            return Err(PermissionError::InvalidAdminThreshold(threshold));
         }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world_state::REPLAY_WINDOW;
+    use pinxit::{Identity, Signable};
+    use prellblock_client_api::{
+        account::{Matching, Permission, Permissions},
+        encryption::ContentKey,
+        transaction, PROPOSAL_LIFETIME,
+    };
+
+    fn at(seconds: u64) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(seconds)
+    }
+
+    /// A `TransactionCheck` of a block at second `1000` with the accounts of the `identities`.
+    fn check(identities: &[(&Identity, AccountType)]) -> TransactionCheck {
+        let mut world_state = WorldState::default();
+        for (identity, account_type) in identities {
+            let mut account = Account::new("account".to_string());
+            account.account_type = account_type.clone();
+            account.writing_rights = true;
+            world_state
+                .accounts
+                .insert(identity.id().clone(), Arc::new(account));
+        }
+        let mut check = TransactionCheck {
+            world_state,
+            block_timestamp: at(1000),
+        };
+        check.set_block_timestamp(at(1000));
+        check
+    }
+
+    fn apply(
+        check: &mut TransactionCheck,
+        identity: &Identity,
+        transaction: Transaction,
+    ) -> Result<(), PermissionError> {
+        let signed = transaction.sign(identity).unwrap();
+        check.verify_permissions_and_apply(signed.verify_ref().unwrap())
+    }
+
+    fn key_value(value: Vec<u8>, timestamp: SystemTime) -> Transaction {
+        Transaction::KeyValue(transaction::KeyValue {
+            key: "speed".to_string(),
+            value,
+            timestamp,
+        })
+    }
+
+    fn share_content_key(
+        identity: &Identity,
+        content_key: &ContentKey,
+        timestamp: SystemTime,
+    ) -> Transaction {
+        Transaction::ShareContentKey(transaction::ShareContentKey {
+            scope: Permission::new("speed", Matching::Exact),
+            key_id: content_key.id(),
+            readers: vec![content_key.wrap_for(identity, identity.id()).unwrap()],
+            timestamp,
+        })
+    }
+
+    #[test]
+    fn only_the_current_content_key_is_accepted() {
+        let identity = Identity::generate();
+        let mut check = check(&[(&identity, AccountType::Normal)]);
+        let old_key = ContentKey::generate();
+        let new_key = ContentKey::generate();
+
+        apply(&mut check, &identity, key_value(vec![1], at(1000))).unwrap();
+        apply(
+            &mut check,
+            &identity,
+            share_content_key(&identity, &old_key, at(1001)),
+        )
+        .unwrap();
+        let result = apply(&mut check, &identity, key_value(vec![2], at(1002)));
+        assert!(matches!(result, Err(PermissionError::NotEncrypted(_))));
+        let encrypted = old_key.encrypt(&[3]).to_bytes();
+        apply(&mut check, &identity, key_value(encrypted, at(1003))).unwrap();
+
+        // Sharing a new key (e.g. to remove a reader) replaces the old one.
+        apply(
+            &mut check,
+            &identity,
+            share_content_key(&identity, &new_key, at(1004)),
+        )
+        .unwrap();
+        let encrypted = old_key.encrypt(&[5]).to_bytes();
+        let result = apply(&mut check, &identity, key_value(encrypted, at(1005)));
+        assert!(matches!(result, Err(PermissionError::NotEncrypted(_))));
+        let encrypted = new_key.encrypt(&[6]).to_bytes();
+        apply(&mut check, &identity, key_value(encrypted, at(1006))).unwrap();
+    }
+
+    fn create_account(identity: &Identity, timestamp: SystemTime) -> Transaction {
+        Transaction::CreateAccount(transaction::CreateAccount {
+            id: identity.id().clone(),
+            name: "account".to_string(),
+            permissions: Permissions {
+                account_type: None,
+                expire_at: None,
+                has_writing_rights: None,
+                writing_scopes: None,
+                delegates: None,
+                reading_rights: None,
+                limits: None,
+            },
+            timestamp,
+        })
+    }
+
+    fn propose(operation: Transaction, timestamp: SystemTime) -> Transaction {
+        Transaction::ProposeAccountChange(transaction::ProposeAccountChange {
+            operation: Box::new(operation),
+            timestamp,
+        })
+    }
+
+    fn approve(proposal: TransactionHash, timestamp: SystemTime) -> Transaction {
+        Transaction::ApproveAccountChange(transaction::ApproveAccountChange {
+            proposal,
+            timestamp,
+        })
+    }
+
+    /// The `TransactionHash` of a proposal (signatures are deterministic).
+    fn proposal_id(identity: &Identity, proposal: &Transaction) -> TransactionHash {
+        TransactionHash::of(&proposal.clone().sign(identity).unwrap())
+    }
+
+    #[test]
+    fn proposals_are_executed_once_the_threshold_is_reached() {
+        let admins: Vec<_> = (0..3).map(|_| Identity::generate()).collect();
+        let mut check = check(&[
+            (&admins[0], AccountType::Admin),
+            (&admins[1], AccountType::Admin),
+            (&admins[2], AccountType::Admin),
+        ]);
+        check.world_state.admin_threshold = 2;
+        let account = Identity::generate();
+
+        // A single admin cannot manage accounts anymore.
+        let result = apply(&mut check, &admins[0], create_account(&account, at(1000)));
+        assert!(matches!(result, Err(PermissionError::ApprovalRequired(2))));
+
+        let proposal = propose(create_account(&account, at(1000)), at(1000));
+        apply(&mut check, &admins[0], proposal.clone()).unwrap();
+        assert!(!check.world_state.accounts.contains_key(account.id()));
+
+        // The proposer already approved the proposal.
+        let id = proposal_id(&admins[0], &proposal);
+        let result = apply(&mut check, &admins[0], approve(id, at(1001)));
+        assert!(matches!(result, Err(PermissionError::AlreadyApproved(..))));
+
+        apply(&mut check, &admins[1], approve(id, at(1001))).unwrap();
+        assert!(check.world_state.accounts.contains_key(account.id()));
+        assert!(check.world_state.proposals.is_empty());
+
+        // Executed proposals cannot be approved anymore.
+        let result = apply(&mut check, &admins[2], approve(id, at(1002)));
+        assert!(matches!(result, Err(PermissionError::ProposalNotFound(_))));
+    }
+
+    #[test]
+    fn only_admins_can_propose_account_management() {
+        let admin = Identity::generate();
+        let writer = Identity::generate();
+        let mut check = check(&[(&admin, AccountType::Admin), (&writer, AccountType::Normal)]);
+        check.world_state.admin_threshold = 2;
+
+        let proposal = propose(create_account(&Identity::generate(), at(1000)), at(1000));
+        let result = apply(&mut check, &writer, proposal);
+        assert!(matches!(result, Err(PermissionError::NotAnAdmin(_))));
+
+        let proposal = propose(key_value(vec![1], at(1000)), at(1000));
+        let result = apply(&mut check, &admin, proposal);
+        assert!(matches!(result, Err(PermissionError::InvalidProposal)));
+    }
+
+    #[test]
+    fn expired_proposals_cannot_be_approved() {
+        let admins: Vec<_> = (0..2).map(|_| Identity::generate()).collect();
+        let mut check = check(&[
+            (&admins[0], AccountType::Admin),
+            (&admins[1], AccountType::Admin),
+        ]);
+        check.world_state.admin_threshold = 2;
+
+        let proposal = propose(create_account(&Identity::generate(), at(1000)), at(1000));
+        apply(&mut check, &admins[0], proposal.clone()).unwrap();
+        let id = proposal_id(&admins[0], &proposal);
+
+        let expired = at(1001) + PROPOSAL_LIFETIME;
+        check.set_block_timestamp(expired);
+        assert!(check.world_state.proposals.is_empty());
+        let result = apply(&mut check, &admins[1], approve(id, expired));
+        assert!(matches!(result, Err(PermissionError::ProposalNotFound(_))));
+    }
+
+    fn rotate_key(id: &Identity, new_identity: &Identity, timestamp: SystemTime) -> Transaction {
+        Transaction::RotateKey(transaction::RotateKey {
+            timestamp,
+            ..transaction::RotateKey::new(id.id().clone(), new_identity).unwrap()
+        })
+    }
+
+    #[test]
+    fn rotated_keys_keep_the_namespace() {
+        let admin = Identity::generate();
+        let writer = Identity::generate();
+        let new_key = Identity::generate();
+        let mut check = check(&[(&admin, AccountType::Admin), (&writer, AccountType::Normal)]);
+
+        apply(&mut check, &writer, rotate_key(&writer, &new_key, at(1000))).unwrap();
+        let world_state = &check.world_state;
+        assert!(!world_state.accounts.contains_key(writer.id()));
+        assert!(world_state.accounts.contains_key(new_key.id()));
+        assert_eq!(world_state.namespace_of(new_key.id()), writer.id());
+
+        // Only the new key can write (into the old namespace).
+        apply(&mut check, &new_key, key_value(vec![1], at(1001))).unwrap();
+        let result = apply(&mut check, &writer, key_value(vec![2], at(1001)));
+        assert!(matches!(result, Err(PermissionError::AccountNotFound(_))));
+
+        // The old ID cannot be reused.
+        let result = apply(
+            &mut check,
+            &new_key,
+            rotate_key(&new_key, &writer, at(1002)),
+        );
+        assert!(matches!(
+            result,
+            Err(PermissionError::AccountAlreadyExists(_))
+        ));
+    }
+
+    #[test]
+    fn keys_can_only_be_rotated_by_the_account_or_an_admin() {
+        let admin = Identity::generate();
+        let writer = Identity::generate();
+        let other = Identity::generate();
+        let mut check = check(&[
+            (&admin, AccountType::Admin),
+            (&writer, AccountType::Normal),
+            (&other, AccountType::Normal),
+        ]);
+
+        let result = apply(
+            &mut check,
+            &other,
+            rotate_key(&writer, &Identity::generate(), at(1000)),
+        );
+        assert!(matches!(result, Err(PermissionError::NotAnAdmin(_))));
+
+        let new_key = Identity::generate();
+        apply(&mut check, &admin, rotate_key(&writer, &new_key, at(1000))).unwrap();
+        assert_eq!(check.world_state.namespace_of(new_key.id()), writer.id());
+    }
+
+    #[test]
+    fn key_rotations_need_the_signature_of_the_new_key() {
+        let admin = Identity::generate();
+        let writer = Identity::generate();
+        let mut check = check(&[(&admin, AccountType::Admin), (&writer, AccountType::Normal)]);
+
+        let rotation = Transaction::RotateKey(transaction::RotateKey {
+            new_id: Identity::generate().id().clone(),
+            timestamp: at(1000),
+            ..transaction::RotateKey::new(writer.id().clone(), &Identity::generate()).unwrap()
+        });
+        let result = apply(&mut check, &writer, rotation);
+        assert!(matches!(result, Err(PermissionError::InvalidSignature(_))));
+        assert!(check.world_state.accounts.contains_key(writer.id()));
+    }
+
+    #[test]
+    fn replayed_transactions_are_rejected() {
+        let writer = Identity::generate();
+        let mut check = check(&[(&writer, AccountType::Normal)]);
+
+        apply(&mut check, &writer, key_value(vec![1], at(1000))).unwrap();
+        let result = apply(&mut check, &writer, key_value(vec![1], at(1000)));
+        assert!(matches!(
+            result,
+            Err(PermissionError::TransactionReplayed(_))
+        ));
+        // Other transactions with the same timestamp are accepted.
+        apply(&mut check, &writer, key_value(vec![2], at(1000))).unwrap();
+
+        // Committed transactions are remembered in later blocks.
+        check.set_block_timestamp(at(1200));
+        let result = apply(&mut check, &writer, key_value(vec![1], at(1000)));
+        assert!(matches!(
+            result,
+            Err(PermissionError::TransactionReplayed(_))
+        ));
+
+        // Transactions older than the replay window are rejected as too old.
+        check.set_block_timestamp(at(1001) + REPLAY_WINDOW);
+        let result = apply(&mut check, &writer, key_value(vec![1], at(1000)));
+        assert!(matches!(result, Err(PermissionError::TransactionTooOld(_))));
+    }
+
+    #[test]
+    fn transaction_timestamps_must_be_near_the_block_timestamp() {
+        let writer = Identity::generate();
+        let mut check = check(&[(&writer, AccountType::Normal)]);
+        let block_timestamp = at(1000);
+
+        let oldest = block_timestamp - MAX_TRANSACTION_AGE;
+        let result = apply(
+            &mut check,
+            &writer,
+            key_value(vec![1], oldest - Duration::from_millis(1)),
+        );
+        assert!(matches!(result, Err(PermissionError::TransactionTooOld(_))));
+        apply(&mut check, &writer, key_value(vec![2], oldest)).unwrap();
+
+        let newest = block_timestamp + MAX_CLOCK_SKEW;
+        apply(&mut check, &writer, key_value(vec![3], newest)).unwrap();
+        let result = apply(
+            &mut check,
+            &writer,
+            key_value(vec![4], newest + Duration::from_millis(1)),
+        );
+        assert!(matches!(
+            result,
+            Err(PermissionError::TransactionFromFuture(_))
+        ));
+    }
+
+    #[test]
+    fn values_must_match_the_schema_of_their_key() {
+        let admin = Identity::generate();
+        let writer = Identity::generate();
+        let mut check = check(&[(&admin, AccountType::Admin), (&writer, AccountType::Normal)]);
+
+        let set_schema = Transaction::SetSchema(transaction::SetSchema {
+            key: Permission::new("speed", Matching::Exact),
+            value_type: Some(ValueType::Float),
+            timestamp: at(1000),
+        });
+        let result = apply(&mut check, &writer, set_schema.clone());
+        assert!(matches!(result, Err(PermissionError::NotAnAdmin(_))));
+        apply(&mut check, &admin, set_schema).unwrap();
+
+        let value = postcard::to_stdvec(&42.0_f64).unwrap();
+        apply(&mut check, &writer, key_value(value, at(1001))).unwrap();
+        let value = postcard::to_stdvec(&"42").unwrap();
+        let result = apply(&mut check, &writer, key_value(value, at(1002)));
+        assert!(matches!(
+            result,
+            Err(PermissionError::SchemaMismatch(_, ValueType::Float))
+        ));
     }
 }
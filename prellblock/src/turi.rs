@@ -1,13 +1,28 @@
 //! A server for communicating between RPUs.
 
-use crate::{batcher::Batcher, reader::Reader, transaction_checker::TransactionChecker, BoxError};
+use crate::{
+    batcher::Batcher, block_storage::BlockStorage, consensus::Consensus,
+    data_broadcaster::Broadcaster, peer::message as peer_message, reader::Reader,
+    transaction_checker::TransactionChecker, world_state::Usage, BoxError,
+};
 use balise::{
     handler,
     server::{Server, TlsIdentity},
 };
-use prellblock_client_api::{message, ClientMessage, Pong, Transaction};
-use std::sync::Arc;
-use tokio::net::TcpListener;
+use pinxit::{PeerId, Signed};
+use prellblock_client_api::{
+    consensus::TransactionHash, message, ClientMessage, Pong, Transaction, TransactionReceipt,
+    TransactionStatus,
+};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+use tokio::{net::TcpListener, time};
+
+/// The maximum time a client can wait for a transaction to be committed.
+const MAX_EXECUTE_AND_WAIT_TIMEOUT: Duration = Duration::from_secs(60);
 
 type Response<R> = Result<<R as balise::Request<ClientMessage>>::Response, BoxError>;
 
@@ -21,6 +36,11 @@ pub struct Turi {
     batcher: Arc<Batcher>,
     reader: Reader,
     transaction_checker: TransactionChecker,
+    consensus: Arc<Consensus>,
+    block_storage: BlockStorage,
+    broadcaster: Arc<Broadcaster>,
+    /// The usage of the transactions admitted by this RPU (to enforce account `Limits` early).
+    admitted: Arc<Mutex<HashMap<PeerId, Usage>>>,
 }
 
 impl Turi {
@@ -28,17 +48,24 @@ impl Turi {
     ///
     /// The `identity` is a path to a `.pfx` file.
     #[must_use]
-    pub const fn new(
+    pub fn new(
         tls_identity: TlsIdentity,
         batcher: Arc<Batcher>,
         reader: Reader,
         transaction_checker: TransactionChecker,
+        consensus: Arc<Consensus>,
+        block_storage: BlockStorage,
+        broadcaster: Arc<Broadcaster>,
     ) -> Self {
         Self {
             tls_identity,
             batcher,
             reader,
             transaction_checker,
+            consensus,
+            block_storage,
+            broadcaster,
+            admitted: Arc::default(),
         }
     }
 
@@ -49,10 +76,22 @@ impl Turi {
             handler!(ClientMessage, {
                 Ping(_) => Ok(Pong),
                 Execute(params) => self.handle_execute(params).await,
+                ExecuteAndWait(params) => self.handle_execute_and_wait(params).await,
                 GetValue(params) => self.reader.handle_get_value(params).await,
+                GetAggregate(params) => self.reader.handle_get_aggregate(params).await,
+                GetValueProof(params) => self.reader.handle_get_value_proof(params).await,
+                GetValueProofs(params) => self.reader.handle_get_value_proofs(params).await,
+                PutBlob(params) => self.handle_put_blob(params).await,
+                GetBlob(params) => self.reader.handle_get_blob(params).await,
+                ListKeys(params) => self.reader.handle_list_keys(params).await,
                 GetAccount(params) => self.reader.handle_get_account(params).await,
+                ListAccounts(params) => self.reader.handle_list_accounts(params).await,
                 GetBlock(params) => self.reader.handle_get_block(params).await,
+                GetSchemas(params) => self.reader.handle_get_schemas(params).await,
+                GetContentKeys(params) => self.reader.handle_get_content_keys(params).await,
                 GetCurrentBlockNumber(params) => self.reader.handle_get_current_block_number(params).await,
+                GetTransactionStatus(params) => self.handle_get_transaction_status(params).await,
+                Subscribe(params) => self.reader.handle_subscribe(params).await,
             }),
             tls_identity,
         )?;
@@ -62,6 +101,88 @@ impl Turi {
 
     async fn handle_execute(&self, params: message::Execute) -> Response<message::Execute> {
         let message::Execute(transaction) = params;
+        self.execute(transaction)
+    }
+
+    async fn handle_execute_and_wait(
+        &self,
+        params: message::ExecuteAndWait,
+    ) -> Response<message::ExecuteAndWait> {
+        let message::ExecuteAndWait(transaction, timeout) = params;
+        let transaction_hash = self.execute(transaction)?;
+
+        let timeout = timeout.min(MAX_EXECUTE_AND_WAIT_TIMEOUT);
+        let status = match time::timeout(
+            timeout,
+            self.consensus.wait_for_transaction(&transaction_hash),
+        )
+        .await
+        {
+            Ok(status) => status?,
+            // Check a last time, the block could have been committed just now.
+            Err(_) => self.transaction_status(&transaction_hash).await?,
+        };
+
+        match status {
+            TransactionStatus::Committed {
+                block_number,
+                index,
+            } => Ok(TransactionReceipt {
+                transaction_hash,
+                block_number,
+                index,
+            }),
+            TransactionStatus::Rejected { reason } => {
+                Err(format!("Transaction {} was rejected: {}", transaction_hash, reason).into())
+            }
+            TransactionStatus::Pending | TransactionStatus::Unknown => Err(format!(
+                "Transaction {} was not committed within {:?}.",
+                transaction_hash, timeout
+            )
+            .into()),
+        }
+    }
+
+    async fn handle_put_blob(&self, params: message::PutBlob) -> Response<message::PutBlob> {
+        let message::PutBlob(message) = params;
+        let verified = message.verify_ref()?;
+
+        self.transaction_checker
+            .account_checker(verified.signer().clone())?
+            .verify_blob_size(verified.size)?;
+        // The payload is counted when it is uploaded (not when it is referenced).
+        self.admit(verified.signer(), verified.data.len() as u64)?;
+
+        let hash = verified.hash;
+        let complete = self
+            .block_storage
+            .write_blob_chunk(verified.signer(), &verified)?;
+        if complete {
+            log::debug!(
+                "Client {} uploaded blob {} ({} bytes).",
+                verified.signer(),
+                hash,
+                verified.size,
+            );
+        }
+
+        // The chunk is replicated in the background (RPUs missing the payload fetch it on demand).
+        let broadcaster = self.broadcaster.clone();
+        tokio::spawn(async move {
+            if let Err(err) = broadcaster
+                .broadcast(&peer_message::StoreBlob(message))
+                .await
+            {
+                log::warn!("Could not replicate a chunk of blob {}: {}", hash, err);
+            }
+        });
+
+        Ok(complete)
+    }
+
+    /// Verify a `transaction` and send it to the other RPUs.
+    fn execute(&self, transaction: Signed<Transaction>) -> Result<TransactionHash, BoxError> {
+        let transaction_hash = TransactionHash::of(&transaction);
 
         // Check validity of transaction signature.
         let transaction = transaction.verify()?;
@@ -70,6 +191,32 @@ impl Turi {
         self.transaction_checker
             .verify_permissions(transaction.borrow())?;
 
+        // The payload of a blob has to be uploaded before it can be referenced.
+        if let Transaction::Blob(params) = &*transaction {
+            match self.block_storage.blob_size(&params.hash)? {
+                Some(size) if size == params.size => {}
+                Some(size) => {
+                    return Err(format!(
+                        "The payload of blob {} has {} bytes, not {}.",
+                        params.hash, size, params.size
+                    )
+                    .into())
+                }
+                None => {
+                    return Err(
+                        format!("The payload of blob {} was not uploaded.", params.hash).into(),
+                    )
+                }
+            }
+        }
+
+        // The payload of a blob was already counted when it was uploaded.
+        let bytes = match &*transaction {
+            Transaction::Blob(_) => 0,
+            transaction => transaction.payload_size(),
+        };
+        self.admit(transaction.signer(), bytes)?;
+
         let peer_id = transaction.signer();
         match &*transaction {
             Transaction::KeyValue(params) => {
@@ -82,6 +229,16 @@ impl Turi {
                     std::time::SystemTime::now().duration_since(params.timestamp),
                 );
             }
+            Transaction::DelegatedKeyValue(params) => {
+                log::debug!(
+                    "Client {} set {} to {:?} for {} (time since transaction-creation on the client: {:?}).",
+                    peer_id,
+                    params.key,
+                    params.value,
+                    params.owner,
+                    std::time::SystemTime::now().duration_since(params.timestamp),
+                );
+            }
             Transaction::UpdateAccount(params) => {
                 log::debug!(
                     "Client {} updates account {}: {:#?} (time since transaction-creation on the client: {:?}).",
@@ -108,6 +265,68 @@ impl Turi {
                     std::time::SystemTime::now().duration_since(params.timestamp),
                 );
             }
+            Transaction::ProposeAccountChange(params) => {
+                log::debug!(
+                    "Client {} proposes {:#?} (time since transaction-creation on the client: {:?}).",
+                    &transaction.signer(),
+                    params.operation,
+                    std::time::SystemTime::now().duration_since(params.timestamp),
+                );
+            }
+            Transaction::ApproveAccountChange(params) => {
+                log::debug!(
+                    "Client {} approves proposal {} (time since transaction-creation on the client: {:?}).",
+                    &transaction.signer(),
+                    params.proposal,
+                    std::time::SystemTime::now().duration_since(params.timestamp),
+                );
+            }
+            Transaction::SetAdminThreshold(params) => {
+                log::debug!(
+                    "Client {} sets the admin threshold to {} (time since transaction-creation on the client: {:?}).",
+                    &transaction.signer(),
+                    params.threshold,
+                    std::time::SystemTime::now().duration_since(params.timestamp),
+                );
+            }
+            Transaction::RotateKey(params) => {
+                log::debug!(
+                    "Client {} rotates the key of account {} to {} (time since transaction-creation on the client: {:?}).",
+                    &transaction.signer(),
+                    params.id,
+                    params.new_id,
+                    std::time::SystemTime::now().duration_since(params.timestamp),
+                );
+            }
+            Transaction::SetSchema(params) => {
+                log::debug!(
+                    "Client {} sets the schema of {:?} to {:?} (time since transaction-creation on the client: {:?}).",
+                    &transaction.signer(),
+                    params.key,
+                    params.value_type,
+                    std::time::SystemTime::now().duration_since(params.timestamp),
+                );
+            }
+            Transaction::Blob(params) => {
+                log::debug!(
+                    "Client {} set {} to blob {} ({} bytes) (time since transaction-creation on the client: {:?}).",
+                    peer_id,
+                    params.key,
+                    params.hash,
+                    params.size,
+                    std::time::SystemTime::now().duration_since(params.timestamp),
+                );
+            }
+            Transaction::ShareContentKey(params) => {
+                log::debug!(
+                    "Client {} shares content key {} for {:?} with {} readers (time since transaction-creation on the client: {:?}).",
+                    peer_id,
+                    params.key_id,
+                    params.scope,
+                    params.readers.len(),
+                    std::time::SystemTime::now().duration_since(params.timestamp),
+                );
+            }
         }
 
         let batcher = self.batcher.clone();
@@ -115,6 +334,54 @@ impl Turi {
             batcher.add_to_batch(transaction.into()).await;
         });
 
+        Ok(transaction_hash)
+    }
+
+    /// Count a transaction (or blob upload) writing `bytes` towards the `Limits` of its `signer`
+    /// (at the time of admission).
+    ///
+    /// This throttles clients before their transactions reach the consensus `Queue`.
+    /// The limits are enforced deterministically again when the block is validated.
+    fn admit(&self, signer: &PeerId, bytes: u64) -> Result<(), BoxError> {
+        let account_checker = self.transaction_checker.account_checker(signer.clone())?;
+
+        let mut admitted = self.admitted.lock().unwrap();
+        let mut usage = admitted.get(signer).copied().unwrap_or_default();
+        usage.record(SystemTime::now(), bytes);
+        account_checker.verify_usage(&usage)?;
+        admitted.insert(signer.clone(), usage);
         Ok(())
     }
+
+    async fn handle_get_transaction_status(
+        &self,
+        params: message::GetTransactionStatus,
+    ) -> Response<message::GetTransactionStatus> {
+        let message::GetTransactionStatus(message) = params;
+        let message = message.verify()?;
+
+        // The sender needs to have a valid account.
+        self.transaction_checker
+            .account_checker(message.signer().clone())?;
+
+        let status = self.transaction_status(&message.transaction_hash).await?;
+
+        Ok(status)
+    }
+
+    /// Get the status of the transaction identified by `transaction_hash`.
+    ///
+    /// Transactions that are still waiting in the `Batcher` are pending, too.
+    async fn transaction_status(
+        &self,
+        transaction_hash: &TransactionHash,
+    ) -> Result<TransactionStatus, BoxError> {
+        let status = self.consensus.transaction_status(transaction_hash).await?;
+        if let TransactionStatus::Unknown = status {
+            if self.batcher.contains(transaction_hash).await {
+                return Ok(TransactionStatus::Pending);
+            }
+        }
+        Ok(status)
+    }
 }
@@ -6,21 +6,35 @@ pub use prellblock_client_api::account::{Account, Permissions};
 
 use crate::{
     block_storage::BlockStorage,
-    consensus::{Block, BlockHash, BlockNumber},
+    consensus::{Block, BlockHash, BlockNumber, SignatureList, StateRoot, TransactionHash},
     BoxError,
 };
-use im::{HashMap, Vector};
+use im::{HashMap, OrdMap, Vector};
 use pinxit::{PeerId, Signed};
-use prellblock_client_api::{account::AccountType, Transaction};
+use prellblock_client_api::{
+    account::AccountType,
+    consensus::{supermajority_reached, BlockApproval, BlockHeader},
+    encryption::ContentKeyGrant,
+    schema::{self, Schema, ValueType},
+    Transaction, PROPOSAL_LIFETIME,
+};
 use serde::{Deserialize, Serialize};
 use std::{
     fmt,
+    hash::Hash,
     net::SocketAddr,
     ops::{Deref, DerefMut},
     sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 use tokio::sync::{OwnedSemaphorePermit, Semaphore};
 
+/// How long committed transactions are remembered to detect replays.
+///
+/// Transactions with a timestamp older than this (relative to the latest block) are rejected.
+/// This must not be shorter than `MAX_TRANSACTION_AGE`.
+pub const REPLAY_WINDOW: Duration = Duration::from_secs(10 * 60);
+
 /// Struct holding a `Worldstate` and it's previous `Worldstate`, if any.
 #[derive(Debug, Default)]
 pub struct WorldStateReferences {
@@ -58,10 +72,29 @@ impl WorldStateService {
     }
 
     /// Create a new `WorldStateService` initalized with the blocks from a `block_storage`.
+    ///
+    /// Starts at the newest valid snapshot and only replays the blocks after it.
     pub fn from_block_storage(block_storage: &BlockStorage) -> Result<Self, BoxError> {
         let mut world_state_references = WorldStateReferences::default();
 
-        let mut blocks = block_storage.read(..);
+        // The last block is replayed (to be able to roll it back),
+        // unless the chain starts at the snapshot (after a snapshot synchronization).
+        if let Some(last_block) = block_storage.read(..).next_back() {
+            let last_block_number = last_block?.block_number();
+            let snapshot = match block_storage.read_snapshot(last_block_number)? {
+                Some(snapshot) => Some(snapshot),
+                None => block_storage.read_snapshot(last_block_number + 1)?,
+            };
+            if let Some(snapshot) = snapshot {
+                log::info!(
+                    "Starting from WorldState snapshot at block #{}.",
+                    snapshot.block_number
+                );
+                world_state_references.current = snapshot;
+            }
+        }
+
+        let mut blocks = block_storage.read(world_state_references.current.block_number..);
         let last_block = blocks.next_back();
         for block in blocks {
             world_state_references.current.apply_block(block?)?;
@@ -89,6 +122,22 @@ impl WorldStateService {
         self.world_state_references.lock().unwrap().current.clone()
     }
 
+    /// Check whether there is a previous state to roll back to.
+    #[must_use]
+    pub fn can_rollback(&self) -> bool {
+        self.world_state_references.lock().unwrap().prev.is_some()
+    }
+
+    /// Replace the `WorldState` with a verified snapshot (after a snapshot synchronization).
+    ///
+    /// The snapshot cannot be rolled back.
+    pub async fn reset(&self, world_state: WorldState) {
+        let _permit = self.writer.acquire().await;
+        let mut world_state_references = self.world_state_references.lock().unwrap();
+        world_state_references.current = world_state;
+        world_state_references.prev = None;
+    }
+
     /// Rollback the `WorldState` to the previous state.
     #[allow(clippy::must_use_candidate)]
     pub fn rollback(&self) -> Option<WorldState> {
@@ -159,27 +208,416 @@ pub struct WorldState {
     pub block_number: BlockNumber,
     /// Hash of the last `Block` in the `BlockStorage`.
     pub last_block_hash: BlockHash,
+    /// Timestamp of the last `Block` in the `BlockStorage`.
+    pub last_block_timestamp: Option<SystemTime>,
+    /// The number of admins needed to manage accounts (`0` is treated as `1`).
+    pub admin_threshold: usize,
+    /// Proposed account management transactions waiting for approval.
+    pub proposals: HashMap<TransactionHash, Proposal>,
+    /// The namespaces of rotated keys (the original ID of the account).
+    ///
+    /// Accounts keep their namespace when their key is rotated.
+    pub namespaces: HashMap<PeerId, PeerId>,
+    /// The usage of each account (by namespace) to enforce its `Limits`.
+    pub usage: HashMap<PeerId, Usage>,
+    /// The transactions committed within the `REPLAY_WINDOW` by their timestamp.
+    ///
+    /// The transactions are identified by `TransactionHash::of_unsigned`.
+    pub recent_transactions: OrdMap<SystemTime, Vector<TransactionHash>>,
+    /// Transactions with an older timestamp are rejected (because replays cannot be detected).
+    pub replay_horizon: Option<SystemTime>,
+    /// The schemas of the values (the first schema matching a key applies).
+    pub schemas: Vector<Schema>,
+    /// The content keys shared by each account (by namespace) in the order they were shared.
+    pub content_keys: HashMap<PeerId, Vector<ContentKeyGrant>>,
+}
+
+/// An account management transaction waiting for the approval of multiple admins.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Proposal {
+    /// The proposed transaction.
+    pub operation: Transaction,
+    /// The admins that approved the proposal.
+    pub approvals: Vector<PeerId>,
+    /// The timestamp of the `ProposeAccountChange` transaction.
+    pub proposed_at: SystemTime,
+}
+
+impl Proposal {
+    /// Whether the proposal is older than the `PROPOSAL_LIFETIME` at `timestamp`.
+    #[must_use]
+    pub fn is_expired_at(&self, timestamp: SystemTime) -> bool {
+        self.proposed_at
+            .checked_add(PROPOSAL_LIFETIME)
+            .map_or(false, |expiry| expiry < timestamp)
+    }
+}
+
+/// The usage of an account within the current second and day.
+///
+/// The time windows only move forward: transactions with an older timestamp
+/// are counted towards the current window.
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
+pub struct Usage {
+    /// The current second (since the UNIX epoch).
+    pub second: u64,
+    /// The number of transactions within the current second.
+    pub transactions: u32,
+    /// The current day (since the UNIX epoch).
+    pub day: u64,
+    /// The number of bytes written within the current day.
+    pub bytes: u64,
+}
+
+impl Usage {
+    /// Record a transaction created at `timestamp` writing `bytes`.
+    pub fn record(&mut self, timestamp: SystemTime, bytes: u64) {
+        const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+        let second = timestamp
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_secs());
+        if second > self.second {
+            self.second = second;
+            self.transactions = 0;
+        }
+        self.transactions = self.transactions.saturating_add(1);
+
+        let day = second / SECONDS_PER_DAY;
+        if day > self.day {
+            self.day = day;
+            self.bytes = 0;
+        }
+        self.bytes = self.bytes.saturating_add(bytes);
+    }
 }
 
 impl WorldState {
+    /// The number of admins needed to manage accounts.
+    #[must_use]
+    pub fn admin_threshold(&self) -> usize {
+        self.admin_threshold.max(1)
+    }
+
+    /// Verify that the block of the `header` is signed by a supermajority of the RPUs
+    /// (like `Core::verify_rpu_majority_signatures` does for the current RPUs).
+    ///
+    /// Returns the reason if the signatures are invalid.
+    pub fn verify_block_signatures(
+        &self,
+        header: &BlockHeader,
+        signatures: &SignatureList,
+    ) -> Result<(), String> {
+        if !signatures.is_unique() {
+            return Err("A signature is duplicated.".to_string());
+        }
+
+        // There is no consensus with less than four RPUs.
+        let rpus = self.peers.len();
+        if rpus < 4 || !supermajority_reached(signatures.len(), rpus) {
+            return Err("Not enough signatures.".to_string());
+        }
+
+        let approval = BlockApproval {
+            leader_term: header.leader_term,
+            block_number: header.height,
+            block_hash: header.hash(),
+        };
+        for (peer_id, signature) in signatures {
+            if !self.peers.iter().any(|(rpu, _)| rpu == peer_id) {
+                return Err(format!("{} is not an RPU.", peer_id));
+            }
+            peer_id
+                .verify(&approval, signature)
+                .map_err(|err| err.to_string())?;
+        }
+
+        Ok(())
+    }
+
+    /// Get the namespace (the original ID) of an account ID.
+    #[must_use]
+    pub fn namespace_of<'a>(&'a self, peer_id: &'a PeerId) -> &'a PeerId {
+        self.namespaces.get(peer_id).unwrap_or(peer_id)
+    }
+
+    /// Check whether the ID was ever used by an account (before or after a key rotation).
+    #[must_use]
+    pub fn is_known_id(&self, peer_id: &PeerId) -> bool {
+        self.accounts.contains_key(peer_id)
+            || self.namespaces.contains_key(peer_id)
+            || self
+                .namespaces
+                .values()
+                .any(|namespace| namespace == peer_id)
+    }
+
+    /// Get the type of the values of `key` (if there is a schema for the `key`).
+    #[must_use]
+    pub fn value_type_of(&self, key: &str) -> Option<ValueType> {
+        schema::value_type_of(&self.schemas, key)
+    }
+
+    /// Check whether the values of `key` in the `namespace` are encrypted by the clients.
+    #[must_use]
+    pub fn is_encrypted(&self, namespace: &PeerId, key: &str) -> bool {
+        self.content_keys.get(namespace).map_or(false, |grants| {
+            grants.iter().any(|grant| grant.scope.matches(key))
+        })
+    }
+
+    /// Calculate the `StateRoot` of the `WorldState`.
+    ///
+    /// The block number and the last block hash are not included
+    /// (the hash of a block depends on its state root).
+    /// The `usage` and the `recent_transactions` are not included either: they change with
+    /// every transaction and grow with the number of accounts and transactions, while the
+    /// state root is calculated for every block. They only affect the limits and the replay
+    /// detection, so a snapshot can be verified against the state root except for them.
+    #[must_use]
+    pub fn state_root(&self) -> StateRoot {
+        StateRoot::of(&(
+            sorted_by(&self.accounts, PeerId::as_bytes),
+            &self.peers,
+            self.admin_threshold,
+            sorted_by(&self.proposals, TransactionHash::as_bytes),
+            sorted_by(&self.namespaces, PeerId::as_bytes),
+            &self.schemas,
+            sorted_by(&self.content_keys, PeerId::as_bytes),
+            self.replay_horizon,
+            self.last_block_timestamp,
+        ))
+    }
+
+    /// Get the usage of an account including the given `transaction`.
+    #[must_use]
+    pub fn usage_with(&self, signer: &PeerId, transaction: &Transaction) -> Usage {
+        let mut usage = self
+            .usage
+            .get(self.namespace_of(signer))
+            .copied()
+            .unwrap_or_default();
+        usage.record(transaction.timestamp(), transaction.payload_size());
+        usage
+    }
+
+    /// Check whether the `transaction` was already committed (within the `REPLAY_WINDOW`).
+    #[must_use]
+    pub fn is_replayed(&self, signer: &PeerId, transaction: &Transaction) -> bool {
+        let transaction_id = TransactionHash::of_unsigned(signer, transaction);
+        self.recent_transactions
+            .get(&transaction.timestamp())
+            .map_or(false, |transaction_ids| {
+                transaction_ids.contains(&transaction_id)
+            })
+    }
+
+    /// Remember a committed `transaction` (to enforce `Limits` and to detect replays).
+    pub fn record_transaction(&mut self, signer: &PeerId, transaction: &Transaction) {
+        let usage = self.usage_with(signer, transaction);
+        let namespace = self.namespace_of(signer).clone();
+        self.usage.insert(namespace, usage);
+
+        let timestamp = transaction.timestamp();
+        let mut transaction_ids = self
+            .recent_transactions
+            .get(&timestamp)
+            .cloned()
+            .unwrap_or_default();
+        transaction_ids.push_back(TransactionHash::of_unsigned(signer, transaction));
+        self.recent_transactions.insert(timestamp, transaction_ids);
+    }
+
+    /// Forget the transactions that are older than the `REPLAY_WINDOW` before `timestamp`.
+    fn advance_replay_horizon(&mut self, timestamp: SystemTime) {
+        let horizon = match timestamp.checked_sub(REPLAY_WINDOW) {
+            Some(horizon) => horizon,
+            None => return,
+        };
+        if self
+            .replay_horizon
+            .map_or(false, |current| current >= horizon)
+        {
+            return;
+        }
+        self.replay_horizon = Some(horizon);
+
+        let (_, at_horizon, mut recent_transactions) =
+            self.recent_transactions.split_lookup(&horizon);
+        if let Some(transaction_ids) = at_horizon {
+            recent_transactions.insert(horizon, transaction_ids);
+        }
+        self.recent_transactions = recent_transactions;
+    }
+
+    /// Check whether enough of the `approvals` are (still) given by admins.
+    #[must_use]
+    pub fn is_approved<'a>(&self, approvals: impl IntoIterator<Item = &'a PeerId>) -> bool {
+        let admin_approvals = approvals
+            .into_iter()
+            .filter(|peer_id| {
+                self.accounts
+                    .get(*peer_id)
+                    .map_or(false, |account| account.account_type == AccountType::Admin)
+            })
+            .count();
+        admin_approvals >= self.admin_threshold()
+    }
+
     /// Apply a block to the current world state.
     pub fn apply_block(&mut self, block: Block) -> Result<(), BoxError> {
-        if block.body.prev_block_hash != self.last_block_hash {
+        if block.body.prev_block_hash() != self.last_block_hash {
             return Err("Last block hash is not equal to hash of last block.".into());
         }
         // TODO: validate block (peers, signatures, etc)
         self.last_block_hash = block.body.hash();
-        self.block_number = block.body.height + 1;
-        for transaction in block.body.transactions {
+        self.block_number = block.body.height() + 1;
+        self.start_block(block.body.timestamp());
+        for transaction in block.body.into_transactions() {
+            self.record_transaction(transaction.signer(), transaction.unverified_ref());
             self.apply_transaction(transaction);
         }
         Ok(())
     }
 
+    /// Move the time-dependent state to the `timestamp` of the next block
+    /// (before its transactions are applied).
+    ///
+    /// The replay horizon is advanced and expired proposals are dropped.
+    pub fn start_block(&mut self, timestamp: SystemTime) {
+        self.last_block_timestamp = Some(timestamp);
+        self.advance_replay_horizon(timestamp);
+        self.expire_proposals(timestamp);
+    }
+
+    /// Drop the proposals that are expired at the block `timestamp`.
+    fn expire_proposals(&mut self, timestamp: SystemTime) {
+        self.proposals
+            .retain(|_, proposal| !proposal.is_expired_at(timestamp));
+    }
+
     /// Apply a transaction to the current world state.
+    ///
+    /// Afterwards, all proposals with enough approvals are executed.
     pub fn apply_transaction(&mut self, transaction: Signed<Transaction>) {
+        self.apply_transaction_only(transaction);
+        while let Some(proposal_id) = self.next_approved_proposal() {
+            self.execute_proposal(&proposal_id);
+        }
+    }
+
+    /// Apply a transaction without executing the proposals.
+    ///
+    /// The `TransactionChecker` verifies each proposal before it is executed
+    /// (see `next_approved_proposal`).
+    pub fn apply_transaction_only(&mut self, transaction: Signed<Transaction>) {
+        let transaction_hash = TransactionHash::of(&transaction);
+        let signer = transaction.signer().clone();
         match transaction.unverified() {
-            Transaction::KeyValue(_) => {}
+            Transaction::ProposeAccountChange(params) => {
+                let proposal = Proposal {
+                    operation: *params.operation,
+                    approvals: Vector::unit(signer),
+                    proposed_at: params.timestamp,
+                };
+                self.proposals.insert(transaction_hash, proposal);
+            }
+            Transaction::ApproveAccountChange(params) => {
+                if let Some(proposal) = self.proposals.get_mut(&params.proposal) {
+                    proposal.approvals.push_back(signer);
+                } else {
+                    // Should be checked in `TransactionChecker`.
+                    unreachable!("Proposal {} does not exist.", params.proposal);
+                }
+            }
+            Transaction::ShareContentKey(params) => {
+                let namespace = self.namespace_of(&signer).clone();
+                let grant = ContentKeyGrant {
+                    scope: params.scope,
+                    key_id: params.key_id,
+                    sharer: signer,
+                    readers: params.readers,
+                };
+                let mut grants = self
+                    .content_keys
+                    .get(&namespace)
+                    .cloned()
+                    .unwrap_or_default();
+                grants.push_back(grant);
+                self.content_keys.insert(namespace, grants);
+            }
+            operation => self.apply_operation(operation),
+        }
+    }
+
+    /// Get the oldest proposal that has enough approvals.
+    ///
+    /// Proposals are usually executed with their last approval. Changing the admins
+    /// or the admin threshold can make pending proposals executable as well.
+    #[must_use]
+    pub fn next_approved_proposal(&self) -> Option<TransactionHash> {
+        self.proposals
+            .iter()
+            .filter(|(_, proposal)| self.is_approved(&proposal.approvals))
+            // The order must not depend on the (random) order of the `HashMap`.
+            .min_by_key(|&(proposal_id, proposal)| (proposal.proposed_at, proposal_id.as_bytes()))
+            .map(|(proposal_id, _)| *proposal_id)
+    }
+
+    /// Execute a proposed transaction (and remove the proposal).
+    pub fn execute_proposal(&mut self, proposal_id: &TransactionHash) {
+        if let Some(proposal) = self.proposals.remove(proposal_id) {
+            self.apply_operation(proposal.operation);
+        }
+    }
+
+    /// Apply a transaction that does not depend on its signer.
+    fn apply_operation(&mut self, transaction: Transaction) {
+        match transaction {
+            // Content keys cannot be proposed (they are applied with their signer).
+            Transaction::KeyValue(_)
+            | Transaction::DelegatedKeyValue(_)
+            | Transaction::Blob(_)
+            | Transaction::ShareContentKey(_) => {}
+            Transaction::SetAdminThreshold(params) => {
+                self.admin_threshold = params.threshold;
+            }
+            Transaction::RotateKey(params) => {
+                if let Some(account) = self.accounts.remove(&params.id) {
+                    let namespace = self.namespace_of(&params.id).clone();
+                    self.namespaces.insert(params.new_id.clone(), namespace);
+
+                    // RPUs take part in the consensus with their new key.
+                    if let Some(peer) = self.peers.iter_mut().find(|(id, _)| *id == params.id) {
+                        peer.0 = params.new_id.clone();
+                    }
+                    self.accounts.insert(params.new_id, account);
+                } else {
+                    // Should be checked in `TransactionChecker`.
+                    unreachable!("Account {} does not exist.", params.id);
+                }
+            }
+            Transaction::SetSchema(params) => {
+                // A schema for the same keys is replaced (but keeps its position).
+                let position = self
+                    .schemas
+                    .iter()
+                    .position(|schema| schema.key == params.key);
+                match (position, params.value_type) {
+                    (Some(index), Some(value_type)) => self.schemas[index].value_type = value_type,
+                    (Some(index), None) => {
+                        self.schemas.remove(index);
+                    }
+                    (None, Some(value_type)) => {
+                        self.schemas.push_back(Schema::new(params.key, value_type));
+                    }
+                    (None, None) => {}
+                }
+            }
+            Transaction::ProposeAccountChange(_) | Transaction::ApproveAccountChange(_) => {
+                // Should be checked in `TransactionChecker`.
+                unreachable!("Proposals cannot be nested.");
+            }
             Transaction::UpdateAccount(params) => {
                 if let Some(account) = self.accounts.get_mut(&params.id).map(Arc::make_mut) {
                     // If was RPU and now it isn't, remove from peers list.
@@ -271,3 +709,122 @@ impl fmt::Display for WorldState {
         fmt::Debug::fmt(self, f)
     }
 }
+
+/// Get the entries of a `map` sorted by the bytes of their keys
+/// (the iteration order of a `HashMap` differs between RPUs).
+fn sorted_by<'a, K, V>(map: &'a HashMap<K, V>, bytes: fn(&K) -> &[u8]) -> Vec<(&'a K, &'a V)>
+where
+    K: Hash + Eq + Clone,
+    V: Clone,
+{
+    let mut entries: Vec<_> = map.iter().collect();
+    entries.sort_by(|(a, _), (b, _)| bytes(a).cmp(bytes(b)));
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pinxit::Identity;
+    use prellblock_client_api::transaction;
+
+    const DAY: u64 = 24 * 60 * 60;
+
+    fn at(seconds: u64, millis: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(seconds) + Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn usage_counts_transactions_per_second() {
+        let mut usage = Usage::default();
+        usage.record(at(10 * DAY, 0), 1);
+        usage.record(at(10 * DAY, 999), 1);
+        assert_eq!(usage.transactions, 2);
+
+        usage.record(at(10 * DAY + 1, 0), 1);
+        assert_eq!(usage.second, 10 * DAY + 1);
+        assert_eq!(usage.transactions, 1);
+    }
+
+    #[test]
+    fn usage_counts_bytes_per_day() {
+        let mut usage = Usage::default();
+        usage.record(at(10 * DAY, 0), 100);
+        usage.record(at(11 * DAY - 1, 0), 50);
+        assert_eq!(usage.day, 10);
+        assert_eq!(usage.bytes, 150);
+
+        usage.record(at(11 * DAY, 0), 20);
+        assert_eq!(usage.day, 11);
+        assert_eq!(usage.bytes, 20);
+    }
+
+    #[test]
+    fn usage_windows_only_move_forward() {
+        let mut usage = Usage::default();
+        usage.record(at(11 * DAY + 5, 0), 10);
+
+        // Older transactions count towards the current windows.
+        usage.record(at(11 * DAY + 4, 0), 10);
+        usage.record(at(10 * DAY, 0), 10);
+        assert_eq!(usage.second, 11 * DAY + 5);
+        assert_eq!(usage.transactions, 3);
+        assert_eq!(usage.day, 11);
+        assert_eq!(usage.bytes, 30);
+    }
+
+    #[test]
+    fn usage_saturates() {
+        let mut usage = Usage::default();
+        usage.record(at(DAY, 0), u64::max_value());
+        usage.record(at(DAY, 0), 1);
+        assert_eq!(usage.bytes, u64::max_value());
+    }
+
+    #[test]
+    fn recorded_transactions_are_counted_per_namespace() {
+        let identity = Identity::generate();
+        let rotated = Identity::generate();
+        let mut world_state = WorldState::default();
+        world_state
+            .namespaces
+            .insert(rotated.id().clone(), identity.id().clone());
+
+        let transaction = |value: u8| {
+            Transaction::KeyValue(transaction::KeyValue {
+                key: "speed".to_string(),
+                value: vec![value],
+                timestamp: at(DAY, 0),
+            })
+        };
+        world_state.record_transaction(identity.id(), &transaction(1));
+        world_state.record_transaction(rotated.id(), &transaction(2));
+
+        let usage = world_state.usage[identity.id()];
+        assert_eq!(usage.transactions, 2);
+        assert_eq!(usage.bytes, 2 * transaction(1).payload_size());
+        assert!(world_state.usage.get(rotated.id()).is_none());
+
+        assert!(world_state.is_replayed(identity.id(), &transaction(1)));
+        assert!(!world_state.is_replayed(rotated.id(), &transaction(1)));
+        assert!(!world_state.is_replayed(identity.id(), &transaction(3)));
+    }
+
+    #[test]
+    fn recorded_transactions_do_not_change_the_state_root() {
+        let identity = Identity::generate();
+        let mut world_state = WorldState::default();
+        let state_root = world_state.state_root();
+
+        let transaction = Transaction::KeyValue(transaction::KeyValue {
+            key: "speed".to_string(),
+            value: vec![1],
+            timestamp: at(DAY, 0),
+        });
+        world_state.record_transaction(identity.id(), &transaction);
+        assert_eq!(world_state.state_root(), state_root);
+
+        world_state.admin_threshold = 2;
+        assert_ne!(world_state.state_root(), state_root);
+    }
+}
@@ -56,7 +56,7 @@ async fn test_prellblock() {
 
     let batcher = Batcher::new(broadcaster);
 
-    let reader = Reader::new(block_storage, world_state.clone());
+    let reader = Reader::new(block_storage, world_state.clone(), consensus.clone());
 
     let transaction_checker = TransactionChecker::new(world_state);
 
@@ -66,10 +66,17 @@ async fn test_prellblock() {
     // execute the turi in a new thread
     let turi_task = {
         let transaction_checker = transaction_checker.clone();
+        let consensus = consensus.clone();
         let test_identity = test_identity.clone();
         tokio::spawn(async move {
             let mut listener = TcpListener::bind(turi_address).await?;
-            let turi = Turi::new(test_identity, batcher, reader, transaction_checker);
+            let turi = Turi::new(
+                test_identity,
+                batcher,
+                reader,
+                transaction_checker,
+                consensus,
+            );
             turi.serve(&mut listener).await
         })
     };